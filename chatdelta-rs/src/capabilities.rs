@@ -0,0 +1,118 @@
+//! Known limits and prices of individual models
+//!
+//! This table covers the well-known models of each provider, so a model picked at runtime can
+//! still be validated and priced. `ModelCatalog::builtin()` takes its default models' entries
+//! from it, and a catalog file can override those. Dated snapshots (`gpt-4o-2024-11-20`,
+//! `claude-3-5-sonnet-20241022`) match the entry for their family.
+
+/// What a model accepts and what it costs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModelCapabilities {
+    /// Maximum prompt plus response size, in tokens
+    pub max_context_tokens: u32,
+    /// Longest response the model can be asked for, in tokens
+    pub max_output_tokens: u32,
+    pub supports_streaming: bool,
+    pub supports_system_prompt: bool,
+    /// Whether the model accepts images
+    pub supports_vision: bool,
+    /// USD per thousand prompt tokens
+    pub cost_per_1k_input_tokens: f64,
+    /// USD per thousand response tokens
+    pub cost_per_1k_output_tokens: f64,
+}
+
+impl ModelCapabilities {
+    /// Estimated cost in USD of one request
+    pub fn estimate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.cost_per_1k_input_tokens + output_tokens as f64 * self.cost_per_1k_output_tokens) / 1_000.0
+    }
+}
+
+/// A row of the table. Prices are in USD per million tokens, the unit `ModelCatalog` uses.
+pub(crate) struct KnownModel {
+    pub max_context_tokens: u32,
+    pub max_output_tokens: u32,
+    pub supports_vision: bool,
+    pub input_price_per_mtok: f64,
+    pub output_price_per_mtok: f64,
+}
+
+impl KnownModel {
+    fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities {
+            max_context_tokens: self.max_context_tokens,
+            max_output_tokens: self.max_output_tokens,
+            supports_streaming: true,
+            supports_system_prompt: true,
+            supports_vision: self.supports_vision,
+            cost_per_1k_input_tokens: self.input_price_per_mtok / 1_000.0,
+            cost_per_1k_output_tokens: self.output_price_per_mtok / 1_000.0,
+        }
+    }
+}
+
+const fn known(max_context_tokens: u32, max_output_tokens: u32, supports_vision: bool, input: f64, output: f64) -> KnownModel {
+    KnownModel {
+        max_context_tokens,
+        max_output_tokens,
+        supports_vision,
+        input_price_per_mtok: input,
+        output_price_per_mtok: output,
+    }
+}
+
+/// (provider, model name or family prefix, limits and prices)
+const KNOWN_CAPABILITIES: &[(&str, &str, KnownModel)] = &[
+    ("openai", "gpt-4o", known(128_000, 16_384, true, 2.5, 10.0)),
+    ("openai", "gpt-4o-mini", known(128_000, 16_384, true, 0.15, 0.6)),
+    ("openai", "gpt-4-turbo", known(128_000, 4_096, true, 10.0, 30.0)),
+    ("openai", "gpt-3.5-turbo", known(16_385, 4_096, false, 0.5, 1.5)),
+    ("gemini", "gemini-1.5-pro", known(2_000_000, 8_192, true, 1.25, 5.0)),
+    ("gemini", "gemini-1.5-flash", known(1_000_000, 8_192, true, 0.075, 0.3)),
+    ("gemini", "gemini-2.0-flash", known(1_000_000, 8_192, true, 0.1, 0.4)),
+    ("claude", "claude-3-5-sonnet", known(200_000, 8_192, true, 3.0, 15.0)),
+    ("claude", "claude-3-5-haiku", known(200_000, 8_192, false, 0.8, 4.0)),
+    ("claude", "claude-3-opus", known(200_000, 4_096, true, 15.0, 75.0)),
+    ("claude", "claude-3-haiku", known(200_000, 4_096, true, 0.25, 1.25)),
+];
+
+/// The table's row for `model` of `provider`. The longest matching name wins, so
+/// `gpt-4o-mini` isn't taken for `gpt-4o`.
+pub(crate) fn known_model(provider: &str, model: &str) -> Option<&'static KnownModel> {
+    KNOWN_CAPABILITIES
+        .iter()
+        .filter(|(known_provider, name, _)| *known_provider == provider && model.starts_with(name))
+        .max_by_key(|(_, name, _)| name.len())
+        .map(|(_, _, known)| known)
+}
+
+/// What is known about `model` of `provider` (`"openai"`, `"gemini"`, `"claude"`)
+pub fn model_capabilities(provider: &str, model: &str) -> Option<ModelCapabilities> {
+    known_model(provider, model).map(KnownModel::capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_capabilities() {
+        let mini = model_capabilities("openai", "gpt-4o-mini").unwrap();
+        assert_eq!(mini.cost_per_1k_input_tokens, 0.00015);
+        let snapshot = model_capabilities("openai", "gpt-4o-2024-11-20").unwrap();
+        assert_eq!(snapshot.cost_per_1k_input_tokens, 0.0025);
+        assert_eq!(model_capabilities("claude", "claude-3-5-sonnet-20241022").unwrap().max_context_tokens, 200_000);
+        assert!(!model_capabilities("openai", "gpt-3.5-turbo").unwrap().supports_vision);
+        assert_eq!(model_capabilities("claude", "claude-3-opus-20240229").unwrap().max_output_tokens, 4_096);
+
+        assert_eq!(model_capabilities("gemini", "gpt-4o"), None);
+        assert_eq!(model_capabilities("claude", "claude-2.1"), None);
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let opus = model_capabilities("claude", "claude-3-opus-20240229").unwrap();
+        assert!((opus.estimate_cost(1_000, 2_000) - 0.165).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,268 @@
+//! In-flight request deduplication
+//!
+//! While a request is pending, an identical one (same provider, model, API key, prompt,
+//! options and response-affecting settings) waits for its response instead of sending a
+//! second HTTP request. Clients built from the same `ClientConfig` share the set of pending
+//! requests; `ClientConfig::deduplicate_requests` turns this off.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::{AiResponse, GeminiSafetySettings};
+// Only the provider clients send requests
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+use std::{collections::hash_map::DefaultHasher, future::Future, hash::{Hash, Hasher}};
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+use crate::{ClientConfig, PromptOptions};
+
+type Outcome = Result<Vec<AiResponse>, SharedError>;
+
+/// What makes two requests interchangeable: the credential, the prompt and every option or
+/// `ClientConfig` setting that changes the response. The request ID is left out: it only
+/// correlates logs and doesn't change the response. Streamed and non-streamed requests share a key, as a
+/// streamed response is passed on in full as well.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct RequestKey {
+    provider: &'static str,
+    model: String,
+    /// Hash of the API key, so clients with other keys never share a response and the key
+    /// itself isn't kept around
+    credential: u64,
+    prompt: String,
+    samples: u32,
+    stop_sequences: Vec<String>,
+    seed: Option<u64>,
+    max_tokens: Option<u32>,
+    openai_organization: Option<String>,
+    openai_project: Option<String>,
+    openai_web_search: bool,
+    safety_settings: Option<Vec<GeminiSafetySettings>>,
+}
+
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+impl RequestKey {
+    pub(crate) fn new(provider: &'static str, model: &str, api_key: &str, prompt: &str, options: &PromptOptions, config: &ClientConfig) -> Self {
+        let mut credential = DefaultHasher::new();
+        api_key.hash(&mut credential);
+        Self {
+            provider,
+            model: model.to_string(),
+            credential: credential.finish(),
+            prompt: prompt.to_string(),
+            samples: options.sample_count(),
+            stop_sequences: options.stop_sequences(config).to_vec(),
+            seed: options.seed(config),
+            max_tokens: config.max_tokens,
+            openai_organization: config.openai_organization.clone(),
+            openai_project: config.openai_project.clone(),
+            openai_web_search: config.openai_web_search,
+            safety_settings: config.safety_settings.clone(),
+        }
+    }
+}
+
+/// Requests that are currently being sent, shared by every clone
+#[derive(Clone, Default)]
+pub struct InFlightRequests(Arc<Mutex<HashMap<RequestKey, broadcast::Sender<Outcome>>>>);
+
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+impl InFlightRequests {
+    pub(crate) fn is_pending(&self, key: &RequestKey) -> bool {
+        self.0.lock().unwrap().contains_key(key)
+    }
+
+    /// Run `request`, or wait for the identical request that is already pending
+    pub(crate) async fn run<Fut>(&self, key: RequestKey, request: Fut) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>>
+    where
+        Fut: Future<Output = Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>>>,
+    {
+        // A pending request that is dropped closes its channel; then try to send our own
+        while let Some(mut receiver) = self.join(&key) {
+            if let Ok(outcome) = receiver.recv().await {
+                tracing::debug!(provider = key.provider, "shared the response of an identical pending request");
+                return outcome.map_err(Into::into);
+            }
+        }
+
+        let pending = Pending { requests: self, key: &key, finished: false };
+        let result = request.await;
+        let outcome = match &result {
+            Ok(responses) => Ok(responses.clone()),
+            Err(e) => Err(SharedError(e.to_string())),
+        };
+        pending.finish(outcome);
+        result
+    }
+
+    /// Subscribe to the pending request for `key`, or register ours if there is none
+    fn join(&self, key: &RequestKey) -> Option<broadcast::Receiver<Outcome>> {
+        let mut pending = self.0.lock().unwrap();
+        if let Some(sender) = pending.get(key) {
+            return Some(sender.subscribe());
+        }
+        let (sender, _) = broadcast::channel(1);
+        pending.insert(key.clone(), sender);
+        None
+    }
+}
+
+impl fmt::Debug for InFlightRequests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InFlightRequests({} pending)", self.0.lock().unwrap().len())
+    }
+}
+
+/// Removes a registered request when it finishes or is dropped midway
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+struct Pending<'a> {
+    requests: &'a InFlightRequests,
+    key: &'a RequestKey,
+    finished: bool,
+}
+
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+impl Pending<'_> {
+    fn finish(mut self, outcome: Outcome) {
+        self.finished = true;
+        if let Some(sender) = self.requests.0.lock().unwrap().remove(self.key) {
+            // Nobody may be waiting
+            let _ = sender.send(outcome);
+        }
+    }
+}
+
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.requests.0.lock().unwrap().remove(self.key);
+        }
+    }
+}
+
+/// The error of a shared request, as seen by the requests that waited for it
+#[derive(Clone, Debug)]
+struct SharedError(String);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for SharedError {}
+
+#[cfg(all(test, any(feature = "openai", feature = "gemini", feature = "claude"), not(feature = "wasm")))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::oneshot;
+
+    fn key(prompt: &str) -> RequestKey {
+        RequestKey::new("openai", "gpt-4o", "sk-1", prompt, &PromptOptions::default(), &ClientConfig::default())
+    }
+
+    fn response(content: &str) -> Vec<AiResponse> {
+        vec![AiResponse {
+            content: content.to_string(),
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn test_request_id_does_not_change_key() {
+        let options = PromptOptions {
+            request_id: Some("req-1".to_string()),
+            ..Default::default()
+        };
+        let config = ClientConfig::default();
+        assert_eq!(RequestKey::new("openai", "gpt-4o", "sk-1", "Hi", &options, &config), key("Hi"));
+        let samples = PromptOptions {
+            n: Some(3),
+            ..Default::default()
+        };
+        assert_ne!(RequestKey::new("openai", "gpt-4o", "sk-1", "Hi", &samples, &config), key("Hi"));
+        assert_ne!(RequestKey::new("claude", "gpt-4o", "sk-1", "Hi", &options, &config), key("Hi"));
+        assert_ne!(key("Hello"), key("Hi"));
+        // Another API key, e.g. another tenant on the same config, never shares a response
+        assert_ne!(RequestKey::new("openai", "gpt-4o", "sk-2", "Hi", &options, &config), key("Hi"));
+    }
+
+    #[test]
+    fn test_settings_that_change_the_response_change_key() {
+        let options = PromptOptions::default();
+        let web_search = ClientConfig {
+            openai_web_search: true,
+            ..Default::default()
+        };
+        assert_ne!(RequestKey::new("openai", "gpt-4o", "sk-1", "Hi", &options, &web_search), key("Hi"));
+        let project = ClientConfig {
+            openai_project: Some("proj-1".to_string()),
+            ..Default::default()
+        };
+        assert_ne!(RequestKey::new("openai", "gpt-4o", "sk-1", "Hi", &options, &project), key("Hi"));
+        let safety = ClientConfig {
+            safety_settings: Some(vec![GeminiSafetySettings {
+                category: "HARM_CATEGORY_HARASSMENT".to_string(),
+                threshold: "BLOCK_NONE".to_string(),
+            }]),
+            ..Default::default()
+        };
+        let gemini = |config: &ClientConfig| RequestKey::new("gemini", "gemini-1.5-pro", "sk-1", "Hi", &options, config);
+        assert_ne!(gemini(&safety), gemini(&ClientConfig::default()));
+        // Settings like the timeout only change how the request is sent
+        let timeout = ClientConfig {
+            timeout: std::time::Duration::from_secs(5),
+            ..Default::default()
+        };
+        assert_eq!(RequestKey::new("openai", "gpt-4o", "sk-1", "Hi", &options, &timeout), key("Hi"));
+    }
+
+    #[tokio::test]
+    async fn test_identical_requests_share_one_call() {
+        let requests = InFlightRequests::default();
+        let calls = AtomicUsize::new(0);
+        let (release, released) = oneshot::channel::<()>();
+
+        let first = requests.run(key("Hi"), async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            released.await.unwrap();
+            Ok(response("Hello"))
+        });
+        let second = async {
+            tokio::task::yield_now().await;
+            assert!(requests.is_pending(&key("Hi")));
+            let shared = requests.run(key("Hi"), async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(response("again"))
+            });
+            tokio::pin!(shared);
+            // Let the second request subscribe before the first one finishes
+            assert!(futures::poll!(shared.as_mut()).is_pending());
+            release.send(()).unwrap();
+            shared.await
+        };
+
+        let (first, second) = tokio::join!(first, second);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap()[0].content, "Hello");
+        assert_eq!(second.unwrap()[0].content, "Hello");
+        assert!(!requests.is_pending(&key("Hi")));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_unregistered() {
+        let requests = InFlightRequests::default();
+        let mut pending = Box::pin(requests.run(key("Hi"), futures::future::pending()));
+        assert!(futures::poll!(pending.as_mut()).is_pending());
+        assert!(requests.is_pending(&key("Hi")));
+
+        drop(pending);
+        assert!(!requests.is_pending(&key("Hi")));
+        let responses = requests.run(key("Hi"), async { Ok(response("Hello")) }).await.unwrap();
+        assert_eq!(responses[0].content, "Hello");
+    }
+}
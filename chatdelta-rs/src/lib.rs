@@ -1,291 +1,4179 @@
 use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+use tracing::Instrument;
 
-#[derive(Clone, Debug)]
-pub struct ClientConfig;
+pub mod capabilities;
+pub mod dedup;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod pool;
+#[cfg(all(feature = "tls-pinning", any(feature = "openai", feature = "gemini", feature = "claude"), not(feature = "wasm")))]
+pub mod tls;
 
-impl Default for ClientConfig {
-    fn default() -> Self {
-        ClientConfig
-    }
+pub use capabilities::{model_capabilities, ModelCapabilities};
+pub use metrics::{ClientMetrics, MetricsSnapshot};
+pub use pool::ConnectionPool;
+
+use dedup::InFlightRequests;
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+use dedup::RequestKey;
+use middleware::{Middleware, MiddlewareStack, RequestInterceptor};
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+use middleware::{RequestParts, ResponseParts};
+
+/// Settings shared by every client created through `create_client`.
+///
+/// Serializes to the plain settings, e.g. `timeout = "30s"`; the validator, middleware,
+/// pending requests and connection pool are runtime state and come back as their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    /// Timeout applied to each HTTP request
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Number of extra attempts after a failed non-streaming request
+    pub retries: u32,
+    /// Sent as the `OpenAI-Organization` header on OpenAI requests
+    pub openai_organization: Option<String>,
+    /// Sent as the `OpenAI-Project` header on OpenAI requests
+    pub openai_project: Option<String>,
+    /// Let `openai-responses` clients search the web; citations are appended to the answer
+    pub openai_web_search: bool,
+    /// Markers at which every provider stops generating, unless a request sets its own
+    pub stop_sequences: Vec<String>,
+    /// Sampling seed for providers that support reproducible output (OpenAI, Gemini)
+    pub seed: Option<u64>,
+    /// Longest response to ask for, in tokens. Unset, OpenAI and Claude are asked for
+    /// `DEFAULT_MAX_TOKENS` and Gemini uses the model's own limit.
+    pub max_tokens: Option<u32>,
+    /// Gemini content filter thresholds; unset keeps the API's defaults
+    pub safety_settings: Option<Vec<GeminiSafetySettings>>,
+    /// Decides whether a response is usable; a rejected one is requested once more with a nudge
+    #[serde(skip)]
+    pub validate_response: ResponseValidator,
+    /// Hooks run around every HTTP request, see the `middleware` module
+    #[serde(skip)]
+    pub middleware: MiddlewareStack,
+    /// Sees request and response bodies as JSON. Unset unless `CHATDELTA_DEBUG_HTTP=1`, so
+    /// bodies are only parsed twice while debugging.
+    #[serde(skip)]
+    pub interceptor: Option<Arc<dyn RequestInterceptor>>,
+    /// Let an identical request wait for a pending one instead of sending its own, see the
+    /// `dedup` module
+    pub deduplicate_requests: bool,
+    /// Requests pending on clients created with this config or a clone of it; only requests
+    /// with the same API key are shared
+    #[serde(skip)]
+    pub in_flight: InFlightRequests,
+    /// HTTP connections reused by every client created with this config or a clone of it,
+    /// see the `pool` module
+    #[serde(skip)]
+    pub connection_pool: ConnectionPool,
+    /// Retries left for every client created with this config or a clone of it. Only the
+    /// limit is serialized; a deserialized budget starts unused.
+    #[serde(with = "retry_budget_limit")]
+    pub retry_budget: Arc<RetryBudget>,
+    /// Sent as the `User-Agent` header, and to Gemini as `x-goog-api-client`
+    pub user_agent: String,
+    /// HTTP or SOCKS5 proxy every request goes through. Unset, reqwest still follows the
+    /// `HTTP_PROXY` / `HTTPS_PROXY` environment variables.
+    pub proxy: Option<ProxyConfig>,
+    /// Hex SHA-256 hashes of DER-encoded server certificates to accept, see the `tls` module.
+    /// Empty leaves certificate checks to reqwest.
+    #[cfg(feature = "tls-pinning")]
+    pub tls_cert_pins: Vec<String>,
+    /// Reject a server whose certificate matches none of `tls_cert_pins`, instead of logging it
+    #[cfg(feature = "tls-pinning")]
+    pub tls_strict: bool,
 }
 
-#[async_trait]
-pub trait AiClient: Send + Sync {
-    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>>;
+/// Proxy to send requests through, e.g. `http://proxy.corp:3128` or `socks5://127.0.0.1:1080`
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    pub url: String,
+    /// Basic auth user; credentials in `url` work too
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
 }
 
-pub fn create_client(provider: &str, api_key: &str, model: &str, _config: ClientConfig) -> Result<Box<dyn AiClient>, Box<dyn Error + Send + Sync>> {
-    match provider {
-        "openai" => Ok(Box::new(OpenAIClient::new(api_key, model))),
-        "gemini" => Ok(Box::new(GeminiClient::new(api_key, model))),
-        "claude" => Ok(Box::new(ClaudeClient::new(api_key, model))),
-        _ => Err(format!("Unknown provider: {}", provider).into()),
+impl ProxyConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), username: None, password: None }
+    }
+
+    /// The proxy in `HTTPS_PROXY` (or `https_proxy`), if set
+    pub fn from_env() -> Option<Self> {
+        ["HTTPS_PROXY", "https_proxy"]
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|url| !url.trim().is_empty()))
+            .map(Self::new)
+    }
+
+    /// Authenticate to the proxy with basic auth
+    pub fn with_credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    #[cfg(all(any(feature = "openai", feature = "gemini", feature = "claude"), not(feature = "wasm")))]
+    fn to_reqwest(&self) -> Result<reqwest::Proxy, reqwest::Error> {
+        let proxy = reqwest::Proxy::all(&self.url)?;
+        Ok(match &self.username {
+            Some(username) => proxy.basic_auth(username, self.password.as_deref().unwrap_or_default()),
+            None => proxy,
+        })
     }
 }
 
-// OpenAI Client
-struct OpenAIClient {
-    api_key: String,
-    model: String,
-    client: reqwest::Client,
+/// Leaves the password out, as configs end up in debug logs
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .finish()
+    }
 }
 
-impl OpenAIClient {
-    fn new(api_key: &str, model: &str) -> Self {
-        Self {
-            api_key: api_key.to_string(),
-            model: model.to_string(),
-            client: reqwest::Client::new(),
+/// Threshold at which Gemini blocks one harm category, sent in `safetySettings`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GeminiSafetySettings {
+    /// e.g. `HARM_CATEGORY_HARASSMENT`
+    pub category: String,
+    /// e.g. `BLOCK_ONLY_HIGH`
+    pub threshold: String,
+}
+
+const GEMINI_HARM_CATEGORIES: [&str; 5] = ["HARASSMENT", "HATE_SPEECH", "SEXUALLY_EXPLICIT", "DANGEROUS_CONTENT", "CIVIC_INTEGRITY"];
+const GEMINI_BLOCK_THRESHOLDS: [&str; 5] = ["BLOCK_NONE", "BLOCK_ONLY_HIGH", "BLOCK_MEDIUM_AND_ABOVE", "BLOCK_LOW_AND_ABOVE", "OFF"];
+
+/// Parses `<category>=<threshold>`, case-insensitively and with the `HARM_CATEGORY_` prefix
+/// optional, e.g. `harassment=block_only_high`
+impl std::str::FromStr for GeminiSafetySettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (category, threshold) = s.split_once('=').ok_or_else(|| format!("expected <category>=<threshold>, got '{}'", s))?;
+        let category = category.trim().to_uppercase();
+        let category = category.strip_prefix("HARM_CATEGORY_").unwrap_or(&category);
+        if !GEMINI_HARM_CATEGORIES.contains(&category) {
+            return Err(format!("unknown category '{}', expected one of: {}", category, GEMINI_HARM_CATEGORIES.join(", ")));
         }
+        let threshold = threshold.trim().to_uppercase();
+        if !GEMINI_BLOCK_THRESHOLDS.contains(&threshold.as_str()) {
+            return Err(format!("unknown threshold '{}', expected one of: {}", threshold, GEMINI_BLOCK_THRESHOLDS.join(", ")));
+        }
+        Ok(Self {
+            category: format!("HARM_CATEGORY_{}", category),
+            threshold,
+        })
     }
 }
 
-#[derive(Serialize)]
-struct OpenAIRequest {
-    model: String,
-    messages: Vec<OpenAIMessage>,
-    max_tokens: u32,
+/// Response length in tokens asked of OpenAI and Claude unless `ClientConfig::max_tokens` is set
+pub const DEFAULT_MAX_TOKENS: u32 = 1000;
+
+/// `User-Agent` sent unless `ClientConfigBuilder::user_agent` replaces it
+pub const DEFAULT_USER_AGENT: &str = concat!("chatdelta/", env!("CARGO_PKG_VERSION"));
+
+/// OpenAI chat completions endpoint
+pub const OPENAI_ENDPOINT: &str = "https://api.openai.com/v1/chat/completions";
+/// OpenAI's Responses API, which can run built-in tools such as web search
+pub const OPENAI_RESPONSES_ENDPOINT: &str = "https://api.openai.com/v1/responses";
+/// Gemini generateContent endpoint; `{model}` is replaced with the model name
+pub const GEMINI_ENDPOINT_TEMPLATE: &str = "https://generativelanguage.googleapis.com/v1beta/models/{model}:generateContent";
+/// Gemini streamGenerateContent endpoint; `{model}` is replaced with the model name
+pub const GEMINI_STREAM_ENDPOINT_TEMPLATE: &str = "https://generativelanguage.googleapis.com/v1beta/models/{model}:streamGenerateContent";
+/// Anthropic messages endpoint
+pub const CLAUDE_ENDPOINT: &str = "https://api.anthropic.com/v1/messages";
+/// OpenAI model lookup, used by `health_check`; the model name is appended
+pub const OPENAI_MODELS_ENDPOINT: &str = "https://api.openai.com/v1/models";
+/// Gemini model lookup, used by `health_check`; the model name is appended
+pub const GEMINI_MODELS_ENDPOINT: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+/// Anthropic model lookup, used by `health_check`; the model name is appended
+pub const CLAUDE_MODELS_ENDPOINT: &str = "https://api.anthropic.com/v1/models";
+
+/// Check applied to every non-streaming response. The default rejects blank responses.
+#[derive(Clone)]
+pub struct ResponseValidator(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl ResponseValidator {
+    pub fn new(validate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(validate))
+    }
+
+    pub fn accepts(&self, content: &str) -> bool {
+        (self.0)(content)
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct OpenAIMessage {
-    role: String,
-    content: String,
+impl Default for ResponseValidator {
+    fn default() -> Self {
+        Self::new(|content| !content.trim().is_empty())
+    }
 }
 
-#[derive(Deserialize)]
-struct OpenAIResponse {
-    choices: Vec<OpenAIChoice>,
+impl fmt::Debug for ResponseValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResponseValidator")
+    }
 }
 
-#[derive(Deserialize)]
-struct OpenAIChoice {
-    message: OpenAIMessage,
+/// Caps the retries of a whole session, so a provider that keeps failing can't use up the
+/// quota with `retries` extra attempts for every request
+#[derive(Debug)]
+pub struct RetryBudget {
+    pub max_total_retries: u32,
+    retries_used: AtomicU32,
 }
 
-#[async_trait]
-impl AiClient for OpenAIClient {
-    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let request = OpenAIRequest {
-            model: self.model.clone(),
-            messages: vec![OpenAIMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
-            max_tokens: 1000,
-        };
+impl RetryBudget {
+    pub fn new(max_total_retries: u32) -> Self {
+        Self {
+            max_total_retries,
+            retries_used: AtomicU32::new(0),
+        }
+    }
 
-        let response = self.client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+    /// Take one retry from the budget; false once it is exhausted
+    pub fn try_consume(&self) -> bool {
+        self.retries_used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| (used < self.max_total_retries).then_some(used + 1))
+            .is_ok()
+    }
 
-        if !response.status().is_success() {
-            return Err(format!("OpenAI API error: {}", response.status()).into());
+    pub fn retries_used(&self) -> u32 {
+        self.retries_used.load(Ordering::SeqCst)
+    }
+
+    /// Share of the budget used so far, from 0.0 to 1.0
+    pub fn fraction_used(&self) -> f64 {
+        match self.max_total_retries {
+            0 => 1.0,
+            max => self.retries_used() as f64 / max as f64,
         }
+    }
 
-        let openai_response: OpenAIResponse = response.json().await?;
-        let content = openai_response.choices
-            .first()
-            .map(|choice| choice.message.content.clone())
-            .unwrap_or_else(|| "No response".to_string());
+    pub fn reset(&self) {
+        self.retries_used.store(0, Ordering::SeqCst);
+    }
+}
 
-        Ok(content)
+/// Unlimited: only `ClientConfig::retries` applies
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(u32::MAX)
     }
 }
 
-// Gemini Client
-struct GeminiClient {
-    api_key: String,
-    model: String,
-    client: reqwest::Client,
+/// (De)serializes a shared `RetryBudget` as its `max_total_retries`
+mod retry_budget_limit {
+    use super::RetryBudget;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::sync::Arc;
+
+    pub fn serialize<S: Serializer>(budget: &Arc<RetryBudget>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(budget.max_total_retries)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<RetryBudget>, D::Error> {
+        u32::deserialize(deserializer).map(|max_total_retries| Arc::new(RetryBudget::new(max_total_retries)))
+    }
 }
 
-impl GeminiClient {
-    fn new(api_key: &str, model: &str) -> Self {
+#[cfg(not(feature = "wasm"))]
+fn default_interceptor() -> Option<Arc<dyn RequestInterceptor>> {
+    middleware::FileInterceptor::from_env()
+}
+
+#[cfg(feature = "wasm")]
+fn default_interceptor() -> Option<Arc<dyn RequestInterceptor>> {
+    None
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
         Self {
-            api_key: api_key.to_string(),
-            model: model.to_string(),
-            client: reqwest::Client::new(),
+            timeout: Duration::from_secs(30),
+            retries: 0,
+            openai_organization: None,
+            openai_project: None,
+            openai_web_search: false,
+            stop_sequences: Vec::new(),
+            seed: None,
+            max_tokens: None,
+            safety_settings: None,
+            validate_response: ResponseValidator::default(),
+            middleware: MiddlewareStack::default(),
+            interceptor: default_interceptor(),
+            deduplicate_requests: true,
+            in_flight: InFlightRequests::default(),
+            connection_pool: ConnectionPool::default(),
+            retry_budget: Arc::new(RetryBudget::default()),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            #[cfg(feature = "tls-pinning")]
+            tls_cert_pins: Vec::new(),
+            #[cfg(feature = "tls-pinning")]
+            tls_strict: false,
         }
     }
 }
 
-#[derive(Serialize)]
-struct GeminiRequest {
-    contents: Vec<GeminiContent>,
+/// Builder for `ClientConfig`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ClientConfigBuilder {
+    config: ClientConfig,
 }
 
-#[derive(Serialize)]
-struct GeminiContent {
-    parts: Vec<GeminiPart>,
+impl ClientConfigBuilder {
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.config.retries = retries;
+        self
+    }
+
+    pub fn openai_organization(mut self, organization: impl Into<String>) -> Self {
+        self.config.openai_organization = Some(organization.into());
+        self
+    }
+
+    pub fn openai_project(mut self, project: impl Into<String>) -> Self {
+        self.config.openai_project = Some(project.into());
+        self
+    }
+
+    /// Offer the web search tool to `openai-responses` clients
+    pub fn openai_web_search(mut self, enabled: bool) -> Self {
+        self.config.openai_web_search = enabled;
+        self
+    }
+
+    pub fn stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.config.stop_sequences = stop_sequences;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.config.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Gemini content filter thresholds, replacing the API's defaults for these categories
+    pub fn safety_settings(mut self, safety_settings: Vec<GeminiSafetySettings>) -> Self {
+        self.config.safety_settings = Some(safety_settings);
+        self
+    }
+
+    /// Replace the default non-blank check, e.g. to also reject bare refusals
+    pub fn validate_response(mut self, validate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.config.validate_response = ResponseValidator::new(validate);
+        self
+    }
+
+    /// Add middleware to run around every request, after any added before
+    pub fn middleware(mut self, stack: Vec<Arc<dyn Middleware>>) -> Self {
+        for middleware in stack {
+            self.config.middleware.push(middleware);
+        }
+        self
+    }
+
+    /// Show every request and response body to `interceptor`, replacing any set before
+    pub fn interceptor(mut self, interceptor: Arc<dyn RequestInterceptor>) -> Self {
+        self.config.interceptor = Some(interceptor);
+        self
+    }
+
+    /// Send every request even while an identical one is pending
+    pub fn deduplicate_requests(mut self, enabled: bool) -> Self {
+        self.config.deduplicate_requests = enabled;
+        self
+    }
+
+    /// Allow at most `max_total_retries` retries across every client sharing this config
+    pub fn retry_budget(mut self, max_total_retries: u32) -> Self {
+        self.config.retry_budget = Arc::new(RetryBudget::new(max_total_retries));
+        self
+    }
+
+    /// Share `pool`'s connections with clients of another config, e.g. one built for a
+    /// different app that talks to the same providers
+    pub fn connection_pool(mut self, pool: ConnectionPool) -> Self {
+        self.config.connection_pool = pool;
+        self
+    }
+
+    /// Identify as `user_agent` instead of `chatdelta/<version>`
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = user_agent.into();
+        self
+    }
+
+    /// Send every request through `proxy`
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.config.proxy = Some(proxy);
+        self
+    }
+
+    /// Accept only servers whose certificate hashes to one of `pins` (hex SHA-256 of the DER);
+    /// with `strict` unset, other certificates are logged but still accepted
+    #[cfg(feature = "tls-pinning")]
+    pub fn tls_cert_pins(mut self, pins: Vec<String>, strict: bool) -> Self {
+        self.config.tls_cert_pins = pins;
+        self.config.tls_strict = strict;
+        self
+    }
+
+    pub fn build(self) -> ClientConfig {
+        self.config
+    }
 }
 
-#[derive(Serialize)]
-struct GeminiPart {
-    text: String,
+/// A default `ClientConfig` plus per-provider replacements, e.g. a long timeout for a local model
+#[derive(Clone, Debug, Default)]
+pub struct ProviderConfigs {
+    default: ClientConfig,
+    overrides: HashMap<String, ClientConfig>,
 }
 
+/// One provider's section of a provider config file; unset fields keep the default
 #[derive(Deserialize)]
-struct GeminiResponse {
-    candidates: Option<Vec<GeminiCandidate>>,
+#[serde(deny_unknown_fields)]
+struct ClientConfigOverride {
+    timeout_secs: Option<u64>,
+    retries: Option<u32>,
 }
 
-#[derive(Deserialize, Clone)]
-struct GeminiCandidate {
-    content: GeminiResponseContent,
-}
+impl ProviderConfigs {
+    pub fn new(default: ClientConfig) -> Self {
+        Self { default, overrides: HashMap::new() }
+    }
 
-#[derive(Deserialize, Clone)]
-struct GeminiResponseContent {
-    parts: Vec<GeminiResponsePart>,
+    /// Use `config` for `provider` (`"openai"`, `"gemini"`, `"claude"`) instead of the default
+    pub fn with_override(mut self, provider: impl Into<String>, config: ClientConfig) -> Self {
+        self.overrides.insert(provider.into(), config);
+        self
+    }
+
+    /// Apply the `timeout_secs` and `retries` set per provider in a TOML document:
+    ///
+    /// ```toml
+    /// [claude]
+    /// timeout_secs = 300
+    /// retries = 1
+    /// ```
+    pub fn from_toml(default: ClientConfig, contents: &str) -> Result<Self, toml::de::Error> {
+        let sections: HashMap<String, ClientConfigOverride> = toml::from_str(contents)?;
+        let mut configs = Self::new(default);
+        for (provider, section) in sections {
+            let mut config = configs.default.clone();
+            if let Some(timeout_secs) = section.timeout_secs {
+                config.timeout = Duration::from_secs(timeout_secs);
+            }
+            if let Some(retries) = section.retries {
+                config.retries = retries;
+            }
+            configs.overrides.insert(provider, config);
+        }
+        Ok(configs)
+    }
+
+    /// The config for `provider`, falling back to the default
+    pub fn get(&self, provider: &str) -> &ClientConfig {
+        self.overrides.get(provider).unwrap_or(&self.default)
+    }
+
+    pub fn default_config(&self) -> &ClientConfig {
+        &self.default
+    }
 }
 
-#[derive(Deserialize, Clone)]
-struct GeminiResponsePart {
-    text: String,
+/// Errors that callers may want to tell apart from a generic failure.
+/// Clients return them boxed; use `downcast_ref::<ChatDeltaError>()` to inspect.
+#[derive(Debug)]
+pub enum ChatDeltaError {
+    /// The provider rejected the credentials, organization or project
+    Authentication { provider: &'static str, message: String },
+    /// The provider answered with an error status
+    Api { provider: &'static str, status: u16, message: String },
+    /// The request could not be sent or its response could not be read
+    Request(reqwest::Error),
+    /// A local file could not be read
+    Io(std::io::Error),
+    /// An upload is larger than the provider accepts
+    FileTooLarge { size: u64, limit: u64 },
+    /// The response failed `ClientConfig::validate_response`, even after a retry
+    InvalidResponse { provider: &'static str },
+    /// A request failed and `ClientConfig::retry_budget` had no retries left
+    RetryBudgetExhausted { max_total_retries: u32, last_error: String },
 }
 
-#[async_trait]
-impl AiClient for GeminiClient {
-    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let request = GeminiRequest {
-            contents: vec![GeminiContent {
-                parts: vec![GeminiPart {
-                    text: prompt.to_string(),
-                }],
-            }],
-        };
+impl ChatDeltaError {
+    /// The error for a response with a failed `status`: rejected credentials for 401 and 403,
+    /// an API error otherwise. `message` is the response body, or the status when it is empty.
+    #[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+    fn from_status(provider: &'static str, status: reqwest::StatusCode, message: String) -> Self {
+        let message = if message.is_empty() { status.to_string() } else { message };
+        match status {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => ChatDeltaError::Authentication { provider, message },
+            _ => ChatDeltaError::Api { provider, status: status.as_u16(), message },
+        }
+    }
 
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-            self.model, self.api_key
-        );
+    /// Whether trying the same request again could succeed: not for a request the provider
+    /// refused (4xx), unless it timed out (408) or was rate limited (429)
+    #[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+    fn is_retryable(&self) -> bool {
+        match self {
+            ChatDeltaError::Api { status, .. } => !(400..500).contains(status) || matches!(status, 408 | 429),
+            ChatDeltaError::Request(_) => true,
+            _ => false,
+        }
+    }
+}
 
-        let response = self.client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+impl fmt::Display for ChatDeltaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatDeltaError::Authentication { provider, message } => {
+                write!(f, "{} authentication error: {}", provider, message)
+            }
+            ChatDeltaError::Api { provider, status, message } => {
+                write!(f, "{} API error: {} - {}", provider, status, message)
+            }
+            ChatDeltaError::Request(e) => write!(f, "Request failed: {}", e),
+            ChatDeltaError::Io(e) => write!(f, "Could not read file: {}", e),
+            ChatDeltaError::FileTooLarge { size, limit } => write!(
+                f,
+                "File is {:.1} MB, the limit is {} MB",
+                *size as f64 / 1_000_000.0,
+                limit / 1_000_000
+            ),
+            ChatDeltaError::InvalidResponse { provider } => {
+                write!(f, "{} returned an empty or invalid response twice", provider)
+            }
+            ChatDeltaError::RetryBudgetExhausted { max_total_retries, last_error } => write!(
+                f,
+                "Retry budget of {} retries exhausted, not retrying: {}",
+                max_total_retries, last_error
+            ),
+        }
+    }
+}
 
-        if !response.status().is_success() {
-            return Err(format!("Gemini API error: {}", response.status()).into());
+impl Error for ChatDeltaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChatDeltaError::Request(e) => Some(e),
+            ChatDeltaError::Io(e) => Some(e),
+            _ => None,
         }
+    }
+}
 
-        let gemini_response: GeminiResponse = response.json().await?;
-        let content = gemini_response.candidates
-            .and_then(|candidates| candidates.first().cloned())
-            .and_then(|candidate| candidate.content.parts.first().cloned())
-            .map(|part| part.text)
-            .unwrap_or_else(|| "No response".to_string());
+impl From<reqwest::Error> for ChatDeltaError {
+    fn from(e: reqwest::Error) -> Self {
+        ChatDeltaError::Request(e)
+    }
+}
 
-        Ok(content)
+impl From<std::io::Error> for ChatDeltaError {
+    fn from(e: std::io::Error) -> Self {
+        ChatDeltaError::Io(e)
     }
 }
 
-// Claude Client
-struct ClaudeClient {
-    api_key: String,
-    model: String,
-    client: reqwest::Client,
+/// Per-request options that travel alongside a prompt
+#[derive(Clone, Debug, Default)]
+pub struct PromptOptions {
+    /// Correlation ID for this exchange, forwarded to providers that accept one
+    /// (OpenAI `user`, Anthropic `metadata.user_id`) and recorded on tracing spans
+    pub request_id: Option<String>,
+    /// Number of samples `send_prompt_multi` asks for; `None` means one.
+    /// Other methods always return a single response.
+    pub n: Option<u32>,
+    /// Stop sequences for this request; when empty, `ClientConfig::stop_sequences` applies
+    pub stop_sequences: Vec<String>,
+    /// Sampling seed for this request, overriding `ClientConfig::seed`. Claude has no
+    /// seed parameter and ignores it.
+    pub seed: Option<u64>,
 }
 
-impl ClaudeClient {
-    fn new(api_key: &str, model: &str) -> Self {
+impl PromptOptions {
+    /// Number of samples requested, at least one
+    pub fn sample_count(&self) -> u32 {
+        self.n.unwrap_or(1).max(1)
+    }
+
+    /// Stop sequences in effect for this request
+    #[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+    fn stop_sequences<'a>(&'a self, config: &'a ClientConfig) -> &'a [String] {
+        if self.stop_sequences.is_empty() {
+            &config.stop_sequences
+        } else {
+            &self.stop_sequences
+        }
+    }
+
+    /// Seed in effect for this request
+    #[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+    fn seed(&self, config: &ClientConfig) -> Option<u64> {
+        self.seed.or(config.seed)
+    }
+
+    /// These options with `n` cleared, for requests that return a single response
+    fn single(&self) -> Self {
         Self {
-            api_key: api_key.to_string(),
-            model: model.to_string(),
-            client: reqwest::Client::new(),
+            n: None,
+            ..self.clone()
         }
     }
 }
 
-#[derive(Serialize)]
-struct ClaudeRequest {
-    model: String,
-    max_tokens: u32,
-    messages: Vec<ClaudeMessage>,
+/// Information reported by the provider alongside a response
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResponseMetadata {
+    /// Exact model checkpoint that answered, e.g. `gpt-4o-2024-11-20`
+    pub model_version: Option<String>,
+    /// The response was cut off by the token limit rather than finishing on its own
+    pub truncated: bool,
+    /// Backend configuration that produced the response (OpenAI `system_fingerprint`);
+    /// seeded runs are only comparable when it matches
+    pub system_fingerprint: Option<String>,
+    /// The first response failed validation and this one came from the nudged retry
+    pub validation_retried: bool,
+    /// Time from sending a streamed request to its first content chunk; `None` when the
+    /// response was not streamed
+    pub time_to_first_token: Option<Duration>,
+    /// Tokens billed for the request, when the provider reports them (non-streamed
+    /// responses). Variants from one request share its usage.
+    pub token_usage: Option<TokenUsage>,
 }
 
-#[derive(Serialize)]
-struct ClaudeMessage {
-    role: String,
-    content: String,
+/// Tokens a provider billed for one request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
 }
 
-#[derive(Deserialize, Debug)]
-struct ClaudeResponse {
-    content: Vec<ClaudeContent>,
-    #[serde(default)]
-    id: Option<String>,
-    #[serde(default)]
-    model: Option<String>,
-    #[serde(default)]
-    role: Option<String>,
-    #[serde(default)]
-    stop_reason: Option<String>,
-    #[serde(default)]
-    stop_sequence: Option<String>,
-    #[serde(rename = "type", default)]
-    response_type: Option<String>,
-    #[serde(default)]
-    usage: Option<ClaudeUsage>,
+/// Who wrote a message in a conversation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
 }
 
-#[derive(Deserialize, Debug)]
-struct ClaudeUsage {
-    input_tokens: Option<u32>,
-    output_tokens: Option<u32>,
+/// One turn of a multi-turn conversation
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct ClaudeContent {
-    text: Option<String>,
-    #[serde(rename = "type", default)]
-    content_type: Option<String>,
+impl Role {
+    /// The role's name in OpenAI and Anthropic messages
+    #[cfg(any(feature = "openai", feature = "claude"))]
+    fn api_name(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
 }
 
-#[async_trait]
-impl AiClient for ClaudeClient {
-    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let request = ClaudeRequest {
-            model: self.model.clone(),
-            max_tokens: 1000,
-            messages: vec![ClaudeMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
-        };
+impl ChatMessage {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: Role::User, content: content.into() }
+    }
 
-        let response = self.client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: Role::Assistant, content: content.into() }
+    }
+}
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("Claude API error: {} - {}", status, error_text).into());
+/// The whole history as one prompt, for providers without native multi-turn support
+fn flatten_conversation(messages: &[ChatMessage]) -> String {
+    if let [only] = messages {
+        return only.content.clone();
+    }
+    messages
+        .iter()
+        .map(|message| match message.role {
+            Role::User => format!("User: {}", message.content),
+            Role::Assistant => format!("Assistant: {}", message.content),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Largest image `ImageAttachment::from_file` reads; the providers reject bigger ones
+pub const MAX_IMAGE_BYTES: u64 = 20_000_000;
+
+/// An image sent along with a prompt to a model that can see it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageAttachment {
+    pub data: Vec<u8>,
+    /// `image/png`, `image/jpeg`, `image/gif` or `image/webp`
+    pub mime_type: String,
+}
+
+impl ImageAttachment {
+    /// Read a PNG, JPEG, GIF or WebP file, telling the format from its first bytes
+    pub fn from_file(path: &Path) -> Result<Self, ChatDeltaError> {
+        let size = std::fs::metadata(path)?.len();
+        if size > MAX_IMAGE_BYTES {
+            return Err(ChatDeltaError::FileTooLarge { size, limit: MAX_IMAGE_BYTES });
         }
+        let data = std::fs::read(path)?;
+        let mime_type = image_mime_type(&data).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} is not a PNG, JPEG, GIF or WebP image", path.display()),
+            )
+        })?;
+        Ok(Self { data, mime_type: mime_type.to_string() })
+    }
 
-        let response_text = response.text().await?;
-        let claude_response: ClaudeResponse = serde_json::from_str(&response_text)
-            .map_err(|e| format!("Failed to parse Claude response: {} - Response: {}", e, response_text))?;
-        let content = claude_response.content
-            .first()
-            .and_then(|content| content.text.clone())
-            .unwrap_or_else(|| "No response".to_string());
+    /// The image as standard base64, as every provider wants it in JSON
+    pub fn base64(&self) -> String {
+        BASE64.encode(&self.data)
+    }
+
+    /// `data:` URL of the image, for OpenAI's `image_url` parts
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type, self.base64())
+    }
+}
+
+/// MIME type of an image from its magic bytes; `None` for formats the providers don't take
+fn image_mime_type(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// A complete response together with its metadata
+#[derive(Clone, Debug, Default)]
+pub struct AiResponse {
+    pub content: String,
+    pub metadata: ResponseMetadata,
+}
+
+/// Sends every prompt to several models of one provider at once, e.g. to compare `gpt-4o`
+/// with `gpt-4o-mini`. `send_prompt` returns the first answer to arrive; `all_responses` and
+/// `send_prompt_multi` wait for every model.
+pub struct MultiModelClient {
+    clients: Vec<(String, Box<dyn AiClient>)>,
+}
+
+impl MultiModelClient {
+    /// Clients paired with the name of the model each one asks
+    pub fn new(clients: Vec<(String, Box<dyn AiClient>)>) -> Self {
+        Self { clients }
+    }
+
+    /// Build from `create_client`'s `<provider>-multi:` arguments
+    fn from_spec(provider: &str, models: &str, api_key: &str, configs: &ProviderConfigs) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let models: Vec<&str> = models.split(',').map(str::trim).filter(|m| !m.is_empty()).collect();
+        if models.is_empty() {
+            return Err(format!("{}-multi needs at least one model", provider).into());
+        }
+        let clients = models
+            .into_iter()
+            .map(|model| Ok((model.to_string(), create_client_with_configs(provider, api_key, model, configs)?)))
+            .collect::<Result<Vec<_>, Box<dyn Error + Send + Sync>>>()?;
+        Ok(Self::new(clients))
+    }
+
+    /// Every model's response or error, in the order the models were given
+    pub async fn all_responses(&self, prompt: &str, options: &PromptOptions) -> Vec<(String, Result<AiResponse, Box<dyn Error + Send + Sync>>)> {
+        let requests = self.clients
+            .iter()
+            .map(|(model, client)| async move { (model.clone(), client.send_prompt_detailed(prompt, options).await) });
+        futures::future::join_all(requests).await
+    }
+}
+
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl AiClient for MultiModelClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.send_prompt_with_options(prompt, &PromptOptions::default()).await
+    }
+
+    async fn send_prompt_with_options(&self, prompt: &str, options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.send_prompt_detailed(prompt, options).await?.content)
+    }
+
+    /// The first successful response; the error of the last model to fail when none succeed
+    async fn send_prompt_detailed(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        if self.clients.is_empty() {
+            return Err("Multi-model client has no clients".into());
+        }
+        let requests = self.clients.iter().map(|(_, client)| client.send_prompt_detailed(prompt, options));
+        let (response, _) = futures::future::select_ok(requests).await?;
+        Ok(response)
+    }
+
+    /// One response per model, in order, ignoring `options.n`. A model that failed is shown by
+    /// its error; the request only fails when every model did.
+    async fn send_prompt_multi(&self, prompt: &str, options: &PromptOptions) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        let mut last_error: Box<dyn Error + Send + Sync> = "Multi-model client has no clients".into();
+        let mut succeeded = false;
+        let mut responses = Vec::new();
+        for (_, response) in self.all_responses(prompt, &options.single()).await {
+            match response {
+                Ok(response) => {
+                    succeeded = true;
+                    responses.push(response);
+                }
+                Err(e) => {
+                    responses.push(AiResponse {
+                        content: format!("Error: {}", e),
+                        metadata: ResponseMetadata::default(),
+                    });
+                    last_error = e;
+                }
+            }
+        }
+        if succeeded {
+            Ok(responses)
+        } else {
+            Err(last_error)
+        }
+    }
+
+    async fn send_conversation(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        if self.clients.is_empty() {
+            return Err("Multi-model client has no clients".into());
+        }
+        let requests = self.clients.iter().map(|(_, client)| client.send_conversation(messages, options));
+        let (response, _) = futures::future::select_ok(requests).await?;
+        Ok(response)
+    }
+
+    async fn send_prompt_with_images(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        if self.clients.is_empty() {
+            return Err("Multi-model client has no clients".into());
+        }
+        let requests = self.clients.iter().map(|(_, client)| client.send_prompt_with_images(prompt, images, options));
+        let (response, _) = futures::future::select_ok(requests).await?;
+        Ok(response)
+    }
+
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for (_, client) in &self.clients {
+            client.health_check().await?;
+        }
+        Ok(())
+    }
+
+    fn fan_out_models(&self) -> Vec<&str> {
+        self.clients.iter().map(|(model, _)| model.as_str()).collect()
+    }
+}
+
+/// A piece of a streamed response. The last chunk has `finished` set.
+#[derive(Clone, Debug, Default)]
+pub struct StreamChunk {
+    pub content: String,
+    pub finished: bool,
+    /// Set on the final chunk when the provider reported metadata during the stream
+    pub metadata: Option<ResponseMetadata>,
+}
+
+/// `Send + Sync`, except with the `wasm` feature where browser handles are neither
+#[cfg(not(feature = "wasm"))]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(not(feature = "wasm"))]
+impl<T: Send + Sync + ?Sized> MaybeSendSync for T {}
+
+#[cfg(feature = "wasm")]
+pub trait MaybeSendSync {}
+#[cfg(feature = "wasm")]
+impl<T: ?Sized> MaybeSendSync for T {}
+
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+pub trait AiClient: MaybeSendSync {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>>;
+
+    /// Send a prompt with per-request options. Clients that ignore the options can rely on the default.
+    async fn send_prompt_with_options(&self, prompt: &str, _options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.send_prompt(prompt).await
+    }
+
+    /// Send a prompt and return the response with whatever metadata the provider reports
+    async fn send_prompt_detailed(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let content = self.send_prompt_with_options(prompt, options).await?;
+        Ok(AiResponse {
+            content,
+            metadata: ResponseMetadata::default(),
+        })
+    }
+
+    /// Request `options.n` independent samples for the same prompt. Providers without a native
+    /// parameter for this rely on the default, which issues that many requests in parallel.
+    async fn send_prompt_multi(&self, prompt: &str, options: &PromptOptions) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        let single = options.single();
+        let requests = (0..options.sample_count()).map(|_| self.send_prompt_detailed(prompt, &single));
+        futures::future::try_join_all(requests).await
+    }
+
+    /// Continue a conversation that ends with a user turn. Providers without native multi-turn
+    /// support rely on the default, which flattens the history into a single prompt.
+    async fn send_conversation(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        self.send_prompt_detailed(&flatten_conversation(messages), options).await
+    }
+
+    /// Send a prompt together with images, for multimodal models. `options.n` is ignored: one
+    /// response comes back. Clients that can't see images rely on the default, which only
+    /// accepts an empty `images`.
+    async fn send_prompt_with_images(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        if images.is_empty() {
+            return self.send_prompt_with_options(prompt, &options.single()).await;
+        }
+        Err("This client can't send images".into())
+    }
+
+    /// Whether `send_prompt_streaming` delivers the response incrementally
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+
+    /// Models each prompt goes to when the client asks several at once (`MultiModelClient`);
+    /// empty for clients with a single model
+    fn fan_out_models(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    /// Settings the client was created with, if it has its own
+    fn config(&self) -> Option<&ClientConfig> {
+        None
+    }
+
+    /// Confirm the API key and model are accepted without generating anything, by looking the
+    /// model up. Clients with nothing to look up report success.
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Whether an identical request is pending, so sending this one would only wait for its
+    /// response. Always `false` for clients without deduplication.
+    fn is_request_pending(&self, _prompt: &str, _options: &PromptOptions) -> bool {
+        false
+    }
+
+    async fn send_prompt_streaming(&self, prompt: &str, tx: mpsc::Sender<StreamChunk>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.send_prompt_streaming_with_options(prompt, &PromptOptions::default(), tx).await
+    }
+
+    /// Stream a response into `tx`, waiting whenever the channel is full.
+    /// Without native streaming the full response is sent as a single chunk.
+    async fn send_prompt_streaming_with_options(
+        &self,
+        prompt: &str,
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let response = self.send_prompt_detailed(prompt, options).await?;
+        let _ = tx.send(StreamChunk { content: response.content, finished: false, metadata: None }).await;
+        let _ = tx.send(StreamChunk { content: String::new(), finished: true, metadata: Some(response.metadata) }).await;
+        Ok(())
+    }
+
+    /// Stream the answer to a conversation into `tx`. A lone user turn streams like a prompt;
+    /// longer conversations are sent with `send_conversation` and arrive as a single chunk.
+    async fn send_conversation_streaming(
+        &self,
+        messages: &[ChatMessage],
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let [only] = messages {
+            if only.role == Role::User {
+                return self.send_prompt_streaming_with_options(&only.content, options, tx).await;
+            }
+        }
+        let response = self.send_conversation(messages, options).await?;
+        let _ = tx.send(StreamChunk { content: response.content, finished: false, metadata: None }).await;
+        let _ = tx.send(StreamChunk { content: String::new(), finished: true, metadata: Some(response.metadata) }).await;
+        Ok(())
+    }
+}
+
+/// Turns text into embedding vectors, e.g. to compare responses without asking a model
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+pub trait EmbeddingsClient: MaybeSendSync {
+    /// One embedding per input text, in the same order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn Error + Send + Sync>>;
+}
+
+/// Create an embeddings client for `"openai"` or `"gemini"`
+pub fn create_embeddings_client(provider: &str, api_key: &str, model: &str, config: ClientConfig) -> Result<Box<dyn EmbeddingsClient>, Box<dyn Error + Send + Sync>> {
+    // Only the embedding providers use them
+    #[cfg(not(any(feature = "openai", feature = "gemini")))]
+    let _ = (api_key, model, config);
+    match provider {
+        #[cfg(feature = "openai")]
+        "openai" => Ok(Box::new(OpenAIEmbeddingsClient {
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            client: config.connection_pool.client(&config)?,
+            config,
+        })),
+        #[cfg(feature = "gemini")]
+        "gemini" => Ok(Box::new(GeminiEmbeddingsClient {
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            client: config.connection_pool.client(&config)?,
+            config,
+        })),
+        _ if matches!(provider, "openai" | "gemini") => Err(not_compiled_in(provider)),
+        _ => Err(format!("Unknown embeddings provider: {}", provider).into()),
+    }
+}
+
+/// Cosine similarity of two vectors: 1.0 for the same direction, 0.0 when either is empty or zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Create a client for `"openai"`, `"gemini"` or `"claude"`.
+///
+/// `"fallback:openai,gemini,claude"` creates a `FallbackChain` over those providers. Its
+/// `api_key` and `model` are comma-separated lists in the same order; a single value is
+/// used for every provider.
+///
+/// `"openai-multi:gpt-4o,gpt-4o-mini"` (or `gemini-multi:`, `claude-multi:`) creates a
+/// `MultiModelClient` asking each listed model of that provider with the same `api_key`;
+/// `model` is not used.
+pub fn create_client(provider: &str, api_key: &str, model: &str, config: ClientConfig) -> Result<Box<dyn AiClient>, Box<dyn Error + Send + Sync>> {
+    create_client_with_configs(provider, api_key, model, &ProviderConfigs::new(config))
+}
+
+/// Like `create_client`, but each provider (including every member of a fallback chain or
+/// multi-model client) gets its own config from `configs`
+pub fn create_client_with_configs(provider: &str, api_key: &str, model: &str, configs: &ProviderConfigs) -> Result<Box<dyn AiClient>, Box<dyn Error + Send + Sync>> {
+    if let Some(providers) = provider.strip_prefix("fallback:") {
+        return Ok(Box::new(FallbackChain::from_spec(providers, api_key, model, configs)?));
+    }
+    if let Some((provider, models)) = provider.split_once(':').and_then(|(p, models)| Some((p.strip_suffix("-multi")?, models))) {
+        return Ok(Box::new(MultiModelClient::from_spec(provider, models, api_key, configs)?));
+    }
+    #[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+    let config = configs.get(provider).clone();
+    match provider {
+        #[cfg(feature = "openai")]
+        "openai" => Ok(Box::new(OpenAIClient::new(api_key, model, config)?)),
+        #[cfg(feature = "openai")]
+        "openai-responses" => Ok(Box::new(OpenAIResponsesClient::new(api_key, model, config)?)),
+        #[cfg(not(feature = "openai"))]
+        "openai-responses" => Err(not_compiled_in("openai")),
+        #[cfg(feature = "gemini")]
+        "gemini" => Ok(Box::new(GeminiClient::new(api_key, model, config)?)),
+        #[cfg(feature = "claude")]
+        "claude" => Ok(Box::new(ClaudeClient::new(api_key, model, config)?)),
+        _ if PROVIDER_FEATURES.contains(&provider) => Err(not_compiled_in(provider)),
+        _ => Err(format!("Unknown provider: {}", provider).into()),
+    }
+}
+
+/// Providers this library supports, each behind the cargo feature of the same name
+const PROVIDER_FEATURES: [&str; 3] = ["openai", "gemini", "claude"];
+
+fn not_compiled_in(provider: &str) -> Box<dyn Error + Send + Sync> {
+    format!("Provider '{}' is not compiled in; enable the `{}` feature of chatdelta", provider, provider).into()
+}
+
+/// Tries each client in order and returns the first successful response.
+/// Useful for riding out rate limits or outages without changing call sites.
+pub struct FallbackChain {
+    clients: Vec<Box<dyn AiClient>>,
+}
+
+impl FallbackChain {
+    pub fn new(clients: Vec<Box<dyn AiClient>>) -> Self {
+        Self { clients }
+    }
+
+    /// Build a chain from `create_client`'s `fallback:` arguments
+    fn from_spec(providers: &str, api_keys: &str, models: &str, configs: &ProviderConfigs) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let providers: Vec<&str> = providers.split(',').map(str::trim).filter(|p| !p.is_empty()).collect();
+        if providers.is_empty() {
+            return Err("Fallback chain needs at least one provider".into());
+        }
+        let api_keys: Vec<&str> = api_keys.split(',').map(str::trim).collect();
+        let models: Vec<&str> = models.split(',').map(str::trim).collect();
+
+        let clients = providers
+            .iter()
+            .enumerate()
+            .map(|(i, provider)| {
+                let api_key = if api_keys.len() == 1 { api_keys[0] } else { api_keys.get(i).copied().unwrap_or_default() };
+                let model = if models.len() == 1 { models[0] } else { models.get(i).copied().unwrap_or_default() };
+                create_client_with_configs(provider, api_key, model, configs)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(clients))
+    }
+}
+
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl AiClient for FallbackChain {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.send_prompt_with_options(prompt, &PromptOptions::default()).await
+    }
+
+    async fn send_prompt_with_options(&self, prompt: &str, options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.send_prompt_detailed(prompt, options).await?.content)
+    }
+
+    async fn send_prompt_detailed(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let mut last_error: Box<dyn Error + Send + Sync> = "Fallback chain has no clients".into();
+        for (position, client) in self.clients.iter().enumerate() {
+            match client.send_prompt_detailed(prompt, options).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    tracing::warn!(position, error = %e, "fallback client failed, trying the next one");
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn send_conversation(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let mut last_error: Box<dyn Error + Send + Sync> = "Fallback chain has no clients".into();
+        for (position, client) in self.clients.iter().enumerate() {
+            match client.send_conversation(messages, options).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    tracing::warn!(position, error = %e, "fallback client failed, trying the next one");
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn send_prompt_with_images(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut last_error: Box<dyn Error + Send + Sync> = "Fallback chain has no clients".into();
+        for (position, client) in self.clients.iter().enumerate() {
+            match client.send_prompt_with_images(prompt, images, options).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    tracing::warn!(position, error = %e, "fallback client failed, trying the next one");
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Every client in the chain must pass, so a broken fallback is found before it is needed
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for client in &self.clients {
+            client.health_check().await?;
+        }
+        Ok(())
+    }
+}
+
+/// A new `reqwest::Client` for `config`; clients get theirs from `config.connection_pool`
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+fn build_http_client(config: &ClientConfig) -> Result<reqwest::Client, reqwest::Error> {
+    let builder = reqwest::Client::builder();
+    // The browser's fetch has no client-side timeout, sets its own User-Agent and manages
+    // its own connections
+    #[cfg(not(feature = "wasm"))]
+    let builder = builder
+        .timeout(config.timeout)
+        .user_agent(&config.user_agent)
+        .pool_max_idle_per_host(pool::POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(pool::TCP_KEEPALIVE);
+    // The browser picks its own proxy
+    #[cfg(not(feature = "wasm"))]
+    let builder = match &config.proxy {
+        Some(proxy) => builder.proxy(proxy.to_reqwest()?),
+        None => builder,
+    };
+    #[cfg(all(feature = "tls-pinning", not(feature = "wasm")))]
+    let builder = if config.tls_cert_pins.is_empty() {
+        builder
+    } else {
+        builder.use_preconfigured_tls(tls::pinned_tls_config(config))
+    };
+    #[cfg(feature = "wasm")]
+    let _ = config;
+    builder.build()
+}
+
+/// Send a request, passing it and its response through the configured middleware and
+/// showing its body to the interceptor
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+async fn send_request(
+    client: &reqwest::Client,
+    builder: reqwest::RequestBuilder,
+    config: &ClientConfig,
+    provider: &'static str,
+) -> Result<reqwest::Response, reqwest::Error> {
+    if config.middleware.is_empty() && config.interceptor.is_none() {
+        return builder.send().await;
+    }
+
+    let mut request = builder.build()?;
+    let body = request.body().and_then(|body| body.as_bytes()).map(<[u8]>::to_vec);
+    // Streamed bodies (multipart uploads) can't be replaced
+    let in_memory = body.is_some();
+    let mut parts = RequestParts {
+        provider,
+        method: request.method().to_string(),
+        url: request.url().to_string(),
+        headers: request.headers().clone(),
+        body,
+    };
+    config.middleware.on_request(&mut parts);
+    if let (Some(interceptor), Some(body)) = (&config.interceptor, &parts.body) {
+        if let Ok(body) = serde_json::from_slice(body) {
+            interceptor.on_request(provider, &body);
+        }
+    }
+
+    *request.headers_mut() = parts.headers;
+    if let Ok(url) = reqwest::Url::parse(&parts.url) {
+        *request.url_mut() = url;
+    }
+    if let (true, Some(body)) = (in_memory, parts.body) {
+        *request.body_mut() = Some(body.into());
+    }
+
+    let response = client.execute(request).await?;
+    config.middleware.on_response(&ResponseParts {
+        provider,
+        url: response.url().to_string(),
+        status: response.status().as_u16(),
+        headers: response.headers().clone(),
+    });
+    Ok(response)
+}
+
+/// Show a response body to the configured interceptor, if it is JSON
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+fn intercept_response(config: &ClientConfig, provider: &'static str, body: &str) {
+    if let Some(interceptor) = &config.interceptor {
+        if let Ok(body) = serde_json::from_str(body) {
+            interceptor.on_response(provider, &body);
+        }
+    }
+}
+
+/// Read the body of an error response, showing it to the configured interceptor first
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+async fn read_error_body(response: reqwest::Response, config: &ClientConfig, provider: &'static str) -> Result<String, reqwest::Error> {
+    let body = response.text().await?;
+    intercept_response(config, provider, &body);
+    Ok(body)
+}
+
+/// Parse a JSON response body, showing it to the configured interceptor first
+#[cfg(any(feature = "openai", feature = "gemini"))]
+async fn read_json<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+    config: &ClientConfig,
+    provider: &'static str,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    if config.interceptor.is_none() {
+        return Ok(response.json().await?);
+    }
+    let body = response.text().await?;
+    intercept_response(config, provider, &body);
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// Run `attempt` until it succeeds or the configured number of retries is used up. Each retry
+/// also takes one from the session's retry budget.
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+async fn with_retries<T, F, Fut>(config: &ClientConfig, mut attempt: F) -> Result<T, Box<dyn Error + Send + Sync>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Box<dyn Error + Send + Sync>>>,
+{
+    let mut tries = 0;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            // Retrying can't fix rejected credentials or a bad upload
+            Err(e) if tries < config.retries && !matches!(e.downcast_ref::<ChatDeltaError>(), Some(err) if !err.is_retryable()) => {
+                if !config.retry_budget.try_consume() {
+                    tracing::warn!(error = %e, "retry budget exhausted");
+                    return Err(Box::new(ChatDeltaError::RetryBudgetExhausted {
+                        max_total_retries: config.retry_budget.max_total_retries,
+                        last_error: e.to_string(),
+                    }));
+                }
+                tries += 1;
+                tracing::warn!(attempt = tries, error = %e, "request failed, retrying");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Appended to the prompt when a response fails validation
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+const VALIDATION_NUDGE: &str = "\n\nYour previous reply was empty or did not answer. Please answer the request above.";
+
+/// Run `request` with the prompt and, if `validator` rejects the response, once more with a nudge
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+async fn with_validation<F, Fut>(
+    validator: &ResponseValidator,
+    provider: &'static str,
+    prompt: &str,
+    request: F,
+) -> Result<AiResponse, Box<dyn Error + Send + Sync>>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<AiResponse, Box<dyn Error + Send + Sync>>>,
+{
+    let response = request(prompt.to_string()).await?;
+    if validator.accepts(&response.content) {
+        return Ok(response);
+    }
+
+    tracing::warn!(provider, "response failed validation, retrying with a nudge");
+    let mut response = request(format!("{}{}", prompt, VALIDATION_NUDGE)).await?;
+    if !validator.accepts(&response.content) {
+        return Err(Box::new(ChatDeltaError::InvalidResponse { provider }));
+    }
+    response.metadata.validation_retried = true;
+    Ok(response)
+}
+
+/// Run `request` unless an identical one is already pending on a client sharing `config`,
+/// in which case wait for its responses instead
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+async fn deduplicated<Fut>(config: &ClientConfig, key: RequestKey, request: Fut) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>>
+where
+    Fut: Future<Output = Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>>>,
+{
+    if config.deduplicate_requests {
+        config.in_flight.run(key, request).await
+    } else {
+        request.await
+    }
+}
+
+/// `deduplicated` for a single response
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+async fn deduplicated_one<Fut>(config: &ClientConfig, key: RequestKey, request: Fut) -> Result<AiResponse, Box<dyn Error + Send + Sync>>
+where
+    Fut: Future<Output = Result<AiResponse, Box<dyn Error + Send + Sync>>>,
+{
+    let responses = deduplicated(config, key, async { request.await.map(|response| vec![response]) }).await?;
+    Ok(responses.into_iter().next().unwrap_or_default())
+}
+
+/// Streaming counterpart of `deduplicated`. The request that is sent streams into `tx` as
+/// usual; one that waited for it receives the whole response as a single chunk.
+#[cfg(all(any(feature = "openai", feature = "gemini", feature = "claude"), not(feature = "wasm")))]
+async fn deduplicated_stream<F, Fut>(
+    config: &ClientConfig,
+    key: RequestKey,
+    tx: mpsc::Sender<StreamChunk>,
+    stream: F,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    F: FnOnce(mpsc::Sender<StreamChunk>) -> Fut,
+    Fut: Future<Output = Result<(), Box<dyn Error + Send + Sync>>>,
+{
+    if !config.deduplicate_requests {
+        return stream(tx).await;
+    }
+
+    // Set only when this call sent the request itself
+    let mut own_result = None;
+    let shared = config.in_flight.run(key, async {
+        let (chunk_tx, mut chunk_rx) = mpsc::channel::<StreamChunk>(1);
+        // Pass chunks on while collecting the full response for any waiting requests
+        let forward = async {
+            let mut content = String::new();
+            let mut finished = None;
+            while let Some(chunk) = chunk_rx.recv().await {
+                content.push_str(&chunk.content);
+                if chunk.finished {
+                    finished = Some(chunk.metadata.clone().unwrap_or_default());
+                }
+                if tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+            finished.map(|metadata| AiResponse { content, metadata })
+        };
+        let (result, response) = tokio::join!(stream(chunk_tx), forward);
+        let shared: Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> = match (&result, response) {
+            (Ok(()), Some(response)) => Ok(vec![response]),
+            (Err(e), _) => Err(e.to_string().into()),
+            (Ok(()), None) => Err("the stream was stopped before the response finished".into()),
+        };
+        own_result = Some(result);
+        shared
+    })
+    .await;
+
+    if let Some(result) = own_result {
+        return result;
+    }
+    let response = shared?.into_iter().next().unwrap_or_default();
+    let _ = tx.send(StreamChunk { content: response.content, finished: false, metadata: None }).await;
+    let _ = tx.send(StreamChunk { content: String::new(), finished: true, metadata: Some(response.metadata) }).await;
+    Ok(())
+}
+
+/// Reads the `data:` payloads of a server-sent events body one at a time
+#[cfg(all(any(feature = "openai", feature = "gemini", feature = "claude"), not(feature = "wasm")))]
+struct SseReader {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    provider: &'static str,
+    interceptor: Option<Arc<dyn RequestInterceptor>>,
+}
+
+#[cfg(all(any(feature = "openai", feature = "gemini", feature = "claude"), not(feature = "wasm")))]
+impl SseReader {
+    fn new(response: reqwest::Response, config: &ClientConfig, provider: &'static str) -> Self {
+        Self {
+            response,
+            buffer: Vec::new(),
+            provider,
+            interceptor: config.interceptor.clone(),
+        }
+    }
+
+    /// Next `data:` payload, or `None` once the body is exhausted
+    async fn next_data(&mut self) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+        loop {
+            while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                if let Some(data) = line.trim().strip_prefix("data:") {
+                    let data = data.trim();
+                    if let (Some(interceptor), Ok(event)) = (&self.interceptor, serde_json::from_str(data)) {
+                        interceptor.on_response(self.provider, &event);
+                    }
+                    return Ok(Some(data.to_string()));
+                }
+            }
+            match self.response.chunk().await? {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+fn request_span(provider: &'static str, model: &str, options: &PromptOptions) -> tracing::Span {
+    tracing::info_span!(
+        "chatdelta.request",
+        provider,
+        model,
+        request_id = options.request_id.as_deref().unwrap_or(""),
+    )
+}
+
+// OpenAI Client
+#[cfg(feature = "openai")]
+struct OpenAIClient {
+    api_key: String,
+    model: String,
+    config: ClientConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "openai")]
+impl OpenAIClient {
+    fn new(api_key: &str, model: &str, config: ClientConfig) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            client: config.connection_pool.client(&config)?,
+            config,
+        })
+    }
+
+    fn build_request(&self, prompt: &str, options: &PromptOptions, stream: bool) -> OpenAIRequest {
+        self.build_conversation_request(&[ChatMessage::user(prompt)], options, stream)
+    }
+
+    fn build_conversation_request(&self, messages: &[ChatMessage], options: &PromptOptions, stream: bool) -> OpenAIRequest {
+        OpenAIRequest {
+            model: self.model.clone(),
+            messages: messages
+                .iter()
+                .map(|message| OpenAIRequestMessage {
+                    role: message.role.api_name().to_string(),
+                    content: OpenAIContent::Text(message.content.clone()),
+                })
+                .collect(),
+            max_tokens: self.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            user: options.request_id.clone(),
+            // Several choices can't be told apart in a single stream
+            n: if stream { None } else { options.n.filter(|&n| n > 1) },
+            stop: options.stop_sequences(&self.config).to_vec(),
+            seed: options.seed(&self.config),
+            stream,
+        }
+    }
+
+    async fn post(&self, request: &OpenAIRequest) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
+        let builder = self.client
+            .post(OPENAI_ENDPOINT)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json");
+        let builder = with_openai_headers(builder, &self.config).json(request);
+        let response = send_request(&self.client, builder, &self.config, "OpenAI").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "OpenAI").await.unwrap_or_default();
+            return Err(openai_error(status, &body));
+        }
+
+        Ok(response)
+    }
+
+    /// The prompt's text followed by the images, as `image_url` parts with `data:` URLs
+    fn build_image_request(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> OpenAIRequest {
+        let mut request = self.build_request(prompt, &options.single(), false);
+        let mut parts = vec![OpenAIContentPart::Text { text: prompt.to_string() }];
+        parts.extend(images.iter().map(|image| OpenAIContentPart::ImageUrl {
+            image_url: OpenAIImageUrl { url: image.data_url() },
+        }));
+        request.messages[0].content = OpenAIContent::Parts(parts);
+        request
+    }
+
+    /// One response per returned choice; there are `options.n` choices when it is set
+    async fn request_choices(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        self.send_for_choices(&self.build_conversation_request(messages, options, false)).await
+    }
+
+    async fn send_for_choices(&self, request: &OpenAIRequest) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        let response = self.post(request).await?;
+
+        let openai_response: OpenAIResponse = read_json(response, &self.config, "OpenAI").await?;
+        let metadata = ResponseMetadata {
+            model_version: openai_response.model,
+            system_fingerprint: openai_response.system_fingerprint,
+            token_usage: openai_response.usage.map(|usage| TokenUsage {
+                input_tokens: usage.prompt_tokens,
+                output_tokens: usage.completion_tokens,
+            }),
+            ..Default::default()
+        };
+        let mut responses: Vec<AiResponse> = openai_response.choices
+            .into_iter()
+            .map(|choice| AiResponse {
+                content: choice.message.content,
+                metadata: ResponseMetadata {
+                    truncated: choice.finish_reason.as_deref() == Some("length"),
+                    ..metadata.clone()
+                },
+            })
+            .collect();
+
+        if responses.is_empty() {
+            responses.push(AiResponse {
+                content: "No response".to_string(),
+                metadata,
+            });
+        }
+        Ok(responses)
+    }
+
+    async fn request_once(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let mut responses = self.request_choices(messages, &options.single()).await?;
+        Ok(responses.swap_remove(0))
+    }
+
+    /// Stream the answer to `messages` into `tx`
+    #[cfg(not(feature = "wasm"))]
+    async fn stream_conversation(
+        &self,
+        messages: &[ChatMessage],
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let request = self.build_conversation_request(messages, options, true);
+        let started = std::time::Instant::now();
+        let mut events = SseReader::new(self.post(&request).await?, &self.config, "OpenAI");
+        let mut metadata = ResponseMetadata::default();
+
+        while let Some(data) = events.next_data().await? {
+            if data == "[DONE]" {
+                break;
+            }
+            let event: OpenAIStreamResponse = serde_json::from_str(&data)?;
+            if event.model.is_some() {
+                metadata.model_version = event.model;
+            }
+            if event.system_fingerprint.is_some() {
+                metadata.system_fingerprint = event.system_fingerprint;
+            }
+            let Some(choice) = event.choices.into_iter().next() else {
+                continue;
+            };
+            if choice.finish_reason.as_deref() == Some("length") {
+                metadata.truncated = true;
+            }
+            if let Some(content) = choice.delta.content {
+                metadata.time_to_first_token.get_or_insert_with(|| started.elapsed());
+                // Waits while the receiver is full; stop quietly if it has gone away
+                if tx.send(StreamChunk { content, finished: false, metadata: None }).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+
+        let _ = tx.send(StreamChunk { content: String::new(), finished: true, metadata: Some(metadata) }).await;
+        Ok(())
+    }
+}
+
+/// Attach the organization and project headers configured for OpenAI
+#[cfg(feature = "openai")]
+fn with_openai_headers(mut builder: reqwest::RequestBuilder, config: &ClientConfig) -> reqwest::RequestBuilder {
+    if let Some(organization) = &config.openai_organization {
+        builder = builder.header("OpenAI-Organization", organization);
+    }
+    if let Some(project) = &config.openai_project {
+        builder = builder.header("OpenAI-Project", project);
+    }
+    builder
+}
+
+/// Look the model up, which fails for a rejected key or an unknown model
+#[cfg(feature = "openai")]
+async fn openai_health_check(
+    client: &reqwest::Client,
+    api_key: &str,
+    model: &str,
+    config: &ClientConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let builder = client
+        .get(format!("{}/{}", OPENAI_MODELS_ENDPOINT, model))
+        .header("Authorization", format!("Bearer {}", api_key));
+    let builder = with_openai_headers(builder, config);
+    let response = send_request(client, builder, config, "OpenAI").await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = read_error_body(response, config, "OpenAI").await.unwrap_or_default();
+        return Err(openai_error(status, &body));
+    }
+    Ok(())
+}
+
+/// Map a failed OpenAI response to an error with the message from its body. Rejected keys and
+/// projects (401, 403) are authentication errors, so they aren't retried.
+#[cfg(feature = "openai")]
+fn openai_error(status: reqwest::StatusCode, body: &str) -> Box<dyn Error + Send + Sync> {
+    let message = serde_json::from_str::<OpenAIErrorResponse>(body).map_or_else(|_| body.to_string(), |r| r.error.message);
+    Box::new(ChatDeltaError::from_status("OpenAI", status, message))
+}
+
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+struct OpenAIRequest {
+    model: String,
+    messages: Vec<OpenAIRequestMessage>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Serialize, Deserialize)]
+struct OpenAIMessage {
+    role: String,
+    content: String,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+struct OpenAIRequestMessage {
+    role: String,
+    content: OpenAIContent,
+}
+
+/// Plain text, or text and images as typed parts
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    Parts(Vec<OpenAIContentPart>),
+}
+
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIContentPart {
+    Text { text: String },
+    ImageUrl { image_url: OpenAIImageUrl },
+}
+
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+struct OpenAIImageUrl {
+    url: String,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct OpenAIResponse {
+    choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    system_fingerprint: Option<String>,
+    #[serde(default)]
+    usage: Option<OpenAIUsage>,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct OpenAIUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct OpenAIChoice {
+    message: OpenAIMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[cfg(all(feature = "openai", not(feature = "wasm")))]
+#[derive(Deserialize)]
+struct OpenAIStreamResponse {
+    choices: Vec<OpenAIStreamChoice>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    system_fingerprint: Option<String>,
+}
+
+#[cfg(all(feature = "openai", not(feature = "wasm")))]
+#[derive(Deserialize)]
+struct OpenAIStreamChoice {
+    delta: OpenAIDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[cfg(all(feature = "openai", not(feature = "wasm")))]
+#[derive(Deserialize)]
+struct OpenAIDelta {
+    content: Option<String>,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct OpenAIErrorResponse {
+    error: OpenAIErrorBody,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct OpenAIErrorBody {
+    message: String,
+}
+
+#[cfg(feature = "openai")]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl AiClient for OpenAIClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.send_prompt_with_options(prompt, &PromptOptions::default()).await
+    }
+
+    async fn send_prompt_with_options(&self, prompt: &str, options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.send_prompt_detailed(prompt, options).await?.content)
+    }
+
+    async fn send_prompt_detailed(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let key = RequestKey::new("openai", &self.model, &self.api_key, prompt, &options.single(), &self.config);
+        deduplicated_one(&self.config, key, self.send_conversation(&[ChatMessage::user(prompt)], options)).await
+    }
+
+    async fn send_conversation(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let Some((last, history)) = messages.split_last().filter(|(last, _)| last.role == Role::User) else {
+            return Err("OpenAI conversations must end with a user message".into());
+        };
+        // A failed validation re-sends the history with the nudge added to the last user turn
+        with_validation(&self.config.validate_response, "OpenAI", &last.content, |content| async move {
+            let mut messages = history.to_vec();
+            messages.push(ChatMessage::user(content));
+            with_retries(&self.config, || self.request_once(&messages, options)).await
+        })
+        .instrument(request_span("openai", &self.model, options))
+        .await
+    }
+
+    async fn send_prompt_multi(&self, prompt: &str, options: &PromptOptions) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        let messages = [ChatMessage::user(prompt)];
+        let key = RequestKey::new("openai", &self.model, &self.api_key, prompt, options, &self.config);
+        deduplicated(&self.config, key, with_retries(&self.config, || self.request_choices(&messages, options)))
+            .instrument(request_span("openai", &self.model, options))
+            .await
+    }
+
+    async fn send_prompt_with_images(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let request = self.build_image_request(prompt, images, options);
+        let mut responses = with_retries(&self.config, || self.send_for_choices(&request))
+            .instrument(request_span("openai", &self.model, options))
+            .await?;
+        Ok(responses.swap_remove(0).content)
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn config(&self) -> Option<&ClientConfig> {
+        Some(&self.config)
+    }
+
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        openai_health_check(&self.client, &self.api_key, &self.model, &self.config).await
+    }
+
+    fn is_request_pending(&self, prompt: &str, options: &PromptOptions) -> bool {
+        self.config.deduplicate_requests
+            && self.config.in_flight.is_pending(&RequestKey::new("openai", &self.model, &self.api_key, prompt, options, &self.config))
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn send_prompt_streaming_with_options(
+        &self,
+        prompt: &str,
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let messages = [ChatMessage::user(prompt)];
+        let key = RequestKey::new("openai", &self.model, &self.api_key, prompt, &options.single(), &self.config);
+        deduplicated_stream(&self.config, key, tx, |tx| self.stream_conversation(&messages, options, tx))
+            .instrument(request_span("openai", &self.model, options))
+            .await
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn send_conversation_streaming(
+        &self,
+        messages: &[ChatMessage],
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // A lone prompt goes the usual way, where an identical pending request is shared
+        if let [only] = messages {
+            if only.role == Role::User {
+                return self.send_prompt_streaming_with_options(&only.content, options, tx).await;
+            }
+        }
+        self.stream_conversation(messages, options, tx)
+            .instrument(request_span("openai", &self.model, options))
+            .await
+    }
+}
+
+// OpenAI Responses API client, created as "openai-responses"
+#[cfg(feature = "openai")]
+struct OpenAIResponsesClient {
+    api_key: String,
+    model: String,
+    config: ClientConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "openai")]
+impl OpenAIResponsesClient {
+    fn new(api_key: &str, model: &str, config: ClientConfig) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            client: config.connection_pool.client(&config)?,
+            config,
+        })
+    }
+
+    fn build_request(&self, prompt: &str, options: &PromptOptions) -> ResponsesRequest {
+        ResponsesRequest {
+            model: self.model.clone(),
+            input: prompt.to_string(),
+            tools: self.config.openai_web_search.then(|| vec![ResponsesTool::WebSearchPreview]),
+            max_output_tokens: self.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            user: options.request_id.clone(),
+        }
+    }
+
+    async fn request_once(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let builder = self.client
+            .post(OPENAI_RESPONSES_ENDPOINT)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json");
+        let builder = with_openai_headers(builder, &self.config).json(&self.build_request(prompt, options));
+        let response = send_request(&self.client, builder, &self.config, "OpenAI").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "OpenAI").await.unwrap_or_default();
+            return Err(openai_error(status, &body));
+        }
+
+        let response: ResponsesResponse = read_json(response, &self.config, "OpenAI").await?;
+        let content = response.text();
+        Ok(AiResponse {
+            content: if content.is_empty() { "No response".to_string() } else { content },
+            metadata: ResponseMetadata {
+                model_version: response.model,
+                token_usage: response.usage.map(|usage| TokenUsage {
+                    input_tokens: usage.input_tokens,
+                    output_tokens: usage.output_tokens,
+                }),
+                truncated: response.incomplete_details.and_then(|details| details.reason).as_deref() == Some("max_output_tokens"),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+struct ResponsesRequest {
+    model: String,
+    input: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ResponsesTool>>,
+    max_output_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+}
+
+/// Built-in tools the Responses API runs on its side
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesTool {
+    WebSearchPreview,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct ResponsesResponse {
+    /// Messages and tool calls in the order they happened
+    #[serde(default)]
+    output: Vec<ResponsesOutputItem>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    incomplete_details: Option<ResponsesIncompleteDetails>,
+}
+
+#[cfg(feature = "openai")]
+impl ResponsesResponse {
+    /// The text of every message, followed by a `[Source: URL]` line per cited page
+    fn text(&self) -> String {
+        let parts = self.output
+            .iter()
+            .filter(|item| item.kind == "message")
+            .flat_map(|item| &item.content)
+            .filter(|part| part.kind == "output_text");
+        let mut text = String::new();
+        let mut sources: Vec<&str> = Vec::new();
+        for part in parts {
+            text.push_str(part.text.as_deref().unwrap_or_default());
+            for url in part.annotations.iter().filter(|a| a.kind == "url_citation").filter_map(|a| a.url.as_deref()) {
+                if !sources.contains(&url) {
+                    sources.push(url);
+                }
+            }
+        }
+        if !sources.is_empty() {
+            text.push('\n');
+            for url in sources {
+                text.push_str(&format!("\n[Source: {}]", url));
+            }
+        }
+        text
+    }
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct ResponsesOutputItem {
+    /// `message`, or a tool call such as `web_search_call`
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    content: Vec<ResponsesContent>,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct ResponsesContent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    annotations: Vec<ResponsesAnnotation>,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct ResponsesAnnotation {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct ResponsesUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct ResponsesIncompleteDetails {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[cfg(feature = "openai")]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl AiClient for OpenAIResponsesClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.send_prompt_with_options(prompt, &PromptOptions::default()).await
+    }
+
+    async fn send_prompt_with_options(&self, prompt: &str, options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.send_prompt_detailed(prompt, options).await?.content)
+    }
+
+    async fn send_prompt_detailed(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let key = RequestKey::new("openai-responses", &self.model, &self.api_key, prompt, &options.single(), &self.config);
+        let request = with_validation(&self.config.validate_response, "OpenAI", prompt, |prompt| async move {
+            with_retries(&self.config, || self.request_once(&prompt, options)).await
+        });
+        deduplicated_one(&self.config, key, request)
+            .instrument(request_span("openai-responses", &self.model, options))
+            .await
+    }
+
+    fn config(&self) -> Option<&ClientConfig> {
+        Some(&self.config)
+    }
+
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        openai_health_check(&self.client, &self.api_key, &self.model, &self.config).await
+    }
+
+    fn is_request_pending(&self, prompt: &str, options: &PromptOptions) -> bool {
+        self.config.deduplicate_requests
+            && self.config.in_flight.is_pending(&RequestKey::new("openai-responses", &self.model, &self.api_key, prompt, options, &self.config))
+    }
+}
+
+// OpenAI embeddings
+#[cfg(feature = "openai")]
+struct OpenAIEmbeddingsClient {
+    api_key: String,
+    model: String,
+    config: ClientConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Serialize)]
+struct OpenAIEmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct OpenAIEmbeddingsResponse {
+    data: Vec<OpenAIEmbedding>,
+}
+
+#[cfg(feature = "openai")]
+#[derive(Deserialize)]
+struct OpenAIEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[cfg(feature = "openai")]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl EmbeddingsClient for OpenAIEmbeddingsClient {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn Error + Send + Sync>> {
+        let builder = self.client
+            .post("https://api.openai.com/v1/embeddings")
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        let request = OpenAIEmbeddingsRequest { model: &self.model, input: texts };
+        let builder = with_openai_headers(builder, &self.config).json(&request);
+        let response = send_request(&self.client, builder, &self.config, "OpenAI").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "OpenAI").await.unwrap_or_default();
+            return Err(openai_error(status, &body));
+        }
+
+        let mut embeddings: OpenAIEmbeddingsResponse = read_json(response, &self.config, "OpenAI").await?;
+        embeddings.data.sort_by_key(|embedding| embedding.index);
+        Ok(embeddings.data.into_iter().map(|embedding| embedding.embedding).collect())
+    }
+}
+
+// Gemini embeddings
+#[cfg(feature = "gemini")]
+struct GeminiEmbeddingsClient {
+    api_key: String,
+    model: String,
+    config: ClientConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Serialize)]
+struct GeminiEmbedRequest {
+    content: GeminiContent,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize)]
+struct GeminiEmbedResponse {
+    embedding: GeminiEmbeddingValues,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize)]
+struct GeminiEmbeddingValues {
+    values: Vec<f32>,
+}
+
+#[cfg(feature = "gemini")]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl EmbeddingsClient for GeminiEmbeddingsClient {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:embedContent?key={}",
+            self.model, self.api_key
+        );
+
+        // embedContent takes one text per request
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            let request = GeminiEmbedRequest {
+                content: GeminiContent {
+                    role: String::new(),
+                    parts: vec![GeminiPart::text(text.clone())],
+                },
+            };
+            let builder = self.client
+                .post(&url)
+                .header("x-goog-api-client", &self.config.user_agent)
+                .json(&request);
+            let response = send_request(&self.client, builder, &self.config, "Gemini").await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = read_error_body(response, &self.config, "Gemini").await.unwrap_or_default();
+                return Err(Box::new(ChatDeltaError::from_status("Gemini", status, body)));
+            }
+            let embedding: GeminiEmbedResponse = read_json(response, &self.config, "Gemini").await?;
+            embeddings.push(embedding.embedding.values);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// Largest audio file the OpenAI transcription endpoint accepts
+#[cfg(feature = "openai")]
+pub const MAX_TRANSCRIPTION_BYTES: u64 = 25_000_000;
+
+/// OpenAI's Whisper endpoint, used by `TranscriptionClient` unless another is set
+#[cfg(feature = "openai")]
+pub const OPENAI_TRANSCRIPTION_ENDPOINT: &str = "https://api.openai.com/v1/audio/transcriptions";
+
+/// Turns recorded audio into prompt text with OpenAI's transcription endpoint
+#[cfg(all(feature = "openai", not(feature = "wasm")))]
+pub struct TranscriptionClient {
+    api_key: String,
+    model: String,
+    endpoint: String,
+    config: ClientConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(all(feature = "openai", not(feature = "wasm")))]
+impl TranscriptionClient {
+    /// Client using the `whisper-1` model
+    pub fn new(api_key: &str, config: ClientConfig) -> Result<Self, ChatDeltaError> {
+        Ok(Self {
+            api_key: api_key.to_string(),
+            model: "whisper-1".to_string(),
+            endpoint: OPENAI_TRANSCRIPTION_ENDPOINT.to_string(),
+            client: config.connection_pool.client(&config)?,
+            config,
+        })
+    }
+
+    /// Send recordings to another Whisper-compatible endpoint, e.g. a local whisper.cpp server
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// Transcribe a WAV, OGG, MP3, M4A or WEBM recording
+    pub async fn transcribe(&self, audio: &Path) -> Result<String, ChatDeltaError> {
+        let size = tokio::fs::metadata(audio).await?.len();
+        if size > MAX_TRANSCRIPTION_BYTES {
+            return Err(ChatDeltaError::FileTooLarge { size, limit: MAX_TRANSCRIPTION_BYTES });
+        }
+
+        let file_name = audio
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "audio".to_string());
+        let part = reqwest::multipart::Part::bytes(tokio::fs::read(audio).await?)
+            .file_name(file_name)
+            .mime_str(audio_mime_type(audio))?;
+        let form = reqwest::multipart::Form::new()
+            .text("model", self.model.clone())
+            .part("file", part);
+
+        let builder = self.client
+            .post(&self.endpoint)
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        let builder = with_openai_headers(builder, &self.config).multipart(form);
+        let response = send_request(&self.client, builder, &self.config, "OpenAI").await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = read_error_body(response, &self.config, "OpenAI").await.unwrap_or_default();
+            return Err(ChatDeltaError::from_status("OpenAI", status, message));
+        }
+
+        let transcription: TranscriptionResponse = read_json(response, &self.config, "OpenAI")
+            .await
+            .map_err(|e| ChatDeltaError::Api { provider: "OpenAI", status: status.as_u16(), message: e.to_string() })?;
+        Ok(transcription.text.trim().to_string())
+    }
+}
+
+/// MIME type for an audio file, from its extension
+#[cfg(all(feature = "openai", not(feature = "wasm")))]
+fn audio_mime_type(audio: &Path) -> &'static str {
+    let extension = audio.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    match extension.to_lowercase().as_str() {
+        "wav" => "audio/wav",
+        "ogg" | "oga" => "audio/ogg",
+        "mp3" | "mpga" | "mpeg" => "audio/mpeg",
+        "m4a" | "mp4" => "audio/mp4",
+        "webm" => "audio/webm",
+        "flac" => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(all(feature = "openai", not(feature = "wasm")))]
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+// Gemini Client
+#[cfg(feature = "gemini")]
+struct GeminiClient {
+    api_key: String,
+    model: String,
+    config: ClientConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "gemini")]
+impl GeminiClient {
+    fn new(api_key: &str, model: &str, config: ClientConfig) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            client: config.connection_pool.client(&config)?,
+            config,
+        })
+    }
+
+    fn build_request(&self, prompt: &str, options: &PromptOptions) -> GeminiRequest {
+        self.build_conversation_request(&[ChatMessage::user(prompt)], options)
+    }
+
+    /// Gemini wants `user` and `model` turns to alternate, so consecutive messages from the
+    /// same side are merged into one turn with several parts
+    fn build_conversation_request(&self, messages: &[ChatMessage], options: &PromptOptions) -> GeminiRequest {
+        let mut contents: Vec<GeminiContent> = Vec::new();
+        for message in messages {
+            let role = match message.role {
+                Role::User => "user",
+                Role::Assistant => "model",
+            };
+            let part = GeminiPart::text(message.content.clone());
+            match contents.last_mut() {
+                Some(turn) if turn.role == role => turn.parts.push(part),
+                _ => contents.push(GeminiContent { role: role.to_string(), parts: vec![part] }),
+            }
+        }
+
+        let generation_config = GeminiGenerationConfig {
+            candidate_count: options.n.filter(|&count| count > 1),
+            stop_sequences: options.stop_sequences(&self.config).to_vec(),
+            seed: options.seed(&self.config),
+            max_output_tokens: self.config.max_tokens,
+        };
+        let has_settings = generation_config.candidate_count.is_some()
+            || !generation_config.stop_sequences.is_empty()
+            || generation_config.seed.is_some()
+            || generation_config.max_output_tokens.is_some();
+
+        GeminiRequest {
+            contents,
+            generation_config: has_settings.then_some(generation_config),
+            safety_settings: self.config.safety_settings.clone(),
+        }
+    }
+
+    /// The images as inline data parts ahead of the prompt's text
+    fn build_image_request(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> GeminiRequest {
+        let mut request = self.build_request(prompt, &options.single());
+        let image_parts = images.iter().map(|image| GeminiPart {
+            inline_data: Some(GeminiInlineData { mime_type: image.mime_type.clone(), data: image.base64() }),
+            ..Default::default()
+        });
+        request.contents[0].parts.splice(0..0, image_parts);
+        request
+    }
+
+    /// One response per returned candidate; there are `options.n` candidates when it is set
+    async fn request_candidates(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        self.send_for_candidates(&self.build_conversation_request(messages, options)).await
+    }
+
+    async fn send_for_candidates(&self, request: &GeminiRequest) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        let url = format!("{}?key={}", GEMINI_ENDPOINT_TEMPLATE.replace("{model}", &self.model), self.api_key);
+
+        let builder = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("x-goog-api-client", &self.config.user_agent)
+            .json(request);
+        let response = send_request(&self.client, builder, &self.config, "Gemini").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "Gemini").await.unwrap_or_default();
+            return Err(Box::new(ChatDeltaError::from_status("Gemini", status, body)));
+        }
+
+        let gemini_response: GeminiResponse = read_json(response, &self.config, "Gemini").await?;
+        Ok(gemini_response.into_responses())
+    }
+
+    async fn request_once(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let mut responses = self.request_candidates(messages, &options.single()).await?;
+        Ok(responses.swap_remove(0))
+    }
+
+    /// Stream the answer to `messages` into `tx`
+    #[cfg(not(feature = "wasm"))]
+    async fn stream_conversation(
+        &self,
+        messages: &[ChatMessage],
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let request = self.build_conversation_request(messages, &options.single());
+        let started = std::time::Instant::now();
+        // `alt=sse` sends each partial response as a server-sent event
+        let url = format!(
+            "{}?alt=sse&key={}",
+            GEMINI_STREAM_ENDPOINT_TEMPLATE.replace("{model}", &self.model),
+            self.api_key
+        );
+        let builder = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("x-goog-api-client", &self.config.user_agent)
+            .json(&request);
+        let response = send_request(&self.client, builder, &self.config, "Gemini").await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "Gemini").await.unwrap_or_default();
+            return Err(Box::new(ChatDeltaError::from_status("Gemini", status, body)));
+        }
+
+        let mut events = SseReader::new(response, &self.config, "Gemini");
+        let mut metadata = ResponseMetadata::default();
+        while let Some(data) = events.next_data().await? {
+            let event: GeminiResponse = serde_json::from_str(&data)?;
+            if event.model_version.is_some() {
+                metadata.model_version = event.model_version;
+            }
+            // Each event reports the usage so far; the last one has the totals
+            if let Some(usage) = event.usage_metadata {
+                metadata.token_usage = Some(TokenUsage {
+                    input_tokens: usage.prompt_token_count,
+                    output_tokens: usage.candidates_token_count,
+                });
+            }
+            let Some(candidate) = event.candidates.and_then(|candidates| candidates.into_iter().next()) else {
+                continue;
+            };
+            if candidate.finish_reason.as_deref() == Some("MAX_TOKENS") {
+                metadata.truncated = true;
+            }
+            if let Some(content) = gemini_stream_text(candidate.content.parts) {
+                metadata.time_to_first_token.get_or_insert_with(|| started.elapsed());
+                // Waits while the receiver is full; stop quietly if it has gone away
+                if tx.send(StreamChunk { content, finished: false, metadata: None }).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+
+        let _ = tx.send(StreamChunk { content: String::new(), finished: true, metadata: Some(metadata) }).await;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GeminiGenerationConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    safety_settings: Option<Vec<GeminiSafetySettings>>,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidate_count: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Serialize)]
+struct GeminiContent {
+    /// `user` or `model`; embedding requests leave it empty
+    #[serde(skip_serializing_if = "String::is_empty")]
+    role: String,
+    parts: Vec<GeminiPart>,
+}
+
+/// Text or an inline image; a part carries one of the two
+#[cfg(feature = "gemini")]
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPart {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inline_data: Option<GeminiInlineData>,
+}
+
+#[cfg(feature = "gemini")]
+impl GeminiPart {
+    fn text(text: String) -> Self {
+        Self { text, inline_data: None }
+    }
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiInlineData {
+    mime_type: String,
+    /// Base64 of the image
+    data: String,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    candidates: Option<Vec<GeminiCandidate>>,
+    #[serde(default)]
+    model_version: Option<String>,
+    #[serde(default)]
+    usage_metadata: Option<GeminiUsageMetadata>,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCandidate {
+    /// Missing from the last event of a stream that only reports the finish reason
+    #[serde(default)]
+    content: GeminiResponseContent,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize, Clone, Default)]
+struct GeminiResponseContent {
+    #[serde(default)]
+    parts: Vec<GeminiResponsePart>,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponsePart {
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    executable_code: Option<GeminiExecutableCode>,
+    #[serde(default)]
+    code_execution_result: Option<GeminiCodeExecutionResult>,
+}
+
+/// Code the model wrote and ran with the code execution tool
+#[cfg(feature = "gemini")]
+#[derive(Deserialize, Clone)]
+struct GeminiExecutableCode {
+    #[serde(default)]
+    language: String,
+    code: String,
+}
+
+#[cfg(feature = "gemini")]
+#[derive(Deserialize, Clone)]
+struct GeminiCodeExecutionResult {
+    /// e.g. `OUTCOME_OK`, `OUTCOME_FAILED`
+    #[serde(default)]
+    outcome: String,
+    #[serde(default)]
+    output: String,
+}
+
+#[cfg(feature = "gemini")]
+impl GeminiResponse {
+    /// One response per candidate, or a "No response" placeholder when there are none
+    fn into_responses(self) -> Vec<AiResponse> {
+        let metadata = ResponseMetadata {
+            model_version: self.model_version,
+            token_usage: self.usage_metadata.map(|usage| TokenUsage {
+                input_tokens: usage.prompt_token_count,
+                output_tokens: usage.candidates_token_count,
+            }),
+            ..Default::default()
+        };
+        let mut responses: Vec<AiResponse> = self.candidates
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|candidate| {
+                let truncated = candidate.finish_reason.as_deref() == Some("MAX_TOKENS");
+                render_gemini_parts(candidate.content.parts).map(|content| AiResponse {
+                    content,
+                    metadata: ResponseMetadata {
+                        truncated,
+                        ..metadata.clone()
+                    },
+                })
+            })
+            .collect();
+
+        if responses.is_empty() {
+            responses.push(AiResponse {
+                content: "No response".to_string(),
+                metadata,
+            });
+        }
+        responses
+    }
+}
+
+/// Text of one streamed event's parts, kept exactly as sent so consecutive events join up.
+/// Code the model ran and its output are rendered as in `render_gemini_parts`, on lines of
+/// their own.
+#[cfg(all(feature = "gemini", not(feature = "wasm")))]
+fn gemini_stream_text(parts: Vec<GeminiResponsePart>) -> Option<String> {
+    let mut text = String::new();
+    for part in parts {
+        if let Some(part_text) = &part.text {
+            text.push_str(part_text);
+        } else if let Some(block) = render_gemini_parts(vec![part]) {
+            text.push_str("\n\n");
+            text.push_str(&block);
+            text.push_str("\n\n");
+        }
+    }
+    (!text.is_empty()).then_some(text)
+}
+
+/// Join a candidate's parts in order: consecutive text parts are concatenated, and code the
+/// model ran and its output become labelled fenced blocks. `None` when nothing is readable.
+#[cfg(feature = "gemini")]
+fn render_gemini_parts(parts: Vec<GeminiResponsePart>) -> Option<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut after_text = false;
+    for part in parts {
+        if let Some(text) = part.text {
+            match blocks.last_mut() {
+                Some(last) if after_text => last.push_str(&text),
+                _ => blocks.push(text),
+            }
+            after_text = true;
+            continue;
+        }
+
+        let block = if let Some(code) = part.executable_code {
+            let language = code.language.to_lowercase();
+            format!("Executed code ({}):\n```{}\n{}\n```", language, language, code.code.trim_end())
+        } else if let Some(result) = part.code_execution_result {
+            let outcome = result.outcome.trim_start_matches("OUTCOME_").to_lowercase();
+            format!("Execution output ({}):\n```\n{}\n```", outcome, result.output.trim_end())
+        } else {
+            // Part types we don't render, e.g. inline data
+            continue;
+        };
+        blocks.push(block);
+        after_text = false;
+    }
+
+    if blocks.is_empty() {
+        return None;
+    }
+    let blocks: Vec<&str> = blocks.iter().map(|block| block.trim_end_matches('\n')).collect();
+    Some(blocks.join("\n\n"))
+}
+
+#[cfg(feature = "gemini")]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl AiClient for GeminiClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.send_prompt_with_options(prompt, &PromptOptions::default()).await
+    }
+
+    async fn send_prompt_with_options(&self, prompt: &str, options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.send_prompt_detailed(prompt, options).await?.content)
+    }
+
+    // Gemini has no request metadata field, so the request ID is only recorded on the span
+    async fn send_prompt_detailed(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let key = RequestKey::new("gemini", &self.model, &self.api_key, prompt, &options.single(), &self.config);
+        deduplicated_one(&self.config, key, self.send_conversation(&[ChatMessage::user(prompt)], options)).await
+    }
+
+    async fn send_conversation(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let Some((last, history)) = messages.split_last().filter(|(last, _)| last.role == Role::User) else {
+            return Err("Gemini conversations must end with a user message".into());
+        };
+        // A failed validation re-sends the history with the nudge added to the last user turn
+        with_validation(&self.config.validate_response, "Gemini", &last.content, |content| async move {
+            let mut messages = history.to_vec();
+            messages.push(ChatMessage::user(content));
+            with_retries(&self.config, || self.request_once(&messages, options)).await
+        })
+        .instrument(request_span("gemini", &self.model, options))
+        .await
+    }
+
+    async fn send_prompt_multi(&self, prompt: &str, options: &PromptOptions) -> Result<Vec<AiResponse>, Box<dyn Error + Send + Sync>> {
+        let messages = [ChatMessage::user(prompt)];
+        let key = RequestKey::new("gemini", &self.model, &self.api_key, prompt, options, &self.config);
+        deduplicated(&self.config, key, with_retries(&self.config, || self.request_candidates(&messages, options)))
+            .instrument(request_span("gemini", &self.model, options))
+            .await
+    }
+
+    async fn send_prompt_with_images(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let request = self.build_image_request(prompt, images, options);
+        let mut responses = with_retries(&self.config, || self.send_for_candidates(&request))
+            .instrument(request_span("gemini", &self.model, options))
+            .await?;
+        Ok(responses.swap_remove(0).content)
+    }
+
+    fn config(&self) -> Option<&ClientConfig> {
+        Some(&self.config)
+    }
+
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let url = format!("{}/{}?key={}", GEMINI_MODELS_ENDPOINT, self.model, self.api_key);
+        let builder = self.client
+            .get(&url)
+            .header("x-goog-api-client", &self.config.user_agent);
+        let response = send_request(&self.client, builder, &self.config, "Gemini").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "Gemini").await.unwrap_or_default();
+            return Err(Box::new(ChatDeltaError::from_status("Gemini", status, body)));
+        }
+        Ok(())
+    }
+
+    fn is_request_pending(&self, prompt: &str, options: &PromptOptions) -> bool {
+        self.config.deduplicate_requests
+            && self.config.in_flight.is_pending(&RequestKey::new("gemini", &self.model, &self.api_key, prompt, options, &self.config))
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn send_prompt_streaming_with_options(
+        &self,
+        prompt: &str,
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let messages = [ChatMessage::user(prompt)];
+        let key = RequestKey::new("gemini", &self.model, &self.api_key, prompt, &options.single(), &self.config);
+        deduplicated_stream(&self.config, key, tx, |tx| self.stream_conversation(&messages, options, tx))
+            .instrument(request_span("gemini", &self.model, options))
+            .await
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn send_conversation_streaming(
+        &self,
+        messages: &[ChatMessage],
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // A lone prompt goes the usual way, where an identical pending request is shared
+        if let [only] = messages {
+            if only.role == Role::User {
+                return self.send_prompt_streaming_with_options(&only.content, options, tx).await;
+            }
+        }
+        self.stream_conversation(messages, options, tx)
+            .instrument(request_span("gemini", &self.model, options))
+            .await
+    }
+}
+
+// Claude Client
+#[cfg(feature = "claude")]
+struct ClaudeClient {
+    api_key: String,
+    model: String,
+    config: ClientConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "claude")]
+impl ClaudeClient {
+    fn new(api_key: &str, model: &str, config: ClientConfig) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            client: config.connection_pool.client(&config)?,
+            config,
+        })
+    }
+
+    fn build_request(&self, prompt: &str, options: &PromptOptions, stream: bool) -> ClaudeRequest {
+        self.build_conversation_request(&[ChatMessage::user(prompt)], options, stream)
+    }
+
+    fn build_conversation_request(&self, messages: &[ChatMessage], options: &PromptOptions, stream: bool) -> ClaudeRequest {
+        ClaudeRequest {
+            model: self.model.clone(),
+            max_tokens: self.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            messages: messages
+                .iter()
+                .map(|message| ClaudeMessage {
+                    role: message.role.api_name().to_string(),
+                    content: ClaudeMessageContent::Text(message.content.clone()),
+                })
+                .collect(),
+            metadata: options.request_id.clone().map(|user_id| ClaudeMetadata { user_id }),
+            stop_sequences: options.stop_sequences(&self.config).to_vec(),
+            stream,
+        }
+    }
+
+    async fn post(&self, request: &ClaudeRequest) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
+        let builder = self.client
+            .post(CLAUDE_ENDPOINT)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Content-Type", "application/json")
+            .json(request);
+        let response = send_request(&self.client, builder, &self.config, "Claude").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "Claude").await.unwrap_or_default();
+            return Err(Box::new(ChatDeltaError::from_status("Claude", status, body)));
+        }
+
+        Ok(response)
+    }
+
+    /// The images as base64 blocks ahead of the prompt's text, as Anthropic recommends
+    fn build_image_request(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> ClaudeRequest {
+        let mut request = self.build_request(prompt, &options.single(), false);
+        let mut blocks: Vec<ClaudeContentBlock> = images
+            .iter()
+            .map(|image| ClaudeContentBlock::Image {
+                source: ClaudeImageSource {
+                    kind: "base64",
+                    media_type: image.mime_type.clone(),
+                    data: image.base64(),
+                },
+            })
+            .collect();
+        blocks.push(ClaudeContentBlock::Text { text: prompt.to_string() });
+        request.messages[0].content = ClaudeMessageContent::Blocks(blocks);
+        request
+    }
+
+    async fn request_once(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        self.send(&self.build_conversation_request(messages, options, false)).await
+    }
+
+    async fn send(&self, request: &ClaudeRequest) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let response = self.post(request).await?;
+
+        let response_text = response.text().await?;
+        intercept_response(&self.config, "Claude", &response_text);
+        let claude_response: ClaudeResponse = serde_json::from_str(&response_text)
+            .map_err(|e| format!("Failed to parse Claude response: {} - Response: {}", e, response_text))?;
+        let content = render_claude_content(claude_response.content).unwrap_or_else(|| "No response".to_string());
+
+        Ok(AiResponse {
+            content,
+            metadata: ResponseMetadata {
+                model_version: claude_response.model,
+                truncated: claude_response.stop_reason.as_deref() == Some("max_tokens"),
+                token_usage: claude_response.usage.map(|usage| TokenUsage {
+                    input_tokens: usage.input_tokens.unwrap_or(0),
+                    output_tokens: usage.output_tokens.unwrap_or(0),
+                }),
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Stream the answer to `messages` into `tx`
+    #[cfg(not(feature = "wasm"))]
+    async fn stream_conversation(
+        &self,
+        messages: &[ChatMessage],
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let request = self.build_conversation_request(messages, options, true);
+        let started = std::time::Instant::now();
+        let mut events = SseReader::new(self.post(&request).await?, &self.config, "Claude");
+        let mut metadata = ResponseMetadata::default();
+
+        while let Some(data) = events.next_data().await? {
+            let event: ClaudeStreamEvent = serde_json::from_str(&data)?;
+            match event.event_type.as_str() {
+                "message_start" => {
+                    metadata.model_version = event.message.and_then(|message| message.model);
+                }
+                "content_block_delta" => {
+                    if let Some(content) = event.delta.and_then(|delta| delta.text) {
+                        metadata.time_to_first_token.get_or_insert_with(|| started.elapsed());
+                        // Waits while the receiver is full; stop quietly if it has gone away
+                        if tx.send(StreamChunk { content, finished: false, metadata: None }).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                "message_delta" if event.delta.and_then(|delta| delta.stop_reason).as_deref() == Some("max_tokens") => {
+                    metadata.truncated = true;
+                }
+                "message_stop" => break,
+                _ => {}
+            }
+        }
+
+        let _ = tx.send(StreamChunk { content: String::new(), finished: true, metadata: Some(metadata) }).await;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "claude")]
+#[derive(Serialize)]
+struct ClaudeRequest {
+    model: String,
+    max_tokens: u32,
+    messages: Vec<ClaudeMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<ClaudeMetadata>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[cfg(feature = "claude")]
+#[derive(Serialize)]
+struct ClaudeMessage {
+    role: String,
+    content: ClaudeMessageContent,
+}
+
+/// Plain text, or content blocks when the message carries images
+#[cfg(feature = "claude")]
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ClaudeMessageContent {
+    Text(String),
+    Blocks(Vec<ClaudeContentBlock>),
+}
+
+#[cfg(feature = "claude")]
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeContentBlock {
+    Text { text: String },
+    Image { source: ClaudeImageSource },
+}
+
+#[cfg(feature = "claude")]
+#[derive(Serialize)]
+struct ClaudeImageSource {
+    /// Always `base64`
+    #[serde(rename = "type")]
+    kind: &'static str,
+    media_type: String,
+    data: String,
+}
+
+#[cfg(feature = "claude")]
+#[derive(Serialize)]
+struct ClaudeMetadata {
+    user_id: String,
+}
+
+#[cfg(feature = "claude")]
+#[derive(Deserialize, Debug)]
+struct ClaudeResponse {
+    content: Vec<ClaudeContent>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: Option<ClaudeUsage>,
+}
+
+#[cfg(feature = "claude")]
+#[derive(Deserialize, Debug)]
+struct ClaudeUsage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+#[cfg(feature = "claude")]
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeContent {
+    Text {
+        text: String,
+    },
+    /// A tool call; ChatDelta sends no tools, so these are only shown, never run
+    ToolUse {
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    /// Block types we don't render, e.g. extended thinking
+    #[serde(other)]
+    Other,
+}
+
+/// Concatenate the text blocks of a response in order, with tool calls as labelled JSON
+/// blocks between them. `None` when there is nothing to show.
+#[cfg(feature = "claude")]
+fn render_claude_content(content: Vec<ClaudeContent>) -> Option<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut after_text = false;
+    for block in content {
+        match block {
+            ClaudeContent::Text { text } => {
+                match blocks.last_mut() {
+                    Some(last) if after_text => last.push_str(&text),
+                    _ => blocks.push(text),
+                }
+                after_text = true;
+            }
+            ClaudeContent::ToolUse { name, input } => {
+                let input = serde_json::to_string_pretty(&input).unwrap_or_default();
+                blocks.push(format!("Tool call ({}):\n```json\n{}\n```", name, input));
+                after_text = false;
+            }
+            ClaudeContent::Other => {}
+        }
+    }
+
+    if blocks.is_empty() {
+        return None;
+    }
+    let blocks: Vec<&str> = blocks.iter().map(|block| block.trim_end_matches('\n')).collect();
+    Some(blocks.join("\n\n"))
+}
+
+#[cfg(all(feature = "claude", not(feature = "wasm")))]
+#[derive(Deserialize, Debug)]
+struct ClaudeStreamEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    delta: Option<ClaudeStreamDelta>,
+    #[serde(default)]
+    message: Option<ClaudeStreamMessage>,
+}
+
+#[cfg(all(feature = "claude", not(feature = "wasm")))]
+#[derive(Deserialize, Debug)]
+struct ClaudeStreamMessage {
+    #[serde(default)]
+    model: Option<String>,
+}
+
+#[cfg(all(feature = "claude", not(feature = "wasm")))]
+#[derive(Deserialize, Debug)]
+struct ClaudeStreamDelta {
+    #[serde(default)]
+    text: Option<String>,
+    /// Set on `message_delta` events
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[cfg(feature = "claude")]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+impl AiClient for ClaudeClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.send_prompt_with_options(prompt, &PromptOptions::default()).await
+    }
+
+    async fn send_prompt_with_options(&self, prompt: &str, options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(self.send_prompt_detailed(prompt, options).await?.content)
+    }
+
+    async fn send_prompt_detailed(&self, prompt: &str, options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let key = RequestKey::new("claude", &self.model, &self.api_key, prompt, &options.single(), &self.config);
+        deduplicated_one(&self.config, key, self.send_conversation(&[ChatMessage::user(prompt)], options)).await
+    }
+
+    async fn send_conversation(&self, messages: &[ChatMessage], options: &PromptOptions) -> Result<AiResponse, Box<dyn Error + Send + Sync>> {
+        let Some((last, history)) = messages.split_last().filter(|(last, _)| last.role == Role::User) else {
+            return Err("Claude conversations must end with a user message".into());
+        };
+        // A failed validation re-sends the history with the nudge added to the last user turn
+        with_validation(&self.config.validate_response, "Claude", &last.content, |content| async move {
+            let mut messages = history.to_vec();
+            messages.push(ChatMessage::user(content));
+            with_retries(&self.config, || self.request_once(&messages, options)).await
+        })
+        .instrument(request_span("claude", &self.model, options))
+        .await
+    }
+
+    async fn send_prompt_with_images(&self, prompt: &str, images: &[ImageAttachment], options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let request = self.build_image_request(prompt, images, options);
+        let response = with_retries(&self.config, || self.send(&request))
+            .instrument(request_span("claude", &self.model, options))
+            .await?;
+        Ok(response.content)
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn config(&self) -> Option<&ClientConfig> {
+        Some(&self.config)
+    }
+
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let builder = self.client
+            .get(format!("{}/{}", CLAUDE_MODELS_ENDPOINT, self.model))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01");
+        let response = send_request(&self.client, builder, &self.config, "Claude").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = read_error_body(response, &self.config, "Claude").await.unwrap_or_default();
+            return Err(Box::new(ChatDeltaError::from_status("Claude", status, body)));
+        }
+        Ok(())
+    }
+
+    fn is_request_pending(&self, prompt: &str, options: &PromptOptions) -> bool {
+        self.config.deduplicate_requests
+            && self.config.in_flight.is_pending(&RequestKey::new("claude", &self.model, &self.api_key, prompt, options, &self.config))
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn send_prompt_streaming_with_options(
+        &self,
+        prompt: &str,
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let messages = [ChatMessage::user(prompt)];
+        let key = RequestKey::new("claude", &self.model, &self.api_key, prompt, &options.single(), &self.config);
+        deduplicated_stream(&self.config, key, tx, |tx| self.stream_conversation(&messages, options, tx))
+            .instrument(request_span("claude", &self.model, options))
+            .await
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn send_conversation_streaming(
+        &self,
+        messages: &[ChatMessage],
+        options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // A lone prompt goes the usual way, where an identical pending request is shared
+        if let [only] = messages {
+            if only.role == Role::User {
+                return self.send_prompt_streaming_with_options(&only.content, options, tx).await;
+            }
+        }
+        self.stream_conversation(messages, options, tx)
+            .instrument(request_span("claude", &self.model, options))
+            .await
+    }
+}
+
+// The tests exercise every provider, so they need the default features
+#[cfg(all(test, feature = "openai", feature = "gemini", feature = "claude", not(feature = "wasm")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id_is_forwarded() {
+        let options = PromptOptions {
+            request_id: Some("req-123".to_string()),
+            ..Default::default()
+        };
+
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["user"], "req-123");
+        assert!(body.get("stream").is_none());
+
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(claude.build_request("Hi", &options, true)).unwrap();
+        assert_eq!(body["metadata"]["user_id"], "req-123");
+        assert_eq!(body["stream"], true);
+    }
+
+    #[test]
+    fn test_model_version_parsing() {
+        let openai: OpenAIResponse = serde_json::from_str(
+            r#"{"model":"gpt-4o-2024-11-20","choices":[{"message":{"role":"assistant","content":"Hi"}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(openai.model.as_deref(), Some("gpt-4o-2024-11-20"));
+
+        let gemini: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"parts":[{"text":"Hi"}]}}],"modelVersion":"gemini-1.5-pro-002"}"#,
+        )
+        .unwrap();
+        assert_eq!(gemini.model_version.as_deref(), Some("gemini-1.5-pro-002"));
+    }
+
+    #[test]
+    fn test_token_usage_parsing() {
+        let openai: OpenAIResponse = serde_json::from_str(
+            r#"{"choices":[{"message":{"role":"assistant","content":"Hi"}}],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#,
+        )
+        .unwrap();
+        let usage = openai.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (12, 3));
+
+        let gemini: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"parts":[{"text":"Hi"}]}}],"usageMetadata":{"promptTokenCount":8,"candidatesTokenCount":2,"totalTokenCount":10}}"#,
+        )
+        .unwrap();
+        let responses = gemini.into_responses();
+        assert_eq!(
+            responses[0].metadata.token_usage,
+            Some(TokenUsage { input_tokens: 8, output_tokens: 2 })
+        );
+    }
+
+    #[test]
+    fn test_claude_multiple_content_blocks() {
+        let claude: ClaudeResponse = serde_json::from_str(
+            r#"{
+                "id": "msg_01XFDUDYJgAACzvnptvVoYEL",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-3-5-sonnet-20241022",
+                "content": [
+                    {"type": "thinking", "thinking": "The user wants the weather.", "signature": "abc"},
+                    {"type": "text", "text": "I'll check the weather "},
+                    {"type": "text", "text": "in Paris."},
+                    {"type": "tool_use", "id": "toolu_01A09q90qw90lq917835lq9", "name": "get_weather", "input": {"location": "Paris"}}
+                ],
+                "stop_reason": "tool_use",
+                "stop_sequence": null,
+                "usage": {"input_tokens": 380, "output_tokens": 96}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            render_claude_content(claude.content).unwrap(),
+            "I'll check the weather in Paris.\n\nTool call (get_weather):\n```json\n{\n  \"location\": \"Paris\"\n}\n```"
+        );
+    }
+
+    #[test]
+    fn test_claude_content_without_text() {
+        let claude: ClaudeResponse = serde_json::from_str(
+            r#"{"content": [{"type": "redacted_thinking", "data": "xyz"}], "stop_reason": "end_turn"}"#,
+        )
+        .unwrap();
+        assert_eq!(render_claude_content(claude.content), None);
+    }
+
+    #[test]
+    fn test_gemini_mixed_parts() {
+        let gemini: GeminiResponse = serde_json::from_str(
+            r#"{
+                "candidates": [{
+                    "content": {
+                        "role": "model",
+                        "parts": [
+                            {"text": "Let me compute "},
+                            {"text": "that."},
+                            {"executableCode": {"language": "PYTHON", "code": "print(2 ** 10)\n"}},
+                            {"codeExecutionResult": {"outcome": "OUTCOME_OK", "output": "1024\n"}},
+                            {"text": "The answer is 1024."}
+                        ]
+                    },
+                    "finishReason": "STOP"
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let responses = gemini.into_responses();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(
+            responses[0].content,
+            "Let me compute that.\n\n\
+             Executed code (python):\n```python\nprint(2 ** 10)\n```\n\n\
+             Execution output (ok):\n```\n1024\n```\n\n\
+             The answer is 1024."
+        );
+    }
+
+    #[test]
+    fn test_request_id_omitted_by_default() {
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &PromptOptions::default(), false)).unwrap();
+        assert!(body.get("user").is_none());
+    }
+
+    #[test]
+    fn test_invalid_project_is_authentication_error() {
+        let body = r#"{"error":{"message":"Project proj_x does not exist","type":"invalid_request_error","code":"invalid_project"}}"#;
+        let error = openai_error(reqwest::StatusCode::FORBIDDEN, body);
+        assert!(matches!(
+            error.downcast_ref::<ChatDeltaError>(),
+            Some(ChatDeltaError::Authentication { provider: "OpenAI", .. })
+        ));
+
+        let error = openai_error(reqwest::StatusCode::INTERNAL_SERVER_ERROR, body);
+        assert!(matches!(
+            error.downcast_ref::<ChatDeltaError>(),
+            Some(ChatDeltaError::Api { provider: "OpenAI", status: 500, message }) if message == "Project proj_x does not exist"
+        ));
+        let error = openai_error(reqwest::StatusCode::UNAUTHORIZED, "");
+        assert_eq!(error.to_string(), "OpenAI authentication error: 401 Unauthorized");
+    }
+
+    #[test]
+    fn test_openai_headers_config() {
+        let config = ClientConfigBuilder::default()
+            .openai_organization("org-123")
+            .openai_project("proj_456")
+            .build();
+        assert_eq!(config.openai_organization.as_deref(), Some("org-123"));
+        assert_eq!(config.openai_project.as_deref(), Some("proj_456"));
+        assert!(ClientConfig::default().openai_project.is_none());
+    }
+
+    #[test]
+    fn test_client_config_round_trip() {
+        let config = ClientConfigBuilder::default()
+            .timeout(Duration::from_secs(90))
+            .retries(2)
+            .openai_project("proj_456")
+            .stop_sequences(vec!["END".to_string()])
+            .seed(7)
+            .deduplicate_requests(false)
+            .retry_budget(10)
+            .user_agent("my-app/1.0")
+            .build();
+        config.retry_budget.try_consume();
+
+        let toml = toml::to_string(&config).unwrap();
+        assert!(toml.contains("timeout = \"1m 30s\""));
+        let parsed: ClientConfig = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.timeout, Duration::from_secs(90));
+        assert_eq!(parsed.retries, 2);
+        assert_eq!(parsed.openai_organization, None);
+        assert_eq!(parsed.openai_project.as_deref(), Some("proj_456"));
+        assert_eq!(parsed.stop_sequences, ["END"]);
+        assert_eq!(parsed.seed, Some(7));
+        assert!(!parsed.deduplicate_requests);
+        assert_eq!(parsed.retry_budget.max_total_retries, 10);
+        assert_eq!(parsed.retry_budget.retries_used(), 0);
+        assert_eq!(parsed.user_agent, "my-app/1.0");
+
+        let json = serde_json::to_string(&ClientConfigBuilder::default().retries(4)).unwrap();
+        let builder: ClientConfigBuilder = serde_json::from_str(&json).unwrap();
+        assert_eq!(builder.build().retries, 4);
+
+        // Unset fields keep their defaults
+        let partial: ClientConfig = toml::from_str("retries = 1").unwrap();
+        assert_eq!(partial.timeout, Duration::from_secs(30));
+        assert_eq!(partial.user_agent, DEFAULT_USER_AGENT);
+    }
+
+    #[test]
+    fn test_openai_responses_api() {
+        let config = ClientConfigBuilder::default().openai_web_search(true).build();
+        let client = OpenAIResponsesClient::new("key", "gpt-4o", config).unwrap();
+        let body = serde_json::to_value(client.build_request("Hi", &PromptOptions::default())).unwrap();
+        assert_eq!(body["input"], "Hi");
+        assert_eq!(body["tools"], serde_json::json!([{"type": "web_search_preview"}]));
+
+        let client = OpenAIResponsesClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(client.build_request("Hi", &PromptOptions::default())).unwrap();
+        assert!(body.get("tools").is_none());
+
+        let response: ResponsesResponse = serde_json::from_str(
+            r#"{"model":"gpt-4o-2024-08-06","output":[
+                {"type":"web_search_call","id":"ws_1","status":"completed"},
+                {"type":"message","role":"assistant","content":[{"type":"output_text","text":"Rust 1.80 is out.","annotations":[
+                    {"type":"url_citation","start_index":0,"end_index":9,"url":"https://blog.rust-lang.org/","title":"Rust Blog"},
+                    {"type":"url_citation","start_index":10,"end_index":14,"url":"https://blog.rust-lang.org/","title":"Rust Blog"}]}]}],
+                "usage":{"input_tokens":10,"output_tokens":5,"total_tokens":15}}"#,
+        )
+        .unwrap();
+        assert_eq!(response.text(), "Rust 1.80 is out.\n\n[Source: https://blog.rust-lang.org/]");
+        assert_eq!(response.usage.map(|usage| usage.output_tokens), Some(5));
+
+        assert!(create_client("openai-responses", "key", "gpt-4o", ClientConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_user_agent_config() {
+        assert_eq!(ClientConfig::default().user_agent, format!("chatdelta/{}", env!("CARGO_PKG_VERSION")));
+        let config = ClientConfigBuilder::default().user_agent("my-app/1.0").build();
+        assert_eq!(config.user_agent, "my-app/1.0");
+        assert!(build_http_client(&config).is_ok());
+    }
+
+    #[test]
+    fn test_proxy_config() {
+        let proxy = ProxyConfig::new("http://proxy.corp:3128").with_credentials("alice", "s3cret");
+        let config = ClientConfigBuilder::default().proxy(proxy.clone()).build();
+        assert_eq!(config.proxy, Some(proxy));
+        assert!(build_http_client(&config).is_ok());
+        assert!(!format!("{:?}", config).contains("s3cret"));
+
+        let socks = ClientConfigBuilder::default().proxy(ProxyConfig::new("socks5://127.0.0.1:1080")).build();
+        assert!(build_http_client(&socks).is_ok());
+        let invalid = ClientConfigBuilder::default().proxy(ProxyConfig::new("not a url")).build();
+        assert!(build_http_client(&invalid).is_err());
+
+        // Clients behind different proxies don't share connections
+        let direct = ClientConfig::default();
+        direct.connection_pool.client(&direct).unwrap();
+        let mut proxied = direct.clone();
+        proxied.proxy = Some(ProxyConfig::new("http://proxy.corp:3128"));
+        proxied.connection_pool.client(&proxied).unwrap();
+        assert_eq!(direct.connection_pool.client_count(), 2);
+    }
+
+    #[test]
+    fn test_sample_count_is_forwarded() {
+        let options = PromptOptions {
+            n: Some(3),
+            ..Default::default()
+        };
+
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["n"], 3);
+        let body = serde_json::to_value(openai.build_request("Hi", &options, true)).unwrap();
+        assert!(body.get("n").is_none());
+
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &options)).unwrap();
+        assert_eq!(body["generationConfig"]["candidateCount"], 3);
+        let body = serde_json::to_value(gemini.build_request("Hi", &PromptOptions::default())).unwrap();
+        assert!(body.get("generationConfig").is_none());
+    }
+
+    struct CountingClient;
+
+    #[async_trait]
+    impl AiClient for CountingClient {
+        async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+            Ok(prompt.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_multi_default_fans_out() {
+        let options = PromptOptions {
+            n: Some(3),
+            ..Default::default()
+        };
+        let responses = CountingClient.send_prompt_multi("Hi", &options).await.unwrap();
+        assert_eq!(responses.len(), 3);
+        assert!(responses.iter().all(|r| r.content == "Hi"));
+
+        let responses = CountingClient.send_prompt_multi("Hi", &PromptOptions::default()).await.unwrap();
+        assert_eq!(responses.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_with_images_default() {
+        assert_eq!(CountingClient.send_prompt_with_images("Hi", &[], &PromptOptions::default()).await.unwrap(), "Hi");
+        let images = [ImageAttachment { data: vec![0xFF, 0xD8, 0xFF], mime_type: "image/jpeg".to_string() }];
+        assert!(CountingClient.send_prompt_with_images("Hi", &images, &PromptOptions::default()).await.is_err());
+    }
+
+    #[test]
+    fn test_stop_sequences_are_forwarded() {
+        let config = ClientConfigBuilder::default()
+            .stop_sequences(vec!["END".to_string()])
+            .build();
+        let options = PromptOptions::default();
+
+        let openai = OpenAIClient::new("key", "gpt-4o", config.clone()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["stop"], serde_json::json!(["END"]));
+
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", config.clone()).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &options)).unwrap();
+        assert_eq!(body["generationConfig"]["stopSequences"], serde_json::json!(["END"]));
+        assert!(body["generationConfig"].get("candidateCount").is_none());
+
+        // Per-request stop sequences replace the configured ones
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", config).unwrap();
+        let options = PromptOptions {
+            stop_sequences: vec!["###".to_string()],
+            ..Default::default()
+        };
+        let body = serde_json::to_value(claude.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["stop_sequences"], serde_json::json!(["###"]));
+
+        let body = serde_json::to_value(openai.build_request("Hi", &PromptOptions::default(), false)).unwrap();
+        assert_eq!(body["stop"], serde_json::json!(["END"]));
+    }
+
+    #[test]
+    fn test_max_tokens_is_forwarded() {
+        let options = PromptOptions::default();
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &options)).unwrap();
+        assert!(body.get("generationConfig").is_none());
+
+        let config = ClientConfigBuilder::default().max_tokens(4_000).build();
+        let openai = OpenAIClient::new("key", "gpt-4o", config.clone()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["max_tokens"], 4_000);
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", config.clone()).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &options)).unwrap();
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 4_000);
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", config).unwrap();
+        let body = serde_json::to_value(claude.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["max_tokens"], 4_000);
+    }
+
+    #[test]
+    fn test_finish_reason_parsing() {
+        let openai: OpenAIResponse = serde_json::from_str(
+            r#"{"choices":[{"message":{"role":"assistant","content":"Hi"},"finish_reason":"length"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(openai.choices[0].finish_reason.as_deref(), Some("length"));
+
+        let gemini: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"parts":[{"text":"Hi"}]},"finishReason":"MAX_TOKENS"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(gemini.candidates.unwrap()[0].finish_reason.as_deref(), Some("MAX_TOKENS"));
+
+        let event: ClaudeStreamEvent = serde_json::from_str(
+            r#"{"type":"message_delta","delta":{"stop_reason":"max_tokens","stop_sequence":null}}"#,
+        )
+        .unwrap();
+        assert_eq!(event.delta.unwrap().stop_reason.as_deref(), Some("max_tokens"));
+    }
+
+    #[test]
+    fn test_seed_is_forwarded() {
+        let config = ClientConfigBuilder::default().seed(42).build();
+
+        let openai = OpenAIClient::new("key", "gpt-4o", config.clone()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &PromptOptions::default(), false)).unwrap();
+        assert_eq!(body["seed"], 42);
+
+        let options = PromptOptions {
+            seed: Some(7),
+            ..Default::default()
+        };
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", config.clone()).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &options)).unwrap();
+        assert_eq!(body["generationConfig"]["seed"], 7);
+
+        // Claude has no seed parameter, so nothing is sent
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", config).unwrap();
+        let body = serde_json::to_value(claude.build_request("Hi", &options, false)).unwrap();
+        assert!(body.get("seed").is_none());
+
+        let openai: OpenAIResponse = serde_json::from_str(
+            r#"{"choices":[],"system_fingerprint":"fp_44709d6fcb"}"#,
+        )
+        .unwrap();
+        assert_eq!(openai.system_fingerprint.as_deref(), Some("fp_44709d6fcb"));
+    }
+
+    #[test]
+    fn test_audio_mime_type() {
+        assert_eq!(audio_mime_type(Path::new("note.wav")), "audio/wav");
+        assert_eq!(audio_mime_type(Path::new("note.OGG")), "audio/ogg");
+        assert_eq!(audio_mime_type(Path::new("note")), "application/octet-stream");
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_rejects_large_files() {
+        let path = std::env::temp_dir().join(format!("chatdelta-large-{}.wav", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_len(MAX_TRANSCRIPTION_BYTES + 1).unwrap();
+
+        let client = TranscriptionClient::new("key", ClientConfig::default()).unwrap();
+        let result = client.transcribe(&path).await;
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ChatDeltaError::FileTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_image_attachment_from_file() {
+        let path = std::env::temp_dir().join(format!("chatdelta-image-{}.png", std::process::id()));
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\nrest").unwrap();
+        let image = ImageAttachment::from_file(&path).unwrap();
+        assert_eq!(image.mime_type, "image/png");
+        assert_eq!(image.data_url(), "data:image/png;base64,iVBORw0KGgpyZXN0");
+
+        // The extension doesn't matter, the contents do
+        std::fs::write(&path, b"plain text").unwrap();
+        let result = ImageAttachment::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ChatDeltaError::Io(_))));
+
+        assert_eq!(image_mime_type(b"\xFF\xD8\xFF\xE0"), Some("image/jpeg"));
+        assert_eq!(image_mime_type(b"RIFF\x00\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        assert_eq!(image_mime_type(b"GIF89a"), Some("image/gif"));
+    }
+
+    #[test]
+    fn test_image_requests() {
+        let images = [ImageAttachment { data: b"img".to_vec(), mime_type: "image/jpeg".to_string() }];
+        let options = PromptOptions {
+            n: Some(2),
+            seed: Some(7),
+            stop_sequences: vec!["END".to_string()],
+            ..Default::default()
+        };
+
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(openai.build_image_request("What is this?", &images, &options)).unwrap();
+        assert_eq!(
+            body["messages"][0]["content"],
+            serde_json::json!([
+                { "type": "text", "text": "What is this?" },
+                { "type": "image_url", "image_url": { "url": "data:image/jpeg;base64,aW1n" } },
+            ])
+        );
+        // The prompt's options apply, but only one response comes back
+        assert_eq!(body["seed"], 7);
+        assert_eq!(body["stop"], serde_json::json!(["END"]));
+        assert!(body.get("n").is_none());
+
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(gemini.build_image_request("What is this?", &images, &options)).unwrap();
+        assert_eq!(
+            body["contents"][0]["parts"],
+            serde_json::json!([
+                { "inlineData": { "mimeType": "image/jpeg", "data": "aW1n" } },
+                { "text": "What is this?" },
+            ])
+        );
+        assert_eq!(body["generationConfig"]["seed"], 7);
+        assert!(body["generationConfig"].get("candidateCount").is_none());
+
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(claude.build_image_request("What is this?", &images, &options)).unwrap();
+        assert_eq!(
+            body["messages"][0]["content"],
+            serde_json::json!([
+                { "type": "image", "source": { "type": "base64", "media_type": "image/jpeg", "data": "aW1n" } },
+                { "type": "text", "text": "What is this?" },
+            ])
+        );
+        assert_eq!(body["stop_sequences"], serde_json::json!(["END"]));
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_embeddings_response_parsing() {
+        let openai: OpenAIEmbeddingsResponse = serde_json::from_str(
+            r#"{"data":[{"index":1,"embedding":[0.5]},{"index":0,"embedding":[0.25]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(openai.data[0].index, 1);
+
+        let gemini: GeminiEmbedResponse = serde_json::from_str(r#"{"embedding":{"values":[0.1,0.2]}}"#).unwrap();
+        assert_eq!(gemini.embedding.values, vec![0.1, 0.2]);
+    }
+
+    /// Fails every request
+    struct FailingClient;
+
+    #[async_trait]
+    impl AiClient for FailingClient {
+        async fn send_prompt(&self, _prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+            Err("rate limited".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_chain_uses_first_success() {
+        let chain = FallbackChain::new(vec![Box::new(FailingClient), Box::new(CountingClient)]);
+        assert_eq!(chain.send_prompt("Hi").await.unwrap(), "Hi");
+
+        let chain = FallbackChain::new(vec![Box::new(FailingClient), Box::new(FailingClient)]);
+        assert_eq!(chain.send_prompt("Hi").await.unwrap_err().to_string(), "rate limited");
+
+        assert!(FallbackChain::new(Vec::new()).send_prompt("Hi").await.is_err());
+    }
+
+    #[test]
+    fn test_create_fallback_client() {
+        assert!(create_client("fallback:openai,claude", "key-a,key-b", "gpt-4o,claude-3-5-sonnet-20241022", ClientConfig::default()).is_ok());
+        assert!(create_client("fallback:openai,gemini", "key", "model", ClientConfig::default()).is_ok());
+        assert!(create_client("fallback:openai,mistral", "key", "model", ClientConfig::default()).is_err());
+        assert!(create_client("fallback:", "key", "model", ClientConfig::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multi_model_client() {
+        let client = MultiModelClient::new(vec![
+            ("slow-model".to_string(), Box::new(FailingClient)),
+            ("fast-model".to_string(), Box::new(CountingClient)),
+        ]);
+        assert_eq!(client.fan_out_models(), ["slow-model", "fast-model"]);
+        assert_eq!(client.send_prompt("Hi").await.unwrap(), "Hi");
+
+        let all = client.all_responses("Hi", &PromptOptions::default()).await;
+        assert_eq!(all[0].0, "slow-model");
+        assert!(all[0].1.is_err());
+        assert_eq!(all[1].1.as_ref().unwrap().content, "Hi");
+
+        let per_model = client.send_prompt_multi("Hi", &PromptOptions::default()).await.unwrap();
+        let contents: Vec<&str> = per_model.iter().map(|r| r.content.as_str()).collect();
+        assert_eq!(contents, ["Error: rate limited", "Hi"]);
+
+        let failing = MultiModelClient::new(vec![("a".to_string(), Box::new(FailingClient))]);
+        assert_eq!(failing.send_prompt("Hi").await.unwrap_err().to_string(), "rate limited");
+        assert!(failing.send_prompt_multi("Hi", &PromptOptions::default()).await.is_err());
+        assert!(MultiModelClient::new(Vec::new()).send_prompt("Hi").await.is_err());
+    }
+
+    #[test]
+    fn test_create_multi_model_client() {
+        let client = create_client("openai-multi:gpt-4o, gpt-4o-mini", "key", "", ClientConfig::default()).unwrap();
+        assert_eq!(client.fan_out_models(), ["gpt-4o", "gpt-4o-mini"]);
+        assert!(create_client("openai-multi:", "key", "", ClientConfig::default()).is_err());
+        assert!(create_client("mistral-multi:large", "key", "", ClientConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_per_provider_configs() {
+        let default = ClientConfigBuilder::default().timeout(Duration::from_secs(30)).retries(3).build();
+        let configs = ProviderConfigs::from_toml(default, "[claude]\ntimeout_secs = 300\n").unwrap();
+        assert_eq!(configs.get("claude").timeout, Duration::from_secs(300));
+        assert_eq!(configs.get("claude").retries, 3);
+        assert!(ProviderConfigs::from_toml(ClientConfig::default(), "[claude]\ntimeout = 300\n").is_err());
+
+        let openai = create_client_with_configs("openai", "key", "gpt-4o", &configs).unwrap();
+        let claude = create_client_with_configs("claude", "key", "claude-3-5-sonnet-20241022", &configs).unwrap();
+        let gemini = create_client_with_configs("gemini", "key", "gemini-1.5-pro", &configs).unwrap();
+        assert_eq!(openai.config().unwrap().timeout, Duration::from_secs(30));
+        assert_eq!(claude.config().unwrap().timeout, Duration::from_secs(300));
+        assert_eq!(gemini.config().unwrap().timeout, Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_failed_validation_retries_once_with_nudge() {
+        // Blank unless the nudge was appended
+        let answer = |prompt: String| async move {
+            let content = if prompt.ends_with(VALIDATION_NUDGE) { "Answer" } else { "  " };
+            Ok::<_, Box<dyn Error + Send + Sync>>(AiResponse { content: content.to_string(), metadata: ResponseMetadata::default() })
+        };
+        let response = with_validation(&ResponseValidator::default(), "OpenAI", "Hi", answer).await.unwrap();
+        assert_eq!(response.content, "Answer");
+        assert!(response.metadata.validation_retried);
+
+        let refuse = ResponseValidator::new(|content| !content.starts_with("I can't"));
+        let always_refuses = |_prompt: String| async move {
+            Ok::<_, Box<dyn Error + Send + Sync>>(AiResponse { content: "I can't help with that.".to_string(), metadata: ResponseMetadata::default() })
+        };
+        let err = with_validation(&refuse, "Claude", "Hi", always_refuses).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ChatDeltaError>(),
+            Some(ChatDeltaError::InvalidResponse { provider: "Claude" })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_is_shared_and_exhausted() {
+        let config = ClientConfigBuilder::default().retries(3).retry_budget(4).build();
+        let shared = config.clone();
+        let attempts = &AtomicU32::new(0);
+        let failing = move || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Box::new(ChatDeltaError::Api { provider: "OpenAI", status: 503, message: "busy".to_string() }) as Box<dyn Error + Send + Sync>)
+        };
+
+        assert!(with_retries(&config, failing).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+        assert_eq!(shared.retry_budget.retries_used(), 3);
+        assert_eq!(shared.retry_budget.fraction_used(), 0.75);
+
+        // The clone has one retry left, then gives up: 4 attempts above, plus its first try and
+        // the one retry the budget still allows
+        let err = with_retries(&shared, failing).await.unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 4 + 1 + 1);
+        assert!(matches!(
+            err.downcast_ref::<ChatDeltaError>(),
+            Some(ChatDeltaError::RetryBudgetExhausted { max_total_retries: 4, .. })
+        ));
+
+        config.retry_budget.reset();
+        assert_eq!(shared.retry_budget.retries_used(), 0);
+
+        // A rejected key or request fails at once and leaves the budget alone; timeouts and
+        // rate limits are retried
+        let attempts = &AtomicU32::new(0);
+        let rejected = |status| move || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Box::new(ChatDeltaError::from_status("Claude", status, String::new())) as Box<dyn Error + Send + Sync>)
+        };
+        for status in [reqwest::StatusCode::UNAUTHORIZED, reqwest::StatusCode::FORBIDDEN, reqwest::StatusCode::BAD_REQUEST] {
+            assert!(with_retries(&config, rejected(status)).await.is_err());
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(shared.retry_budget.retries_used(), 0);
+        assert!(with_retries(&config, rejected(reqwest::StatusCode::TOO_MANY_REQUESTS)).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3 + 4);
+        assert_eq!(shared.retry_budget.retries_used(), 3);
+        assert!(RetryBudget::default().try_consume());
+    }
+
+    #[test]
+    fn test_gemini_stream_events() {
+        let first: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"parts":[{"text":"Hello\n"}],"role":"model"}}],"usageMetadata":{"promptTokenCount":4,"candidatesTokenCount":1},"modelVersion":"gemini-1.5-pro-002"}"#,
+        )
+        .unwrap();
+        let last: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"finishReason":"MAX_TOKENS"}],"usageMetadata":{"promptTokenCount":4,"candidatesTokenCount":9}}"#,
+        )
+        .unwrap();
+
+        let first_candidate = first.candidates.unwrap().swap_remove(0);
+        // Trailing newlines are kept so the next event's text starts on a new line
+        assert_eq!(gemini_stream_text(first_candidate.content.parts).as_deref(), Some("Hello\n"));
+        let last_candidate = last.candidates.unwrap().swap_remove(0);
+        assert_eq!(last_candidate.finish_reason.as_deref(), Some("MAX_TOKENS"));
+        assert_eq!(gemini_stream_text(last_candidate.content.parts), None);
+        assert_eq!(last.usage_metadata.unwrap().candidates_token_count, 9);
+    }
+
+    #[test]
+    fn test_gemini_safety_settings() {
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &PromptOptions::default())).unwrap();
+        assert!(body.get("safetySettings").is_none());
+
+        let setting: GeminiSafetySettings = "harassment=block_only_high".parse().unwrap();
+        assert_eq!(setting.category, "HARM_CATEGORY_HARASSMENT");
+        assert_eq!(setting.threshold, "BLOCK_ONLY_HIGH");
+        let config = ClientConfigBuilder::default()
+            .safety_settings(vec![setting, "HARM_CATEGORY_DANGEROUS_CONTENT=BLOCK_NONE".parse().unwrap()])
+            .build();
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", config).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &PromptOptions::default())).unwrap();
+        assert_eq!(
+            body["safetySettings"],
+            serde_json::json!([
+                { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_ONLY_HIGH" },
+                { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE" },
+            ])
+        );
+
+        assert!("harassment".parse::<GeminiSafetySettings>().is_err());
+        assert!("violence=block_none".parse::<GeminiSafetySettings>().is_err());
+        assert!("harassment=sometimes".parse::<GeminiSafetySettings>().is_err());
+    }
+
+    #[test]
+    fn test_gemini_conversation_alternates_roles() {
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", ClientConfig::default()).unwrap();
+        let messages = [
+            ChatMessage::user("What is Rust?"),
+            ChatMessage::assistant("A systems programming language."),
+            ChatMessage::user("Who created it?"),
+        ];
+        let body = serde_json::to_value(gemini.build_conversation_request(&messages, &PromptOptions::default())).unwrap();
+        let roles: Vec<&str> = body["contents"].as_array().unwrap().iter().map(|c| c["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, ["user", "model", "user"]);
+        assert_eq!(body["contents"][1]["parts"][0]["text"], "A systems programming language.");
+
+        // Back-to-back user messages become one turn
+        let messages = [ChatMessage::user("Context"), ChatMessage::user("Question")];
+        let body = serde_json::to_value(gemini.build_conversation_request(&messages, &PromptOptions::default())).unwrap();
+        assert_eq!(body["contents"].as_array().unwrap().len(), 1);
+        assert_eq!(body["contents"][0]["parts"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_conversation_must_end_with_user() {
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", ClientConfig::default()).unwrap();
+        let messages = [ChatMessage::user("Hi"), ChatMessage::assistant("Hello!")];
+        assert!(gemini.send_conversation(&messages, &PromptOptions::default()).await.is_err());
+        assert!(gemini.send_conversation(&[], &PromptOptions::default()).await.is_err());
+    }
+
+    #[test]
+    fn test_openai_and_claude_conversations_alternate_roles() {
+        let messages = [
+            ChatMessage::user("What is Rust?"),
+            ChatMessage::assistant("A systems programming language."),
+            ChatMessage::user("Who created it?"),
+        ];
+
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        for stream in [false, true] {
+            let body = serde_json::to_value(openai.build_conversation_request(&messages, &PromptOptions::default(), stream)).unwrap();
+            let roles: Vec<&str> = body["messages"].as_array().unwrap().iter().map(|m| m["role"].as_str().unwrap()).collect();
+            assert_eq!(roles, ["user", "assistant", "user"]);
+            assert_eq!(body["messages"][1]["content"], "A systems programming language.");
+            assert_eq!(body["messages"][2]["content"], "Who created it?");
+        }
+
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", ClientConfig::default()).unwrap();
+        for stream in [false, true] {
+            let body = serde_json::to_value(claude.build_conversation_request(&messages, &PromptOptions::default(), stream)).unwrap();
+            let roles: Vec<&str> = body["messages"].as_array().unwrap().iter().map(|m| m["role"].as_str().unwrap()).collect();
+            assert_eq!(roles, ["user", "assistant", "user"]);
+            assert_eq!(body["messages"][1]["content"], "A systems programming language.");
+            assert_eq!(body["messages"][2]["content"], "Who created it?");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openai_and_claude_conversations_must_end_with_user() {
+        let messages = [ChatMessage::user("Hi"), ChatMessage::assistant("Hello!")];
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        assert!(openai.send_conversation(&messages, &PromptOptions::default()).await.is_err());
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", ClientConfig::default()).unwrap();
+        assert!(claude.send_conversation(&messages, &PromptOptions::default()).await.is_err());
+    }
+
+    #[test]
+    fn test_flatten_conversation() {
+        assert_eq!(flatten_conversation(&[ChatMessage::user("Hi")]), "Hi");
+        let messages = [ChatMessage::user("Hi"), ChatMessage::assistant("Hello!"), ChatMessage::user("Bye")];
+        assert_eq!(flatten_conversation(&messages), "User: Hi\n\nAssistant: Hello!\n\nUser: Bye");
+    }
+
+    #[tokio::test]
+    async fn test_send_conversation_streaming_default() {
+        let messages = [ChatMessage::user("Hi"), ChatMessage::assistant("Hello!"), ChatMessage::user("Bye")];
+        let (tx, mut rx) = mpsc::channel(4);
+        CountingClient.send_conversation_streaming(&messages, &PromptOptions::default(), tx).await.unwrap();
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.content, flatten_conversation(&messages));
+        assert!(rx.recv().await.unwrap().finished);
+    }
+
+    /// Answer one request on a local port with `status` and a JSON `body`, returning the
+    /// server's URL
+    async fn serve_once(status: &'static str, body: serde_json::Value) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                let read = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..read]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if read == 0 || request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+            }
+            let body = body.to_string();
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        url
+    }
+
+    struct Redirect(String);
+
+    impl Middleware for Redirect {
+        fn on_request(&self, request: &mut RequestParts) {
+            request.url = self.0.clone();
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder(std::sync::Mutex<Vec<(&'static str, serde_json::Value)>>);
+
+    impl RequestInterceptor for Recorder {
+        fn on_request(&self, _provider: &str, body: &serde_json::Value) {
+            self.0.lock().unwrap().push(("request", body.clone()));
+        }
+
+        fn on_response(&self, _provider: &str, body: &serde_json::Value) {
+            self.0.lock().unwrap().push(("response", body.clone()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interceptor_sees_requests_and_responses() {
+        let reply = serde_json::json!({"choices": [{"message": {"role": "assistant", "content": "Hello!"}}]});
+        let url = serve_once("200 OK", reply.clone()).await;
+        let recorder = Arc::new(Recorder::default());
+        let config = ClientConfigBuilder::default()
+            .retries(0)
+            .middleware(vec![Arc::new(Redirect(url))])
+            .interceptor(recorder.clone())
+            .build();
+        let openai = OpenAIClient::new("key", "gpt-4o", config).unwrap();
+        assert_eq!(openai.send_prompt("Hi").await.unwrap(), "Hello!");
+        {
+            let seen = recorder.0.lock().unwrap();
+            assert_eq!(seen.len(), 2);
+            assert_eq!(seen[0].0, "request");
+            assert_eq!(seen[0].1["messages"][0]["content"], "Hi");
+            assert_eq!(seen[1], ("response", reply));
+        }
+
+        // Error bodies are shown too, before they become the error message
+        let reply = serde_json::json!({"type": "error", "error": {"type": "invalid_request_error", "message": "bad"}});
+        let url = serve_once("400 Bad Request", reply.clone()).await;
+        let recorder = Arc::new(Recorder::default());
+        let config = ClientConfigBuilder::default()
+            .retries(0)
+            .middleware(vec![Arc::new(Redirect(url))])
+            .interceptor(recorder.clone())
+            .build();
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", config).unwrap();
+        let error = claude.send_prompt("Hi").await.unwrap_err();
+        assert!(error.to_string().contains("invalid_request_error"));
+        let seen = recorder.0.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[1], ("response", reply));
+    }
+
+    #[tokio::test]
+    async fn test_pending_requests_are_shared_per_api_key() {
+        // Never answers, so the first request stays pending
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let config = ClientConfigBuilder::default()
+            .retries(0)
+            .middleware(vec![Arc::new(Redirect(url))])
+            .build();
+        let tenant_a = OpenAIClient::new("key-a", "gpt-4o", config.clone()).unwrap();
+        let also_a = OpenAIClient::new("key-a", "gpt-4o", config.clone()).unwrap();
+        let tenant_b = OpenAIClient::new("key-b", "gpt-4o", config).unwrap();
+
+        let pending = tenant_a.send_prompt("Hi");
+        tokio::pin!(pending);
+        assert!(futures::poll!(pending.as_mut()).is_pending());
+        let options = PromptOptions::default();
+        assert!(also_a.is_request_pending("Hi", &options));
+        assert!(!tenant_b.is_request_pending("Hi", &options));
+        drop(listener);
+    }
+}
+
+// Run with `wasm-pack test --node chatdelta-rs --features wasm`
+#[cfg(all(test, feature = "openai", feature = "gemini", feature = "claude", feature = "wasm", target_arch = "wasm32"))]
+mod wasm_tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn test_requests_serialize() {
+        let options = PromptOptions {
+            seed: Some(7),
+            ..Default::default()
+        };
+
+        let openai = OpenAIClient::new("key", "gpt-4o", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(openai.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["messages"][0]["content"], "Hi");
+        assert_eq!(body["seed"], 7);
+        assert!(!openai.supports_streaming());
+
+        let gemini = GeminiClient::new("key", "gemini-1.5-pro", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(gemini.build_request("Hi", &options)).unwrap();
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "Hi");
 
-        Ok(content)
+        let claude = ClaudeClient::new("key", "claude-3-5-sonnet-20241022", ClientConfig::default()).unwrap();
+        let body = serde_json::to_value(claude.build_request("Hi", &options, false)).unwrap();
+        assert_eq!(body["messages"][0]["content"], "Hi");
+        assert!(body.get("stream").is_none());
     }
 }
@@ -0,0 +1,112 @@
+//! Request counters for a client: how many requests succeeded, how long they took and how
+//! many tokens they used
+//!
+//! `ClientMetrics` is cheap to clone and clones share their counters, so a handle can be kept
+//! by whoever records requests while another reads the totals.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests_total: AtomicU64,
+    requests_successful: AtomicU64,
+    latency_ms_total: AtomicU64,
+    tokens_total: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientMetrics {
+    counters: Arc<Counters>,
+}
+
+/// Totals of a `ClientMetrics` at one moment
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub requests_total: u64,
+    pub requests_successful: u64,
+    /// Percentage of requests that succeeded, 0 before the first one
+    pub success_rate: f64,
+    /// Mean latency of all requests, failed ones included
+    pub average_latency_ms: u64,
+    pub total_tokens_used: u64,
+    /// Percentage of cache lookups that hit, 0 before the first one
+    pub cache_hit_rate: f64,
+}
+
+impl ClientMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a finished request; `tokens` is the usage the provider reported, if any
+    pub fn record_request(&self, success: bool, latency_ms: u64, tokens: Option<u32>) {
+        let counters = &self.counters;
+        counters.requests_total.fetch_add(1, Ordering::Relaxed);
+        if success {
+            counters.requests_successful.fetch_add(1, Ordering::Relaxed);
+        }
+        counters.latency_ms_total.fetch_add(latency_ms, Ordering::Relaxed);
+        if let Some(tokens) = tokens {
+            counters.tokens_total.fetch_add(u64::from(tokens), Ordering::Relaxed);
+        }
+    }
+
+    /// Add usage reported separately from the request it belongs to
+    pub fn record_tokens(&self, tokens: u32) {
+        self.counters.tokens_total.fetch_add(u64::from(tokens), Ordering::Relaxed);
+    }
+
+    /// Count a response cache lookup
+    pub fn record_cache_lookup(&self, hit: bool) {
+        let counter = if hit { &self.counters.cache_hits } else { &self.counters.cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_stats(&self) -> MetricsSnapshot {
+        let counters = &self.counters;
+        let requests_total = counters.requests_total.load(Ordering::Relaxed);
+        let requests_successful = counters.requests_successful.load(Ordering::Relaxed);
+        let cache_hits = counters.cache_hits.load(Ordering::Relaxed);
+        let cache_lookups = cache_hits + counters.cache_misses.load(Ordering::Relaxed);
+        let percent = |part: u64, whole: u64| if whole > 0 { part as f64 / whole as f64 * 100.0 } else { 0.0 };
+        MetricsSnapshot {
+            requests_total,
+            requests_successful,
+            success_rate: percent(requests_successful, requests_total),
+            average_latency_ms: counters.latency_ms_total.load(Ordering::Relaxed).checked_div(requests_total).unwrap_or(0),
+            total_tokens_used: counters.tokens_total.load(Ordering::Relaxed),
+            cache_hit_rate: percent(cache_hits, cache_lookups),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let metrics = ClientMetrics::new();
+        assert_eq!(metrics.get_stats(), MetricsSnapshot::default());
+
+        let handle = metrics.clone();
+        handle.record_request(true, 100, Some(50));
+        handle.record_request(true, 300, None);
+        handle.record_request(false, 200, None);
+        handle.record_request(true, 400, None);
+        handle.record_tokens(25);
+        metrics.record_cache_lookup(true);
+        metrics.record_cache_lookup(false);
+
+        let stats = metrics.get_stats();
+        assert_eq!(stats.requests_total, 4);
+        assert_eq!(stats.requests_successful, 3);
+        assert_eq!(stats.success_rate, 75.0);
+        assert_eq!(stats.average_latency_ms, 250);
+        assert_eq!(stats.total_tokens_used, 75);
+        assert_eq!(stats.cache_hit_rate, 50.0);
+    }
+}
@@ -0,0 +1,285 @@
+//! Hooks that run around every provider request, e.g. to add gateway headers or log bodies
+//!
+//! Middleware is added with `ClientConfigBuilder::middleware` and runs in the order given,
+//! for streaming and non-streaming requests alike. A `RequestInterceptor` only watches: it sees
+//! request and response bodies as JSON, e.g. to debug what a provider actually returned.
+
+use reqwest::header::HeaderMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The parts of an outgoing request that middleware may inspect or change
+#[derive(Clone, Debug)]
+pub struct RequestParts {
+    /// `"OpenAI"`, `"Gemini"` or `"Claude"`
+    pub provider: &'static str,
+    pub method: String,
+    pub url: String,
+    pub headers: HeaderMap,
+    /// Request body, when it is held in memory (not for multipart uploads)
+    pub body: Option<Vec<u8>>,
+}
+
+/// Status and headers of a response, available before its body is read
+#[derive(Clone, Debug)]
+pub struct ResponseParts {
+    pub provider: &'static str,
+    pub url: String,
+    pub status: u16,
+    pub headers: HeaderMap,
+}
+
+pub trait Middleware: Send + Sync {
+    /// Inspect or change a request before it is sent
+    fn on_request(&self, _request: &mut RequestParts) {}
+
+    /// Inspect a response as soon as its headers arrive
+    fn on_response(&self, _response: &ResponseParts) {}
+}
+
+/// Middleware run around every request, in order
+#[derive(Clone, Default)]
+pub struct MiddlewareStack(Vec<Arc<dyn Middleware>>);
+
+impl MiddlewareStack {
+    pub fn push(&mut self, middleware: Arc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn on_request(&self, request: &mut RequestParts) {
+        for middleware in &self.0 {
+            middleware.on_request(request);
+        }
+    }
+
+    pub fn on_response(&self, response: &ResponseParts) {
+        for middleware in &self.0 {
+            middleware.on_response(response);
+        }
+    }
+}
+
+impl fmt::Debug for MiddlewareStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MiddlewareStack({} middleware)", self.0.len())
+    }
+}
+
+/// Adds fixed headers to every request, e.g. a gateway token
+#[derive(Clone, Debug)]
+pub struct HeaderInjector {
+    headers: HeaderMap,
+}
+
+impl HeaderInjector {
+    pub fn new(headers: HeaderMap) -> Self {
+        Self { headers }
+    }
+}
+
+impl Middleware for HeaderInjector {
+    fn on_request(&self, request: &mut RequestParts) {
+        for (name, value) in &self.headers {
+            request.headers.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+/// Appends every request body to a file, one `provider METHOD url` header line per request.
+/// The file holds API payloads in plain text, so keep it out of shared locations.
+#[cfg(not(feature = "wasm"))]
+#[derive(Clone, Debug)]
+pub struct RawBodyLogger {
+    path: std::path::PathBuf,
+}
+
+#[cfg(not(feature = "wasm"))]
+impl RawBodyLogger {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+impl Middleware for RawBodyLogger {
+    fn on_request(&self, request: &mut RequestParts) {
+        use std::io::Write;
+
+        let body = request
+            .body
+            .as_deref()
+            .map(String::from_utf8_lossy)
+            .unwrap_or_else(|| "<streamed body>".into());
+        // Gemini passes its API key in the query string
+        let url = request.url.split('?').next().unwrap_or_default();
+        let entry = format!("{} {} {}\n{}\n\n", request.provider, request.method, url, body);
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(entry.as_bytes()));
+        if let Err(e) = written {
+            tracing::warn!(path = %self.path.display(), error = %e, "could not write request body log");
+        }
+    }
+}
+
+/// Environment variable that makes `ClientConfig::default` install a `FileInterceptor` when
+/// set to `1`
+pub const DEBUG_HTTP_ENV: &str = "CHATDELTA_DEBUG_HTTP";
+
+/// File in the working directory that `CHATDELTA_DEBUG_HTTP=1` writes to
+pub const DEBUG_HTTP_FILE: &str = ".http-debug.jsonl";
+
+/// Sees the JSON body of every request after middleware has run, and of every response.
+/// Streamed responses are passed one event at a time. Both hooks do nothing by default.
+pub trait RequestInterceptor: Send + Sync {
+    fn on_request(&self, _provider: &str, _body: &serde_json::Value) {}
+
+    fn on_response(&self, _provider: &str, _body: &serde_json::Value) {}
+}
+
+impl fmt::Debug for dyn RequestInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestInterceptor")
+    }
+}
+
+/// An interceptor that ignores everything
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopInterceptor;
+
+impl RequestInterceptor for NoopInterceptor {}
+
+/// Appends each body to a JSON Lines file as
+/// `{"direction": "request" | "response", "provider": …, "body": …}`. Like `RawBodyLogger`,
+/// the file holds API payloads in plain text.
+#[cfg(not(feature = "wasm"))]
+#[derive(Clone, Debug)]
+pub struct FileInterceptor {
+    path: std::path::PathBuf,
+}
+
+#[cfg(not(feature = "wasm"))]
+impl FileInterceptor {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// A `FileInterceptor` writing to `.http-debug.jsonl` when `CHATDELTA_DEBUG_HTTP=1`
+    pub fn from_env() -> Option<Arc<dyn RequestInterceptor>> {
+        let enabled = std::env::var(DEBUG_HTTP_ENV).is_ok_and(|value| value == "1");
+        enabled.then(|| Arc::new(Self::new(DEBUG_HTTP_FILE)) as Arc<dyn RequestInterceptor>)
+    }
+
+    fn append(&self, direction: &str, provider: &str, body: &serde_json::Value) {
+        use std::io::Write;
+
+        let entry = serde_json::json!({ "direction": direction, "provider": provider, "body": body });
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", entry));
+        if let Err(e) = written {
+            tracing::warn!(path = %self.path.display(), error = %e, "could not write HTTP debug log");
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+impl RequestInterceptor for FileInterceptor {
+    fn on_request(&self, provider: &str, body: &serde_json::Value) {
+        self.append("request", provider, body);
+    }
+
+    fn on_response(&self, provider: &str, body: &serde_json::Value) {
+        self.append("response", provider, body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn sample_request() -> RequestParts {
+        RequestParts {
+            provider: "OpenAI",
+            method: "POST".to_string(),
+            url: "https://api.openai.com/v1/chat/completions".to_string(),
+            headers: HeaderMap::new(),
+            body: Some(br#"{"model":"gpt-4o"}"#.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_header_injector() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-gateway-token", HeaderValue::from_static("secret"));
+        let mut stack = MiddlewareStack::default();
+        stack.push(Arc::new(HeaderInjector::new(headers)));
+
+        let mut request = sample_request();
+        stack.on_request(&mut request);
+        assert_eq!(request.headers["x-gateway-token"], "secret");
+    }
+
+    #[test]
+    fn test_builder_stacks_middleware() {
+        let config = crate::ClientConfigBuilder::default()
+            .middleware(vec![Arc::new(HeaderInjector::new(HeaderMap::new()))])
+            .middleware(vec![Arc::new(HeaderInjector::new(HeaderMap::new()))])
+            .build();
+        assert_eq!(config.middleware.0.len(), 2);
+        assert!(crate::ClientConfig::default().middleware.is_empty());
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn test_raw_body_logger() {
+        let path = std::env::temp_dir().join(format!("chatdelta-bodies-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let logger = RawBodyLogger::new(&path);
+
+        logger.on_request(&mut sample_request());
+        logger.on_request(&mut sample_request());
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(log.matches("OpenAI POST https://api.openai.com/v1/chat/completions\n{\"model\":\"gpt-4o\"}").count(), 2);
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn test_file_interceptor() {
+        let path = std::env::temp_dir().join(format!("chatdelta-http-debug-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let interceptor = FileInterceptor::new(&path);
+
+        interceptor.on_request("OpenAI", &serde_json::json!({ "model": "gpt-4o" }));
+        interceptor.on_response("OpenAI", &serde_json::json!({ "choices": [] }));
+        NoopInterceptor.on_request("OpenAI", &serde_json::json!({ "model": "gpt-4o" }));
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let entries: Vec<serde_json::Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["direction"], "request");
+        assert_eq!(entries[0]["body"]["model"], "gpt-4o");
+        assert_eq!(entries[1]["direction"], "response");
+        assert_eq!(entries[1]["provider"], "OpenAI");
+    }
+
+    #[test]
+    fn test_builder_sets_interceptor() {
+        let config = crate::ClientConfigBuilder::default()
+            .interceptor(Arc::new(NoopInterceptor))
+            .build();
+        assert!(config.interceptor.is_some());
+    }
+}
@@ -0,0 +1,259 @@
+//! Per-provider model defaults and capabilities
+//!
+//! `ModelCatalog::builtin()` describes the default model of each provider. A TOML file can
+//! override any field or add providers:
+//!
+//! ```toml
+//! [openai]
+//! default_model = "gpt-4o-mini"
+//! input_price_per_mtok = 0.15
+//! output_price_per_mtok = 0.6
+//! ```
+
+use crate::capabilities::{known_model, model_capabilities, ModelCapabilities};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+/// Models offered for switching to besides each provider's default, newest first
+const KNOWN_MODELS: [(&str, &[&str]); 3] = [
+    ("openai", &["gpt-4o", "gpt-4o-mini", "gpt-4-turbo", "gpt-3.5-turbo"]),
+    ("gemini", &["gemini-1.5-pro", "gemini-1.5-flash", "gemini-2.0-flash"]),
+    (
+        "claude",
+        &[
+            "claude-3-5-sonnet-20241022",
+            "claude-3-5-haiku-20241022",
+            "claude-3-opus-20240229",
+            "claude-3-haiku-20240307",
+        ],
+    ),
+];
+
+/// What the library knows about a provider's default model
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelInfo {
+    /// Model name sent to the API
+    pub default_model: String,
+    /// Maximum prompt plus response size, in tokens
+    pub context_window: u32,
+    /// Longest response the model can be asked for, in tokens
+    pub max_output_tokens: u32,
+    /// Whether this library's client streams responses for the provider
+    pub supports_streaming: bool,
+    /// Whether the model accepts images
+    pub supports_vision: bool,
+    /// USD per million prompt tokens
+    pub input_price_per_mtok: f64,
+    /// USD per million response tokens
+    pub output_price_per_mtok: f64,
+}
+
+impl ModelInfo {
+    /// Estimated cost in USD of one request
+    pub fn estimate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.input_price_per_mtok + output_tokens as f64 * self.output_price_per_mtok) / 1_000_000.0
+    }
+
+    /// This entry as `ModelCapabilities`, for callers that handle other models too
+    pub fn capabilities(&self) -> ModelCapabilities {
+        ModelCapabilities {
+            max_context_tokens: self.context_window,
+            max_output_tokens: self.max_output_tokens,
+            supports_streaming: self.supports_streaming,
+            supports_system_prompt: true,
+            supports_vision: self.supports_vision,
+            cost_per_1k_input_tokens: self.input_price_per_mtok / 1_000.0,
+            cost_per_1k_output_tokens: self.output_price_per_mtok / 1_000.0,
+        }
+    }
+}
+
+/// Model information keyed by provider (`"openai"`, `"gemini"`, `"claude"`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelCatalog {
+    providers: HashMap<String, ModelInfo>,
+}
+
+/// One provider's section of a catalog file; unset fields keep the built-in values
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ModelOverride {
+    default_model: Option<String>,
+    context_window: Option<u32>,
+    max_output_tokens: Option<u32>,
+    supports_streaming: Option<bool>,
+    supports_vision: Option<bool>,
+    input_price_per_mtok: Option<f64>,
+    output_price_per_mtok: Option<f64>,
+}
+
+impl ModelCatalog {
+    /// Defaults shipped with the library, with each default model's limits and prices taken
+    /// from the `model_capabilities` table
+    pub fn builtin() -> Self {
+        let providers = [
+            ("openai", "gpt-4o"),
+            ("gemini", "gemini-1.5-pro"),
+            ("claude", "claude-3-5-sonnet-20241022"),
+        ]
+        .into_iter()
+        .map(|(provider, model)| {
+            let known = known_model(provider, model).expect("default models are in the capabilities table");
+            let info = ModelInfo {
+                default_model: model.to_string(),
+                context_window: known.max_context_tokens,
+                max_output_tokens: known.max_output_tokens,
+                supports_streaming: true,
+                supports_vision: known.supports_vision,
+                input_price_per_mtok: known.input_price_per_mtok,
+                output_price_per_mtok: known.output_price_per_mtok,
+            };
+            (provider.to_string(), info)
+        })
+        .collect();
+        Self { providers }
+    }
+
+    /// The built-in catalog with the overrides from a TOML document applied
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        let overrides: HashMap<String, ModelOverride> = toml::from_str(contents)?;
+        let mut catalog = Self::builtin();
+        for (provider, values) in overrides {
+            catalog.apply(provider, values);
+        }
+        Ok(catalog)
+    }
+
+    /// Read a catalog file, see `from_toml`
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(Self::from_toml(&contents)?)
+    }
+
+    pub fn get(&self, provider: &str) -> Option<&ModelInfo> {
+        self.providers.get(provider)
+    }
+
+    /// What `model` of `provider` can do, or its default model for `None`. The default model's
+    /// entry comes from the catalog, so file overrides apply; other models are looked up with
+    /// `model_capabilities`.
+    pub fn capabilities(&self, provider: &str, model: Option<&str>) -> Option<ModelCapabilities> {
+        match (model, self.get(provider)) {
+            (Some(model), Some(info)) if model != info.default_model => model_capabilities(provider, model),
+            (_, Some(info)) => Some(info.capabilities()),
+            (Some(model), None) => model_capabilities(provider, model),
+            (None, None) => None,
+        }
+    }
+
+    /// Default model of a provider
+    pub fn default_model(&self, provider: &str) -> Option<&str> {
+        self.get(provider).map(|info| info.default_model.as_str())
+    }
+
+    /// The provider's default model followed by the other well-known models of the provider
+    pub fn known_models(&self, provider: &str) -> Vec<&str> {
+        let mut models: Vec<&str> = self.default_model(provider).into_iter().collect();
+        let known = KNOWN_MODELS
+            .iter()
+            .find(|(name, _)| *name == provider)
+            .map_or(&[][..], |(_, models)| *models);
+        for model in known {
+            if !models.contains(model) {
+                models.push(model);
+            }
+        }
+        models
+    }
+
+    fn apply(&mut self, provider: String, values: ModelOverride) {
+        let info = self.providers.entry(provider).or_insert_with(|| ModelInfo {
+            default_model: String::new(),
+            context_window: 0,
+            max_output_tokens: 0,
+            supports_streaming: false,
+            supports_vision: false,
+            input_price_per_mtok: 0.0,
+            output_price_per_mtok: 0.0,
+        });
+        if let Some(model) = values.default_model {
+            info.default_model = model;
+        }
+        if let Some(context_window) = values.context_window {
+            info.context_window = context_window;
+        }
+        if let Some(max_output_tokens) = values.max_output_tokens {
+            info.max_output_tokens = max_output_tokens;
+        }
+        if let Some(streaming) = values.supports_streaming {
+            info.supports_streaming = streaming;
+        }
+        if let Some(vision) = values.supports_vision {
+            info.supports_vision = vision;
+        }
+        if let Some(price) = values.input_price_per_mtok {
+            info.input_price_per_mtok = price;
+        }
+        if let Some(price) = values.output_price_per_mtok {
+            info.output_price_per_mtok = price;
+        }
+    }
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_keep_other_fields() {
+        let catalog = ModelCatalog::from_toml("[openai]\ndefault_model = \"gpt-4o-mini\"\n").unwrap();
+        let openai = catalog.get("openai").unwrap();
+        assert_eq!(openai.default_model, "gpt-4o-mini");
+        assert_eq!(openai.context_window, 128_000);
+        assert_eq!(catalog.get("claude"), ModelCatalog::builtin().get("claude"));
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        assert!(ModelCatalog::from_toml("[openai]\nmodel = \"gpt-4o-mini\"\n").is_err());
+    }
+
+    #[test]
+    fn test_known_models() {
+        let catalog = ModelCatalog::from_toml("[claude]\ndefault_model = \"claude-3-5-haiku-20241022\"\n").unwrap();
+        let models = catalog.known_models("claude");
+        assert_eq!(models[0], "claude-3-5-haiku-20241022");
+        assert_eq!(models.iter().filter(|m| **m == "claude-3-5-haiku-20241022").count(), 1);
+        assert!(models.contains(&"claude-3-5-sonnet-20241022"));
+        assert!(catalog.known_models("mistral").is_empty());
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let catalog = ModelCatalog::from_toml("[openai]\ninput_price_per_mtok = 5.0\n").unwrap();
+        // The default model keeps the catalog's overridden price
+        assert_eq!(catalog.capabilities("openai", None).unwrap().cost_per_1k_input_tokens, 0.005);
+        assert_eq!(catalog.capabilities("openai", Some("gpt-4o")).unwrap().cost_per_1k_input_tokens, 0.005);
+        assert_eq!(catalog.capabilities("openai", Some("gpt-4o-mini")).unwrap().cost_per_1k_input_tokens, 0.00015);
+        assert_eq!(catalog.capabilities("openai", Some("unknown-model")), None);
+        assert_eq!(catalog.capabilities("mistral", None), None);
+
+        // Without overrides the default model is priced like any other
+        let builtin = ModelCatalog::builtin();
+        assert_eq!(builtin.capabilities("claude", None), model_capabilities("claude", "claude-3-5-sonnet-20241022"));
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let claude = ModelCatalog::builtin().get("claude").cloned().unwrap();
+        assert!((claude.estimate_cost(1_000_000, 1_000_000) - 18.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,96 @@
+//! Shared HTTP connections
+//!
+//! Clients built from the same `ClientConfig`, or a clone of it, take their `reqwest::Client`
+//! from its `ConnectionPool`, so their requests reuse open connections instead of every
+//! client setting up its own. The timeout, `User-Agent`, proxy and certificate pins are fixed
+//! when a `reqwest::Client` is built, so clients that differ in those get one each.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::ProxyConfig;
+#[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+use crate::{build_http_client, ClientConfig};
+
+/// Idle connections kept open to each host
+pub const POOL_MAX_IDLE_PER_HOST: usize = 5;
+
+/// TCP keepalive probes on pooled connections, so an idle one isn't dropped between prompts
+pub const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// What a `reqwest::Client` is built with: timeout, `User-Agent`, proxy and certificate pins
+type ClientKey = (Duration, String, Option<ProxyConfig>, TlsKey);
+
+#[cfg(feature = "tls-pinning")]
+type TlsKey = (Vec<String>, bool);
+#[cfg(not(feature = "tls-pinning"))]
+type TlsKey = ();
+
+#[cfg(all(feature = "tls-pinning", any(feature = "openai", feature = "gemini", feature = "claude")))]
+fn tls_key(config: &ClientConfig) -> TlsKey {
+    (config.tls_cert_pins.clone(), config.tls_strict)
+}
+
+#[cfg(all(not(feature = "tls-pinning"), any(feature = "openai", feature = "gemini", feature = "claude")))]
+fn tls_key(_config: &ClientConfig) -> TlsKey {}
+
+/// `reqwest::Client`s by timeout, `User-Agent`, proxy and pins, shared by every clone
+#[derive(Clone, Default)]
+pub struct ConnectionPool(Arc<Mutex<HashMap<ClientKey, reqwest::Client>>>);
+
+impl ConnectionPool {
+    /// The pool's `reqwest::Client` for `config`'s timeout, `User-Agent`, proxy and pins,
+    /// built on first use
+    #[cfg(any(feature = "openai", feature = "gemini", feature = "claude"))]
+    pub(crate) fn client(&self, config: &ClientConfig) -> Result<reqwest::Client, reqwest::Error> {
+        let key = (config.timeout, config.user_agent.clone(), config.proxy.clone(), tls_key(config));
+        let mut clients = self.0.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = build_http_client(config)?;
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    /// Number of distinct `reqwest::Client`s built so far
+    pub fn client_count(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ConnectionPool({} clients)", self.client_count())
+    }
+}
+
+// Builds a client for every provider, so it needs the default features
+#[cfg(all(test, feature = "openai", feature = "gemini", feature = "claude", not(feature = "wasm")))]
+mod tests {
+    use super::*;
+    use crate::{create_client, ClientConfigBuilder};
+
+    #[test]
+    fn test_clients_share_the_pool() {
+        let config = ClientConfig::default();
+        create_client("openai", "key", "gpt-4o", config.clone()).unwrap();
+        create_client("gemini", "key", "gemini-1.5-pro", config.clone()).unwrap();
+        create_client("claude", "key", "claude-3-5-sonnet-20241022", config.clone()).unwrap();
+        assert_eq!(config.connection_pool.client_count(), 1);
+
+        // A longer timeout needs a client of its own, still in the same pool
+        let mut slow = config.clone();
+        slow.timeout = Duration::from_secs(300);
+        create_client("claude", "key", "claude-3-5-sonnet-20241022", slow).unwrap();
+        assert_eq!(config.connection_pool.client_count(), 2);
+
+        // A config built separately has its own pool
+        let other = ClientConfigBuilder::default().build();
+        create_client("openai", "key", "gpt-4o", other.clone()).unwrap();
+        assert_eq!(other.connection_pool.client_count(), 1);
+        assert_eq!(config.connection_pool.client_count(), 2);
+    }
+}
@@ -0,0 +1,173 @@
+//! TLS certificate pinning (`tls-pinning` feature)
+//!
+//! A pin is the SHA-256 hash of a server certificate's DER encoding, in hex, e.g. as printed by
+//! `openssl x509 -in cert.pem -outform der | sha256sum`. The certificate chain is still checked
+//! against the usual web roots; a pin narrows which of the certificates that pass are accepted,
+//! it never lets through one that fails. With `ClientConfig::tls_strict` a server whose
+//! certificate matches no pin is rejected, otherwise the mismatch is only logged.
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, OwnedTrustAnchor, RootCertStore, ServerName};
+use sha2::{Digest, Sha256};
+
+use crate::ClientConfig;
+
+/// Hex SHA-256 of a DER-encoded certificate, the form pins are written in
+pub fn certificate_pin(der: &[u8]) -> String {
+    Sha256::digest(der).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Lowercase hex without separators, so `AB:CD:…` as shown by browsers matches too
+fn normalize_pin(pin: &str) -> String {
+    pin.chars().filter(|c| *c != ':').flat_map(char::to_lowercase).collect()
+}
+
+/// Checks the server certificate against the pins after the standard chain validation
+pub struct PinnedCertVerifier {
+    pins: Vec<String>,
+    strict: bool,
+    webpki: WebPkiVerifier,
+}
+
+impl PinnedCertVerifier {
+    pub fn new(pins: &[String], strict: bool) -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+        }));
+        Self {
+            pins: pins.iter().map(|pin| normalize_pin(pin)).collect(),
+            strict,
+            webpki: WebPkiVerifier::new(roots, None),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let pin = certificate_pin(&end_entity.0);
+        if !self.pins.contains(&pin) {
+            if self.strict {
+                return Err(rustls::Error::General(format!("server certificate {} matches no pinned hash", pin)));
+            }
+            tracing::warn!(certificate = %pin, "server certificate matches no pinned hash");
+        }
+        self.webpki.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)
+    }
+}
+
+/// rustls settings checking `config.tls_cert_pins`, for `reqwest::ClientBuilder::use_preconfigured_tls`
+pub(crate) fn pinned_tls_config(config: &ClientConfig) -> rustls::ClientConfig {
+    let mut tls = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::new(&config.tls_cert_pins, config.tls_strict)))
+        .with_no_client_auth();
+    // reqwest leaves ALPN to a preconfigured TLS backend
+    tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    tls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verify(verifier: &PinnedCertVerifier, der: &[u8]) -> Result<ServerCertVerified, rustls::Error> {
+        verifier.verify_server_cert(
+            &Certificate(der.to_vec()),
+            &[],
+            &ServerName::try_from("localhost").unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            SystemTime::now(),
+        )
+    }
+
+    #[test]
+    fn test_self_signed_certificate_is_rejected() {
+        let der = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .unwrap()
+            .serialize_der()
+            .unwrap();
+        let other_pin = "00".repeat(32);
+
+        // Strict pinning turns away a certificate with no matching pin before anything else
+        let strict = PinnedCertVerifier::new(std::slice::from_ref(&other_pin), true);
+        let error = verify(&strict, &der).unwrap_err().to_string();
+        assert!(error.contains("matches no pinned hash"), "{}", error);
+
+        // Pinning it doesn't make an untrusted chain acceptable, nor does a lenient mismatch
+        let pinned = PinnedCertVerifier::new(&[certificate_pin(&der).to_uppercase()], true);
+        assert!(verify(&pinned, &der).is_err());
+        let lenient = PinnedCertVerifier::new(&[other_pin], false);
+        assert!(verify(&lenient, &der).is_err());
+    }
+
+    /// Whether any error in `error`'s source chain mentions `text`
+    fn chain_mentions(error: &(dyn std::error::Error + 'static), text: &str) -> bool {
+        let mut source = Some(error);
+        while let Some(error) = source {
+            if error.to_string().contains(text) {
+                return true;
+            }
+            source = error.source();
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_pinned_client_refuses_server() {
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = certificate.serialize_der().unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![Certificate(der.clone())], rustls::PrivateKey(certificate.serialize_private_key_der()))
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://localhost:{}/", listener.local_addr().unwrap().port());
+        let server_config = Arc::new(server_config);
+        std::thread::spawn(move || {
+            for socket in listener.incoming().take(2).flatten() {
+                let connection = rustls::ServerConnection::new(server_config.clone()).unwrap();
+                let mut stream = rustls::StreamOwned::new(connection, socket);
+                // The client hangs up during the handshake
+                let _ = stream.conn.complete_io(&mut stream.sock);
+            }
+        });
+
+        let config = crate::ClientConfigBuilder::default()
+            .tls_cert_pins(vec!["00".repeat(32)], true)
+            .build();
+        let client = crate::build_http_client(&config).unwrap();
+        let error = client.get(&url).send().await.unwrap_err();
+        assert!(chain_mentions(&error, "matches no pinned hash"), "{:?}", error);
+
+        // Pinning the server's own certificate still leaves its chain untrusted
+        let config = crate::ClientConfigBuilder::default()
+            .tls_cert_pins(vec![certificate_pin(&der)], true)
+            .build();
+        let client = crate::build_http_client(&config).unwrap();
+        let error = client.get(&url).send().await.unwrap_err();
+        assert!(error.is_connect(), "{:?}", error);
+        assert!(!chain_mentions(&error, "matches no pinned hash"), "{:?}", error);
+    }
+
+    #[test]
+    fn test_pin_format() {
+        let pin = certificate_pin(b"certificate");
+        assert_eq!(pin.len(), 64);
+        let with_colons: Vec<String> = pin.as_bytes().chunks(2).map(|pair| String::from_utf8_lossy(pair).to_uppercase()).collect();
+        assert_eq!(normalize_pin(&with_colons.join(":")), pin);
+    }
+}
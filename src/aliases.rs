@@ -0,0 +1,127 @@
+//! Short names for providers in slash command arguments
+//!
+//! `gpt`, `openai` and `chatgpt` all mean the ChatGPT column. Users can add their own names in
+//! `~/.chatdelta/aliases.toml`, which extends the built-in ones:
+//!
+//! ```toml
+//! "4o" = "ChatGPT"
+//! sonnet = "Claude"
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Column names aliases can point at
+pub const PROVIDER_NAMES: [&str; 3] = ["ChatGPT", "Gemini", "Claude"];
+
+/// Aliases every session knows, before the user's file is applied
+const BUILTIN_ALIASES: [(&str, &str); 7] = [
+    ("gpt", "ChatGPT"),
+    ("openai", "ChatGPT"),
+    ("gpt4", "ChatGPT"),
+    ("gemini", "Gemini"),
+    ("bard", "Gemini"),
+    ("claude", "Claude"),
+    ("anthropic", "Claude"),
+];
+
+/// Lowercase alias to column name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderAliases {
+    aliases: HashMap<String, &'static str>,
+}
+
+impl ProviderAliases {
+    pub fn builtin() -> Self {
+        Self {
+            aliases: BUILTIN_ALIASES
+                .iter()
+                .map(|&(alias, name)| (alias.to_string(), name))
+                .collect(),
+        }
+    }
+
+    /// Built-in aliases extended with `~/.chatdelta/aliases.toml`; a missing file adds nothing,
+    /// an invalid one adds a warning to `warnings`
+    pub fn load(warnings: &mut Vec<String>) -> Self {
+        let Some(path) = Self::config_path() else {
+            return Self::builtin();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Self::builtin();
+        };
+
+        match Self::from_toml(&contents) {
+            Ok(aliases) => aliases,
+            Err(e) => {
+                warnings.push(format!("Ignoring invalid alias file {}: {}", path.display(), e));
+                Self::builtin()
+            }
+        }
+    }
+
+    /// Built-in aliases extended with a table of `alias = "Column name"` entries. User aliases
+    /// replace built-in ones of the same name.
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        let table: HashMap<String, String> = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let mut aliases = Self::builtin();
+        for (alias, target) in table {
+            let name = Self::column_name(&target)
+                .ok_or_else(|| format!("'{}' points at unknown provider '{}'", alias, target))?;
+            aliases.aliases.insert(alias.to_lowercase(), name);
+        }
+        Ok(aliases)
+    }
+
+    /// The column name for `name`, which may be an alias or the column name itself, in any case
+    pub fn resolve(&self, name: &str) -> Option<&'static str> {
+        let name = name.trim();
+        self.aliases
+            .get(&name.to_lowercase())
+            .copied()
+            .or_else(|| Self::column_name(name))
+    }
+
+    fn column_name(name: &str) -> Option<&'static str> {
+        PROVIDER_NAMES.into_iter().find(|column| column.eq_ignore_ascii_case(name))
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".chatdelta").join("aliases.toml"))
+    }
+}
+
+impl Default for ProviderAliases {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_aliases() {
+        let aliases = ProviderAliases::builtin();
+        assert_eq!(aliases.resolve("gpt"), Some("ChatGPT"));
+        assert_eq!(aliases.resolve("OpenAI"), Some("ChatGPT"));
+        assert_eq!(aliases.resolve("chatgpt"), Some("ChatGPT"));
+        assert_eq!(aliases.resolve("bard"), Some("Gemini"));
+        assert_eq!(aliases.resolve(" Anthropic "), Some("Claude"));
+        assert_eq!(aliases.resolve("llama"), None);
+    }
+
+    #[test]
+    fn test_user_aliases() {
+        let aliases = ProviderAliases::from_toml("\"4o\" = \"ChatGPT\"\nSonnet = \"claude\"\nbard = \"Claude\"\n").unwrap();
+        assert_eq!(aliases.resolve("4o"), Some("ChatGPT"));
+        assert_eq!(aliases.resolve("sonnet"), Some("Claude"));
+        assert_eq!(aliases.resolve("bard"), Some("Claude"));
+        assert_eq!(aliases.resolve("gpt"), Some("ChatGPT"));
+
+        assert!(ProviderAliases::from_toml("llama = \"Meta\"").is_err());
+        assert!(ProviderAliases::from_toml("gpt = 4").is_err());
+    }
+}
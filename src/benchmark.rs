@@ -0,0 +1,189 @@
+//! Benchmark mode: send one prompt to every configured provider several times and
+//! summarize the latencies
+//!
+//! Used by `chatdelta --benchmark "<prompt>" --n 20` to compare providers from the
+//! user's own network.
+
+use crate::aliases::PROVIDER_NAMES;
+use crate::progress::{CliProgressReporter, ProgressReporter};
+use crate::tui::AppState;
+use chatdelta::{ClientConfig, PromptOptions};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+/// Latencies of one provider's successful runs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchmarkResult {
+    pub provider: String,
+    pub latencies_ms: Vec<u64>,
+    /// Runs that returned an error; they have no latency
+    pub errors: usize,
+}
+
+impl BenchmarkResult {
+    pub fn mean(&self) -> Option<f64> {
+        if self.latencies_ms.is_empty() {
+            return None;
+        }
+        Some(self.latencies_ms.iter().sum::<u64>() as f64 / self.latencies_ms.len() as f64)
+    }
+
+    /// Middle latency, averaging the two middle ones for an even count
+    pub fn median(&self) -> Option<f64> {
+        let sorted = self.sorted();
+        let mid = sorted.len() / 2;
+        match sorted.len() {
+            0 => None,
+            len if len % 2 == 1 => Some(sorted[mid] as f64),
+            _ => Some((sorted[mid - 1] + sorted[mid]) as f64 / 2.0),
+        }
+    }
+
+    /// Nearest-rank percentile, `p` from 0 to 100
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        let sorted = self.sorted();
+        if sorted.is_empty() {
+            return None;
+        }
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    fn sorted(&self) -> Vec<u64> {
+        let mut sorted = self.latencies_ms.clone();
+        sorted.sort_unstable();
+        sorted
+    }
+}
+
+/// Send `prompt` to every provider with an API key `runs` times, one request at a time per
+/// provider, and print a latency table
+pub async fn run_benchmark(prompt: &str, runs: u32, client_config: ClientConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let mut warnings = Vec::new();
+    let configs = AppState::load_provider_configs(client_config, &mut warnings);
+    let models = AppState::load_model_catalog(&mut warnings);
+    for warning in &warnings {
+        eprintln!("⚠️  {}", warning);
+    }
+    let mut benchmarks = JoinSet::new();
+    // One message per finished run, for the progress bar
+    let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+    for (name, _, client) in AppState::keyed_provider_clients(&configs, &models) {
+        let prompt = prompt.to_string();
+        let progress_tx = progress_tx.clone();
+        benchmarks.spawn(async move {
+            let mut result = BenchmarkResult {
+                provider: name.to_string(),
+                ..Default::default()
+            };
+            for _ in 0..runs {
+                let started = Instant::now();
+                match client.send_prompt_detailed(&prompt, &PromptOptions::default()).await {
+                    Ok(_) => result.latencies_ms.push(started.elapsed().as_millis() as u64),
+                    Err(e) => {
+                        eprintln!("⚠️  {} request failed: {}", name, e);
+                        result.errors += 1;
+                    }
+                }
+                let _ = progress_tx.send(());
+            }
+            result
+        });
+    }
+
+    if benchmarks.is_empty() {
+        return Err("No provider API keys set (CHATGPT_API_KEY, GEMINI_API_KEY, CLAUDE_API_KEY)".into());
+    }
+
+    // The channel closes once every task has finished
+    drop(progress_tx);
+    let total = benchmarks.len() * runs as usize;
+    let mut reporter = CliProgressReporter::new();
+    reporter.update(0, total);
+    let mut done = 0;
+    while progress_rx.recv().await.is_some() {
+        done += 1;
+        reporter.update(done, total);
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = benchmarks.join_next().await {
+        results.push(result?);
+    }
+    results.sort_by_key(|result| PROVIDER_NAMES.iter().position(|name| *name == result.provider));
+    print!("{}", format_summary(&results));
+    Ok(())
+}
+
+/// Table with one row per provider: runs, errors, mean, median, p95 and p99 latency in ms
+pub fn format_summary(results: &[BenchmarkResult]) -> String {
+    let ms = |value: Option<f64>| value.map(|v| format!("{:.0}", v)).unwrap_or_else(|| "-".to_string());
+    let mut output = format!(
+        "{:<10} {:>5} {:>7} {:>10} {:>12} {:>9} {:>9}\n",
+        "Provider", "Runs", "Errors", "Mean (ms)", "Median (ms)", "p95 (ms)", "p99 (ms)"
+    );
+    for result in results {
+        output.push_str(&format!(
+            "{:<10} {:>5} {:>7} {:>10} {:>12} {:>9} {:>9}\n",
+            result.provider,
+            result.latencies_ms.len() + result.errors,
+            result.errors,
+            ms(result.mean()),
+            ms(result.median()),
+            ms(result.percentile(95.0).map(|v| v as f64)),
+            ms(result.percentile(99.0).map(|v| v as f64)),
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(latencies_ms: Vec<u64>) -> BenchmarkResult {
+        BenchmarkResult {
+            provider: "Claude".to_string(),
+            latencies_ms,
+            errors: 0,
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let result = result((1..=100).collect());
+        assert_eq!(result.mean(), Some(50.5));
+        assert_eq!(result.median(), Some(50.5));
+        assert_eq!(result.percentile(95.0), Some(95));
+        assert_eq!(result.percentile(99.0), Some(99));
+        assert_eq!(result.percentile(0.0), Some(1));
+        assert_eq!(result.percentile(100.0), Some(100));
+    }
+
+    #[test]
+    fn test_statistics_unsorted_and_empty() {
+        let result = result(vec![300, 100, 200]);
+        assert_eq!(result.median(), Some(200.0));
+        assert_eq!(result.percentile(50.0), Some(200));
+
+        let empty = BenchmarkResult::default();
+        assert_eq!(empty.mean(), None);
+        assert_eq!(empty.median(), None);
+        assert_eq!(empty.percentile(95.0), None);
+    }
+
+    #[test]
+    fn test_format_summary() {
+        let mut failing = result(Vec::new());
+        failing.provider = "Gemini".to_string();
+        failing.errors = 2;
+        let summary = format_summary(&[result(vec![100, 200]), failing]);
+
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Provider"));
+        assert_eq!(lines[1].split_whitespace().collect::<Vec<_>>(), ["Claude", "2", "0", "150", "150", "200", "200"]);
+        assert_eq!(lines[2].split_whitespace().collect::<Vec<_>>(), ["Gemini", "2", "2", "-", "-", "-", "-"]);
+    }
+}
@@ -1,12 +1,62 @@
 //! Command-line interface for ChatDelta
 
-use clap::Parser;
+use chatdelta::models::ModelCatalog;
+use chatdelta::{GeminiSafetySettings, CLAUDE_ENDPOINT, GEMINI_ENDPOINT_TEMPLATE, OPENAI_ENDPOINT};
+use crate::keys::Keymap;
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// How `--headless` prints the finished exchange
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Pretty-printed `ConversationEntry`
+    #[default]
+    Json,
+    /// Human-readable document with one section per provider
+    Markdown,
+    /// One `PROVIDER: response` line per provider
+    Plain,
+    /// `provider,latency_ms,response` rows
+    Csv,
+}
+
+/// Subcommands that work on saved session logs instead of querying providers
+#[derive(Subcommand, Clone, Debug, PartialEq, Eq)]
+pub enum CliCommand {
+    /// Write a session log's prompts and responses as JSON Lines, e.g. for fine-tuning
+    Convert {
+        /// Session log written by chatdelta
+        #[arg(long)]
+        input: PathBuf,
+        /// JSON Lines file to write, one `{"prompt", "responses"}` object per prompt
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+/// `--version` output: the version followed by the provider endpoints, for debugging
+/// connectivity
+pub fn long_version() -> &'static str {
+    static LONG_VERSION: OnceLock<String> = OnceLock::new();
+    LONG_VERSION.get_or_init(|| {
+        format!(
+            "{}\n\nEndpoints:\n  openai: {}\n  gemini: {}\n  claude: {}",
+            env!("CARGO_PKG_VERSION"),
+            OPENAI_ENDPOINT,
+            GEMINI_ENDPOINT_TEMPLATE,
+            CLAUDE_ENDPOINT
+        )
+    })
+}
 
 /// Command line arguments for chatdelta
 #[derive(Parser, Debug)]
-#[command(version, about = "Query multiple AIs and connect their responses")]
+#[command(version, long_version = long_version(), about = "Query multiple AIs and connect their responses")]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+
     /// Prompt to send to the AIs
     pub prompt: Option<String>,
 
@@ -58,9 +108,9 @@ pub struct Args {
     #[arg(long, default_value = "claude-3-5-sonnet-20241022")]
     pub claude_model: String,
 
-    /// Maximum tokens for Claude responses
-    #[arg(long, default_value = "1024")]
-    pub max_tokens: u32,
+    /// Longest response to ask each provider for, in tokens [default: 1000; Gemini: the model's limit]
+    #[arg(long)]
+    pub max_tokens: Option<u32>,
 
     /// Temperature for AI responses (0.0-2.0)
     #[arg(long)]
@@ -73,6 +123,102 @@ pub struct Args {
     /// Test API connections and exit
     #[arg(long)]
     pub test: bool,
+
+    /// Send the prompt once and print the results instead of opening the TUI
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Answer prompts read from stdin, one per line, with `PROVIDER: response` lines on stdout.
+    /// With a prompt argument, stdin is sent once after it instead. Implied when stdin is not a
+    /// terminal.
+    #[arg(long)]
+    pub pipe: bool,
+
+    /// Output format for --headless mode
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub output_format: OutputFormat,
+
+    /// Transcribe an audio file (WAV, OGG, MP3, ...) and print the text, or use it as the
+    /// prompt with --headless. Requires CHATGPT_API_KEY.
+    #[arg(long, value_name = "FILE")]
+    pub transcribe: Option<PathBuf>,
+
+    /// Image to send with the prompt to multimodal models (repeatable). With --headless it goes
+    /// with the prompt argument, with --pipe with every prompt; in the TUI, with the first
+    /// prompt sent.
+    #[arg(long = "image", value_name = "FILE")]
+    pub images: Vec<PathBuf>,
+
+    /// Sampling seed for reproducible --headless and --pipe runs (OpenAI and Gemini)
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Built-in TUI color theme: dark, light, monochrome (defaults to ~/.chatdelta/theme.toml)
+    #[arg(long)]
+    pub theme: Option<String>,
+
+    /// Start the TUI with delta analysis turned off (F4 turns it on)
+    #[arg(long)]
+    pub no_delta: bool,
+
+    /// Provider that writes the TUI's differences summary: chatgpt, gemini, claude (or an
+    /// alias), or auto to pick one whose response isn't being compared
+    #[arg(long, value_name = "PROVIDER")]
+    pub judge: Option<String>,
+
+    /// Leave the mouse to the terminal, keeping its own text selection, instead of using it
+    /// to select columns and scroll
+    #[arg(long)]
+    pub no_mouse: bool,
+
+    /// TUI keys: default, or vim for h/j/k/l navigation with normal and insert modes
+    /// (defaults to ~/.chatdelta/config.toml)
+    #[arg(long, value_enum)]
+    pub keymap: Option<Keymap>,
+
+    /// Send this prompt to every provider --n times and print latency statistics
+    #[arg(long, value_name = "PROMPT")]
+    pub benchmark: Option<String>,
+
+    /// Number of runs per provider for --benchmark
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub n: u32,
+
+    /// Gemini content filter threshold for one category, e.g. `harassment=block_only_high`
+    /// (repeatable; unset categories keep Gemini's defaults)
+    #[arg(long = "safety-threshold", value_name = "CATEGORY=THRESHOLD")]
+    pub safety_thresholds: Vec<GeminiSafetySettings>,
+
+    /// Send every request through this HTTP or SOCKS5 proxy, e.g. http://proxy.corp:3128
+    /// (defaults to HTTPS_PROXY)
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// User for the proxy's basic auth
+    #[arg(long, value_name = "USER", requires = "proxy_pass")]
+    pub proxy_user: Option<String>,
+
+    /// Password for the proxy's basic auth
+    #[arg(long, value_name = "PASSWORD", requires = "proxy_user")]
+    pub proxy_pass: Option<String>,
+
+    /// Check every provider's API key and model without sending a prompt, print the results
+    /// and exit with 1 if any check failed
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print statistics for a saved session log and exit
+    #[arg(long, value_name = "FILE")]
+    pub stats: Option<PathBuf>,
+
+    /// Only use the exchanges starred with Alt+S from a saved session log (`convert`, `--stats`)
+    #[arg(long, global = true)]
+    pub starred_only: bool,
+
+    /// POST each finished delta analysis to this URL as JSON (default: CHATDELTA_WEBHOOK_URL);
+    /// signed when CHATDELTA_WEBHOOK_SECRET is set
+    #[arg(long, value_name = "URL")]
+    pub webhook: Option<String>,
 }
 
 impl Args {
@@ -123,6 +269,29 @@ impl Args {
             return Err("Timeout must be greater than 0".to_string());
         }
 
+        self.validate_max_tokens(&ModelCatalog::builtin())
+    }
+
+    /// Check `--max-tokens` against the longest response the default model of each provider
+    /// in use can write. Models without a known limit aren't checked.
+    pub fn validate_max_tokens(&self, models: &ModelCatalog) -> Result<(), String> {
+        let Some(max_tokens) = self.max_tokens else {
+            return Ok(());
+        };
+        for (ai, provider) in [("gpt", "openai"), ("gemini", "gemini"), ("claude", "claude")] {
+            if !self.should_use_ai(ai) {
+                continue;
+            }
+            let (Some(model), Some(capabilities)) = (models.default_model(provider), models.capabilities(provider, None)) else {
+                continue;
+            };
+            if capabilities.max_output_tokens > 0 && max_tokens > capabilities.max_output_tokens {
+                return Err(format!(
+                    "--max-tokens {} is more than {} can write ({} tokens)",
+                    max_tokens, model, capabilities.max_output_tokens
+                ));
+            }
+        }
         Ok(())
     }
 
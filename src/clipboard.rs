@@ -0,0 +1,70 @@
+//! Copying responses to the system clipboard (Alt+C in the TUI)
+//!
+//! Text goes to the desktop clipboard through `arboard`. Over SSH, or where no clipboard can
+//! be reached, it is sent to the terminal as an OSC 52 escape sequence instead, which most
+//! terminal emulators put on the clipboard of the machine they run on.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io::{self, Write};
+
+/// How copied text reached the clipboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardMethod {
+    System,
+    Osc52,
+}
+
+/// The desktop clipboard, opened on first use. It stays open afterwards because on X11 the
+/// copied text is only available while its owner is alive.
+#[derive(Default)]
+pub struct Clipboard {
+    system: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put `text` on the clipboard, falling back to OSC 52 over SSH or when the desktop
+    /// clipboard fails
+    pub fn copy(&mut self, text: &str) -> io::Result<ClipboardMethod> {
+        if !is_ssh_session() && self.copy_to_system(text) {
+            return Ok(ClipboardMethod::System);
+        }
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(osc52_sequence(text).as_bytes())?;
+        stdout.flush()?;
+        Ok(ClipboardMethod::Osc52)
+    }
+
+    fn copy_to_system(&mut self, text: &str) -> bool {
+        if self.system.is_none() {
+            self.system = arboard::Clipboard::new().ok();
+        }
+        self.system
+            .as_mut()
+            .is_some_and(|clipboard| clipboard.set_text(text).is_ok())
+    }
+}
+
+/// Escape sequence asking the terminal to set its clipboard to `text`
+pub fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+fn is_ssh_session() -> bool {
+    std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("Hello"), "\x1b]52;c;SGVsbG8=\x07");
+        assert_eq!(osc52_sequence(""), "\x1b]52;c;\x07");
+    }
+}
@@ -0,0 +1,247 @@
+//! Slash commands typed into the shared input box
+//!
+//! Input starting with `/` is parsed into a `Command` instead of being sent to the providers.
+
+use std::path::PathBuf;
+
+/// Largest number of samples `/samples` will request from each provider
+pub const MAX_SAMPLES: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `/pin <text>` - annotate the session with a note shown in the delta field header
+    Pin(String),
+    /// `/unpin` - remove the pinned note
+    Unpin,
+    /// `/samples <n>` - request `n` variants from each provider for the following prompts
+    Samples(u32),
+    /// `/context <text>` - prepend shared context to every following prompt
+    SetContext(String),
+    /// `/context load <file>` - use a file's contents as the shared context
+    LoadContext(PathBuf),
+    /// `/context clear` - stop prepending shared context
+    ClearContext,
+    /// `/attach <image>` - send a PNG, JPEG, GIF or WebP image with the next prompt
+    Attach(PathBuf),
+    /// `/seed <n>` or `/seed off` - sampling seed for reproducible responses
+    Seed(Option<u64>),
+    /// `/tag a,b` - label the latest exchange
+    Tag(Vec<String>),
+    /// `/browse --tag <name>` - list this session's exchanges carrying a tag
+    Browse { tag: String },
+    /// `/browse --starred-only` - list this session's exchanges starred with Alt+S
+    BrowseStarred,
+    /// `/similarity <0-1>` or `/similarity off` - skip the delta summary when all responses
+    /// embed at least this close to each other
+    Similarity(Option<f32>),
+    /// `/preset list` - show the presets from `~/.chatdelta/presets.toml`
+    PresetList,
+    /// `/preset <name>` loads a preset into the input box; `/preset send <name>` also sends it
+    Preset { name: String, send: bool },
+    /// `/only <prompt>` - send a prompt to the selected column alone, like Ctrl+Enter
+    Only(String),
+    /// `/reset-budget` - allow the session's full retry budget again
+    ResetBudget,
+    /// `/rename <provider> <label>` shows a column under another name; without a label the
+    /// column gets its own name back
+    Rename { provider: String, label: Option<String> },
+}
+
+impl Command {
+    /// Parse a slash command. Returns `None` when the input is not a command at all.
+    pub fn parse(input: &str) -> Option<Result<Command, String>> {
+        let input = input.trim();
+        let rest = input.strip_prefix('/')?;
+        let (name, args) = match rest.split_once(char::is_whitespace) {
+            Some((name, args)) => (name, args.trim()),
+            None => (rest, ""),
+        };
+
+        let command = match name {
+            "pin" if args.is_empty() => Err("Usage: /pin <text>".to_string()),
+            "pin" => Ok(Command::Pin(args.to_string())),
+            "unpin" => Ok(Command::Unpin),
+            "context" if args.is_empty() => {
+                Err("Usage: /context <text> | /context load <file> | /context clear".to_string())
+            }
+            "context" if args == "clear" => Ok(Command::ClearContext),
+            "context" => match args.strip_prefix("load ") {
+                Some(path) => Ok(Command::LoadContext(PathBuf::from(path.trim()))),
+                None => Ok(Command::SetContext(args.to_string())),
+            },
+            "attach" if args.is_empty() => Err("Usage: /attach <image file>".to_string()),
+            "attach" => Ok(Command::Attach(PathBuf::from(args))),
+            "seed" if args == "off" => Ok(Command::Seed(None)),
+            "seed" => match args.parse::<u64>() {
+                Ok(seed) => Ok(Command::Seed(Some(seed))),
+                Err(_) => Err("Usage: /seed <number> | /seed off".to_string()),
+            },
+            "tag" => {
+                let tags: Vec<String> = args
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if tags.is_empty() {
+                    Err("Usage: /tag <tag>[,<tag>...]".to_string())
+                } else {
+                    Ok(Command::Tag(tags))
+                }
+            }
+            "only" if args.is_empty() => Err("Usage: /only <prompt>".to_string()),
+            "only" => Ok(Command::Only(args.to_string())),
+            "browse" if args == "--starred-only" => Ok(Command::BrowseStarred),
+            "browse" => match args.strip_prefix("--tag") {
+                Some(tag) if !tag.trim().is_empty() => Ok(Command::Browse { tag: tag.trim().to_string() }),
+                _ => Err("Usage: /browse --tag <name> | /browse --starred-only".to_string()),
+            },
+            "similarity" if args == "off" => Ok(Command::Similarity(None)),
+            "similarity" => match args.parse::<f32>() {
+                Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(Command::Similarity(Some(threshold))),
+                _ => Err("Usage: /similarity <0.0-1.0> | /similarity off".to_string()),
+            },
+            "preset" if args.is_empty() => Err("Usage: /preset list | /preset <name> | /preset send <name>".to_string()),
+            "preset" if args == "list" => Ok(Command::PresetList),
+            "preset" => match args.strip_prefix("send ") {
+                Some(name) => Ok(Command::Preset { name: name.trim().to_string(), send: true }),
+                None => Ok(Command::Preset { name: args.to_string(), send: false }),
+            },
+            "reset-budget" => Ok(Command::ResetBudget),
+            "rename" if args.is_empty() => Err("Usage: /rename <provider> <label> | /rename <provider>".to_string()),
+            "rename" => {
+                let (provider, label) = match args.split_once(char::is_whitespace) {
+                    Some((provider, label)) => (provider, Some(label.trim().to_string())),
+                    None => (args, None),
+                };
+                Ok(Command::Rename { provider: provider.to_string(), label })
+            }
+            "samples" => match args.parse::<u32>() {
+                Ok(n) if (1..=MAX_SAMPLES).contains(&n) => Ok(Command::Samples(n)),
+                _ => Err(format!("Usage: /samples <1-{}>", MAX_SAMPLES)),
+            },
+            _ => Err(format!("Unknown command: /{}", name)),
+        };
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pin() {
+        assert_eq!(
+            Command::parse("/pin  Rust async frameworks "),
+            Some(Ok(Command::Pin("Rust async frameworks".to_string())))
+        );
+        assert_eq!(Command::parse("/unpin"), Some(Ok(Command::Unpin)));
+        assert!(matches!(Command::parse("/pin"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_samples() {
+        assert_eq!(Command::parse("/samples 3"), Some(Ok(Command::Samples(3))));
+        assert!(matches!(Command::parse("/samples 0"), Some(Err(_))));
+        assert!(matches!(Command::parse("/samples many"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_context() {
+        assert_eq!(
+            Command::parse("/context The report says X."),
+            Some(Ok(Command::SetContext("The report says X.".to_string())))
+        );
+        assert_eq!(
+            Command::parse("/context load notes/report.txt"),
+            Some(Ok(Command::LoadContext(PathBuf::from("notes/report.txt"))))
+        );
+        assert_eq!(Command::parse("/context clear"), Some(Ok(Command::ClearContext)));
+        assert!(matches!(Command::parse("/context"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_attach() {
+        assert_eq!(
+            Command::parse("/attach screenshots/error dialog.png"),
+            Some(Ok(Command::Attach(PathBuf::from("screenshots/error dialog.png"))))
+        );
+        assert!(matches!(Command::parse("/attach"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_only() {
+        assert_eq!(
+            Command::parse("/only  And in Rust 2024?"),
+            Some(Ok(Command::Only("And in Rust 2024?".to_string())))
+        );
+        assert!(matches!(Command::parse("/only"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        assert_eq!(Command::parse("/seed 42"), Some(Ok(Command::Seed(Some(42)))));
+        assert_eq!(Command::parse("/seed off"), Some(Ok(Command::Seed(None))));
+        assert!(matches!(Command::parse("/seed"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_tag_and_browse() {
+        assert_eq!(
+            Command::parse("/tag bug, performance"),
+            Some(Ok(Command::Tag(vec!["bug".to_string(), "performance".to_string()])))
+        );
+        assert!(matches!(Command::parse("/tag ,"), Some(Err(_))));
+        assert_eq!(
+            Command::parse("/browse --tag bug"),
+            Some(Ok(Command::Browse { tag: "bug".to_string() }))
+        );
+        assert_eq!(Command::parse("/browse --starred-only"), Some(Ok(Command::BrowseStarred)));
+        assert!(matches!(Command::parse("/browse"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_similarity() {
+        assert_eq!(Command::parse("/similarity 0.97"), Some(Ok(Command::Similarity(Some(0.97)))));
+        assert_eq!(Command::parse("/similarity off"), Some(Ok(Command::Similarity(None))));
+        assert!(matches!(Command::parse("/similarity 2"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_non_command() {
+        assert_eq!(Command::parse("What is Rust?"), None);
+        assert!(matches!(Command::parse("/frobnicate"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_preset() {
+        assert_eq!(Command::parse("/preset list"), Some(Ok(Command::PresetList)));
+        assert_eq!(
+            Command::parse("/preset review"),
+            Some(Ok(Command::Preset { name: "review".to_string(), send: false }))
+        );
+        assert_eq!(
+            Command::parse("/preset send  review"),
+            Some(Ok(Command::Preset { name: "review".to_string(), send: true }))
+        );
+        assert!(matches!(Command::parse("/preset"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_parse_reset_budget() {
+        assert_eq!(Command::parse("/reset-budget"), Some(Ok(Command::ResetBudget)));
+    }
+
+    #[test]
+    fn test_parse_rename() {
+        assert_eq!(
+            Command::parse("/rename chatgpt  GPT-4o mini"),
+            Some(Ok(Command::Rename { provider: "chatgpt".to_string(), label: Some("GPT-4o mini".to_string()) }))
+        );
+        assert_eq!(
+            Command::parse("/rename gpt"),
+            Some(Ok(Command::Rename { provider: "gpt".to_string(), label: None }))
+        );
+        assert!(matches!(Command::parse("/rename"), Some(Err(_))));
+    }
+}
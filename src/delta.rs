@@ -0,0 +1,422 @@
+//! Delta strategies for comparing provider responses
+//!
+//! The default strategy asks a model to summarize the differences; `DeltaJudge` picks which
+//! one, and `local_summary` stands in when no judge can answer. `CharDiff` computes a local,
+//! color-coded diff between two responses without any API call.
+
+use crate::aliases::{ProviderAliases, PROVIDER_NAMES};
+use chatdelta::cosine_similarity;
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans, Text};
+
+/// Responses longer than this many words are diffed by sentence instead of by character
+pub const CHAR_DIFF_MAX_WORDS: usize = 500;
+
+/// Shown in the delta field while delta analysis is turned off
+pub const DELTA_DISABLED_MESSAGE: &str = "Delta disabled (F4 to enable)";
+
+/// Shown instead of a summary when the similarity pre-check finds nothing to compare
+pub const IDENTICAL_RESPONSES_MESSAGE: &str = "Responses are substantively identical";
+
+/// Lowest cosine similarity between any two embeddings, or `None` with fewer than two
+pub fn min_pairwise_similarity(embeddings: &[Vec<f32>]) -> Option<f32> {
+    let mut min: Option<f32> = None;
+    for (i, first) in embeddings.iter().enumerate() {
+        for second in &embeddings[i + 1..] {
+            let similarity = cosine_similarity(first, second);
+            min = Some(min.map_or(similarity, |m| m.min(similarity)));
+        }
+    }
+    min
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaStrategy {
+    /// LLM-generated summary of the key differences
+    Summary,
+    /// Character-level diff between the first two responses
+    CharDiff,
+    /// No delta analysis (`--no-delta`, F4)
+    Disabled,
+}
+
+impl DeltaStrategy {
+    /// The strategy selected after this one when cycling. `Disabled` is left with F4, not by cycling.
+    pub fn next(self) -> Self {
+        match self {
+            DeltaStrategy::Summary => DeltaStrategy::CharDiff,
+            DeltaStrategy::CharDiff => DeltaStrategy::Summary,
+            DeltaStrategy::Disabled => DeltaStrategy::Disabled,
+        }
+    }
+}
+
+/// Marks a differences summary computed by `local_summary` instead of a judge model
+pub const LOCAL_DIFF_LABEL: &str = "(local diff)";
+
+/// Points listed per response by `local_summary`; the rest are only counted
+const LOCAL_SUMMARY_MAX_POINTS: usize = 3;
+
+/// Quoted sentences in `local_summary` are cut to this many characters
+const LOCAL_SUMMARY_MAX_CHARS: usize = 100;
+
+/// Share of the shorter sentence's words two sentences must have in common to make the same point
+const SAME_POINT_OVERLAP: f32 = 0.6;
+
+/// A differences summary without any API call: for each response, the sentences no other
+/// response makes a similar point to, e.g. `Claude mentions "…"; ChatGPT omits it`
+pub fn local_summary(responses: &[(String, String)]) -> String {
+    let points: Vec<Vec<(&str, HashSet<String>)>> = responses
+        .iter()
+        .map(|(_, text)| {
+            split_sentences(text)
+                .into_iter()
+                .map(str::trim)
+                .map(|sentence| (sentence, content_words(sentence)))
+                .filter(|(_, words)| !words.is_empty())
+                .collect()
+        })
+        .collect();
+
+    let mut lines = Vec::new();
+    for (i, (name, _)) in responses.iter().enumerate() {
+        let others: Vec<&str> = responses
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, (other, _))| other.as_str())
+            .collect();
+        let unique: Vec<&str> = points[i]
+            .iter()
+            .filter(|(_, words)| {
+                !points
+                    .iter()
+                    .enumerate()
+                    .any(|(j, other)| j != i && other.iter().any(|(_, other_words)| same_point(words, other_words)))
+            })
+            .map(|(sentence, _)| *sentence)
+            .collect();
+
+        let omit = if others.len() == 1 { "omits" } else { "omit" };
+        for sentence in unique.iter().take(LOCAL_SUMMARY_MAX_POINTS) {
+            lines.push(format!("• {} mentions \"{}\"; {} {} it", name, shorten(sentence), others.join(" and "), omit));
+        }
+        if unique.len() > LOCAL_SUMMARY_MAX_POINTS {
+            lines.push(format!("  …and {} more only {} makes", unique.len() - LOCAL_SUMMARY_MAX_POINTS, name));
+        }
+    }
+
+    if lines.is_empty() {
+        return format!("The responses make the same points {}", LOCAL_DIFF_LABEL);
+    }
+    format!("Key differences {}:\n{}", LOCAL_DIFF_LABEL, lines.join("\n"))
+}
+
+/// Lowercase words of three letters or more, which leaves out most filler words
+fn content_words(sentence: &str) -> HashSet<String> {
+    sentence
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn same_point(first: &HashSet<String>, second: &HashSet<String>) -> bool {
+    let shared = first.intersection(second).count();
+    shared as f32 >= SAME_POINT_OVERLAP * first.len().min(second.len()) as f32
+}
+
+fn shorten(sentence: &str) -> String {
+    if sentence.chars().count() <= LOCAL_SUMMARY_MAX_CHARS {
+        return sentence.to_string();
+    }
+    let cut: String = sentence.chars().take(LOCAL_SUMMARY_MAX_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Columns tried in turn for the differences summary, when no judge is set or it has no client
+pub const JUDGE_ORDER: [&str; 3] = ["Gemini", "Claude", "ChatGPT"];
+
+/// Which provider writes the differences summary. Set with `judge = "claude"` in
+/// `~/.chatdelta/delta.toml`, with `--judge`, or by cycling with Alt+J.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeltaJudge {
+    /// The first column in `JUDGE_ORDER` whose response isn't being compared, or else the
+    /// first one available
+    #[default]
+    Auto,
+    /// This column, falling back to `Auto`'s choice while it has no client
+    Provider(&'static str),
+}
+
+#[derive(Deserialize)]
+struct DeltaConfig {
+    judge: Option<String>,
+}
+
+impl DeltaJudge {
+    /// `"auto"`, or a provider's column name or alias
+    pub fn parse(name: &str, aliases: &ProviderAliases) -> Result<Self, String> {
+        if name.trim().eq_ignore_ascii_case("auto") {
+            return Ok(DeltaJudge::Auto);
+        }
+        aliases
+            .resolve(name)
+            .map(DeltaJudge::Provider)
+            .ok_or_else(|| format!("unknown judge provider '{}'", name))
+    }
+
+    /// The judge from `~/.chatdelta/delta.toml`; `Auto` when the file is missing, or invalid,
+    /// which adds a warning to `warnings`
+    pub fn load(aliases: &ProviderAliases, warnings: &mut Vec<String>) -> Self {
+        let Some(path) = dirs::home_dir().map(|home| home.join(".chatdelta").join("delta.toml")) else {
+            return DeltaJudge::Auto;
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return DeltaJudge::Auto;
+        };
+        match Self::from_toml(&contents, aliases) {
+            Ok(judge) => judge,
+            Err(e) => {
+                warnings.push(format!("Ignoring invalid delta config {}: {}", path.display(), e));
+                DeltaJudge::Auto
+            }
+        }
+    }
+
+    pub fn from_toml(contents: &str, aliases: &ProviderAliases) -> Result<Self, String> {
+        let config: DeltaConfig = toml::from_str(contents).map_err(|e| e.to_string())?;
+        config.judge.map_or(Ok(DeltaJudge::Auto), |name| Self::parse(&name, aliases))
+    }
+
+    /// The judge selected after this one when cycling: `Auto`, then each column in order
+    pub fn next(self) -> Self {
+        let position = match self {
+            DeltaJudge::Auto => None,
+            DeltaJudge::Provider(name) => PROVIDER_NAMES.iter().position(|&column| column == name),
+        };
+        let next = position.map_or(0, |position| position + 1);
+        PROVIDER_NAMES.get(next).map_or(DeltaJudge::Auto, |&name| DeltaJudge::Provider(name))
+    }
+
+    /// The column to ask among `available` ones (those with a client), given the columns
+    /// whose responses are `compared`
+    pub fn choose(self, available: &[&'static str], compared: &[&str]) -> Option<&'static str> {
+        if let DeltaJudge::Provider(name) = self {
+            if available.contains(&name) {
+                return Some(name);
+            }
+        }
+        JUDGE_ORDER
+            .into_iter()
+            .find(|name| available.contains(name) && !compared.contains(name))
+            .or_else(|| JUDGE_ORDER.into_iter().find(|name| available.contains(name)))
+    }
+}
+
+impl fmt::Display for DeltaJudge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeltaJudge::Auto => f.write_str("auto"),
+            DeltaJudge::Provider(name) => f.write_str(name),
+        }
+    }
+}
+
+/// A diff between two providers' responses, computed once and styled at render time
+#[derive(Debug, Clone)]
+pub struct ResponseDiff {
+    /// Name of the provider whose response is the left side of the diff
+    pub first: String,
+    /// Name of the provider whose response is the right side of the diff
+    pub second: String,
+    changes: Vec<(ChangeTag, String)>,
+}
+
+impl ResponseDiff {
+    /// Diff two responses by character. Responses longer than `CHAR_DIFF_MAX_WORDS` are
+    /// diffed by sentence so the pane isn't flooded with thousands of styled spans.
+    pub fn compute(first: (&str, &str), second: (&str, &str)) -> Self {
+        let (first_name, first_text) = first;
+        let (second_name, second_text) = second;
+        let too_long = first_text.split_whitespace().count() > CHAR_DIFF_MAX_WORDS
+            || second_text.split_whitespace().count() > CHAR_DIFF_MAX_WORDS;
+
+        let changes = if too_long {
+            let first_sentences = split_sentences(first_text);
+            let second_sentences = split_sentences(second_text);
+            TextDiff::from_slices(first_sentences.as_slice(), second_sentences.as_slice())
+                .iter_all_changes()
+                .map(|change| (change.tag(), change.value().to_string()))
+                .collect()
+        } else {
+            TextDiff::from_chars(first_text, second_text)
+                .iter_all_changes()
+                .map(|change| (change.tag(), change.value().to_string()))
+                .collect()
+        };
+
+        Self {
+            first: first_name.to_string(),
+            second: second_name.to_string(),
+            changes,
+        }
+    }
+
+    /// Render the diff. Text only in the first response is drawn in `first_only`,
+    /// text only in the second in `second_only`.
+    pub fn to_text(&self, first_only: Color, second_only: Color) -> Text<'static> {
+        let style_for = |tag: ChangeTag| match tag {
+            ChangeTag::Equal => Style::default(),
+            ChangeTag::Delete => Style::default().fg(first_only),
+            ChangeTag::Insert => Style::default().fg(second_only),
+        };
+
+        let mut lines: Vec<Spans<'static>> = Vec::new();
+        let mut current: Vec<Span<'static>> = Vec::new();
+        let mut run = String::new();
+        let mut run_tag = ChangeTag::Equal;
+
+        for (tag, value) in &self.changes {
+            if *tag != run_tag && !run.is_empty() {
+                current.push(Span::styled(std::mem::take(&mut run), style_for(run_tag)));
+            }
+            run_tag = *tag;
+            for c in value.chars() {
+                if c == '\n' {
+                    if !run.is_empty() {
+                        current.push(Span::styled(std::mem::take(&mut run), style_for(run_tag)));
+                    }
+                    lines.push(Spans::from(std::mem::take(&mut current)));
+                } else {
+                    run.push(c);
+                }
+            }
+        }
+        if !run.is_empty() {
+            current.push(Span::styled(run, style_for(run_tag)));
+        }
+        if !current.is_empty() {
+            lines.push(Spans::from(current));
+        }
+
+        Text::from(lines)
+    }
+
+    /// The text `to_text` draws, without its colors
+    pub fn plain_text(&self) -> String {
+        self.changes.iter().map(|(_, value)| value.as_str()).collect()
+    }
+}
+
+/// Split text into sentences, keeping the terminating punctuation with each sentence
+fn split_sentences(text: &str) -> Vec<&str> {
+    text.split_inclusive(['.', '!', '?', '\n']).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_diff_colors_changes() {
+        let diff = ResponseDiff::compute(("ChatGPT", "cat"), ("Claude", "cut"));
+        let text = diff.to_text(Color::Green, Color::Red);
+        assert_eq!(text.lines.len(), 1);
+        let spans = &text.lines[0].0;
+        assert!(spans.iter().any(|s| s.content == "a" && s.style.fg == Some(Color::Green)));
+        assert!(spans.iter().any(|s| s.content == "u" && s.style.fg == Some(Color::Red)));
+    }
+
+    #[test]
+    fn test_long_responses_use_sentence_diff() {
+        let first = "Same sentence here. ".repeat(300);
+        let second = format!("{}Extra sentence.", first);
+        let text = ResponseDiff::compute(("ChatGPT", &first), ("Claude", &second)).to_text(Color::Green, Color::Red);
+        let inserted: Vec<_> = text.lines[0].0.iter().filter(|s| s.style.fg == Some(Color::Red)).collect();
+        assert_eq!(inserted.len(), 1);
+        assert_eq!(inserted[0].content.trim(), "Extra sentence.");
+    }
+
+    #[test]
+    fn test_min_pairwise_similarity() {
+        assert_eq!(min_pairwise_similarity(&[vec![1.0, 0.0]]), None);
+        let embeddings = vec![vec![1.0, 0.0], vec![1.0, 0.0], vec![0.0, 1.0]];
+        assert!(min_pairwise_similarity(&embeddings).unwrap().abs() < 1e-6);
+        let embeddings = vec![vec![1.0, 0.0], vec![2.0, 0.0]];
+        assert!((min_pairwise_similarity(&embeddings).unwrap() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_strategy_cycles() {
+        assert_eq!(DeltaStrategy::Summary.next(), DeltaStrategy::CharDiff);
+        assert_eq!(DeltaStrategy::CharDiff.next(), DeltaStrategy::Summary);
+        assert_eq!(DeltaStrategy::Disabled.next(), DeltaStrategy::Disabled);
+    }
+
+    #[test]
+    fn test_judge_choice() {
+        let all = ["ChatGPT", "Gemini", "Claude"];
+        // Every column is compared, so the fallback order decides
+        assert_eq!(DeltaJudge::Auto.choose(&all, &all), Some("Gemini"));
+        // Without a Gemini key, Claude compares its own response with ChatGPT's
+        assert_eq!(DeltaJudge::Auto.choose(&["ChatGPT", "Claude"], &["ChatGPT", "Claude"]), Some("Claude"));
+        // A column that isn't compared is preferred
+        assert_eq!(DeltaJudge::Auto.choose(&all, &["Gemini", "Claude"]), Some("ChatGPT"));
+        assert_eq!(DeltaJudge::Provider("ChatGPT").choose(&all, &all), Some("ChatGPT"));
+        assert_eq!(DeltaJudge::Provider("Gemini").choose(&["Claude"], &["Claude"]), Some("Claude"));
+        assert_eq!(DeltaJudge::Auto.choose(&[], &[]), None);
+    }
+
+    #[test]
+    fn test_judge_config() {
+        let aliases = ProviderAliases::builtin();
+        assert_eq!(DeltaJudge::parse("anthropic", &aliases), Ok(DeltaJudge::Provider("Claude")));
+        assert_eq!(DeltaJudge::parse("Auto", &aliases), Ok(DeltaJudge::Auto));
+        assert!(DeltaJudge::parse("llama", &aliases).is_err());
+        assert_eq!(DeltaJudge::from_toml("judge = \"gpt\"", &aliases), Ok(DeltaJudge::Provider("ChatGPT")));
+        assert_eq!(DeltaJudge::from_toml("", &aliases), Ok(DeltaJudge::Auto));
+        assert!(DeltaJudge::from_toml("judge = \"llama\"", &aliases).is_err());
+
+        let mut judge = DeltaJudge::Auto;
+        let mut cycle = Vec::new();
+        for _ in 0..4 {
+            judge = judge.next();
+            cycle.push(judge.to_string());
+        }
+        assert_eq!(cycle, ["ChatGPT", "Gemini", "Claude", "auto"]);
+    }
+
+    #[test]
+    fn test_local_summary() {
+        let responses = vec![
+            ("ChatGPT".to_string(), "Rust is a systems language. It has no garbage collector.".to_string()),
+            ("Claude".to_string(), "Rust is a systems programming language. Its borrow checker prevents data races.".to_string()),
+        ];
+        let summary = local_summary(&responses);
+        assert!(summary.starts_with("Key differences (local diff):"));
+        assert!(summary.contains("• ChatGPT mentions \"It has no garbage collector.\"; Claude omits it"));
+        assert!(summary.contains("• Claude mentions \"Its borrow checker prevents data races.\"; ChatGPT omits it"));
+        assert!(!summary.contains("systems"));
+
+        let same = vec![
+            ("ChatGPT".to_string(), "The answer is 42.".to_string()),
+            ("Gemini".to_string(), "The answer is 42!".to_string()),
+            ("Claude".to_string(), "the answer is 42".to_string()),
+        ];
+        assert_eq!(local_summary(&same), "The responses make the same points (local diff)");
+
+        let long = vec![
+            ("ChatGPT".to_string(), format!("{}.", "word ".repeat(40))),
+            ("Gemini".to_string(), "Something else entirely.".to_string()),
+            ("Claude".to_string(), "Something else entirely.".to_string()),
+        ];
+        let summary = local_summary(&long);
+        assert!(summary.contains("…\"; Gemini and Claude omit it"));
+    }
+}
@@ -0,0 +1,131 @@
+//! `--dry-run`: check every configured provider without sending a prompt
+//!
+//! Each enabled provider's `health_check` looks its model up with the API key, so a missing or
+//! rejected key shows up before a scripted session or benchmark depends on it.
+
+use crate::events::HealthStatus;
+use crate::tui::{AppState, ProviderState};
+use chatdelta::ClientConfig;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Instant;
+
+/// Check every enabled provider of `app` concurrently, in column order
+pub async fn check_providers(app: &AppState) -> Vec<(&'static str, HealthStatus)> {
+    let checks: Vec<_> = app.providers
+        .iter()
+        .map(|provider| {
+            let state = provider.state;
+            let client = provider.client.clone();
+            let check = tokio::spawn(async move {
+                match (state, client) {
+                    (ProviderState::Disabled, _) => HealthStatus::Skipped,
+                    (_, None) => HealthStatus::Failed("client could not be created".to_string()),
+                    (_, Some(client)) => {
+                        let started = Instant::now();
+                        match client.health_check().await {
+                            Ok(()) => HealthStatus::Ok(started.elapsed()),
+                            Err(e) => HealthStatus::Failed(e.to_string()),
+                        }
+                    }
+                }
+            });
+            (provider.name, check)
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for (name, check) in checks {
+        let status = check.await.unwrap_or_else(|e| HealthStatus::Failed(e.to_string()));
+        results.push((name, status));
+    }
+    results
+}
+
+/// Whether the setup is usable: at least one provider was checked and none failed
+pub fn all_passed(results: &[(&'static str, HealthStatus)]) -> bool {
+    results.iter().any(|(_, status)| matches!(status, HealthStatus::Ok(_)))
+        && !results.iter().any(|(_, status)| matches!(status, HealthStatus::Failed(_)))
+}
+
+/// One row per provider: name, OK/FAIL/SKIP, and the latency or the reason
+pub fn format_results(results: &[(&'static str, HealthStatus)]) -> String {
+    let mut table = format!("{:<10} {:<6} {}\n", "Provider", "Status", "Details");
+    for (name, status) in results {
+        let _ = match status {
+            HealthStatus::Ok(latency) => writeln!(table, "{:<10} {:<6} {} ms", name, "OK", latency.as_millis()),
+            HealthStatus::Failed(reason) => writeln!(table, "{:<10} {:<6} {}", name, "FAIL", reason),
+            HealthStatus::Skipped => writeln!(table, "{:<10} {:<6} no API key", name, "SKIP"),
+        };
+    }
+    table
+}
+
+/// Check the providers with an API key and print the results. Returns whether all passed.
+pub async fn run_dry_run(
+    provider_states: HashMap<&'static str, ProviderState>,
+    client_config: ClientConfig,
+) -> bool {
+    let app = AppState::with_client_config(provider_states, client_config);
+    for warning in &app.config_warnings {
+        eprintln!("⚠️  {}", warning);
+    }
+    let results = check_providers(&app).await;
+    print!("{}", format_results(&results));
+    let passed = all_passed(&results);
+    if !results.iter().any(|(_, status)| matches!(status, HealthStatus::Ok(_) | HealthStatus::Failed(_))) {
+        println!("No provider API keys set (CHATGPT_API_KEY, GEMINI_API_KEY, CLAUDE_API_KEY)");
+    }
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use chatdelta::AiClient;
+    use std::error::Error;
+    use std::sync::Arc;
+
+    /// Passes or fails its health check, and never answers prompts
+    struct CheckedClient {
+        healthy: bool,
+    }
+
+    #[async_trait]
+    impl AiClient for CheckedClient {
+        async fn send_prompt(&self, _prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+            panic!("a dry run must not send prompts");
+        }
+
+        async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+            if self.healthy {
+                Ok(())
+            } else {
+                Err("401 Unauthorized".into())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_providers() {
+        let mut states = HashMap::new();
+        states.insert("ChatGPT", ProviderState::Enabled);
+        states.insert("Claude", ProviderState::Enabled);
+        let mut app = AppState::new(states);
+        app.providers[0].client = Some(Arc::new(CheckedClient { healthy: true }));
+        app.providers[2].client = Some(Arc::new(CheckedClient { healthy: false }));
+
+        let results = check_providers(&app).await;
+        assert!(matches!(results[0], ("ChatGPT", HealthStatus::Ok(_))));
+        assert_eq!(results[1], ("Gemini", HealthStatus::Skipped));
+        assert_eq!(results[2], ("Claude", HealthStatus::Failed("401 Unauthorized".to_string())));
+        assert!(!all_passed(&results));
+        assert!(all_passed(&results[..2]));
+        assert!(!all_passed(&results[1..2]));
+
+        let table = format_results(&results);
+        assert!(table.contains("Claude     FAIL   401 Unauthorized"));
+        assert!(table.contains("Gemini     SKIP   no API key"));
+    }
+}
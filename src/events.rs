@@ -0,0 +1,28 @@
+//! Messages that background tasks send to the TUI's event loop
+//!
+//! Kept out of `tui` so that other producers, like `Logger::replay_to_channel`, can send them
+//! without depending on the terminal UI.
+
+use chatdelta::ResponseMetadata;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub enum ResponseType {
+    Provider(usize, String),  // (provider_index, response)
+    Delta(String),            // delta analysis
+    StreamChunk(usize, String, bool),  // (provider_index, chunk, is_final)
+    Metadata(usize, ResponseMetadata), // (provider_index, metadata), sent after the response it describes
+    Variants(usize, Vec<String>),      // (provider_index, samples) when more than one was requested
+    Health(usize, HealthStatus),       // (provider_index, result of its startup health check)
+    Transcription(Result<String, String>), // Text spoken after F5, or why it couldn't be transcribed
+}
+
+/// Outcome of checking one provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// The check passed after this long
+    Ok(Duration),
+    Failed(String),
+    /// No API key, so nothing was checked
+    Skipped,
+}
@@ -0,0 +1,154 @@
+//! Filters applied to a response before it is shown in its column
+//!
+//! Claude's extended thinking puts its reasoning in `<thinking>…</thinking>` spans ahead of
+//! the answer. By default the TUI moves them out of the column into
+//! `Provider::last_thinking`, which Alt+T shows in the delta field.
+
+const OPEN_TAG: &str = "<thinking>";
+const CLOSE_TAG: &str = "</thinking>";
+
+/// Rewrites a response before it reaches the chat history
+pub trait ResponseFilter: Send + Sync {
+    /// The response as it should be shown
+    fn filter(&self, response: &str) -> String;
+
+    /// Text the filter takes out of the response to be kept elsewhere; most keep nothing
+    fn extract(&self, _response: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Drops every `<thinking>…</thinking>` span
+pub struct StripThinkingTagsFilter;
+
+impl ResponseFilter for StripThinkingTagsFilter {
+    fn filter(&self, response: &str) -> String {
+        split_thinking(response).0
+    }
+}
+
+/// Drops every `<thinking>…</thinking>` span like `StripThinkingTagsFilter`, but keeps their
+/// contents for `Provider::last_thinking`
+pub struct ExtractThinkingFilter;
+
+impl ResponseFilter for ExtractThinkingFilter {
+    fn filter(&self, response: &str) -> String {
+        split_thinking(response).0
+    }
+
+    fn extract(&self, response: &str) -> Option<String> {
+        let (_, thinking) = split_thinking(response);
+        (!thinking.is_empty()).then(|| thinking.join("\n\n"))
+    }
+}
+
+/// Filters run in order, each on the output of the one before
+#[derive(Default)]
+pub struct ResponseFilterChain(Vec<Box<dyn ResponseFilter>>);
+
+/// A response after every filter of a chain has run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilteredResponse {
+    pub text: String,
+    /// Everything the filters extracted, in order
+    pub extracted: Option<String>,
+}
+
+impl ResponseFilterChain {
+    pub fn new(filters: Vec<Box<dyn ResponseFilter>>) -> Self {
+        Self(filters)
+    }
+
+    /// The TUI's chain: thinking is moved out of the response
+    pub fn standard() -> Self {
+        Self::new(vec![Box::new(ExtractThinkingFilter)])
+    }
+
+    pub fn push(&mut self, filter: Box<dyn ResponseFilter>) {
+        self.0.push(filter);
+    }
+
+    pub fn apply(&self, response: &str) -> FilteredResponse {
+        let mut text = response.to_string();
+        let mut extracted: Vec<String> = Vec::new();
+        for filter in &self.0 {
+            extracted.extend(filter.extract(&text));
+            text = filter.filter(&text);
+        }
+        FilteredResponse {
+            text,
+            extracted: (!extracted.is_empty()).then(|| extracted.join("\n\n")),
+        }
+    }
+}
+
+/// The response without its thinking spans, and the spans' trimmed contents. A span whose
+/// closing tag never came (the response was cut off) runs to the end.
+fn split_thinking(response: &str) -> (String, Vec<String>) {
+    let mut answer = String::new();
+    let mut thinking = Vec::new();
+    let mut rest = response;
+    while let Some(start) = rest.find(OPEN_TAG) {
+        answer.push_str(&rest[..start]);
+        let inside = &rest[start + OPEN_TAG.len()..];
+        match inside.find(CLOSE_TAG) {
+            Some(end) => {
+                thinking.push(inside[..end].trim().to_string());
+                rest = &inside[end + CLOSE_TAG.len()..];
+            }
+            None => {
+                thinking.push(inside.trim().to_string());
+                rest = "";
+            }
+        }
+    }
+    if thinking.is_empty() {
+        return (response.to_string(), thinking);
+    }
+    answer.push_str(rest);
+    (answer.trim().to_string(), thinking)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: &str = "<thinking>\nThe user wants a number.\n</thinking>\n\nThe answer is 42.<thinking>Double-check.</thinking>";
+
+    #[test]
+    fn test_strip_thinking_tags() {
+        assert_eq!(StripThinkingTagsFilter.filter(RESPONSE), "The answer is 42.");
+        assert_eq!(StripThinkingTagsFilter.filter("  No tags here\n"), "  No tags here\n");
+        assert_eq!(StripThinkingTagsFilter.filter("Answer <thinking>cut off"), "Answer");
+        assert_eq!(StripThinkingTagsFilter.extract(RESPONSE), None);
+    }
+
+    #[test]
+    fn test_extract_thinking() {
+        assert_eq!(
+            ExtractThinkingFilter.extract(RESPONSE).as_deref(),
+            Some("The user wants a number.\n\nDouble-check.")
+        );
+        assert_eq!(ExtractThinkingFilter.extract("The answer is 42."), None);
+    }
+
+    #[test]
+    fn test_filter_chain() {
+        struct Shout;
+        impl ResponseFilter for Shout {
+            fn filter(&self, response: &str) -> String {
+                response.to_uppercase()
+            }
+        }
+
+        let mut chain = ResponseFilterChain::standard();
+        chain.push(Box::new(Shout));
+        let filtered = chain.apply(RESPONSE);
+        assert_eq!(filtered.text, "THE ANSWER IS 42.");
+        assert_eq!(filtered.extracted.as_deref(), Some("The user wants a number.\n\nDouble-check."));
+
+        let unfiltered = ResponseFilterChain::default().apply(RESPONSE);
+        assert_eq!(unfiltered.text, RESPONSE);
+        assert_eq!(unfiltered.extracted, None);
+    }
+}
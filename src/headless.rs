@@ -0,0 +1,104 @@
+//! Headless mode: send one prompt to every configured provider and print the results
+//!
+//! Used by `chatdelta --headless "<prompt>"` for scripting and CI, where the TUI is not wanted.
+
+use crate::cli::OutputFormat;
+use crate::logger::Logger;
+use crate::output::formatter_for;
+use crate::progress::{CliProgressReporter, ProgressReporter};
+use crate::tui::AppState;
+use chatdelta::{AiResponse, ClientConfig, ImageAttachment, PromptOptions};
+use tokio::task::JoinSet;
+
+/// Query every provider with an API key, print the exchange in `format` and save the session log.
+/// With `images`, the providers are asked to look at them too and report no metadata.
+pub async fn run_headless(
+    prompt: &str,
+    images: &[ImageAttachment],
+    format: OutputFormat,
+    seed: Option<u64>,
+    client_config: ClientConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut logger = Logger::new();
+    let request_id = logger.log_prompt(prompt);
+    logger.log_seed(seed);
+    let options = PromptOptions {
+        request_id: Some(request_id.to_string()),
+        seed,
+        ..Default::default()
+    };
+
+    let mut warnings = Vec::new();
+    let configs = AppState::load_provider_configs(client_config, &mut warnings);
+    logger.set_user_agent(&configs.default_config().user_agent);
+    let models = AppState::load_model_catalog(&mut warnings);
+    for warning in &warnings {
+        eprintln!("⚠️  {}", warning);
+    }
+    let mut requests = JoinSet::new();
+    for (name, model, client) in AppState::keyed_provider_clients(&configs, &models) {
+        logger.set_provider_model(name, model);
+        logger.start_provider_timer(name);
+        let prompt = prompt.to_string();
+        let options = options.clone();
+        let images = images.to_vec();
+        requests.spawn(async move {
+            let result = if images.is_empty() {
+                client.send_prompt_detailed(&prompt, &options).await
+            } else {
+                client
+                    .send_prompt_with_images(&prompt, &images, &options)
+                    .await
+                    .map(|content| AiResponse { content, metadata: Default::default() })
+            };
+            (name, result.map_err(|e| e.to_string()))
+        });
+    }
+
+    if requests.is_empty() {
+        return Err("No provider API keys set (CHATGPT_API_KEY, GEMINI_API_KEY, CLAUDE_API_KEY)".into());
+    }
+
+    let total = requests.len();
+    let mut reporter = CliProgressReporter::new();
+    reporter.update(0, total);
+    while let Some(joined) = requests.join_next().await {
+        let (name, result) = joined?;
+        reporter.update(total - requests.len(), total);
+        match result {
+            Ok(response) => {
+                logger.log_provider_response(name, &response.content, false);
+                if let Some(model_version) = &response.metadata.model_version {
+                    logger.log_model_version(name, model_version);
+                }
+                if let Some(fingerprint) = &response.metadata.system_fingerprint {
+                    logger.log_system_fingerprint(name, fingerprint);
+                }
+                if response.metadata.validation_retried {
+                    logger.log_validation_retry(name);
+                }
+                if let Some(usage) = response.metadata.token_usage {
+                    let cost = AppState::provider_id(name)
+                        .and_then(|id| models.get(id))
+                        .map(|model| model.estimate_cost(usage.input_tokens, usage.output_tokens));
+                    logger.log_token_usage(name, usage, cost);
+                }
+            }
+            Err(e) => logger.log_provider_response(name, &e, true),
+        }
+    }
+
+    if let Some(entry) = logger.current_conversation() {
+        print!("{}", formatter_for(format).format(entry));
+    }
+
+    // stdout carries the formatted output, so statistics and the log location go to stderr
+    logger.finalize_conversation();
+    eprint!("{}", logger.get_statistics());
+    match logger.save() {
+        Ok(path) => eprintln!("📝 Conversation saved to: {}", path.display()),
+        Err(e) => eprintln!("⚠️  Failed to save conversation log: {}", e),
+    }
+
+    Ok(())
+}
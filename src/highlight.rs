@@ -0,0 +1,162 @@
+//! Syntax highlighting for fenced code blocks in responses
+//!
+//! Lines inside a code fence with a language tag (```` ```rust ````) are colored with
+//! `syntect`; prose, and code in languages it doesn't know, keep the column's own styling.
+//! Highlighting a long answer takes far longer than a frame, so each message is highlighted
+//! once and kept in a cache keyed by its text.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans};
+
+/// Syntect theme used when the configured one doesn't exist
+pub const DEFAULT_CODE_THEME: &str = "base16-ocean.dark";
+
+/// Messages whose highlighting is kept. Streaming adds a message per flush, so the cache
+/// starts over when it fills up.
+const MAX_CACHED_MESSAGES: usize = 256;
+
+const FENCE: &str = "```";
+
+/// One entry per line of a message: highlighted spans for code, `None` for everything else
+pub type HighlightedLines = Vec<Option<Spans<'static>>>;
+
+pub struct CodeHighlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    cache: HashMap<u64, HighlightedLines>,
+}
+
+impl CodeHighlighter {
+    /// A highlighter with one of syntect's built-in themes, e.g. `InspiredGitHub`
+    pub fn new(theme_name: &str) -> Self {
+        let mut themes = ThemeSet::load_defaults().themes;
+        let theme = themes
+            .remove(theme_name)
+            .or_else(|| themes.remove(DEFAULT_CODE_THEME))
+            .unwrap_or_default();
+        Self {
+            syntaxes: SyntaxSet::load_defaults_nonewlines(),
+            theme,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Highlight `message` unless that was done already. Messages without a code fence are
+    /// left alone.
+    pub fn prepare(&mut self, message: &str) {
+        if !message.contains(FENCE) {
+            return;
+        }
+        let key = message_key(message);
+        if self.cache.contains_key(&key) {
+            return;
+        }
+        if self.cache.len() >= MAX_CACHED_MESSAGES {
+            self.cache.clear();
+        }
+        let lines = self.highlight(message);
+        self.cache.insert(key, lines);
+    }
+
+    /// The lines of a message `prepare` has seen, or `None` when it has no code fence
+    pub fn get(&self, message: &str) -> Option<&HighlightedLines> {
+        if !message.contains(FENCE) {
+            return None;
+        }
+        self.cache.get(&message_key(message))
+    }
+
+    /// Every line of `message`, with the code of each fenced block in a known language
+    /// highlighted. The fences themselves are left plain.
+    pub fn highlight(&self, message: &str) -> HighlightedLines {
+        let mut lines = Vec::new();
+        let mut in_block = false;
+        let mut block: Option<HighlightLines> = None;
+        for line in message.lines() {
+            match line.trim_start().strip_prefix(FENCE) {
+                Some(language) if !in_block => {
+                    in_block = true;
+                    block = self
+                        .syntaxes
+                        .find_syntax_by_token(language.trim())
+                        .map(|syntax| HighlightLines::new(syntax, &self.theme));
+                    lines.push(None);
+                }
+                Some(_) => {
+                    in_block = false;
+                    block = None;
+                    lines.push(None);
+                }
+                None => lines.push(block.as_mut().and_then(|block| self.highlight_line(block, line))),
+            }
+        }
+        lines
+    }
+
+    fn highlight_line(&self, block: &mut HighlightLines, line: &str) -> Option<Spans<'static>> {
+        let ranges = block.highlight_line(line, &self.syntaxes).ok()?;
+        let spans: Vec<Span<'static>> = ranges
+            .into_iter()
+            .map(|(style, text)| {
+                let color = Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b);
+                Span::styled(text.to_string(), Style::default().fg(color))
+            })
+            .collect();
+        Some(Spans::from(spans))
+    }
+}
+
+impl Default for CodeHighlighter {
+    fn default() -> Self {
+        Self::new(DEFAULT_CODE_THEME)
+    }
+}
+
+fn message_key(message: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    message.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "Claude: Use a loop:\n```rust\nfor i in 0..3 {\n    println!(\"{}\", i);\n}\n```\nDone.";
+
+    #[test]
+    fn test_highlight_code_blocks() {
+        let highlighter = CodeHighlighter::default();
+        let lines = highlighter.highlight(MESSAGE);
+        assert_eq!(lines.len(), MESSAGE.lines().count());
+        assert!(lines[0].is_none());
+        assert!(lines[1].is_none());
+        let code = lines[2].as_ref().unwrap();
+        assert!(code.0.len() > 1);
+        assert_eq!(code.0.iter().map(|span| span.content.as_ref()).collect::<String>(), "for i in 0..3 {");
+        assert!(lines[5].is_none());
+        assert!(lines[6].is_none());
+
+        // Unknown and missing languages stay plain
+        let plain = highlighter.highlight("```klingon\nqapla'\n```\n```\nplain\n```");
+        assert!(plain.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut highlighter = CodeHighlighter::new("no such theme");
+        assert!(highlighter.get(MESSAGE).is_none());
+        highlighter.prepare(MESSAGE);
+        assert!(highlighter.get(MESSAGE).unwrap()[2].is_some());
+
+        highlighter.prepare("No code here");
+        assert!(highlighter.get("No code here").is_none());
+        assert_eq!(highlighter.cache.len(), 1);
+    }
+}
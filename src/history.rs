@@ -0,0 +1,159 @@
+//! Sent prompts, recalled with Up/Down while the input has focus
+//!
+//! History is kept across sessions in `~/.chatdelta/history`, one JSON string per line so
+//! multi-line prompts fit on one line.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Prompts kept in memory and in the history file
+pub const MAX_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    /// Entries that came from the file; the rest were sent this session
+    loaded: usize,
+    /// Entry shown in the input, `None` while editing the draft
+    position: Option<usize>,
+    /// What was in the input when browsing started
+    draft: String,
+}
+
+impl InputHistory {
+    /// Load `~/.chatdelta/history`; a missing or unreadable file means no history
+    pub fn load() -> Self {
+        let contents = Self::history_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        Self::from_lines(&contents)
+    }
+
+    /// Parse one JSON string per line, skipping lines that aren't one
+    pub fn from_lines(contents: &str) -> Self {
+        let mut entries: Vec<String> = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        entries.drain(..entries.len().saturating_sub(MAX_HISTORY));
+        Self {
+            loaded: entries.len(),
+            entries,
+            ..Default::default()
+        }
+    }
+
+    /// Record a sent prompt and stop browsing. Repeating the latest prompt adds nothing.
+    pub fn push(&mut self, prompt: &str) {
+        self.position = None;
+        self.draft.clear();
+        if prompt.trim().is_empty() || self.entries.last().is_some_and(|last| last == prompt) {
+            return;
+        }
+        self.entries.push(prompt.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+            self.loaded = self.loaded.saturating_sub(1);
+        }
+    }
+
+    /// Step back to an older prompt. `input` is kept as the draft when browsing starts.
+    /// Returns `None` when there is nothing older.
+    pub fn previous(&mut self, input: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = input.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(position) => position - 1,
+        };
+        self.position = Some(position);
+        Some(&self.entries[position])
+    }
+
+    /// Step forward to a newer prompt, and past the newest back to the draft. Returns `None`
+    /// when not browsing.
+    pub fn newer(&mut self) -> Option<&str> {
+        let position = self.position?;
+        if position + 1 < self.entries.len() {
+            self.position = Some(position + 1);
+            Some(&self.entries[position + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Append this session's prompts to `~/.chatdelta/history`. The file is read again first,
+    /// so sessions running side by side don't drop each other's prompts.
+    pub fn save(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = Self::history_path().ok_or("Could not determine home directory")?;
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        let mut merged = Self::from_lines(&existing);
+        for prompt in &self.entries[self.loaded..] {
+            merged.push(prompt);
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let lines: Vec<String> = merged.entries.iter().map(serde_json::to_string).collect::<Result<_, _>>()?;
+        fs::write(&path, lines.join("\n") + "\n")?;
+        Ok(path)
+    }
+
+    fn history_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".chatdelta").join("history"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(prompts: &[&str]) -> InputHistory {
+        let mut history = InputHistory::default();
+        for prompt in prompts {
+            history.push(prompt);
+        }
+        history
+    }
+
+    #[test]
+    fn test_browse_keeps_draft() {
+        let mut history = history(&["first", "second"]);
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.previous("draft"), Some("second"));
+        assert_eq!(history.previous("ignored"), Some("first"));
+        assert_eq!(history.previous("ignored"), None);
+        assert_eq!(history.newer(), Some("second"));
+        assert_eq!(history.newer(), Some("draft"));
+        assert_eq!(history.newer(), None);
+        assert_eq!(InputHistory::default().previous("draft"), None);
+    }
+
+    #[test]
+    fn test_push_skips_repeats_and_blank() {
+        let history = history(&["a", "a", "  ", "b", "a"]);
+        assert_eq!(history.entries(), ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn test_lines_round_trip() {
+        let history = history(&["one line", "two\nlines"]);
+        let lines: Vec<String> = history.entries().iter().map(|e| serde_json::to_string(e).unwrap()).collect();
+        let parsed = InputHistory::from_lines(&format!("{}\nnot json\n", lines.join("\n")));
+        assert_eq!(parsed.entries(), ["one line", "two\nlines"]);
+
+        let many: Vec<String> = (0..MAX_HISTORY + 5).map(|i| format!("\"{}\"", i)).collect();
+        let parsed = InputHistory::from_lines(&many.join("\n"));
+        assert_eq!(parsed.entries().len(), MAX_HISTORY);
+        assert_eq!(parsed.entries()[0], "5");
+    }
+}
@@ -0,0 +1,922 @@
+//! Keybindings of the TUI
+//!
+//! `KEYBINDINGS` is the only place keys are tied to actions. A `KeyTable` applies the user's
+//! overrides from the `[keys]` table of `~/.chatdelta/config.toml` on top of it; the event loop
+//! looks the pressed key up with `KeyTable::action_for`, and the F1 help overlay is written from
+//! the same table by `KeyTable::help_text`, so the two can't disagree.
+//!
+//! The vim keymap (`--keymap vim`) adds `VIM_BINDINGS` on top: key sequences typed in normal
+//! mode, resolved by `KeyResolver`, which also tracks the mode and the keys typed so far.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+/// The file holding the keymap and the `[keys]` overrides, as shown to the user
+pub const CONFIG_FILE: &str = "~/.chatdelta/config.toml";
+
+fn config_file() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".chatdelta").join("config.toml"))
+}
+
+/// Sections of the help overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyGroup {
+    Navigation,
+    Input,
+    Providers,
+    Delta,
+    Session,
+}
+
+impl KeyGroup {
+    pub const ALL: [KeyGroup; 5] = [
+        KeyGroup::Navigation,
+        KeyGroup::Input,
+        KeyGroup::Providers,
+        KeyGroup::Delta,
+        KeyGroup::Session,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            KeyGroup::Navigation => "Navigation",
+            KeyGroup::Input => "Input",
+            KeyGroup::Providers => "Providers",
+            KeyGroup::Delta => "Delta",
+            KeyGroup::Session => "Session",
+        }
+    }
+}
+
+/// What a key does; the event loop carries each one out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    ToggleInputFocus,
+    WordLeft,
+    WordRight,
+    Home,
+    End,
+    Backspace,
+    Delete,
+    Undo,
+    Redo,
+    NewLine,
+    Voice,
+    Send,
+    SendToSelected,
+    Cancel,
+    Regenerate,
+    Retry,
+    /// The column is the digit pressed with Alt
+    ToggleProvider,
+    PickModel,
+    NextVariant,
+    ShowThinking,
+    Copy,
+    ToggleStreaming,
+    CycleTheme,
+    ToggleStar,
+    ClearConversation,
+    ToggleDelta,
+    ToggleDeltaPanel,
+    Metrics,
+    GrowDelta,
+    ShrinkDelta,
+    CycleDeltaStrategy,
+    CycleDeltaJudge,
+    RegenerateDelta,
+    Fork,
+    Save,
+    RequestLog,
+    Screenshot,
+    Help,
+    Quit,
+    /// Vim: switch columns whatever the input holds
+    PreviousColumn,
+    NextColumn,
+    /// Vim: top / bottom of the selected column
+    ScrollTop,
+    ScrollBottom,
+    /// Vim: type into the input
+    InsertMode,
+    /// Vim: leave the input for navigation
+    NormalMode,
+}
+
+impl Action {
+    /// The action's name in the `[keys]` table of `~/.chatdelta/config.toml`
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::ToggleInputFocus => "toggle_input_focus",
+            Action::WordLeft => "word_left",
+            Action::WordRight => "word_right",
+            Action::Home => "home",
+            Action::End => "end",
+            Action::Backspace => "backspace",
+            Action::Delete => "delete",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::NewLine => "new_line",
+            Action::Voice => "voice",
+            Action::Send => "send",
+            Action::SendToSelected => "send_to_selected",
+            Action::Cancel => "cancel",
+            Action::Regenerate => "regenerate",
+            Action::Retry => "retry",
+            Action::ToggleProvider => "toggle_provider",
+            Action::PickModel => "pick_model",
+            Action::NextVariant => "next_variant",
+            Action::ShowThinking => "show_thinking",
+            Action::Copy => "copy",
+            Action::ToggleStreaming => "toggle_streaming",
+            Action::CycleTheme => "cycle_theme",
+            Action::ToggleStar => "toggle_star",
+            Action::ClearConversation => "clear_conversation",
+            Action::ToggleDelta => "toggle_delta",
+            Action::ToggleDeltaPanel => "toggle_delta_panel",
+            Action::Metrics => "metrics",
+            Action::GrowDelta => "grow_delta",
+            Action::ShrinkDelta => "shrink_delta",
+            Action::CycleDeltaStrategy => "cycle_delta_strategy",
+            Action::CycleDeltaJudge => "cycle_delta_judge",
+            Action::RegenerateDelta => "regenerate_delta",
+            Action::Fork => "fork",
+            Action::Save => "save",
+            Action::RequestLog => "request_log",
+            Action::Screenshot => "screenshot",
+            Action::Help => "help",
+            Action::Quit => "quit",
+            Action::PreviousColumn => "previous_column",
+            Action::NextColumn => "next_column",
+            Action::ScrollTop => "scroll_top",
+            Action::ScrollBottom => "scroll_bottom",
+            Action::InsertMode => "insert_mode",
+            Action::NormalMode => "normal_mode",
+        }
+    }
+
+    /// The action of `KEYBINDINGS` called `name`; the vim keymap's own actions can't be rebound
+    pub fn from_name(name: &str) -> Option<Action> {
+        KEYBINDINGS.iter().map(|binding| binding.action).find(|action| action.name() == name)
+    }
+}
+
+/// Which keys drive the TUI: the default table alone, or vim's modes on top of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Keymap {
+    #[default]
+    Default,
+    Vim,
+}
+
+/// `~/.chatdelta/config.toml`: `keymap = "vim"` and a `[keys]` table of overrides
+#[derive(Debug, Default, Deserialize)]
+struct KeysConfig {
+    keymap: Option<Keymap>,
+    #[serde(default)]
+    keys: BTreeMap<String, KeySpec>,
+}
+
+impl Keymap {
+    /// The keymap from `~/.chatdelta/config.toml`; `Default` when the file is missing or invalid.
+    /// `KeyTable::load` reads the same file and reports what is wrong with it.
+    pub fn load() -> Self {
+        config_file()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| Self::from_toml(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        let config: KeysConfig = toml::from_str(contents)?;
+        Ok(config.keymap.unwrap_or_default())
+    }
+}
+
+/// Keys for one action. A chord matches a key pressed with at least its modifiers.
+#[derive(Debug, Clone, Copy)]
+pub struct Keybinding {
+    pub group: KeyGroup,
+    /// The keys as written in the help overlay
+    pub keys: &'static str,
+    pub description: &'static str,
+    pub chords: &'static [(KeyCode, KeyModifiers)],
+    pub action: Action,
+}
+
+const fn bind(
+    group: KeyGroup,
+    keys: &'static str,
+    description: &'static str,
+    chords: &'static [(KeyCode, KeyModifiers)],
+    action: Action,
+) -> Keybinding {
+    Keybinding { group, keys, description, chords, action }
+}
+
+const NONE: KeyModifiers = KeyModifiers::NONE;
+const CTRL: KeyModifiers = KeyModifiers::CONTROL;
+const ALT: KeyModifiers = KeyModifiers::ALT;
+const SHIFT: KeyModifiers = KeyModifiers::SHIFT;
+
+/// Every key the TUI handles, in help overlay order. Other characters are typed into the input.
+pub const KEYBINDINGS: &[Keybinding] = &[
+    bind(KeyGroup::Navigation, "← / →", "Previous / next column, or move the cursor while the input has text", &[(KeyCode::Left, NONE)], Action::Left),
+    bind(KeyGroup::Navigation, "", "", &[(KeyCode::Right, NONE)], Action::Right),
+    bind(KeyGroup::Navigation, "↑ / ↓", "Scroll the selected column, or browse sent prompts while the input has focus", &[(KeyCode::Up, NONE)], Action::Up),
+    bind(KeyGroup::Navigation, "", "", &[(KeyCode::Down, NONE)], Action::Down),
+    bind(KeyGroup::Navigation, "PageUp / PageDown", "Scroll the selected column a page at a time", &[(KeyCode::PageUp, NONE)], Action::PageUp),
+    bind(KeyGroup::Navigation, "", "", &[(KeyCode::PageDown, NONE)], Action::PageDown),
+    bind(KeyGroup::Navigation, "Tab", "Give the input focus, or give ↑ / ↓ back to scrolling", &[(KeyCode::Tab, NONE)], Action::ToggleInputFocus),
+    bind(KeyGroup::Input, "Enter", "Send to every enabled provider, or run a /command", &[(KeyCode::Enter, NONE)], Action::Send),
+    bind(KeyGroup::Input, "Ctrl+Enter", "Send to the selected column only", &[(KeyCode::Enter, CTRL)], Action::SendToSelected),
+    bind(KeyGroup::Input, "Shift/Alt+Enter", "New line", &[(KeyCode::Enter, SHIFT), (KeyCode::Enter, ALT)], Action::NewLine),
+    bind(KeyGroup::Input, "F5", "Speak a prompt: F5 again stops recording (voice builds)", &[(KeyCode::F(5), NONE)], Action::Voice),
+    bind(KeyGroup::Input, "Ctrl+← / →", "Move the cursor by word", &[(KeyCode::Left, CTRL)], Action::WordLeft),
+    bind(KeyGroup::Input, "", "", &[(KeyCode::Right, CTRL)], Action::WordRight),
+    bind(KeyGroup::Input, "Home / End", "Start / end of the input, or top / bottom of the selected column while the input is empty", &[(KeyCode::Home, NONE)], Action::Home),
+    bind(KeyGroup::Input, "", "", &[(KeyCode::End, NONE)], Action::End),
+    bind(KeyGroup::Input, "Backspace / Delete", "Delete before / under the cursor", &[(KeyCode::Backspace, NONE)], Action::Backspace),
+    bind(KeyGroup::Input, "", "", &[(KeyCode::Delete, NONE)], Action::Delete),
+    bind(KeyGroup::Input, "Ctrl+Z / Ctrl+Y", "Undo / redo input edits", &[(KeyCode::Char('z'), CTRL)], Action::Undo),
+    bind(KeyGroup::Input, "", "", &[(KeyCode::Char('y'), CTRL)], Action::Redo),
+    bind(KeyGroup::Providers, "Ctrl+C", "Cancel pending requests", &[(KeyCode::Char('c'), CTRL)], Action::Cancel),
+    bind(KeyGroup::Providers, "Ctrl+R", "Ask the selected column again", &[(KeyCode::Char('r'), CTRL)], Action::Regenerate),
+    bind(KeyGroup::Providers, "Alt+R", "Retry the selected column's failed or stuck request", &[(KeyCode::Char('r'), ALT)], Action::Retry),
+    bind(
+        KeyGroup::Providers,
+        "Alt+1-9",
+        "Turn a column on or off",
+        &[
+            (KeyCode::Char('1'), ALT),
+            (KeyCode::Char('2'), ALT),
+            (KeyCode::Char('3'), ALT),
+            (KeyCode::Char('4'), ALT),
+            (KeyCode::Char('5'), ALT),
+            (KeyCode::Char('6'), ALT),
+            (KeyCode::Char('7'), ALT),
+            (KeyCode::Char('8'), ALT),
+            (KeyCode::Char('9'), ALT),
+        ],
+        Action::ToggleProvider,
+    ),
+    bind(KeyGroup::Providers, "Alt+M", "Pick the selected column's model", &[(KeyCode::Char('m'), ALT)], Action::PickModel),
+    bind(KeyGroup::Providers, "Alt+V", "Next sample or model of the selected column", &[(KeyCode::Char('v'), ALT)], Action::NextVariant),
+    bind(KeyGroup::Providers, "Alt+T", "Show the selected column's reasoning", &[(KeyCode::Char('t'), ALT)], Action::ShowThinking),
+    bind(KeyGroup::Providers, "Alt+C", "Copy the selected column's latest response", &[(KeyCode::Char('c'), ALT)], Action::Copy),
+    bind(KeyGroup::Providers, "F2", "Streaming on / off", &[(KeyCode::F(2), NONE)], Action::ToggleStreaming),
+    bind(KeyGroup::Delta, "F4", "Delta analysis on / off, or detailed metrics while the metrics panel is open", &[(KeyCode::F(4), NONE)], Action::ToggleDelta),
+    bind(KeyGroup::Delta, "F3", "Metrics panel: success rate, latency and tokens per provider", &[(KeyCode::F(3), NONE)], Action::Metrics),
+    bind(KeyGroup::Delta, "Alt+H", "Collapse / expand the delta field", &[(KeyCode::Char('h'), ALT)], Action::ToggleDeltaPanel),
+    bind(KeyGroup::Delta, "Ctrl+↑ / ↓", "Make the delta field taller / shorter", &[(KeyCode::Up, CTRL)], Action::GrowDelta),
+    bind(KeyGroup::Delta, "", "", &[(KeyCode::Down, CTRL)], Action::ShrinkDelta),
+    bind(KeyGroup::Delta, "Alt+D", "Cycle the delta mode", &[(KeyCode::Char('d'), ALT)], Action::CycleDeltaStrategy),
+    bind(KeyGroup::Delta, "Alt+J", "Cycle the delta judge", &[(KeyCode::Char('j'), ALT)], Action::CycleDeltaJudge),
+    bind(KeyGroup::Delta, "Ctrl+D", "Summarize the differences again", &[(KeyCode::Char('d'), CTRL)], Action::RegenerateDelta),
+    bind(KeyGroup::Session, "F6", "Next built-in color theme: dark, light, monochrome", &[(KeyCode::F(6), NONE)], Action::CycleTheme),
+    bind(KeyGroup::Session, "Alt+S", "Star or unstar the exchange at the top of the selected column (Ctrl+* too, with the kitty keyboard protocol)", &[(KeyCode::Char('s'), ALT), (KeyCode::Char('*'), CTRL)], Action::ToggleStar),
+    bind(KeyGroup::Session, "Ctrl+K", "New conversation: the models forget earlier turns, the columns keep them", &[(KeyCode::Char('k'), CTRL)], Action::ClearConversation),
+    bind(KeyGroup::Session, "Ctrl+S", "Save the session log now, without quitting", &[(KeyCode::Char('s'), CTRL)], Action::Save),
+    bind(KeyGroup::Session, "Ctrl+F", "Fork the session", &[(KeyCode::Char('f'), CTRL)], Action::Fork),
+    bind(KeyGroup::Session, "Ctrl+L", "Latest requests and how they ended, for debugging", &[(KeyCode::Char('l'), CTRL)], Action::RequestLog),
+    bind(KeyGroup::Session, "Ctrl+P", "Save a PNG screenshot to the home directory (screenshot builds)", &[(KeyCode::Char('p'), CTRL)], Action::Screenshot),
+    bind(KeyGroup::Session, "F1 / ?", "This help (? only while the input is empty)", &[(KeyCode::F(1), NONE), (KeyCode::Char('?'), NONE)], Action::Help),
+    bind(KeyGroup::Session, "Esc", "Quit", &[(KeyCode::Esc, NONE)], Action::Quit),
+];
+
+/// A key sequence of the vim keymap's normal mode
+#[derive(Debug, Clone, Copy)]
+pub struct VimBinding {
+    pub sequence: &'static str,
+    pub description: &'static str,
+    pub action: Action,
+}
+
+const fn vim(sequence: &'static str, description: &'static str, action: Action) -> VimBinding {
+    VimBinding { sequence, description, action }
+}
+
+/// Normal mode sequences, in help overlay order. Keys of the default table other than Esc keep
+/// working in both modes.
+pub const VIM_BINDINGS: &[VimBinding] = &[
+    vim("h", "Previous column", Action::PreviousColumn),
+    vim("l", "Next column", Action::NextColumn),
+    vim("j", "Scroll down", Action::Down),
+    vim("k", "Scroll up", Action::Up),
+    vim("gg", "Top of the selected column", Action::ScrollTop),
+    vim("G", "Bottom of the selected column", Action::ScrollBottom),
+    vim("i", "Insert mode: type into the input (Esc leaves it)", Action::InsertMode),
+    vim(":q", "Quit", Action::Quit),
+    vim("ZZ", "Quit", Action::Quit),
+];
+
+/// Modes of the vim keymap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+}
+
+/// Turns key presses into actions for the chosen keymap, keeping the vim mode and any
+/// unfinished sequence between presses
+#[derive(Debug, Clone)]
+pub struct KeyResolver {
+    pub keymap: Keymap,
+    pub table: KeyTable,
+    pub mode: VimMode,
+    /// The terminal reports modified keys like Ctrl+Enter (the kitty keyboard protocol)
+    pub keyboard_enhanced: bool,
+    pending: String,
+}
+
+impl KeyResolver {
+    /// The vim keymap starts in normal mode
+    pub fn new(keymap: Keymap, table: KeyTable) -> Self {
+        Self { keymap, table, mode: VimMode::Normal, keyboard_enhanced: false, pending: String::new() }
+    }
+
+    /// The table's action for `key`, except that Esc is vim's to leave insert mode with
+    fn table_action(&self, key: &KeyEvent) -> Option<Action> {
+        let action = self.table.action_for(key);
+        if self.keymap == Keymap::Vim && key.code == KeyCode::Esc && action == Some(Action::Quit) {
+            return None;
+        }
+        action
+    }
+
+    /// The action for `key`, or `None` when it is to be typed (see `accepts_text`) or starts a
+    /// longer sequence
+    pub fn resolve(&mut self, key: &KeyEvent) -> Option<Action> {
+        if self.keymap == Keymap::Default {
+            return self.table.action_for(key);
+        }
+        if self.mode == VimMode::Insert {
+            if key.code == KeyCode::Esc {
+                self.mode = VimMode::Normal;
+                return Some(Action::NormalMode);
+            }
+            return self.table_action(key);
+        }
+
+        let KeyCode::Char(c) = key.code else {
+            self.pending.clear();
+            return self.table_action(key);
+        };
+        if key.modifiers.intersects(CTRL | ALT) {
+            self.pending.clear();
+            return self.table_action(key);
+        }
+        self.pending.push(c);
+        if let Some(binding) = VIM_BINDINGS.iter().find(|binding| binding.sequence == self.pending) {
+            self.pending.clear();
+            if binding.action == Action::InsertMode {
+                self.mode = VimMode::Insert;
+            }
+            return Some(binding.action);
+        }
+        if VIM_BINDINGS.iter().any(|binding| binding.sequence.starts_with(self.pending.as_str())) {
+            return None;
+        }
+        // A key that isn't part of any sequence still does what the key table says (`?`)
+        let started_fresh = self.pending.chars().count() == 1;
+        self.pending.clear();
+        if started_fresh {
+            self.table_action(key)
+        } else {
+            None
+        }
+    }
+
+    /// Whether keys without an action are typed into the input
+    pub fn accepts_text(&self) -> bool {
+        self.keymap == Keymap::Default || self.mode == VimMode::Insert
+    }
+
+    /// Normal mode keys typed so far towards a sequence, like the `g` of `gg`
+    pub fn pending_keys(&self) -> &str {
+        &self.pending
+    }
+
+    /// The help overlay for this keymap and terminal
+    pub fn help_text(&self) -> String {
+        self.table.help_text(self.keymap, self.keyboard_enhanced)
+    }
+
+    /// `-- NORMAL --` or `-- INSERT --` for the status bar; `None` with the default keymap
+    pub fn mode_label(&self) -> Option<&'static str> {
+        match (self.keymap, self.mode) {
+            (Keymap::Default, _) => None,
+            (Keymap::Vim, VimMode::Normal) => Some("-- NORMAL --"),
+            (Keymap::Vim, VimMode::Insert) => Some("-- INSERT --"),
+        }
+    }
+}
+
+type Chord = (KeyCode, KeyModifiers);
+
+/// The keys of one action in `~/.chatdelta/config.toml`: `quit = "ctrl-q"`, or a list of them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum KeySpec {
+    One(String),
+    Several(Vec<String>),
+}
+
+/// The keybindings in effect: `KEYBINDINGS` with the user's overrides, and what was wrong with
+/// the overrides
+#[derive(Debug, Clone, Default)]
+pub struct KeyTable {
+    overrides: HashMap<Action, Vec<Chord>>,
+    warnings: Vec<String>,
+}
+
+impl KeyTable {
+    /// The table from the `[keys]` of `~/.chatdelta/config.toml`; the defaults when the file is
+    /// missing. Problems with entries are counted in a notice at startup and listed in the help
+    /// overlay.
+    pub fn load() -> Self {
+        let Some(contents) = config_file().and_then(|path| fs::read_to_string(path).ok()) else {
+            return Self::default();
+        };
+        Self::from_toml(&contents).unwrap_or_else(|e| Self { overrides: HashMap::new(), warnings: vec![e.to_string()] })
+    }
+
+    /// The overrides in the `[keys]` table of a keys file
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        let config: KeysConfig = toml::from_str(contents)?;
+        Ok(Self::with_overrides(&config.keys))
+    }
+
+    /// Apply `overrides`, keyed by `Action::name`. Entries that name no action, have a key that
+    /// can't be read, or take a key another action keeps are left out with a warning, so those
+    /// actions keep their default keys. A key without Ctrl or Alt that types a character is kept,
+    /// with a warning that it can't be typed any more.
+    pub fn with_overrides(overrides: &BTreeMap<String, KeySpec>) -> Self {
+        let mut table = Self::default();
+        for (name, spec) in overrides {
+            let Some(action) = Action::from_name(name) else {
+                table.warnings.push(format!("{}: no such action", name));
+                continue;
+            };
+            if action == Action::ToggleProvider {
+                table.warnings.push(format!("{}: can't be rebound, its digit picks the column", name));
+                continue;
+            }
+            let specs = match spec {
+                KeySpec::One(spec) => std::slice::from_ref(spec),
+                KeySpec::Several(specs) => specs.as_slice(),
+            };
+            let chords: Result<Vec<Chord>, String> = specs.iter().map(|spec| parse_chord(spec)).collect();
+            match chords {
+                Ok(chords) if chords.is_empty() => table.warnings.push(format!("{}: no keys given", name)),
+                Ok(chords) => {
+                    for &chord in chords.iter().filter(|&&chord| types_text(chord)) {
+                        table.warnings.push(format!("{}: {} can no longer be typed", name, format_chord(chord)));
+                    }
+                    table.overrides.insert(action, chords);
+                }
+                Err(e) => table.warnings.push(format!("{}: {}", name, e)),
+            }
+        }
+
+        // Overrides sharing a key with another action are dropped until no key is shared, which
+        // ends at the defaults at the latest
+        loop {
+            let conflicts = table.conflicts();
+            if conflicts.is_empty() {
+                break;
+            }
+            for (chord, actions) in conflicts {
+                let names: Vec<&str> = actions.iter().map(|action| action.name()).collect();
+                for action in actions {
+                    if table.overrides.remove(&action).is_some() {
+                        table.warnings.push(format!(
+                            "{}: {} is also bound to {}",
+                            action.name(),
+                            format_chord(chord),
+                            names.iter().filter(|&&name| name != action.name()).copied().collect::<Vec<_>>().join(", ")
+                        ));
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    /// Keys bound to more than one action, where at least one of them was overridden
+    fn conflicts(&self) -> Vec<(Chord, Vec<Action>)> {
+        let mut by_chord: Vec<(Chord, Vec<Action>)> = Vec::new();
+        for binding in KEYBINDINGS {
+            for &chord in self.chords(binding) {
+                match by_chord.iter_mut().find(|(bound, _)| *bound == chord) {
+                    Some((_, actions)) if !actions.contains(&binding.action) => actions.push(binding.action),
+                    Some(_) => {}
+                    None => by_chord.push((chord, vec![binding.action])),
+                }
+            }
+        }
+        by_chord.retain(|(_, actions)| actions.len() > 1 && actions.iter().any(|action| self.overrides.contains_key(action)));
+        by_chord
+    }
+
+    /// Overrides that were ignored or take a key used for typing, and why
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    fn chords<'a>(&'a self, binding: &'a Keybinding) -> &'a [Chord] {
+        self.overrides.get(&binding.action).map_or(binding.chords, Vec::as_slice)
+    }
+
+    /// The action bound to `key`. When several chords match, the one with the most modifiers
+    /// wins, so Ctrl+← moves by word rather than switching columns.
+    pub fn action_for(&self, key: &KeyEvent) -> Option<Action> {
+        let mut best: Option<(u32, Action)> = None;
+        for binding in KEYBINDINGS {
+            for &(code, modifiers) in self.chords(binding) {
+                let specificity = modifiers.bits().count_ones();
+                let more_specific = match best {
+                    Some((most, _)) => specificity > most,
+                    None => true,
+                };
+                if code == key.code && key.modifiers.contains(modifiers) && more_specific {
+                    best = Some((specificity, binding.action));
+                }
+            }
+        }
+        best.map(|(_, action)| action)
+    }
+
+    /// The keys of `action` as the help overlay writes them, e.g. `Esc` or `Ctrl+Q`
+    pub fn label(&self, action: Action) -> String {
+        match (self.overrides.get(&action), KEYBINDINGS.iter().find(|binding| binding.action == action)) {
+            (Some(chords), _) => format_chords(chords),
+            (None, Some(binding)) if !binding.keys.is_empty() => binding.keys.to_string(),
+            (None, Some(binding)) => format_chords(binding.chords),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// The help overlay: every group with its keys and what they do. Bindings without keys of
+    /// their own are described by the line before them; once either of the two is rebound, each
+    /// gets a line of its own under its action name. Without `keyboard_enhanced`, bindings the
+    /// terminal can't report are left out. The vim keymap adds its sequences, and ignored
+    /// overrides are listed at the end.
+    pub fn help_text(&self, keymap: Keymap, keyboard_enhanced: bool) -> String {
+        let mut rows: Vec<(KeyGroup, String, &str)> = Vec::new();
+        for (i, binding) in KEYBINDINGS.iter().enumerate() {
+            if !keyboard_enhanced && self.chords(binding).iter().all(|&chord| needs_keyboard_enhancement(chord)) {
+                continue;
+            }
+            let shares_line = |binding: &Keybinding| binding.keys.is_empty();
+            let partner_rebound = if shares_line(binding) {
+                self.overrides.contains_key(&KEYBINDINGS[i - 1].action)
+            } else {
+                KEYBINDINGS.get(i + 1).is_some_and(|next| shares_line(next) && self.overrides.contains_key(&next.action))
+            };
+            if self.overrides.contains_key(&binding.action) || partner_rebound {
+                rows.push((binding.group, format_chords(self.chords(binding)), binding.action.name()));
+            } else if !shares_line(binding) {
+                rows.push((binding.group, binding.keys.to_string(), binding.description));
+            }
+        }
+
+        let width = rows.iter().map(|(_, keys, _)| keys.chars().count()).max().unwrap_or(0);
+        let mut text = String::new();
+        for group in KeyGroup::ALL {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(group.title());
+            text.push('\n');
+            for (_, keys, description) in rows.iter().filter(|(row_group, _, _)| *row_group == group) {
+                let padding = width - keys.chars().count();
+                text.push_str(&format!("  {}{}  {}\n", keys, " ".repeat(padding), description));
+            }
+        }
+        if keymap == Keymap::Vim {
+            text.push_str("\nVim normal mode (Esc leaves insert mode)\n");
+            for binding in VIM_BINDINGS {
+                let padding = width.saturating_sub(binding.sequence.chars().count());
+                text.push_str(&format!("  {}{}  {}\n", binding.sequence, " ".repeat(padding), binding.description));
+            }
+        }
+        if !self.warnings.is_empty() {
+            text.push_str(&format!("\nProblems in {}\n", CONFIG_FILE));
+            for warning in &self.warnings {
+                text.push_str(&format!("  {}\n", warning));
+            }
+        }
+        text
+    }
+}
+
+/// Whether only terminals with the kitty keyboard protocol report `chord`: legacy terminals send
+/// Ctrl+Enter and Shift+Enter as plain Enter, and Ctrl with a symbol as the symbol alone
+pub fn needs_keyboard_enhancement((code, modifiers): Chord) -> bool {
+    match code {
+        KeyCode::Enter => modifiers.intersects(CTRL | SHIFT) && !modifiers.contains(ALT),
+        KeyCode::Char(c) => modifiers.contains(CTRL) && !c.is_ascii_alphabetic(),
+        _ => false,
+    }
+}
+
+/// Whether `chord` is a key pressed to type a character, so binding it takes the character away
+/// from the input
+fn types_text((code, modifiers): Chord) -> bool {
+    matches!(code, KeyCode::Char(_)) && !modifiers.intersects(CTRL | ALT)
+}
+
+/// Read a key like `ctrl-q`, `alt+enter`, `f5`, `shift-tab` or `?`
+pub fn parse_chord(spec: &str) -> Result<Chord, String> {
+    let spec = spec.trim();
+    // The key itself may be `-` or `+`, as in `ctrl--`
+    let (modifier_part, key) = match spec.char_indices().rev().find(|&(i, c)| (c == '-' || c == '+') && i + 1 < spec.len()) {
+        Some((i, _)) => (&spec[..i], &spec[i + 1..]),
+        None => ("", spec),
+    };
+
+    let mut modifiers = KeyModifiers::NONE;
+    for modifier in modifier_part.split(['-', '+']).filter(|part| !part.is_empty()) {
+        modifiers |= match modifier.to_lowercase().as_str() {
+            "ctrl" | "control" => CTRL,
+            "alt" | "meta" | "option" => ALT,
+            "shift" => SHIFT,
+            _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, spec)),
+        };
+    }
+
+    let mut chars = key.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_lowercase() && modifiers.contains(SHIFT) => KeyCode::Char(c.to_ascii_uppercase()),
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match key.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=12) => KeyCode::F(n),
+                _ => return Err(format!("unknown key '{}'", spec)),
+            },
+        },
+    };
+    Ok((code, modifiers))
+}
+
+/// A chord as the help overlay writes it, e.g. `Ctrl+Q`
+fn format_chord((code, modifiers): Chord) -> String {
+    let mut text = String::new();
+    for (modifier, name) in [(CTRL, "Ctrl+"), (ALT, "Alt+"), (SHIFT, "Shift+")] {
+        if modifiers.contains(modifier) {
+            text.push_str(name);
+        }
+    }
+    match code {
+        KeyCode::Char(' ') => text.push_str("Space"),
+        KeyCode::Char(c) => text.push(c.to_ascii_uppercase()),
+        KeyCode::F(n) => text.push_str(&format!("F{}", n)),
+        KeyCode::Left => text.push('←'),
+        KeyCode::Right => text.push('→'),
+        KeyCode::Up => text.push('↑'),
+        KeyCode::Down => text.push('↓'),
+        KeyCode::PageUp => text.push_str("PageUp"),
+        KeyCode::PageDown => text.push_str("PageDown"),
+        other => text.push_str(&format!("{:?}", other)),
+    }
+    text
+}
+
+fn format_chords(chords: &[Chord]) -> String {
+    chords.iter().map(|&chord| format_chord(chord)).collect::<Vec<_>>().join(" / ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_action_for() {
+        let table = KeyTable::default();
+        assert_eq!(table.action_for(&key(KeyCode::Left, NONE)), Some(Action::Left));
+        assert_eq!(table.action_for(&key(KeyCode::Left, CTRL)), Some(Action::WordLeft));
+        assert_eq!(table.action_for(&key(KeyCode::Enter, NONE)), Some(Action::Send));
+        assert_eq!(table.action_for(&key(KeyCode::Enter, ALT)), Some(Action::NewLine));
+        assert_eq!(table.action_for(&key(KeyCode::Char('c'), CTRL)), Some(Action::Cancel));
+        assert_eq!(table.action_for(&key(KeyCode::Char('c'), ALT)), Some(Action::Copy));
+        assert_eq!(table.action_for(&key(KeyCode::Char('7'), ALT)), Some(Action::ToggleProvider));
+        assert_eq!(table.action_for(&key(KeyCode::Char('s'), ALT)), Some(Action::ToggleStar));
+        assert_eq!(table.action_for(&key(KeyCode::Char('?'), SHIFT)), Some(Action::Help));
+        assert_eq!(table.action_for(&key(KeyCode::Char('c'), NONE)), None);
+        assert_eq!(table.action_for(&key(KeyCode::Char('A'), SHIFT)), None);
+    }
+
+    #[test]
+    fn test_action_names() {
+        for binding in KEYBINDINGS {
+            assert_eq!(Action::from_name(binding.action.name()), Some(binding.action));
+        }
+        assert_eq!(Action::from_name("insert_mode"), None);
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        assert_eq!(parse_chord("ctrl-q"), Ok((KeyCode::Char('q'), CTRL)));
+        assert_eq!(parse_chord("Ctrl+Alt+Enter"), Ok((KeyCode::Enter, CTRL | ALT)));
+        assert_eq!(parse_chord("shift-g"), Ok((KeyCode::Char('G'), SHIFT)));
+        assert_eq!(parse_chord("f5"), Ok((KeyCode::F(5), NONE)));
+        assert_eq!(parse_chord("?"), Ok((KeyCode::Char('?'), NONE)));
+        assert_eq!(parse_chord("alt--"), Ok((KeyCode::Char('-'), ALT)));
+        assert_eq!(parse_chord("pgdn"), Ok((KeyCode::PageDown, NONE)));
+        assert!(parse_chord("hyper-q").is_err());
+        assert!(parse_chord("ctrl-banana").is_err());
+        assert!(parse_chord("f13").is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let table = KeyTable::from_toml("[keys]\nquit = \"ctrl-q\"\nhelp = [\"f1\", \"ctrl-h\"]\n").unwrap();
+        assert!(table.warnings().is_empty());
+        assert_eq!(table.action_for(&key(KeyCode::Char('q'), CTRL)), Some(Action::Quit));
+        assert_eq!(table.action_for(&key(KeyCode::Esc, NONE)), None);
+        assert_eq!(table.action_for(&key(KeyCode::Char('h'), CTRL)), Some(Action::Help));
+        assert_eq!(table.label(Action::Quit), "Ctrl+Q");
+        assert_eq!(table.label(Action::Help), "F1 / Ctrl+H");
+        assert_eq!(KeyTable::default().label(Action::Quit), "Esc");
+
+        // Other sections of the file are not ours
+        assert!(KeyTable::from_toml("[theme]\nname = \"dark\"\n").unwrap().warnings().is_empty());
+    }
+
+    #[test]
+    fn test_bad_overrides_keep_the_defaults() {
+        let table = KeyTable::from_toml(
+            "[keys]\nquit = \"ctrl-c\"\nteleport = \"f9\"\nsave = \"ctrl-banana\"\ntoggle_provider = \"f9\"\nfork = []\n",
+        )
+        .unwrap();
+        let warnings = table.warnings().join("\n");
+        assert!(warnings.contains("quit: Ctrl+C is also bound to cancel"), "{}", warnings);
+        assert!(warnings.contains("teleport: no such action"));
+        assert!(warnings.contains("save: unknown key 'ctrl-banana'"));
+        assert!(warnings.contains("toggle_provider: can't be rebound"));
+        assert!(warnings.contains("fork: no keys given"));
+        assert_eq!(table.warnings().len(), 5);
+        assert_eq!(table.action_for(&key(KeyCode::Esc, NONE)), Some(Action::Quit));
+        assert_eq!(table.action_for(&key(KeyCode::Char('c'), CTRL)), Some(Action::Cancel));
+
+        // Swapping two keys is not a conflict
+        let table = KeyTable::from_toml("[keys]\nquit = \"ctrl-c\"\ncancel = \"esc\"\n").unwrap();
+        assert!(table.warnings().is_empty());
+        assert_eq!(table.action_for(&key(KeyCode::Char('c'), CTRL)), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_help_text_shows_overrides() {
+        let table = KeyTable::from_toml("[keys]\nquit = \"ctrl-q\"\nright = \"ctrl-n\"\nteleport = \"f9\"\n").unwrap();
+        let help = table.help_text(Keymap::Default, true);
+        assert!(help.contains("  Ctrl+Q "));
+        assert!(!help.contains("  Esc "));
+        // The pair that shared a line now gets one line each
+        assert!(help.contains("  ←      "));
+        assert!(help.contains("  Ctrl+N "));
+        assert!(!help.contains("  ← / →"));
+        assert!(help.contains("Problems in ~/.chatdelta/config.toml\n  teleport: no such action"));
+    }
+
+    #[test]
+    fn test_help_text() {
+        let help = KeyTable::default().help_text(Keymap::Default, true);
+        for group in KeyGroup::ALL {
+            assert!(help.contains(group.title()), "{} has no bindings", group.title());
+        }
+        assert!(help.contains("  Alt+1-9             Turn a column on or off\n"));
+        assert!(help.contains("  F1 / ?  "));
+        assert_eq!(help.lines().filter(|line| line.starts_with("  ")).count(), KEYBINDINGS.iter().filter(|b| !b.keys.is_empty()).count());
+        assert!(!help.contains("Vim"));
+
+        // Legacy terminals can't report Ctrl+Enter, so it isn't offered there
+        let help = KeyTable::default().help_text(Keymap::Default, false);
+        assert!(!help.contains("Ctrl+Enter"));
+        assert!(help.contains("  Shift/Alt+Enter "));
+
+        let help = KeyTable::default().help_text(Keymap::Vim, true);
+        assert!(help.contains("Vim normal mode"));
+        assert!(help.contains("  gg  "));
+    }
+
+    fn resolve_all(resolver: &mut KeyResolver, keys: &str) -> Vec<Option<Action>> {
+        keys.chars().map(|c| resolver.resolve(&key(KeyCode::Char(c), NONE))).collect()
+    }
+
+    #[test]
+    fn test_default_keymap_resolves_like_action_for() {
+        let mut resolver = KeyResolver::new(Keymap::Default, KeyTable::default());
+        assert_eq!(resolver.resolve(&key(KeyCode::Esc, NONE)), Some(Action::Quit));
+        assert_eq!(resolver.resolve(&key(KeyCode::Char('h'), NONE)), None);
+        assert!(resolver.accepts_text());
+        assert_eq!(resolver.mode_label(), None);
+    }
+
+    #[test]
+    fn test_vim_normal_mode() {
+        let mut resolver = KeyResolver::new(Keymap::Vim, KeyTable::default());
+        assert!(!resolver.accepts_text());
+        assert_eq!(resolver.mode_label(), Some("-- NORMAL --"));
+        assert_eq!(resolve_all(&mut resolver, "hjkl"), [Some(Action::PreviousColumn), Some(Action::Down), Some(Action::Up), Some(Action::NextColumn)]);
+        assert_eq!(resolver.resolve(&key(KeyCode::Char('G'), SHIFT)), Some(Action::ScrollBottom));
+
+        assert_eq!(resolve_all(&mut resolver, "g"), [None]);
+        assert_eq!(resolver.pending_keys(), "g");
+        assert_eq!(resolve_all(&mut resolver, "g"), [Some(Action::ScrollTop)]);
+        assert_eq!(resolver.pending_keys(), "");
+
+        // A sequence that goes wrong is dropped, and Esc only clears what was typed
+        assert_eq!(resolve_all(&mut resolver, "gx"), [None, None]);
+        assert_eq!(resolve_all(&mut resolver, ":"), [None]);
+        assert_eq!(resolver.resolve(&key(KeyCode::Esc, NONE)), None);
+        assert_eq!(resolver.pending_keys(), "");
+        assert_eq!(resolve_all(&mut resolver, "x"), [None]);
+
+        assert_eq!(resolve_all(&mut resolver, ":q"), [None, Some(Action::Quit)]);
+        assert_eq!(resolve_all(&mut resolver, "ZZ"), [None, Some(Action::Quit)]);
+        assert_eq!(resolve_all(&mut resolver, "?"), [Some(Action::Help)]);
+        assert_eq!(resolver.resolve(&key(KeyCode::Char('r'), CTRL)), Some(Action::Regenerate));
+        assert_eq!(resolver.resolve(&key(KeyCode::Enter, NONE)), Some(Action::Send));
+    }
+
+    #[test]
+    fn test_vim_insert_mode() {
+        let mut resolver = KeyResolver::new(Keymap::Vim, KeyTable::default());
+        assert_eq!(resolve_all(&mut resolver, "i"), [Some(Action::InsertMode)]);
+        assert!(resolver.accepts_text());
+        assert_eq!(resolver.mode_label(), Some("-- INSERT --"));
+        assert_eq!(resolve_all(&mut resolver, "hjk:q"), [None; 5]);
+        assert_eq!(resolver.resolve(&key(KeyCode::Left, NONE)), Some(Action::Left));
+
+        assert_eq!(resolver.resolve(&key(KeyCode::Esc, NONE)), Some(Action::NormalMode));
+        assert_eq!(resolver.mode, VimMode::Normal);
+        assert!(!resolver.accepts_text());
+    }
+
+    #[test]
+    fn test_vim_keeps_rebound_quit() {
+        let table = KeyTable::from_toml("[keys]\nquit = \"f10\"\n").unwrap();
+        let mut resolver = KeyResolver::new(Keymap::Vim, table);
+        assert_eq!(resolver.resolve(&key(KeyCode::F(10), NONE)), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_keymap_from_toml() {
+        assert_eq!(Keymap::from_toml("keymap = \"vim\"").unwrap(), Keymap::Vim);
+        assert_eq!(Keymap::from_toml("").unwrap(), Keymap::Default);
+        assert!(Keymap::from_toml("keymap = \"emacs\"").is_err());
+        // The key table reads the same file and reports the error
+        assert!(KeyTable::from_toml("keymap = \"emacs\"").is_err());
+
+        // The keymap and the overrides share one file
+        let contents = "keymap = \"vim\"\n\n[keys]\nquit = \"f10\"\n";
+        assert_eq!(Keymap::from_toml(contents).unwrap(), Keymap::Vim);
+        let table = KeyTable::from_toml(contents).unwrap();
+        assert!(table.warnings().is_empty());
+        assert_eq!(table.action_for(&key(KeyCode::F(10), NONE)), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_printable_overrides_warn() {
+        let table = KeyTable::from_toml("[keys]\nsend = [\"enter\", \"s\"]\nsave = \"shift-w\"\nfork = \"alt-f\"\n").unwrap();
+        assert_eq!(table.warnings(), ["save: Shift+W can no longer be typed", "send: S can no longer be typed"]);
+        // They are bound all the same
+        assert_eq!(table.action_for(&key(KeyCode::Char('s'), NONE)), Some(Action::Send));
+        assert_eq!(table.action_for(&key(KeyCode::Char('W'), SHIFT)), Some(Action::Save));
+    }
+}
@@ -1,4 +1,28 @@
+pub mod aliases;
+pub mod benchmark;
 pub mod cli;
+pub mod clipboard;
+pub mod commands;
+pub mod delta;
+pub mod dry_run;
+pub mod events;
+pub mod filters;
+pub mod headless;
+pub mod highlight;
+pub mod history;
+pub mod keys;
 pub mod logger;
+pub mod metrics;
 pub mod output;
+pub mod pipe;
+pub mod presets;
+pub mod progress;
+pub mod request_log;
+pub mod sentiment;
+pub mod theme;
 pub mod tui;
+pub mod webhook;
+#[cfg(feature = "screenshot")]
+pub mod screenshot;
+#[cfg(feature = "voice")]
+pub mod voice;
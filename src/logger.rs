@@ -2,13 +2,19 @@
 //!
 //! Saves all conversations, responses, and delta analyses to JSON files in ~/.chatdelta/logs/
 
+use crate::aliases::PROVIDER_NAMES;
+use crate::events::ResponseType;
+use crate::sentiment::{sentiment_classify, SentimentScore};
+use chatdelta::TokenUsage;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,15 +22,40 @@ pub struct ConversationLog {
     pub session_id: Uuid,
     pub start_time: DateTime<Utc>,
     pub end_time: Option<DateTime<Utc>>,
+    /// Note pinned to the session with `/pin`
+    #[serde(default)]
+    pub session_note: Option<String>,
+    /// `User-Agent` the providers saw, for matching the session to their request logs
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Session this one was forked from with Ctrl+F; its conversations up to the fork are
+    /// copied into this log
+    #[serde(default)]
+    pub forked_from: Option<Uuid>,
+    /// Labels given to providers with `/rename`, by provider name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub display_names: HashMap<String, String>,
     pub conversations: Vec<ConversationEntry>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConversationEntry {
+    /// Correlation ID sent to providers with this exchange's requests
+    #[serde(default)]
+    pub request_id: Uuid,
     pub timestamp: DateTime<Utc>,
     pub prompt: String,
+    /// Sampling seed sent with this exchange, for comparing reproducible runs
+    #[serde(default)]
+    pub seed: Option<u64>,
     pub responses: HashMap<String, ProviderResponse>,
     pub delta_analysis: Option<String>,
+    /// Labels added with `/tag`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Starred with Alt+S as one of the session's best exchanges
+    #[serde(default)]
+    pub starred: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,12 +63,67 @@ pub struct ProviderResponse {
     pub text: String,
     pub latency_ms: Option<u64>,
     pub error: Option<String>,
+    /// Model the request asked for, e.g. `gpt-4o`
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Model checkpoint the provider reported, e.g. `gpt-4o-2024-11-20`
+    #[serde(default)]
+    pub model_version: Option<String>,
+    /// The provider stopped at its token limit
+    #[serde(default)]
+    pub truncated: bool,
+    /// Backend configuration reported by the provider (OpenAI `system_fingerprint`)
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// The first response was empty or rejected, and this one came from a nudged retry
+    #[serde(default)]
+    pub validation_retried: bool,
+    /// Time to the first streamed token; unset for non-streamed responses
+    #[serde(default)]
+    pub ttfb_ms: Option<u64>,
+    /// Prompt tokens billed, when the provider reported usage
+    #[serde(default)]
+    pub input_tokens: Option<u32>,
+    /// Response tokens billed, when the provider reported usage
+    #[serde(default)]
+    pub output_tokens: Option<u32>,
+    /// Cost of the request in USD, estimated from the model catalog's prices
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+    /// Set by `Logger::compute_sentiment`; unset for errors
+    #[serde(default)]
+    pub sentiment: Option<SentimentScore>,
+}
+
+impl ProviderResponse {
+    /// Latency, tokens and cost of the response, e.g. `2.4s • 312 tokens • $0.004`; `None` for
+    /// errors and for responses with none of them recorded
+    pub fn stats_line(&self) -> Option<String> {
+        if self.error.is_some() {
+            return None;
+        }
+        let tokens = match (self.input_tokens, self.output_tokens) {
+            (None, None) => None,
+            (input, output) => Some(input.unwrap_or(0) + output.unwrap_or(0)),
+        };
+        let parts: Vec<String> = [
+            self.latency_ms.map(|ms| format!("{:.1}s", ms as f64 / 1000.0)),
+            tokens.map(|tokens| format!("{} tokens", tokens)),
+            self.cost_usd.map(|cost| format!("${:.3}", cost)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!parts.is_empty()).then(|| parts.join(" • "))
+    }
 }
 
 pub struct Logger {
     log: ConversationLog,
     current_conversation: Option<ConversationEntry>,
     response_timers: HashMap<String, Instant>,
+    /// Model each provider is currently asked for, recorded with its responses
+    provider_models: HashMap<String, String>,
 }
 
 impl Logger {
@@ -47,45 +133,85 @@ impl Logger {
                 session_id: Uuid::new_v4(),
                 start_time: Utc::now(),
                 end_time: None,
+                session_note: None,
+                user_agent: None,
+                forked_from: None,
+                display_names: HashMap::new(),
                 conversations: Vec::new(),
             },
             current_conversation: None,
             response_timers: HashMap::new(),
+            provider_models: HashMap::new(),
         }
     }
 
-    pub fn log_prompt(&mut self, prompt: &str) {
+    /// Start a new exchange and return its correlation ID. The previous exchange is kept even
+    /// if it never got a delta analysis.
+    pub fn log_prompt(&mut self, prompt: &str) -> Uuid {
+        self.finalize_conversation();
+        let request_id = Uuid::new_v4();
         let entry = ConversationEntry {
+            request_id,
             timestamp: Utc::now(),
             prompt: prompt.to_string(),
+            seed: None,
             responses: HashMap::new(),
             delta_analysis: None,
+            tags: Vec::new(),
+            starred: false,
         };
         self.current_conversation = Some(entry);
         self.response_timers.clear();
+        request_id
     }
 
     pub fn start_provider_timer(&mut self, provider: &str) {
         self.response_timers.insert(provider.to_string(), Instant::now());
     }
 
+    /// Time since `provider`'s request in the current exchange was sent
+    pub fn elapsed(&self, provider: &str) -> Option<Duration> {
+        self.response_timers.get(provider).map(Instant::elapsed)
+    }
+
     pub fn log_provider_response(&mut self, provider: &str, response: &str, is_error: bool) {
         if let Some(ref mut conversation) = self.current_conversation {
             let latency_ms = self.response_timers
                 .get(provider)
                 .map(|start| start.elapsed().as_millis() as u64);
+            let model = self.provider_models.get(provider).cloned();
 
             let provider_response = if is_error {
                 ProviderResponse {
                     text: String::new(),
                     latency_ms,
                     error: Some(response.to_string()),
+                    model: model.clone(),
+                    model_version: None,
+                    truncated: false,
+                    system_fingerprint: None,
+                    validation_retried: false,
+                    ttfb_ms: None,
+                    input_tokens: None,
+                    output_tokens: None,
+                    cost_usd: None,
+                    sentiment: None,
                 }
             } else {
                 ProviderResponse {
                     text: response.to_string(),
                     latency_ms,
                     error: None,
+                    model,
+                    model_version: None,
+                    truncated: false,
+                    system_fingerprint: None,
+                    validation_retried: false,
+                    ttfb_ms: None,
+                    input_tokens: None,
+                    output_tokens: None,
+                    cost_usd: None,
+                    sentiment: None,
                 }
             };
 
@@ -93,6 +219,151 @@ impl Logger {
         }
     }
 
+    /// Model `provider`'s following responses are requested from
+    pub fn set_provider_model(&mut self, provider: &str, model: &str) {
+        self.provider_models.insert(provider.to_string(), model.to_string());
+    }
+
+    /// Record the model checkpoint reported for a response that was already logged
+    pub fn log_model_version(&mut self, provider: &str, model_version: &str) {
+        if let Some(response) = self.current_conversation
+            .as_mut()
+            .and_then(|conversation| conversation.responses.get_mut(provider))
+        {
+            response.model_version = Some(model_version.to_string());
+        }
+    }
+
+    /// Add tags to the latest exchange, whether or not it has been finalized.
+    /// Tags it already has are skipped. Returns `false` when nothing has been logged yet.
+    pub fn add_tags(&mut self, tags: &[String]) -> bool {
+        let entry = match self.current_conversation.as_mut() {
+            Some(conversation) => conversation,
+            None => match self.log.conversations.last_mut() {
+                Some(conversation) => conversation,
+                None => return false,
+            },
+        };
+        for tag in tags {
+            if !entry.tags.contains(tag) {
+                entry.tags.push(tag.clone());
+            }
+        }
+        true
+    }
+
+    /// All exchanges of this session carrying `tag`, oldest first
+    pub fn get_tagged_entries(&self, tag: &str) -> Vec<&ConversationEntry> {
+        self.log.conversations
+            .iter()
+            .chain(self.current_conversation.as_ref())
+            .filter(|entry| entry.tags.iter().any(|t| t == tag))
+            .collect()
+    }
+
+    /// Star the exchange with `request_id`, or unstar it. Returns whether it is starred now,
+    /// or `None` if the session has no such exchange.
+    pub fn toggle_star(&mut self, request_id: Uuid) -> Option<bool> {
+        let entry = self.log.conversations
+            .iter_mut()
+            .chain(self.current_conversation.as_mut())
+            .find(|entry| entry.request_id == request_id)?;
+        entry.starred = !entry.starred;
+        Some(entry.starred)
+    }
+
+    /// Whether the exchange with `request_id` is starred
+    pub fn is_starred(&self, request_id: Uuid) -> bool {
+        self.log.conversations
+            .iter()
+            .chain(self.current_conversation.as_ref())
+            .any(|entry| entry.request_id == request_id && entry.starred)
+    }
+
+    /// All starred exchanges of this session, oldest first
+    pub fn get_starred(&self) -> Vec<&ConversationEntry> {
+        self.log.conversations
+            .iter()
+            .chain(self.current_conversation.as_ref())
+            .filter(|entry| entry.starred)
+            .collect()
+    }
+
+    /// Correlation ID of the exchange being recorded, or of the latest one
+    pub fn latest_request_id(&self) -> Option<Uuid> {
+        self.current_conversation.as_ref().or(self.log.conversations.last()).map(|entry| entry.request_id)
+    }
+
+    /// Record the seed sent with the current exchange
+    pub fn log_seed(&mut self, seed: Option<u64>) {
+        if let Some(conversation) = self.current_conversation.as_mut() {
+            conversation.seed = seed;
+        }
+    }
+
+    /// Record the backend fingerprint reported for a response that was already logged
+    pub fn log_system_fingerprint(&mut self, provider: &str, fingerprint: &str) {
+        if let Some(response) = self.current_conversation
+            .as_mut()
+            .and_then(|conversation| conversation.responses.get_mut(provider))
+        {
+            response.system_fingerprint = Some(fingerprint.to_string());
+        }
+    }
+
+    /// Mark a logged response as cut off by the token limit
+    pub fn log_truncated(&mut self, provider: &str) {
+        if let Some(response) = self.current_conversation
+            .as_mut()
+            .and_then(|conversation| conversation.responses.get_mut(provider))
+        {
+            response.truncated = true;
+        }
+    }
+
+    /// Record that a logged response needed a validation retry
+    pub fn log_validation_retry(&mut self, provider: &str) {
+        if let Some(response) = self.current_conversation
+            .as_mut()
+            .and_then(|conversation| conversation.responses.get_mut(provider))
+        {
+            response.validation_retried = true;
+        }
+    }
+
+    /// Record how long a logged, streamed response took to its first token
+    pub fn log_time_to_first_token(&mut self, provider: &str, time_to_first_token: Duration) {
+        if let Some(response) = self.current_conversation
+            .as_mut()
+            .and_then(|conversation| conversation.responses.get_mut(provider))
+        {
+            response.ttfb_ms = Some(time_to_first_token.as_millis() as u64);
+        }
+    }
+
+    /// Record the tokens billed for a logged response and their estimated cost
+    pub fn log_token_usage(&mut self, provider: &str, usage: TokenUsage, cost_usd: Option<f64>) {
+        if let Some(response) = self.current_conversation
+            .as_mut()
+            .and_then(|conversation| conversation.responses.get_mut(provider))
+        {
+            response.input_tokens = Some(usage.input_tokens);
+            response.output_tokens = Some(usage.output_tokens);
+            response.cost_usd = cost_usd;
+        }
+    }
+
+    /// Score every response of the session that has no sentiment yet, including those of the
+    /// exchange in progress
+    pub fn compute_sentiment(&mut self) {
+        let conversations = self.log.conversations.iter_mut().chain(self.current_conversation.as_mut());
+        for response in conversations.flat_map(|conversation| conversation.responses.values_mut()) {
+            if response.sentiment.is_none() && response.error.is_none() {
+                response.sentiment = Some(sentiment_classify(&response.text));
+            }
+        }
+    }
+
     pub fn log_delta_analysis(&mut self, delta: &str) {
         if let Some(ref mut conversation) = self.current_conversation {
             conversation.delta_analysis = Some(delta.to_string());
@@ -104,6 +375,27 @@ impl Logger {
         }
     }
 
+    /// The exchange currently being recorded, if any
+    pub fn current_conversation(&self) -> Option<&ConversationEntry> {
+        self.current_conversation.as_ref()
+    }
+
+    pub fn set_session_note(&mut self, note: Option<String>) {
+        self.log.session_note = note;
+    }
+
+    /// Record the label `provider` is shown under, or that it has none any more
+    pub fn set_display_name(&mut self, provider: &str, label: Option<&str>) {
+        match label {
+            Some(label) => self.log.display_names.insert(provider.to_string(), label.to_string()),
+            None => self.log.display_names.remove(provider),
+        };
+    }
+
+    pub fn set_user_agent(&mut self, user_agent: &str) {
+        self.log.user_agent = Some(user_agent.to_string());
+    }
+
     pub fn finalize_conversation(&mut self) {
         // If there's a conversation without delta analysis, still save it
         if let Some(conversation) = self.current_conversation.take() {
@@ -113,7 +405,24 @@ impl Logger {
 
     pub fn save(&mut self) -> Result<PathBuf, Box<dyn std::error::Error>> {
         self.log.end_time = Some(Utc::now());
-        
+        self.write_log(&self.log)
+    }
+
+    /// Save the session so far, including the exchange still being recorded, and keep going.
+    /// The file name only depends on the session, so each snapshot replaces the one before.
+    pub fn save_snapshot(&mut self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.log.end_time = Some(Utc::now());
+        self.write_log(&self.snapshot())
+    }
+
+    /// The log with the exchange still being recorded at the end
+    fn snapshot(&self) -> ConversationLog {
+        let mut log = self.log.clone();
+        log.conversations.extend(self.current_conversation.clone());
+        log
+    }
+
+    fn write_log(&self, log: &ConversationLog) -> Result<PathBuf, Box<dyn std::error::Error>> {
         // Create log directory structure
         let log_dir = self.get_log_directory()?;
         fs::create_dir_all(&log_dir)?;
@@ -128,7 +437,7 @@ impl Logger {
         let file_path = log_dir.join(filename);
         
         // Write JSON to file
-        let json = serde_json::to_string_pretty(&self.log)?;
+        let json = serde_json::to_string_pretty(log)?;
         let mut file = fs::File::create(&file_path)?;
         file.write_all(json.as_bytes())?;
         
@@ -148,6 +457,47 @@ impl Logger {
         Ok(log_dir)
     }
 
+    /// Per-provider totals for the session so far, including the exchange still being recorded
+    pub fn get_statistics(&self) -> SessionStatistics {
+        SessionStatistics::from_entries(self.log.conversations.iter().chain(self.current_conversation.as_ref()))
+    }
+
+    /// Continue recording in a fork of the session, leaving the log so far as it is. Returns
+    /// the fork's session ID.
+    pub fn fork(&mut self) -> Uuid {
+        self.finalize_conversation();
+        self.log = self.log.fork();
+        self.log.session_id
+    }
+
+    /// Prompts sent this session, including the one being answered
+    pub fn exchange_count(&self) -> usize {
+        self.log.conversations.len() + usize::from(self.current_conversation.is_some())
+    }
+
+    /// Send a saved session through `tx` the way live requests arrive: each exchange's
+    /// responses in column order, then its delta analysis. Errors are sent as their
+    /// `Error: ...` text, and providers without a column are skipped. Stops early if the
+    /// receiver is gone.
+    pub fn replay_to_channel(log: &ConversationLog, tx: mpsc::UnboundedSender<ResponseType>) {
+        for entry in &log.conversations {
+            for (idx, provider) in PROVIDER_NAMES.iter().enumerate() {
+                let Some(response) = entry.responses.get(*provider) else {
+                    continue;
+                };
+                let text = response.error.clone().unwrap_or_else(|| response.text.clone());
+                if tx.send(ResponseType::Provider(idx, text)).is_err() {
+                    return;
+                }
+            }
+            if let Some(delta) = &entry.delta_analysis {
+                if tx.send(ResponseType::Delta(delta.clone())).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
     pub fn session_id(&self) -> &Uuid {
         &self.log.session_id
     }
@@ -157,6 +507,156 @@ impl Logger {
     }
 }
 
+impl ConversationLog {
+    /// A copy of the log under a new session ID, starting now, that records where it came from
+    pub fn fork(&self) -> Self {
+        Self {
+            session_id: Uuid::new_v4(),
+            start_time: Utc::now(),
+            end_time: None,
+            forked_from: Some(self.session_id),
+            ..self.clone()
+        }
+    }
+
+    /// A copy of the log with only its starred exchanges, for `--starred-only`
+    pub fn starred_only(&self) -> Self {
+        Self {
+            conversations: self.conversations.iter().filter(|entry| entry.starred).cloned().collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Read a session log written by `Logger::save`
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// The label `provider` was given with `/rename`, or its own name
+    pub fn display_name<'a>(&'a self, provider: &'a str) -> &'a str {
+        self.display_names.get(provider).map_or(provider, String::as_str)
+    }
+
+    /// Each prompt with the text of every provider that answered it, under the provider's
+    /// display name, for fine-tuning and evaluation scripts. Errors are left out, and so are
+    /// prompts no provider answered.
+    pub fn to_prompt_response_pairs(&self) -> Vec<(String, HashMap<String, String>)> {
+        self.conversations
+            .iter()
+            .filter_map(|entry| {
+                let responses: HashMap<String, String> = entry.responses
+                    .iter()
+                    .filter(|(_, response)| response.error.is_none())
+                    .map(|(provider, response)| (self.display_name(provider).to_string(), response.text.clone()))
+                    .collect();
+                (!responses.is_empty()).then(|| (entry.prompt.clone(), responses))
+            })
+            .collect()
+    }
+
+    /// `to_prompt_response_pairs` as JSON Lines, one `{"prompt": ..., "responses": {...}}`
+    /// object per line with the providers in name order
+    pub fn to_jsonl_string(&self) -> String {
+        self.to_prompt_response_pairs()
+            .into_iter()
+            .map(|(prompt, responses)| {
+                let responses: BTreeMap<String, String> = responses.into_iter().collect();
+                serde_json::json!({ "prompt": prompt, "responses": responses }).to_string() + "\n"
+            })
+            .collect()
+    }
+}
+
+/// Totals over a session's exchanges, for reporting once it is over
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionStatistics {
+    pub prompt_count: usize,
+    pub provider_stats: HashMap<String, ProviderStats>,
+}
+
+/// One provider's totals within a session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderStats {
+    /// Responses that arrived, not counting errors
+    pub response_count: usize,
+    pub error_count: usize,
+    /// Mean latency of the requests that were timed, errors included
+    pub avg_latency_ms: Option<u64>,
+    /// Slowest timed request
+    pub max_latency_ms: Option<u64>,
+    /// Input plus output tokens, over the responses that reported usage
+    pub total_tokens: u64,
+    pub estimated_cost_usd: f64,
+}
+
+impl SessionStatistics {
+    pub fn from_log(log: &ConversationLog) -> Self {
+        Self::from_entries(log.conversations.iter())
+    }
+
+    fn from_entries<'a>(entries: impl Iterator<Item = &'a ConversationEntry>) -> Self {
+        let mut statistics = Self::default();
+        // Latency sums and counts per provider, averaged once every entry has been seen
+        let mut latencies: HashMap<&str, (u64, u64)> = HashMap::new();
+        for entry in entries {
+            statistics.prompt_count += 1;
+            for (provider, response) in &entry.responses {
+                let stats = statistics.provider_stats.entry(provider.clone()).or_default();
+                if response.error.is_some() {
+                    stats.error_count += 1;
+                } else {
+                    stats.response_count += 1;
+                }
+                if let Some(latency_ms) = response.latency_ms {
+                    let (sum, count) = latencies.entry(provider.as_str()).or_default();
+                    *sum += latency_ms;
+                    *count += 1;
+                    stats.max_latency_ms = stats.max_latency_ms.max(Some(latency_ms));
+                }
+                stats.total_tokens += u64::from(response.input_tokens.unwrap_or(0))
+                    + u64::from(response.output_tokens.unwrap_or(0));
+                stats.estimated_cost_usd += response.cost_usd.unwrap_or(0.0);
+            }
+        }
+        for (provider, (sum, count)) in latencies {
+            if let Some(stats) = statistics.provider_stats.get_mut(provider) {
+                stats.avg_latency_ms = Some(sum / count);
+            }
+        }
+        statistics
+    }
+}
+
+impl fmt::Display for SessionStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📊 Session statistics: {} prompt(s)", self.prompt_count)?;
+        writeln!(
+            f,
+            "{:<10} {:>9} {:>7} {:>12} {:>12} {:>8} {:>10}",
+            "Provider", "Responses", "Errors", "Avg latency", "Max latency", "Tokens", "Est. cost"
+        )?;
+        let mut providers: Vec<_> = self.provider_stats.iter().collect();
+        providers.sort_by(|a, b| a.0.cmp(b.0));
+        let ms = |latency: Option<u64>| latency.map_or_else(|| "-".to_string(), |ms| format!("{} ms", ms));
+        for (provider, stats) in providers {
+            let cost = format!("${:.4}", stats.estimated_cost_usd);
+            writeln!(
+                f,
+                "{:<10} {:>9} {:>7} {:>12} {:>12} {:>8} {:>10}",
+                provider,
+                stats.response_count,
+                stats.error_count,
+                ms(stats.avg_latency_ms),
+                ms(stats.max_latency_ms),
+                stats.total_tokens,
+                cost,
+            )?;
+        }
+        Ok(())
+    }
+}
+
 impl Default for Logger {
     fn default() -> Self {
         Self::new()
@@ -166,6 +666,7 @@ impl Default for Logger {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sentiment::Sentiment;
 
     #[test]
     fn test_logger_creation() {
@@ -174,6 +675,21 @@ mod tests {
         assert!(logger.log.end_time.is_none());
     }
 
+    #[test]
+    fn test_user_agent_is_logged() {
+        let mut logger = Logger::new();
+        logger.set_user_agent("chatdelta/0.1.0");
+        let json = serde_json::to_value(&logger.log).unwrap();
+        assert_eq!(json["user_agent"], "chatdelta/0.1.0");
+
+        // Logs saved before the field existed still load
+        let old: ConversationLog = serde_json::from_str(
+            r#"{"session_id":"00000000-0000-0000-0000-000000000000","start_time":"2024-01-01T00:00:00Z","end_time":null,"conversations":[]}"#,
+        )
+        .unwrap();
+        assert!(old.user_agent.is_none());
+    }
+
     #[test]
     fn test_log_conversation_flow() {
         let mut logger = Logger::new();
@@ -198,6 +714,7 @@ mod tests {
         
         let conversation = &logger.log.conversations[0];
         assert_eq!(conversation.prompt, "What is Rust?");
+        assert!(!conversation.request_id.is_nil());
         assert_eq!(conversation.responses.len(), 2);
         assert!(conversation.delta_analysis.is_some());
     }
@@ -216,4 +733,299 @@ mod tests {
         assert_eq!(response.error.as_ref().unwrap(), "API key invalid");
         assert_eq!(response.text, "");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_compute_sentiment() {
+        let mut logger = Logger::new();
+
+        logger.log_prompt("Is Rust good?");
+        logger.log_provider_response("ChatGPT", "Rust is great and reliable.", false);
+        logger.log_delta_analysis("");
+        logger.log_prompt("Any downsides?");
+        logger.log_provider_response("ChatGPT", "Compile times are slow and the borrow checker is hard.", false);
+        logger.log_provider_response("Claude", "Overloaded", true);
+        logger.compute_sentiment();
+
+        let first = &logger.log.conversations[0].responses["ChatGPT"];
+        assert_eq!(first.sentiment.unwrap().dominant(), Sentiment::Positive);
+        let current = logger.current_conversation().unwrap();
+        assert_eq!(current.responses["ChatGPT"].sentiment.unwrap().dominant(), Sentiment::Negative);
+        assert!(current.responses["Claude"].sentiment.is_none());
+    }
+
+    #[test]
+    fn test_model_version_logging() {
+        let mut logger = Logger::new();
+
+        logger.log_prompt("Test prompt");
+        logger.log_seed(Some(42));
+        logger.log_provider_response("ChatGPT", "Hello", false);
+        logger.log_model_version("ChatGPT", "gpt-4o-2024-11-20");
+        logger.log_system_fingerprint("ChatGPT", "fp_44709d6fcb");
+        logger.log_truncated("ChatGPT");
+        logger.log_validation_retry("ChatGPT");
+
+        let conversation = logger.current_conversation.as_ref().unwrap();
+        assert_eq!(conversation.seed, Some(42));
+        let response = conversation.responses.get("ChatGPT").unwrap();
+        assert_eq!(response.model_version.as_deref(), Some("gpt-4o-2024-11-20"));
+        assert_eq!(response.system_fingerprint.as_deref(), Some("fp_44709d6fcb"));
+        assert!(response.truncated);
+        assert!(response.validation_retried);
+    }
+
+    #[test]
+    fn test_model_is_logged_per_response() {
+        let mut logger = Logger::new();
+        logger.set_provider_model("Claude", "claude-3-5-sonnet-20241022");
+        logger.log_prompt("Hi");
+        logger.log_provider_response("Claude", "Hello", false);
+        logger.log_provider_response("Gemini", "Hello", false);
+        logger.finalize_conversation();
+
+        logger.set_provider_model("Claude", "claude-3-5-haiku-20241022");
+        logger.log_prompt("Again");
+        logger.log_provider_response("Claude", "timeout", true);
+
+        let first = &logger.log.conversations[0].responses;
+        assert_eq!(first["Claude"].model.as_deref(), Some("claude-3-5-sonnet-20241022"));
+        assert_eq!(first["Gemini"].model, None);
+        let second = &logger.current_conversation().unwrap().responses;
+        assert_eq!(second["Claude"].model.as_deref(), Some("claude-3-5-haiku-20241022"));
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut logger = Logger::new();
+        assert!(!logger.add_tags(&["bug".to_string()]));
+
+        logger.log_prompt("First");
+        logger.log_delta_analysis("Same");
+        // The first exchange is finalized, so tags land on it
+        assert!(logger.add_tags(&["bug".to_string(), "performance".to_string()]));
+        assert!(logger.add_tags(&["bug".to_string()]));
+
+        logger.log_prompt("Second");
+        logger.add_tags(&["performance".to_string()]);
+
+        let bugs = logger.get_tagged_entries("bug");
+        assert_eq!(bugs.len(), 1);
+        assert_eq!(bugs[0].tags, vec!["bug", "performance"]);
+        assert_eq!(logger.get_tagged_entries("performance").len(), 2);
+        assert!(logger.get_tagged_entries("docs").is_empty());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut logger = Logger::new();
+        logger.log_prompt("First");
+        logger.log_provider_response("ChatGPT", "Hi", false);
+        logger.log_token_usage("ChatGPT", TokenUsage { input_tokens: 10, output_tokens: 5 }, Some(0.25));
+        logger.log_provider_response("Claude", "timeout", true);
+        logger.log_delta_analysis("Only one answered");
+
+        logger.log_prompt("Second");
+        logger.log_provider_response("ChatGPT", "Hello", false);
+        logger.log_token_usage("ChatGPT", TokenUsage { input_tokens: 20, output_tokens: 7 }, Some(0.5));
+        for response in logger.current_conversation.as_mut().unwrap().responses.values_mut() {
+            response.latency_ms = Some(300);
+        }
+        logger.log.conversations[0].responses.get_mut("ChatGPT").unwrap().latency_ms = Some(100);
+
+        // The exchange still being recorded counts
+        let statistics = logger.get_statistics();
+        assert_eq!(statistics.prompt_count, 2);
+        let chatgpt = &statistics.provider_stats["ChatGPT"];
+        assert_eq!((chatgpt.response_count, chatgpt.error_count), (2, 0));
+        assert_eq!(chatgpt.avg_latency_ms, Some(200));
+        assert_eq!(chatgpt.max_latency_ms, Some(300));
+        assert_eq!(chatgpt.total_tokens, 42);
+        assert!((chatgpt.estimated_cost_usd - 0.75).abs() < 1e-9);
+        let claude = &statistics.provider_stats["Claude"];
+        assert_eq!((claude.response_count, claude.error_count), (0, 1));
+        assert_eq!(claude.avg_latency_ms, None);
+
+        logger.finalize_conversation();
+        assert_eq!(SessionStatistics::from_log(&logger.log), statistics);
+        let table = statistics.to_string();
+        assert!(table.contains("2 prompt(s)"));
+        assert!(table.contains("$0.7500"));
+        assert!(table.find("ChatGPT").unwrap() < table.find("Claude").unwrap());
+    }
+
+    #[test]
+    fn test_load_saved_log() {
+        let mut logger = Logger::new();
+        logger.log_prompt("Hi");
+        logger.log_provider_response("Gemini", "Hello", false);
+        logger.finalize_conversation();
+
+        let path = std::env::temp_dir().join(format!("chatdelta_{}.json", logger.session_id()));
+        fs::write(&path, serde_json::to_string(&logger.log).unwrap()).unwrap();
+        let loaded = ConversationLog::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.session_id, *logger.session_id());
+        assert_eq!(SessionStatistics::from_log(&loaded).provider_stats["Gemini"].response_count, 1);
+        assert!(ConversationLog::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut logger = Logger::new();
+        logger.log_prompt("Base question");
+        logger.log_provider_response("Gemini", "Base answer", false);
+        let original = *logger.session_id();
+
+        let fork = logger.fork();
+        assert_ne!(fork, original);
+        assert_eq!(*logger.session_id(), fork);
+        assert_eq!(logger.log.forked_from, Some(original));
+        assert_eq!(logger.log.conversations.len(), 1);
+        assert_eq!(logger.log.conversations[0].prompt, "Base question");
+
+        // Later prompts only go to the fork
+        logger.log_prompt("Follow-up");
+        logger.finalize_conversation();
+        assert_eq!(logger.log.conversations.len(), 2);
+        assert_eq!(logger.log.fork().forked_from, Some(fork));
+    }
+
+    #[test]
+    fn test_snapshot_keeps_recording() {
+        let mut logger = Logger::new();
+        logger.log_prompt("Saved so far");
+        logger.log_provider_response("Claude", "Answer", false);
+
+        let snapshot = logger.snapshot();
+        assert_eq!(snapshot.conversations.len(), 1);
+        assert_eq!(snapshot.conversations[0].responses["Claude"].text, "Answer");
+
+        // The exchange is still open for more responses
+        logger.log_provider_response("Gemini", "Another", false);
+        assert_eq!(logger.current_conversation().unwrap().responses.len(), 2);
+        assert!(logger.log.conversations.is_empty());
+    }
+
+    #[test]
+    fn test_prompt_response_pairs() {
+        let mut logger = Logger::new();
+        logger.log_prompt("Hi");
+        logger.log_provider_response("Gemini", "Hello", false);
+        logger.log_provider_response("ChatGPT", "Hi \"there\"", false);
+        logger.log_provider_response("Claude", "timeout", true);
+        logger.finalize_conversation();
+        logger.log_prompt("Nobody answered");
+        logger.log_provider_response("Claude", "timeout", true);
+        logger.finalize_conversation();
+
+        let pairs = logger.log.to_prompt_response_pairs();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].0, "Hi");
+        assert_eq!(pairs[0].1.len(), 2);
+        assert_eq!(pairs[0].1["Gemini"], "Hello");
+
+        assert_eq!(
+            logger.log.to_jsonl_string(),
+            "{\"prompt\":\"Hi\",\"responses\":{\"ChatGPT\":\"Hi \\\"there\\\"\",\"Gemini\":\"Hello\"}}\n"
+        );
+    }
+
+    #[test]
+    fn test_display_names() {
+        let mut logger = Logger::new();
+        logger.set_display_name("ChatGPT", Some("GPT-4o"));
+        logger.set_display_name("Gemini", Some("Flash"));
+        logger.set_display_name("Gemini", None);
+        logger.log_prompt("Hi");
+        logger.log_provider_response("ChatGPT", "Hello", false);
+        logger.log_provider_response("Gemini", "Hey", false);
+        logger.finalize_conversation();
+
+        assert_eq!(logger.log.display_name("ChatGPT"), "GPT-4o");
+        assert_eq!(logger.log.display_name("Gemini"), "Gemini");
+        let pairs = logger.log.to_prompt_response_pairs();
+        assert_eq!(pairs[0].1["GPT-4o"], "Hello");
+        assert_eq!(pairs[0].1["Gemini"], "Hey");
+
+        let saved = serde_json::to_string(&logger.log).unwrap();
+        let loaded: ConversationLog = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.display_names["ChatGPT"], "GPT-4o");
+    }
+
+    #[test]
+    fn test_starred_exchanges() {
+        let mut logger = Logger::new();
+        let first = logger.log_prompt("What is 2 + 2?");
+        logger.log_provider_response("ChatGPT", "4", false);
+        let second = logger.log_prompt("And 3 + 3?");
+        logger.log_provider_response("ChatGPT", "6", false);
+
+        assert_eq!(logger.toggle_star(first), Some(true));
+        assert_eq!(logger.toggle_star(second), Some(true));
+        assert_eq!(logger.toggle_star(second), Some(false));
+        assert_eq!(logger.toggle_star(Uuid::new_v4()), None);
+        assert!(logger.is_starred(first));
+        assert!(!logger.is_starred(second));
+        assert_eq!(logger.latest_request_id(), Some(second));
+
+        let starred = logger.get_starred();
+        assert_eq!(starred.len(), 1);
+        assert_eq!(starred[0].prompt, "What is 2 + 2?");
+
+        logger.finalize_conversation();
+        let export = logger.log.starred_only();
+        assert_eq!(export.conversations.len(), 1);
+        assert_eq!(export.to_prompt_response_pairs()[0].0, "What is 2 + 2?");
+
+        // Logs saved before the field existed load unstarred
+        let json = serde_json::to_string(&logger.log).unwrap().replace(",\"starred\":true", "");
+        let loaded: ConversationLog = serde_json::from_str(&json).unwrap();
+        assert!(loaded.conversations.iter().all(|entry| !entry.starred));
+    }
+
+    #[test]
+    fn test_stats_line() {
+        let mut logger = Logger::new();
+        logger.log_prompt("Hi");
+        // Latency is measured from the timer the TUI starts as each request goes out
+        logger.start_provider_timer("Claude");
+        logger.log_provider_response("Claude", "Hello", false);
+        logger.log_provider_response("ChatGPT", "Error: rate limited", true);
+        let stats = |logger: &Logger, provider: &str| {
+            logger.current_conversation().unwrap().responses[provider].stats_line()
+        };
+        assert!(stats(&logger, "Claude").is_some_and(|line| line.ends_with('s')));
+        assert_eq!(stats(&logger, "ChatGPT"), None);
+
+        logger.log_token_usage("Claude", TokenUsage { input_tokens: 12, output_tokens: 300 }, Some(0.0041));
+        let mut response = logger.current_conversation().unwrap().responses["Claude"].clone();
+        response.latency_ms = Some(2400);
+        assert_eq!(response.stats_line().as_deref(), Some("2.4s • 312 tokens • $0.004"));
+        response.latency_ms = None;
+        response.cost_usd = None;
+        assert_eq!(response.stats_line().as_deref(), Some("312 tokens"));
+    }
+
+    #[test]
+    fn test_replay_to_channel() {
+        let mut logger = Logger::new();
+        logger.log_prompt("Hi");
+        logger.log_provider_response("Claude", "Hello", false);
+        logger.log_provider_response("ChatGPT", "Error: rate limited", true);
+        logger.log_provider_response("Mistral", "Bonjour", false);
+        logger.log_delta_analysis("Only Claude answered");
+        logger.log_prompt("Bye");
+        logger.finalize_conversation();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        Logger::replay_to_channel(&logger.log, tx);
+        let mut replayed = Vec::new();
+        while let Ok(response_type) = rx.try_recv() {
+            replayed.push(response_type);
+        }
+        assert_eq!(replayed.len(), 3);
+        assert!(matches!(&replayed[0], ResponseType::Provider(0, text) if text == "Error: rate limited"));
+        assert!(matches!(&replayed[1], ResponseType::Provider(2, text) if text == "Hello"));
+        assert!(matches!(&replayed[2], ResponseType::Delta(delta) if delta == "Only Claude answered"));
+    }
+}
@@ -2,17 +2,142 @@
 //!
 //! A command-line tool for querying multiple AI APIs and summarizing their responses.
 
-use chatdelta_base::tui::{run_tui, ProviderState};
+use chatdelta::{ImageAttachment, ProxyConfig, TranscriptionClient};
+use chatdelta_base::aliases::ProviderAliases;
+use chatdelta_base::benchmark::run_benchmark;
+use chatdelta_base::cli::{Args, CliCommand};
+use chatdelta_base::delta::DeltaJudge;
+use chatdelta_base::dry_run::run_dry_run;
+use chatdelta_base::headless::run_headless;
+use chatdelta_base::keys::{KeyTable, Keymap};
+use chatdelta_base::logger::{ConversationLog, SessionStatistics};
+use chatdelta_base::pipe::run_pipe;
+use chatdelta_base::theme::{Theme, BUILTIN_THEMES};
+use chatdelta_base::tui::{run_tui, AppState, ProviderState, TuiOptions};
+use chatdelta_base::webhook::WebhookNotifier;
+use clap::Parser;
 use std::collections::HashMap;
+use std::io::IsTerminal;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Detect provider API keys
+    let args = Args::parse();
+
+    if let Some(CliCommand::Convert { input, output }) = &args.command {
+        let log = ConversationLog::load(input).map_err(|e| format!("Failed to read {}: {}", input.display(), e))?;
+        let log = if args.starred_only { log.starred_only() } else { log };
+        std::fs::write(output, log.to_jsonl_string())?;
+        println!("Wrote {} prompt(s) to {}", log.to_prompt_response_pairs().len(), output.display());
+        return Ok(());
+    }
+
+    if let Some(path) = &args.stats {
+        let log = ConversationLog::load(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let log = if args.starred_only { log.starred_only() } else { log };
+        print!("{}", SessionStatistics::from_log(&log));
+        return Ok(());
+    }
+
+    // Command-line settings on top of the ones from the environment
+    let mut client_config = AppState::client_config();
+    if !args.safety_thresholds.is_empty() {
+        client_config.safety_settings = Some(args.safety_thresholds.clone());
+    }
+    if let Some(url) = &args.proxy {
+        client_config.proxy = Some(ProxyConfig::new(url));
+    }
+    if let (Some(user), Some(pass)) = (&args.proxy_user, &args.proxy_pass) {
+        let proxy = client_config.proxy.take().ok_or("--proxy-user needs --proxy or HTTPS_PROXY")?;
+        client_config.proxy = Some(proxy.with_credentials(user, pass));
+    }
+    if let Some(max_tokens) = args.max_tokens {
+        // Problems with the catalog are reported when the mode below loads it again
+        args.validate_max_tokens(&AppState::load_model_catalog(&mut Vec::new()))?;
+        client_config.max_tokens = Some(max_tokens);
+    }
+
+    if args.dry_run {
+        let passed = run_dry_run(detect_provider_states(), client_config).await;
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    if let Some(prompt) = &args.benchmark {
+        return run_benchmark(prompt, args.n, client_config).await;
+    }
+
+    // A transcribed recording stands in for the prompt argument
+    let transcript = match &args.transcribe {
+        Some(audio) => {
+            let api_key = std::env::var("CHATGPT_API_KEY").map_err(|_| "--transcribe requires CHATGPT_API_KEY")?;
+            let client = TranscriptionClient::new(&api_key, client_config.clone())?;
+            let text = client.transcribe(audio).await?;
+            if !args.headless {
+                println!("{}", text);
+                return Ok(());
+            }
+            Some(text)
+        }
+        None => None,
+    };
+
+    let images = args
+        .images
+        .iter()
+        .map(|path| ImageAttachment::from_file(path).map_err(|e| format!("Could not read {}: {}", path.display(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if args.headless {
+        let prompt = transcript.as_deref().or(args.prompt.as_deref()).ok_or("--headless requires a prompt")?;
+        return run_headless(prompt, &images, args.output_format, args.seed, client_config).await;
+    }
+
+    // The TUI needs a terminal to read keys from, so piped input is answered line by line, or
+    // as the context of a prompt argument
+    if args.pipe || !std::io::stdin().is_terminal() {
+        return run_pipe(args.prompt.as_deref(), &images, args.seed, client_config).await;
+    }
+
+    // An explicit --theme wins over the user's theme file
+    let theme = match &args.theme {
+        Some(name) => Theme::by_name(name).ok_or_else(|| {
+            format!("Unknown theme '{}'. Valid options: {}", name, BUILTIN_THEMES.join(", "))
+        })?,
+        None => Theme::load(),
+    };
+
+    // --judge wins over ~/.chatdelta/delta.toml
+    let judge = match &args.judge {
+        // Problems with the alias file are reported by the TUI, which loads it again
+        Some(name) => Some(DeltaJudge::parse(name, &ProviderAliases::load(&mut Vec::new()))?),
+        None => None,
+    };
+
+    // --webhook wins over CHATDELTA_WEBHOOK_URL
+    let webhook = match &args.webhook {
+        Some(url) => Some(WebhookNotifier::with_env_secret(url)),
+        None => WebhookNotifier::from_env(),
+    };
+
+    let options = TuiOptions {
+        theme,
+        no_delta: args.no_delta,
+        mouse: !args.no_mouse,
+        // --keymap wins over ~/.chatdelta/config.toml
+        keymap: args.keymap.unwrap_or_else(Keymap::load),
+        keys: KeyTable::load(),
+        judge,
+        webhook,
+        images,
+    };
+    run_tui(detect_provider_states(), client_config, options).await?;
+    Ok(())
+}
+
+/// Providers with an API key in the environment are enabled
+fn detect_provider_states() -> HashMap<&'static str, ProviderState> {
     let mut provider_states = HashMap::new();
     provider_states.insert("ChatGPT", if std::env::var("CHATGPT_API_KEY").is_ok() { ProviderState::Enabled } else { ProviderState::Disabled });
     provider_states.insert("Gemini", if std::env::var("GEMINI_API_KEY").is_ok() { ProviderState::Enabled } else { ProviderState::Disabled });
     provider_states.insert("Claude", if std::env::var("CLAUDE_API_KEY").is_ok() { ProviderState::Enabled } else { ProviderState::Disabled });
-
-    run_tui(provider_states).await?;
-    Ok(())
+    provider_states
 }
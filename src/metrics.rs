@@ -1,12 +1,13 @@
 //! Metrics integration for ChatDelta TUI
 //! Displays real-time performance metrics using the core library's ClientMetrics
 
-use chatdelta::{ClientMetrics, MetricsSnapshot};
+use crate::theme::Theme;
+use chatdelta::ClientMetrics;
 use std::collections::HashMap;
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Span, Spans},
     widgets::{Block, Borders, List, ListItem, Paragraph},
     Frame,
@@ -16,7 +17,7 @@ use tui::{
 pub struct TuiMetrics {
     /// Metrics for each provider
     provider_metrics: HashMap<String, ClientMetrics>,
-    /// Whether metrics display is enabled
+    /// Whether the metrics panel is shown (F3)
     enabled: bool,
     /// Show detailed metrics
     detailed: bool,
@@ -26,7 +27,7 @@ impl TuiMetrics {
     pub fn new() -> Self {
         Self {
             provider_metrics: HashMap::new(),
-            enabled: true,
+            enabled: false,
             detailed: false,
         }
     }
@@ -36,28 +37,39 @@ impl TuiMetrics {
         self.enabled = !self.enabled;
     }
     
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    
     /// Toggle detailed view
     pub fn toggle_detailed(&mut self) {
         self.detailed = !self.detailed;
     }
     
+    pub fn is_detailed(&self) -> bool {
+        self.detailed
+    }
+    
     /// Get or create metrics for a provider
     pub fn get_metrics(&mut self, provider: &str) -> ClientMetrics {
         self.provider_metrics
             .entry(provider.to_string())
-            .or_insert_with(ClientMetrics::new)
+            .or_default()
             .clone()
     }
     
     /// Record API response
     pub fn record_response(&mut self, provider: &str, success: bool, latency_ms: u64, tokens: Option<u32>) {
-        if let Some(metrics) = self.provider_metrics.get(provider) {
-            metrics.record_request(success, latency_ms, tokens);
-        }
+        self.get_metrics(provider).record_request(success, latency_ms, tokens);
+    }
+    
+    /// Record usage reported after the response it belongs to
+    pub fn record_tokens(&mut self, provider: &str, tokens: u32) {
+        self.get_metrics(provider).record_tokens(tokens);
     }
     
     /// Render metrics widget
-    pub fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+    pub fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme) {
         if !self.enabled {
             return;
         }
@@ -72,14 +84,16 @@ impl TuiMetrics {
         
         // Title block
         let title = Paragraph::new("📊 Performance Metrics")
-            .style(Style::default().fg(Color::Cyan))
+            .style(Style::default().fg(theme.provider_enabled))
             .block(Block::default().borders(Borders::ALL));
         f.render_widget(title, chunks[0]);
         
         // Metrics content
         let mut items: Vec<ListItem> = Vec::new();
         
-        for (provider, metrics) in &self.provider_metrics {
+        let mut providers: Vec<_> = self.provider_metrics.iter().collect();
+        providers.sort_by_key(|(provider, _)| provider.as_str());
+        for (provider, metrics) in providers {
             let stats = metrics.get_stats();
             
             if self.detailed {
@@ -87,7 +101,7 @@ impl TuiMetrics {
                 items.push(ListItem::new(Spans::from(vec![
                     Span::styled(
                         format!("{}: ", provider),
-                        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                        Style::default().fg(theme.selected_title).add_modifier(Modifier::BOLD),
                     ),
                 ])));
                 
@@ -117,17 +131,17 @@ impl TuiMetrics {
             } else {
                 // Compact view
                 let status_color = if stats.success_rate >= 90.0 {
-                    Color::Green
+                    theme.success
                 } else if stats.success_rate >= 70.0 {
-                    Color::Yellow
+                    theme.warning
                 } else {
-                    Color::Red
+                    theme.error
                 };
                 
                 items.push(ListItem::new(Spans::from(vec![
                     Span::styled(
                         format!("{}: ", provider),
-                        Style::default().fg(Color::Cyan),
+                        Style::default().fg(theme.provider_enabled),
                     ),
                     Span::styled(
                         format!("{:.0}% ", stats.success_rate),
@@ -144,7 +158,7 @@ impl TuiMetrics {
         
         let metrics_list = List::new(items)
             .block(Block::default().borders(Borders::ALL))
-            .style(Style::default().fg(Color::White));
+            .style(Style::default().fg(theme.delta_text));
         
         f.render_widget(metrics_list, chunks[1]);
     }
@@ -163,7 +177,7 @@ impl TuiMetrics {
         
         if total_requests > 0 {
             let success_rate = (total_success as f64 / total_requests as f64) * 100.0;
-            format!("📊 {} requests | {:.0}% success", total_requests, success_rate)
+            format!("📊 {} req, {:.0}% ok", total_requests, success_rate)
         } else {
             "📊 Metrics: Ready".to_string()
         }
@@ -1,6 +1,7 @@
 //! Output formatting for ChatDelta CLI
 
-use crate::cli::Args;
+use crate::cli::{Args, OutputFormat};
+use crate::logger::{ConversationEntry, ProviderResponse};
 use std::fs::File;
 use std::io::Write;
 
@@ -134,4 +135,208 @@ pub fn log_interaction(
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+/// Renders a finished exchange for `--headless` output
+pub trait OutputFormatter {
+    fn format(&self, entry: &ConversationEntry) -> String;
+}
+
+/// Pretty-printed JSON of the whole entry
+pub struct JsonFormatter;
+
+/// Markdown document with a section per provider
+pub struct MarkdownFormatter;
+
+/// `PROVIDER: response` lines
+pub struct PlainFormatter;
+
+/// `provider,latency_ms,response` rows with a header line
+pub struct CsvFormatter;
+
+/// Formatter for the given `--output-format`
+pub fn formatter_for(format: OutputFormat) -> Box<dyn OutputFormatter> {
+    match format {
+        OutputFormat::Json => Box::new(JsonFormatter),
+        OutputFormat::Markdown => Box::new(MarkdownFormatter),
+        OutputFormat::Plain => Box::new(PlainFormatter),
+        OutputFormat::Csv => Box::new(CsvFormatter),
+    }
+}
+
+/// Responses sorted by provider name, so output is stable between runs
+fn sorted_responses(entry: &ConversationEntry) -> Vec<(&String, &ProviderResponse)> {
+    let mut responses: Vec<_> = entry.responses.iter().collect();
+    responses.sort_by(|a, b| a.0.cmp(b.0));
+    responses
+}
+
+/// Response text, or the error message for a failed provider
+fn response_text(response: &ProviderResponse) -> String {
+    match &response.error {
+        Some(error) => format!("Error: {}", error),
+        None => response.text.clone(),
+    }
+}
+
+impl OutputFormatter for JsonFormatter {
+    fn format(&self, entry: &ConversationEntry) -> String {
+        let mut output = serde_json::to_string_pretty(entry).unwrap_or_default();
+        output.push('\n');
+        output
+    }
+}
+
+impl OutputFormatter for MarkdownFormatter {
+    fn format(&self, entry: &ConversationEntry) -> String {
+        let mut output = String::from("# ChatDelta Results\n\n");
+        output.push_str(&format!("**Prompt:** {}\n\n", entry.prompt));
+
+        for (name, response) in sorted_responses(entry) {
+            output.push_str(&format!("## {}\n\n", name));
+            let mut details = Vec::new();
+            if let Some(model_version) = &response.model_version {
+                details.push(format!("*Model:* `{}`", model_version));
+            }
+            if let Some(latency_ms) = response.latency_ms {
+                details.push(format!("*Latency:* {} ms", latency_ms));
+            }
+            if let Some(ttfb_ms) = response.ttfb_ms {
+                details.push(format!("*First token:* {} ms", ttfb_ms));
+            }
+            if !details.is_empty() {
+                output.push_str(&format!("{}\n\n", details.join(" · ")));
+            }
+            output.push_str(&format!("{}\n\n", response_text(response)));
+        }
+
+        if let Some(delta) = &entry.delta_analysis {
+            output.push_str(&format!("## Differences\n\n{}\n\n", delta));
+        }
+
+        output
+    }
+}
+
+impl OutputFormatter for PlainFormatter {
+    fn format(&self, entry: &ConversationEntry) -> String {
+        sorted_responses(entry)
+            .into_iter()
+            .map(|(name, response)| format!("{}: {}\n", name.to_uppercase(), response_text(response)))
+            .collect()
+    }
+}
+
+impl OutputFormatter for CsvFormatter {
+    fn format(&self, entry: &ConversationEntry) -> String {
+        let mut output = String::from("provider,latency_ms,response\n");
+        for (name, response) in sorted_responses(entry) {
+            let latency = response.latency_ms.map(|ms| ms.to_string()).unwrap_or_default();
+            output.push_str(&format!(
+                "{},{},{}\n",
+                csv_field(name),
+                latency,
+                csv_field(&response_text(response))
+            ));
+        }
+        output
+    }
+}
+
+/// Quote a CSV field when it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn sample_entry() -> ConversationEntry {
+        let mut responses = HashMap::new();
+        responses.insert(
+            "Claude".to_string(),
+            ProviderResponse {
+                text: "Hello, \"world\"".to_string(),
+                latency_ms: Some(420),
+                error: None,
+                model: None,
+                model_version: None,
+                truncated: false,
+                system_fingerprint: None,
+                validation_retried: false,
+                ttfb_ms: None,
+                input_tokens: None,
+                output_tokens: None,
+                cost_usd: None,
+                sentiment: None,
+            },
+        );
+        responses.insert(
+            "ChatGPT".to_string(),
+            ProviderResponse {
+                text: "Hi".to_string(),
+                latency_ms: Some(310),
+                error: None,
+                model: None,
+                model_version: Some("gpt-4o-2024-11-20".to_string()),
+                truncated: false,
+                system_fingerprint: None,
+                validation_retried: false,
+                ttfb_ms: None,
+                input_tokens: None,
+                output_tokens: None,
+                cost_usd: None,
+                sentiment: None,
+            },
+        );
+        ConversationEntry {
+            request_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            prompt: "Say hello".to_string(),
+            seed: None,
+            responses,
+            delta_analysis: None,
+            tags: Vec::new(),
+            starred: false,
+        }
+    }
+
+    #[test]
+    fn test_plain_format() {
+        assert_eq!(
+            PlainFormatter.format(&sample_entry()),
+            "CHATGPT: Hi\nCLAUDE: Hello, \"world\"\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_format_escapes_fields() {
+        assert_eq!(
+            CsvFormatter.format(&sample_entry()),
+            "provider,latency_ms,response\nChatGPT,310,Hi\nClaude,420,\"Hello, \"\"world\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_json_format_round_trips() {
+        let entry = sample_entry();
+        let parsed: ConversationEntry = serde_json::from_str(&JsonFormatter.format(&entry)).unwrap();
+        assert_eq!(parsed.request_id, entry.request_id);
+        assert_eq!(parsed.responses.len(), 2);
+    }
+
+    #[test]
+    fn test_markdown_format() {
+        let output = MarkdownFormatter.format(&sample_entry());
+        assert!(output.starts_with("# ChatDelta Results"));
+        assert!(output.contains("## ChatGPT\n\n*Model:* `gpt-4o-2024-11-20`"));
+        assert!(output.find("## ChatGPT").unwrap() < output.find("## Claude").unwrap());
+    }
+}
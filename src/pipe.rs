@@ -0,0 +1,235 @@
+//! Pipe mode: answer prompts read from stdin, one per line, as a Unix filter
+//!
+//! Used by `chatdelta --pipe`, or whenever stdin is not a terminal, e.g.
+//! `echo "What is Rust?" | chatdelta`. Each prompt goes to every provider with an API key and
+//! its answers are written as `PROVIDER: response` lines before the next prompt is read. With a
+//! prompt argument, all of stdin is instead sent once after it, e.g.
+//! `cat notes.txt | chatdelta "Summarize this"`.
+
+use crate::logger::Logger;
+use crate::output::{OutputFormatter, PlainFormatter};
+use crate::tui::AppState;
+use chatdelta::{AiClient, ClientConfig, ImageAttachment, PromptOptions};
+use std::error::Error;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+/// Answer stdin with the providers that have an API key and save the session log. Without a
+/// `prompt` every line of stdin is a prompt; with one, stdin is its context. `images` and
+/// `seed` go with every prompt.
+pub async fn run_pipe(
+    prompt: Option<&str>,
+    images: &[ImageAttachment],
+    seed: Option<u64>,
+    client_config: ClientConfig,
+) -> Result<(), Box<dyn Error>> {
+    let mut warnings = Vec::new();
+    let configs = AppState::load_provider_configs(client_config, &mut warnings);
+    let models = AppState::load_model_catalog(&mut warnings);
+    for warning in &warnings {
+        eprintln!("⚠️  {}", warning);
+    }
+    let mut logger = Logger::new();
+    logger.set_user_agent(&configs.default_config().user_agent);
+
+    let mut clients = Vec::new();
+    for (name, model, client) in AppState::keyed_provider_clients(&configs, &models) {
+        logger.set_provider_model(name, model);
+        clients.push((name, client));
+    }
+    if clients.is_empty() {
+        return Err("No provider API keys set (CHATGPT_API_KEY, GEMINI_API_KEY, CLAUDE_API_KEY)".into());
+    }
+
+    let request = PipeRequest { clients: &clients, images, seed };
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let answered = match prompt {
+        Some(prompt) => {
+            let mut context = String::new();
+            stdin.read_to_string(&mut context)?;
+            answer_prompt(&prompt_with_context(prompt, &context), &mut stdout, &request, &mut logger).await?;
+            1
+        }
+        None => pipe_prompts(stdin, &mut stdout, &request, &mut logger).await?,
+    };
+
+    // stdout carries the answers, so the log location goes to stderr
+    if answered > 0 {
+        match logger.save() {
+            Ok(path) => eprintln!("📝 Conversation saved to: {}", path.display()),
+            Err(e) => eprintln!("⚠️  Failed to save conversation log: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// The providers to ask and what to send with every prompt
+pub struct PipeRequest<'a> {
+    pub clients: &'a [(&'static str, Arc<dyn AiClient>)],
+    pub images: &'a [ImageAttachment],
+    pub seed: Option<u64>,
+}
+
+/// `prompt` followed by the text piped in after it, if there was any
+pub fn prompt_with_context(prompt: &str, context: &str) -> String {
+    let context = context.trim_end();
+    if context.trim().is_empty() {
+        prompt.to_string()
+    } else {
+        format!("{}\n\n{}", prompt, context)
+    }
+}
+
+/// Send each non-blank line of `input` to every client in turn, writing the answers to
+/// `output` as each prompt finishes. Returns the number of prompts answered.
+pub async fn pipe_prompts(
+    input: impl BufRead,
+    output: &mut impl Write,
+    request: &PipeRequest<'_>,
+    logger: &mut Logger,
+) -> Result<usize, Box<dyn Error>> {
+    let mut answered = 0;
+    for line in input.lines() {
+        let line = line?;
+        let prompt = line.trim();
+        if prompt.is_empty() {
+            continue;
+        }
+        answer_prompt(prompt, output, request, logger).await?;
+        answered += 1;
+    }
+    Ok(answered)
+}
+
+/// Ask every client at once and write their answers to `output` when all are in
+async fn answer_prompt(
+    prompt: &str,
+    output: &mut impl Write,
+    request: &PipeRequest<'_>,
+    logger: &mut Logger,
+) -> Result<(), Box<dyn Error>> {
+    let request_id = logger.log_prompt(prompt);
+    logger.log_seed(request.seed);
+    let options = PromptOptions {
+        request_id: Some(request_id.to_string()),
+        seed: request.seed,
+        ..Default::default()
+    };
+
+    let mut requests = JoinSet::new();
+    for (name, client) in request.clients {
+        logger.start_provider_timer(name);
+        let name = *name;
+        let client = Arc::clone(client);
+        let prompt = prompt.to_string();
+        let images = request.images.to_vec();
+        let options = options.clone();
+        requests.spawn(async move {
+            let result = if images.is_empty() {
+                client.send_prompt_with_options(&prompt, &options).await
+            } else {
+                client.send_prompt_with_images(&prompt, &images, &options).await
+            };
+            (name, result.map_err(|e| e.to_string()))
+        });
+    }
+    while let Some(joined) = requests.join_next().await {
+        let (name, result) = joined?;
+        match result {
+            Ok(response) => logger.log_provider_response(name, &response, false),
+            Err(e) => logger.log_provider_response(name, &e, true),
+        }
+    }
+
+    if let Some(entry) = logger.current_conversation() {
+        output.write_all(PlainFormatter.format(entry).as_bytes())?;
+        output.flush()?;
+    }
+    logger.finalize_conversation();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Answers with the prompt reversed, or fails when asked to
+    struct ReverseClient;
+
+    #[async_trait]
+    impl AiClient for ReverseClient {
+        async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+            if prompt == "fail" {
+                return Err("rate limited".into());
+            }
+            Ok(prompt.chars().rev().collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipe_prompts() {
+        let clients: Vec<(&'static str, Arc<dyn AiClient>)> =
+            vec![("Claude", Arc::new(ReverseClient)), ("ChatGPT", Arc::new(ReverseClient))];
+        let request = PipeRequest { clients: &clients, images: &[], seed: None };
+        let mut logger = Logger::new();
+        let mut output = Vec::new();
+
+        let answered = pipe_prompts("abc\n\n  xyz  \nfail\n".as_bytes(), &mut output, &request, &mut logger)
+            .await
+            .unwrap();
+        assert_eq!(answered, 3);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "CHATGPT: cba\nCLAUDE: cba\nCHATGPT: zyx\nCLAUDE: zyx\nCHATGPT: Error: rate limited\nCLAUDE: Error: rate limited\n"
+        );
+    }
+
+    /// Describes the seed and images it was sent with
+    struct OptionsClient;
+
+    #[async_trait]
+    impl AiClient for OptionsClient {
+        async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+            Ok(prompt.to_string())
+        }
+
+        async fn send_prompt_with_options(&self, prompt: &str, options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+            Ok(format!("{} seed={:?}", prompt, options.seed))
+        }
+
+        async fn send_prompt_with_images(
+            &self,
+            prompt: &str,
+            images: &[ImageAttachment],
+            options: &PromptOptions,
+        ) -> Result<String, Box<dyn Error + Send + Sync>> {
+            Ok(format!("{} seed={:?} images={}", prompt, options.seed, images.len()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipe_prompts_send_seed_and_images() {
+        let clients: Vec<(&'static str, Arc<dyn AiClient>)> = vec![("Gemini", Arc::new(OptionsClient))];
+        let images = [ImageAttachment { data: b"img".to_vec(), mime_type: "image/png".to_string() }];
+        let request = PipeRequest { clients: &clients, images: &images, seed: Some(7) };
+        let mut logger = Logger::new();
+        let mut output = Vec::new();
+
+        pipe_prompts("Describe it\n".as_bytes(), &mut output, &request, &mut logger).await.unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "GEMINI: Describe it seed=Some(7) images=1\n");
+
+        let request = PipeRequest { clients: &clients, images: &[], seed: None };
+        let mut output = Vec::new();
+        pipe_prompts("Hi\n".as_bytes(), &mut output, &request, &mut logger).await.unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "GEMINI: Hi seed=None\n");
+    }
+
+    #[test]
+    fn test_prompt_with_context() {
+        assert_eq!(prompt_with_context("Summarize", "line one\nline two\n"), "Summarize\n\nline one\nline two");
+        assert_eq!(prompt_with_context("Summarize", " \n"), "Summarize");
+    }
+}
@@ -0,0 +1,107 @@
+//! Named prompt presets for the `/preset` command
+//!
+//! Presets are loaded from `~/.chatdelta/presets.toml`, one `[[preset]]` table each:
+//!
+//! ```toml
+//! [[preset]]
+//! name = "review"
+//! prompt = "Review this Rust code for bugs:"
+//! system = "You are a senior Rust reviewer."
+//! ```
+
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    /// Name used with `/preset <name>`
+    pub name: String,
+    /// Text loaded into the input box
+    pub prompt: String,
+    /// Instructions that become the shared context (as with `/context`) when the preset is used
+    #[serde(default)]
+    pub system: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PromptLibrary {
+    #[serde(default, rename = "preset")]
+    presets: Vec<Preset>,
+}
+
+impl PromptLibrary {
+    /// Load the user's presets from `~/.chatdelta/presets.toml`; a missing file means no presets,
+    /// and so does an invalid one, with a warning added to `warnings`
+    pub fn load(warnings: &mut Vec<String>) -> Self {
+        let Some(path) = Self::config_path() else {
+            return Self::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        match Self::from_toml(&contents) {
+            Ok(library) => library,
+            Err(e) => {
+                warnings.push(format!("Ignoring invalid preset file {}: {}", path.display(), e));
+                Self::default()
+            }
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// Look up a preset by name; the first one wins if a name is repeated
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+
+    /// All presets, in file order
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".chatdelta").join("presets.toml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_presets() {
+        let library = PromptLibrary::from_toml(
+            r#"
+            [[preset]]
+            name = "review"
+            prompt = "Review this code:"
+            system = "You are a careful reviewer."
+
+            [[preset]]
+            name = "eli5"
+            prompt = "Explain like I'm five:"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(library.presets().len(), 2);
+        let review = library.get("review").unwrap();
+        assert_eq!(review.prompt, "Review this code:");
+        assert_eq!(review.system.as_deref(), Some("You are a careful reviewer."));
+        assert_eq!(library.get("eli5").unwrap().system, None);
+        assert!(library.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_preset_requires_prompt() {
+        assert!(PromptLibrary::from_toml("[[preset]]\nname = \"empty\"").is_err());
+        assert!(PromptLibrary::from_toml("").unwrap().presets().is_empty());
+    }
+}
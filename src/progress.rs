@@ -0,0 +1,73 @@
+//! Progress display for operations that wait on many requests
+//!
+//! Headless mode and `--benchmark` report to stderr through `CliProgressReporter`; the TUI
+//! shows the same bar in a row beneath the status bar while an exchange is under way.
+
+use std::io::{self, IsTerminal, Write};
+
+/// Characters between the brackets of a progress bar
+const BAR_WIDTH: usize = 20;
+
+/// Receives progress as requests finish
+pub trait ProgressReporter {
+    /// `done` of `total` steps have finished
+    fn update(&mut self, done: usize, total: usize);
+}
+
+/// `[########............] 4/10`
+pub fn progress_bar(done: usize, total: usize) -> String {
+    let done = done.min(total);
+    // Widened so large counts can't overflow; nothing to wait for counts as finished
+    let filled = (done as u128 * BAR_WIDTH as u128)
+        .checked_div(total as u128)
+        .map_or(BAR_WIDTH, |filled| filled as usize);
+    format!("[{}{}] {}/{}", "#".repeat(filled), ".".repeat(BAR_WIDTH - filled), done, total)
+}
+
+/// Redraws a progress bar in place on stderr. Stays quiet when stderr is not a terminal, so
+/// redirected output isn't filled with carriage returns.
+pub struct CliProgressReporter {
+    enabled: bool,
+}
+
+impl CliProgressReporter {
+    pub fn new() -> Self {
+        Self { enabled: io::stderr().is_terminal() }
+    }
+}
+
+impl Default for CliProgressReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressReporter for CliProgressReporter {
+    fn update(&mut self, done: usize, total: usize) {
+        if !self.enabled {
+            return;
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{}", progress_bar(done, total));
+        // Leave the finished bar on its own line
+        if done >= total {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(0, 10), "[....................] 0/10");
+        assert_eq!(progress_bar(4, 10), "[########............] 4/10");
+        assert_eq!(progress_bar(10, 10), "[####################] 10/10");
+        assert_eq!(progress_bar(1, 3), "[######..............] 1/3");
+        assert_eq!(progress_bar(0, 0), "[####################] 0/0");
+        assert!(progress_bar(usize::MAX, usize::MAX).starts_with("[####################] "));
+    }
+}
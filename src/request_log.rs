@@ -0,0 +1,146 @@
+//! The latest provider requests and how they ended, for debugging (Ctrl+L in the TUI)
+//!
+//! Only the most recent `REQUEST_LOG_SIZE` requests are kept, so the log can stay on for a
+//! whole session.
+
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Requests kept before the oldest is dropped
+pub const REQUEST_LOG_SIZE: usize = 50;
+
+/// Prompts are shortened to this many characters
+const PROMPT_PREVIEW_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestStatus {
+    Pending,
+    Success,
+    Error,
+    /// Stopped with Ctrl+C before it answered
+    Cancelled,
+}
+
+impl RequestStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            RequestStatus::Pending => "⏳ pending",
+            RequestStatus::Success => "✓ ok",
+            RequestStatus::Error => "✗ error",
+            RequestStatus::Cancelled => "⏹ cancelled",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestLogEntry {
+    pub provider: String,
+    /// The first `PROMPT_PREVIEW_CHARS` characters of the prompt
+    pub prompt: String,
+    pub timestamp: DateTime<Utc>,
+    pub status: RequestStatus,
+    /// Set once the request has ended
+    pub latency_ms: Option<u64>,
+    started: Instant,
+}
+
+/// Ring buffer of the latest requests, oldest first
+#[derive(Debug, Clone)]
+pub struct RequestLog {
+    entries: VecDeque<RequestLogEntry>,
+    max_size: usize,
+}
+
+impl RequestLog {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(max_size),
+            max_size,
+        }
+    }
+
+    /// Record a request that was just sent
+    pub fn start(&mut self, provider: &str, prompt: &str) {
+        if self.max_size == 0 {
+            return;
+        }
+        if self.entries.len() == self.max_size {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(RequestLogEntry {
+            provider: provider.to_string(),
+            prompt: prompt.chars().take(PROMPT_PREVIEW_CHARS).collect(),
+            timestamp: Utc::now(),
+            status: RequestStatus::Pending,
+            latency_ms: None,
+            started: Instant::now(),
+        });
+    }
+
+    /// End `provider`'s oldest pending request with `status`; answers arrive in order. Returns
+    /// how long it took, or `None` if it wasn't in the log.
+    pub fn finish(&mut self, provider: &str, status: RequestStatus) -> Option<u64> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.provider == provider && entry.status == RequestStatus::Pending)?;
+        entry.status = status;
+        entry.latency_ms = Some(entry.started.elapsed().as_millis() as u64);
+        entry.latency_ms
+    }
+
+    /// Mark every pending request cancelled
+    pub fn cancel_pending(&mut self) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.status == RequestStatus::Pending) {
+            entry.status = RequestStatus::Cancelled;
+            entry.latency_ms = Some(entry.started.elapsed().as_millis() as u64);
+        }
+    }
+
+    pub fn entries(&self) -> &VecDeque<RequestLogEntry> {
+        &self.entries
+    }
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self::new(REQUEST_LOG_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut log = RequestLog::new(2);
+        log.start("ChatGPT", "first");
+        log.start("Claude", &"x".repeat(300));
+        log.start("ChatGPT", "third");
+        let prompts: Vec<&str> = log.entries().iter().map(|entry| entry.prompt.as_str()).collect();
+        assert_eq!(prompts.len(), 2);
+        assert_eq!(prompts[0].chars().count(), PROMPT_PREVIEW_CHARS);
+        assert_eq!(prompts[1], "third");
+    }
+
+    #[test]
+    fn test_finish_oldest_pending() {
+        let mut log = RequestLog::default();
+        log.start("Claude", "one");
+        log.start("Claude", "two");
+        log.start("Gemini", "two");
+        log.finish("Claude", RequestStatus::Error);
+        log.finish("Claude", RequestStatus::Success);
+        log.finish("ChatGPT", RequestStatus::Success);
+
+        let statuses: Vec<RequestStatus> = log.entries().iter().map(|entry| entry.status).collect();
+        assert_eq!(statuses, [RequestStatus::Error, RequestStatus::Success, RequestStatus::Pending]);
+        assert!(log.entries()[0].latency_ms.is_some());
+        assert!(log.entries()[2].latency_ms.is_none());
+
+        log.cancel_pending();
+        assert_eq!(log.entries()[2].status, RequestStatus::Cancelled);
+    }
+}
@@ -0,0 +1,154 @@
+//! PNG screenshots of the TUI (Ctrl+P), for bug reports
+//!
+//! Only built with the `screenshot` cargo feature. The frame is drawn into an off-screen buffer
+//! and every cell painted with its colors and its glyph from a monospace font, rasterized with
+//! `fontdue`. No font is bundled: `CHATDELTA_SCREENSHOT_FONT` names a TTF/OTF file, otherwise
+//! the usual system monospace fonts are tried.
+
+use fontdue::{Font, FontSettings};
+use image::{ImageOutputFormat, RgbaImage};
+use std::error::Error;
+use std::io::Cursor;
+use std::path::PathBuf;
+use tui::buffer::Buffer;
+use tui::style::{Color, Modifier};
+
+/// Glyph size in pixels; a cell is as wide as the font's `M` and one line high
+const FONT_SIZE: f32 = 16.0;
+
+/// Where the usual monospace fonts are installed on Linux, macOS and Windows
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "/Library/Fonts/Courier New.ttf",
+    "C:\\Windows\\Fonts\\consola.ttf",
+    "C:\\Windows\\Fonts\\cour.ttf",
+];
+
+/// Text and background of cells that keep the terminal's default colors
+const DEFAULT_FG: [u8; 3] = [229, 229, 229];
+const DEFAULT_BG: [u8; 3] = [0, 0, 0];
+
+/// The font file to draw with: `CHATDELTA_SCREENSHOT_FONT`, or the first system font found
+fn font_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("CHATDELTA_SCREENSHOT_FONT") {
+        return Some(PathBuf::from(path));
+    }
+    SYSTEM_FONTS.iter().map(PathBuf::from).find(|path| path.is_file())
+}
+
+/// Paint `buffer` cell by cell and encode it as PNG
+pub fn render_png(buffer: &Buffer) -> Result<Vec<u8>, Box<dyn Error>> {
+    let path = font_path().ok_or("no monospace font found; set CHATDELTA_SCREENSHOT_FONT to a TTF file")?;
+    let font = Font::from_bytes(std::fs::read(&path)?, FontSettings::default())?;
+    let line = font.horizontal_line_metrics(FONT_SIZE).ok_or("the font has no horizontal metrics")?;
+    let cell_width = font.metrics('M', FONT_SIZE).advance_width.ceil() as u32;
+    let cell_height = line.new_line_size.ceil() as u32;
+    let baseline = line.ascent.round() as i32;
+
+    let area = buffer.area;
+    let mut image = RgbaImage::new(u32::from(area.width) * cell_width, u32::from(area.height) * cell_height);
+    for row in 0..area.height {
+        for column in 0..area.width {
+            let cell = buffer.get(area.x + column, area.y + row);
+            let (mut fg, mut bg) = (rgb(cell.fg, DEFAULT_FG), rgb(cell.bg, DEFAULT_BG));
+            if cell.modifier.contains(Modifier::REVERSED) {
+                std::mem::swap(&mut fg, &mut bg);
+            }
+            let left = u32::from(column) * cell_width;
+            let top = u32::from(row) * cell_height;
+            for y in top..top + cell_height {
+                for x in left..left + cell_width {
+                    image.put_pixel(x, y, image::Rgba([bg[0], bg[1], bg[2], 255]));
+                }
+            }
+
+            // Graphemes of several characters (flags, ZWJ emoji) are drawn by their first one
+            let Some(ch) = cell.symbol.chars().next().filter(|ch| !ch.is_whitespace()) else {
+                continue;
+            };
+            let (metrics, coverage) = font.rasterize(ch, FONT_SIZE);
+            let glyph_left = left as i32 + metrics.xmin;
+            let glyph_top = top as i32 + baseline - metrics.height as i32 - metrics.ymin;
+            for (i, &alpha) in coverage.iter().enumerate() {
+                let x = glyph_left + (i % metrics.width) as i32;
+                let y = glyph_top + (i / metrics.width) as i32;
+                if alpha == 0 || x < 0 || y < 0 || x as u32 >= image.width() || y as u32 >= image.height() {
+                    continue;
+                }
+                let pixel = image.get_pixel_mut(x as u32, y as u32);
+                for (channel, &color) in pixel.0.iter_mut().zip(&fg) {
+                    *channel = ((u32::from(color) * u32::from(alpha) + u32::from(*channel) * u32::from(255 - alpha)) / 255) as u8;
+                }
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+/// RGB of a terminal color, with xterm's palette for the named and indexed ones
+fn rgb(color: Color, default: [u8; 3]) -> [u8; 3] {
+    match color {
+        Color::Reset => default,
+        Color::Black => [0, 0, 0],
+        Color::Red => [205, 0, 0],
+        Color::Green => [0, 205, 0],
+        Color::Yellow => [205, 205, 0],
+        Color::Blue => [0, 0, 238],
+        Color::Magenta => [205, 0, 205],
+        Color::Cyan => [0, 205, 205],
+        Color::Gray => [229, 229, 229],
+        Color::DarkGray => [127, 127, 127],
+        Color::LightRed => [255, 0, 0],
+        Color::LightGreen => [0, 255, 0],
+        Color::LightYellow => [255, 255, 0],
+        Color::LightBlue => [92, 92, 255],
+        Color::LightMagenta => [255, 0, 255],
+        Color::LightCyan => [0, 255, 255],
+        Color::White => [255, 255, 255],
+        Color::Rgb(r, g, b) => [r, g, b],
+        Color::Indexed(index) => indexed_rgb(index),
+    }
+}
+
+/// The xterm 256-color palette: the 16 named colors, a 6×6×6 cube and a gray ramp
+fn indexed_rgb(index: u8) -> [u8; 3] {
+    const NAMED: [Color; 16] = [
+        Color::Black, Color::Red, Color::Green, Color::Yellow, Color::Blue, Color::Magenta, Color::Cyan, Color::Gray,
+        Color::DarkGray, Color::LightRed, Color::LightGreen, Color::LightYellow, Color::LightBlue, Color::LightMagenta,
+        Color::LightCyan, Color::White,
+    ];
+    match index {
+        0..=15 => rgb(NAMED[index as usize], DEFAULT_FG),
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let cube = index - 16;
+            [level(cube / 36), level(cube / 6 % 6), level(cube % 6)]
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            [gray, gray, gray]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_colors() {
+        assert_eq!(indexed_rgb(1), [205, 0, 0]);
+        assert_eq!(indexed_rgb(16), [0, 0, 0]);
+        assert_eq!(indexed_rgb(196), [255, 0, 0]);
+        assert_eq!(indexed_rgb(231), [255, 255, 255]);
+        assert_eq!(indexed_rgb(232), [8, 8, 8]);
+        assert_eq!(indexed_rgb(255), [238, 238, 238]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Sentiment of responses, for research into how the providers frame the same answer
+//!
+//! A small word-list classifier runs locally, so scoring costs no API calls: each word found in
+//! `POSITIVE_WORDS` or `NEGATIVE_WORDS` counts for that side, flipped when one of the few words
+//! before it is a negation ("not helpful"). It is meant for comparing responses with each
+//! other, not as an accurate reading of any one of them.
+
+use serde::{Deserialize, Serialize};
+
+/// Words of a response that carry no sentiment for each one that does, before the response
+/// counts as leaning either way
+const NEUTRAL_WORDS_PER_HIT: usize = 20;
+
+/// How many words back a negation flips a sentiment word
+const NEGATION_WINDOW: usize = 3;
+
+const POSITIVE_WORDS: &[&str] = &[
+    "advantage", "amazing", "beneficial", "benefit", "benefits", "best", "better", "brilliant",
+    "clean", "clear", "convenient", "easy", "effective", "efficient", "elegant", "enjoy",
+    "excellent", "fantastic", "fast", "favorite", "good", "great", "happy", "helpful", "ideal",
+    "improve", "improved", "improves", "love", "nice", "perfect", "pleasant", "popular",
+    "powerful", "recommend", "recommended", "reliable", "robust", "safe", "simple", "strong",
+    "success", "successful", "useful", "valuable", "well", "wonderful",
+];
+
+const NEGATIVE_WORDS: &[&str] = &[
+    "avoid", "awful", "bad", "broken", "bug", "bugs", "complex", "complicated", "concern",
+    "concerns", "confusing", "dangerous", "difficult", "disadvantage", "drawback", "drawbacks",
+    "error", "errors", "fail", "failed", "failure", "fragile", "hard", "harmful", "limitation",
+    "limitations", "poor", "problem", "problems", "risk", "risks", "risky", "slow", "terrible",
+    "unfortunately", "unreliable", "unsafe", "warning", "weak", "worse", "worst", "wrong",
+];
+
+const NEGATIONS: &[&str] = &["not", "no", "never", "isn't", "aren't", "wasn't", "don't", "doesn't", "without", "hardly"];
+
+/// Share of a response that reads positive, negative or neutral; the three add up to 1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SentimentScore {
+    pub positive: f32,
+    pub negative: f32,
+    pub neutral: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sentiment {
+    Positive,
+    Neutral,
+    Negative,
+}
+
+impl SentimentScore {
+    /// The largest of the three shares; ties go to neutral
+    pub fn dominant(&self) -> Sentiment {
+        if self.positive > self.negative && self.positive > self.neutral {
+            Sentiment::Positive
+        } else if self.negative > self.positive && self.negative > self.neutral {
+            Sentiment::Negative
+        } else {
+            Sentiment::Neutral
+        }
+    }
+}
+
+/// Score `text` with the local word lists
+pub fn sentiment_classify(text: &str) -> SentimentScore {
+    let words: Vec<String> = text
+        .split(|c: char| !(c.is_alphanumeric() || c == '\''))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut positive = 0;
+    let mut negative = 0;
+    for (i, word) in words.iter().enumerate() {
+        let is_positive = POSITIVE_WORDS.contains(&word.as_str());
+        if !is_positive && !NEGATIVE_WORDS.contains(&word.as_str()) {
+            continue;
+        }
+        let negated = words[i.saturating_sub(NEGATION_WINDOW)..i]
+            .iter()
+            .any(|before| NEGATIONS.contains(&before.as_str()));
+        if is_positive != negated {
+            positive += 1;
+        } else {
+            negative += 1;
+        }
+    }
+
+    let neutral = (words.len() / NEUTRAL_WORDS_PER_HIT).max(1);
+    let total = (positive + negative + neutral) as f32;
+    SentimentScore {
+        positive: positive as f32 / total,
+        negative: negative as f32 / total,
+        neutral: neutral as f32 / total,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentiment_classify() {
+        let score = sentiment_classify("Rust is a great, reliable and efficient language. I recommend it.");
+        assert_eq!(score.dominant(), Sentiment::Positive);
+        assert!((score.positive + score.negative + score.neutral - 1.0).abs() < 1e-6);
+
+        let score = sentiment_classify("Unfortunately this approach is slow, fragile and has serious problems.");
+        assert_eq!(score.dominant(), Sentiment::Negative);
+
+        assert_eq!(sentiment_classify("The capital of France is Paris.").dominant(), Sentiment::Neutral);
+        assert_eq!(sentiment_classify("").dominant(), Sentiment::Neutral);
+    }
+
+    #[test]
+    fn test_negation_flips_sentiment() {
+        let score = sentiment_classify("This is not good and not reliable.");
+        assert_eq!(score.dominant(), Sentiment::Negative);
+        let score = sentiment_classify("It is not bad and doesn't have problems.");
+        assert_eq!(score.dominant(), Sentiment::Positive);
+    }
+}
@@ -0,0 +1,300 @@
+//! Color themes for the ChatDelta TUI
+//!
+//! A theme maps each semantic UI element to a color. Themes can be picked by name
+//! (`--theme dark`, `--theme light`, `--theme monochrome`) or loaded from
+//! `~/.chatdelta/theme.toml`, and cycled at runtime with F6.
+
+use crate::highlight::DEFAULT_CODE_THEME;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::PathBuf;
+use tui::style::Color;
+
+/// Names of the built-in themes, in the order they are listed to users
+pub const BUILTIN_THEMES: &[&str] = &["dark", "light", "monochrome"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Title color of a provider column with an API key
+    #[serde(deserialize_with = "deserialize_color")]
+    pub provider_enabled: Color,
+    /// Title and text color of a provider column without an API key
+    #[serde(deserialize_with = "deserialize_color")]
+    pub provider_disabled: Color,
+    /// Title color of the selected column or delta field
+    #[serde(deserialize_with = "deserialize_color")]
+    pub selected_title: Color,
+    /// Border color of the selected column or delta field
+    #[serde(deserialize_with = "deserialize_color")]
+    pub selected_border: Color,
+    /// Title color of the delta field
+    #[serde(deserialize_with = "deserialize_color")]
+    pub delta_title: Color,
+    /// Border color of the delta field
+    #[serde(deserialize_with = "deserialize_color")]
+    pub delta_border: Color,
+    /// Text color inside the delta field
+    #[serde(deserialize_with = "deserialize_color")]
+    pub delta_text: Color,
+    /// Border color of the shared input box
+    #[serde(deserialize_with = "deserialize_color")]
+    pub input_border: Color,
+    /// Text color inside the shared input box
+    #[serde(deserialize_with = "deserialize_color")]
+    pub input_text: Color,
+    /// Character diff: text only in the first response
+    #[serde(deserialize_with = "deserialize_color")]
+    pub diff_first_only: Color,
+    /// Character diff: text only in the second response
+    #[serde(deserialize_with = "deserialize_color")]
+    pub diff_second_only: Color,
+    /// Marker after a response that hit the token limit
+    #[serde(deserialize_with = "deserialize_color")]
+    pub truncated_marker: Color,
+    /// Text of the status bar below the input box
+    #[serde(deserialize_with = "deserialize_color")]
+    pub status_bar: Color,
+    /// Alerts in the status bar, negative sentiment and failing providers in the metrics panel
+    #[serde(deserialize_with = "deserialize_color")]
+    pub error: Color,
+    /// Neutral sentiment and providers in the metrics panel with some failures
+    #[serde(deserialize_with = "deserialize_color")]
+    pub warning: Color,
+    /// Positive sentiment and healthy providers in the metrics panel
+    #[serde(deserialize_with = "deserialize_color")]
+    pub success: Color,
+    /// Scrollbar thumb on the right border of columns and the delta field
+    #[serde(deserialize_with = "deserialize_color")]
+    pub scrollbar: Color,
+    /// Show "scroll up/down for more" lines instead of scrollbars, for terminals that draw the
+    /// scrollbar glyphs poorly
+    pub scroll_indicators: bool,
+    /// Syntect theme for code blocks in responses, e.g. `base16-ocean.dark` or `InspiredGitHub`
+    pub code_theme: String,
+}
+
+impl Theme {
+    /// The original ChatDelta palette, tuned for dark terminal backgrounds
+    pub fn dark() -> Self {
+        Self {
+            provider_enabled: Color::Cyan,
+            provider_disabled: Color::DarkGray,
+            selected_title: Color::Yellow,
+            selected_border: Color::Yellow,
+            delta_title: Color::Magenta,
+            delta_border: Color::Magenta,
+            delta_text: Color::White,
+            input_border: Color::Yellow,
+            input_text: Color::White,
+            diff_first_only: Color::Green,
+            diff_second_only: Color::Red,
+            truncated_marker: Color::Yellow,
+            status_bar: Color::DarkGray,
+            error: Color::Red,
+            warning: Color::Yellow,
+            success: Color::Green,
+            scrollbar: Color::Gray,
+            scroll_indicators: false,
+            code_theme: DEFAULT_CODE_THEME.to_string(),
+        }
+    }
+
+    /// A palette that stays readable on light terminal backgrounds
+    pub fn light() -> Self {
+        Self {
+            provider_enabled: Color::Blue,
+            provider_disabled: Color::Gray,
+            selected_title: Color::Red,
+            selected_border: Color::Red,
+            delta_title: Color::Magenta,
+            delta_border: Color::Magenta,
+            delta_text: Color::Black,
+            input_border: Color::Blue,
+            input_text: Color::Black,
+            diff_first_only: Color::Green,
+            diff_second_only: Color::Red,
+            truncated_marker: Color::Rgb(181, 137, 0),
+            status_bar: Color::Gray,
+            error: Color::Red,
+            warning: Color::Rgb(181, 137, 0),
+            success: Color::Green,
+            scrollbar: Color::DarkGray,
+            scroll_indicators: false,
+            code_theme: "InspiredGitHub".to_string(),
+        }
+    }
+
+    /// The terminal's own colors plus shades of gray, for terminals where any palette clashes
+    pub fn monochrome() -> Self {
+        Self {
+            provider_enabled: Color::Reset,
+            provider_disabled: Color::DarkGray,
+            selected_title: Color::White,
+            selected_border: Color::White,
+            delta_title: Color::Reset,
+            delta_border: Color::Gray,
+            delta_text: Color::Reset,
+            input_border: Color::Gray,
+            input_text: Color::Reset,
+            diff_first_only: Color::White,
+            diff_second_only: Color::DarkGray,
+            truncated_marker: Color::White,
+            status_bar: Color::Gray,
+            error: Color::White,
+            warning: Color::Gray,
+            success: Color::Reset,
+            scrollbar: Color::Gray,
+            scroll_indicators: false,
+            code_theme: DEFAULT_CODE_THEME.to_string(),
+        }
+    }
+
+    /// Look up a built-in theme by name
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// The built-in theme after this one in `BUILTIN_THEMES`, or the first one for a theme
+    /// from `theme.toml`. `scroll_indicators` is kept, as it is about the terminal rather
+    /// than colors.
+    pub fn next_builtin(&self) -> (&'static str, Self) {
+        let with_indicators = |theme: Self| Self { scroll_indicators: self.scroll_indicators, ..theme };
+        let current = BUILTIN_THEMES
+            .iter()
+            .position(|name| Self::by_name(name).map(with_indicators).as_ref() == Some(self));
+        let name = match current {
+            Some(idx) => BUILTIN_THEMES[(idx + 1) % BUILTIN_THEMES.len()],
+            None => BUILTIN_THEMES[0],
+        };
+        (name, Self::by_name(name).map(with_indicators).unwrap_or_default())
+    }
+
+    /// Load the user's theme from `~/.chatdelta/theme.toml`, falling back to the default
+    pub fn load() -> Self {
+        let Some(path) = Self::config_path() else {
+            return Self::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        match Self::from_toml(&contents) {
+            Ok(theme) => theme,
+            Err(e) => {
+                eprintln!("⚠️  Ignoring invalid theme file {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Parse a theme from TOML; fields that are not set keep their default colors
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".chatdelta").join("theme.toml"))
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// Parse a color name (`"cyan"`, `"dark_gray"`) or a hex value (`"#ff8800"`)
+pub fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+        let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+        let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+        return Some(Color::Rgb(r, g, b));
+    }
+
+    let color = match value.to_lowercase().replace(['_', '-', ' '], "").as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+fn deserialize_color<'de, D>(deserializer: D) -> Result<Color, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_color(&value)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown color '{}'", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("cyan"), Some(Color::Cyan));
+        assert_eq!(parse_color("Dark_Gray"), Some(Color::DarkGray));
+        assert_eq!(parse_color("#ff8800"), Some(Color::Rgb(255, 136, 0)));
+        assert_eq!(parse_color("#ff88"), None);
+        assert_eq!(parse_color("chartreuse"), None);
+    }
+
+    #[test]
+    fn test_partial_theme_keeps_defaults() {
+        let theme = Theme::from_toml("delta_title = \"green\"\ninput_border = \"#000000\"").unwrap();
+        assert_eq!(theme.delta_title, Color::Green);
+        assert_eq!(theme.input_border, Color::Rgb(0, 0, 0));
+        assert_eq!(theme.provider_enabled, Theme::dark().provider_enabled);
+    }
+
+    #[test]
+    fn test_builtin_themes() {
+        for name in BUILTIN_THEMES {
+            assert!(Theme::by_name(name).is_some());
+        }
+        assert!(Theme::by_name("solarized").is_none());
+    }
+
+    #[test]
+    fn test_next_builtin() {
+        let (name, light) = Theme::dark().next_builtin();
+        assert_eq!((name, &light), ("light", &Theme::light()));
+        assert_eq!(light.next_builtin().0, "monochrome");
+        assert_eq!(Theme::monochrome().next_builtin().0, "dark");
+
+        // A custom theme starts over at the first preset, keeping its scroll indicators
+        let custom = Theme::from_toml("error = \"#ff0000\"\nscroll_indicators = true").unwrap();
+        let (name, dark) = custom.next_builtin();
+        assert_eq!(name, "dark");
+        assert!(dark.scroll_indicators);
+        assert_eq!(dark.next_builtin().0, "light");
+    }
+}
@@ -2,67 +2,594 @@
 //!
 //! Displays a column for each AI provider (OpenAI, Gemini, Claude). If the API key is missing, the column is greyed out.
 
-use std::collections::HashMap;
-use tui::backend::CrosstermBackend;
-use tui::layout::{Constraint, Direction, Layout};
-use tui::style::{Color, Style};
-use tui::text::Span;
-use tui::widgets::{Block, Borders, Paragraph, Wrap};
-use tui::Terminal;
-use crossterm::event::{self, Event, KeyCode};
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use tui::backend::{Backend, CrosstermBackend};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, Clear, Paragraph, Row, Table, Wrap};
+use tui::{Frame, Terminal};
+use crossterm::event::{
+    self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event, KeyCode, KeyboardEnhancementFlags, MouseButton, MouseEvent,
+    MouseEventKind, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, Clear as ClearTerminal, ClearType};
 use crossterm::execute;
 use crossterm::cursor;
 use std::io;
-use chatdelta::{create_client, AiClient, ClientConfig, ClientConfigBuilder, StreamChunk};
-use std::time::Duration;
+use std::sync::Arc;
+use chatdelta::models::ModelCatalog;
+use chatdelta::{create_client_with_configs, create_embeddings_client, AiClient, ChatMessage, EmbeddingsClient, ClientConfig, ClientConfigBuilder, ImageAttachment, PromptOptions, ProviderConfigs, ProxyConfig, ResponseMetadata, Role, StreamChunk};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio::task::{AbortHandle, JoinSet};
+use crate::aliases::{ProviderAliases, PROVIDER_NAMES};
+use crate::clipboard::Clipboard;
+use crate::commands::Command;
+use crate::filters::ResponseFilterChain;
+use crate::events::{HealthStatus, ResponseType};
+use crate::delta::{local_summary, min_pairwise_similarity, DeltaJudge, DeltaStrategy, ResponseDiff, DELTA_DISABLED_MESSAGE, IDENTICAL_RESPONSES_MESSAGE};
+use crate::highlight::CodeHighlighter;
+use crate::history::InputHistory;
+use crate::keys::{Action, KeyResolver, KeyTable, Keymap, CONFIG_FILE};
 use crate::logger::Logger;
+use crate::metrics::TuiMetrics;
+use crate::presets::PromptLibrary;
+use crate::request_log::{RequestLog, RequestStatus, REQUEST_LOG_SIZE};
+use crate::sentiment::{Sentiment, SentimentScore};
+use crate::progress::progress_bar;
+use crate::theme::Theme;
+use crate::webhook::{DeltaSummary, WebhookNotifier};
+#[cfg(feature = "voice")]
+use crate::voice::VoiceInputSource;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use uuid::Uuid;
+
+/// Capacity of the channel carrying responses from provider tasks to the UI loop
+pub const RESPONSE_CHANNEL_CAPACITY: usize = 256;
+/// Capacity of each provider's stream chunk channel
+const STREAM_CHANNEL_CAPACITY: usize = 64;
+/// Upper bound on messages taken off the response channel per frame, so a busy
+/// stream can't starve input handling and redraws
+const MAX_RESPONSES_PER_FRAME: usize = 4 * RESPONSE_CHANNEL_CAPACITY;
+/// Streamed text is added to a column at most this often, however fast chunks arrive
+pub const STREAM_FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Retries allowed across all providers in one session, until `/reset-budget`
+pub const SESSION_RETRY_BUDGET: u32 = 20;
+
+/// Start of the delta text when the summary request failed
+const DELTA_ERROR_PREFIX: &str = "Error generating differences";
+
+/// How long a notice such as "Copied 1,214 chars" stays in the status bar
+pub const NOTICE_DURATION: Duration = Duration::from_secs(3);
+
+/// How long Ctrl+S shows where the session was saved
+pub const SNAPSHOT_NOTICE_DURATION: Duration = Duration::from_secs(2);
+
+/// Pinned notes longer than this are shortened in the delta field title
+const MAX_PINNED_NOTE_CHARS: usize = 60;
+
+/// Drawn after a response that stopped at the provider's token limit
+pub const TRUNCATED_MARKER: &str = "[truncated]";
+
+/// Drawn in front of the prompt of an exchange starred with Alt+S
+pub const STAR_MARKER: &str = "★ ";
+
+/// Spinner shown in a column title while its response is streaming, one frame per render tick
+pub const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Share of a provider's timeout after which its waiting timer turns the warning color
+pub const TIMEOUT_WARNING_RATIO: f32 = 0.8;
+
+/// The input field grows with its text up to this many rows, then scrolls
+pub const MAX_INPUT_LINES: usize = 8;
+
+/// Rows of chat history a column is assumed to show before the first frame is drawn
+pub const COLUMN_VISIBLE_LINES: usize = 25;
+
+/// Rows of delta text assumed to be shown before the first frame is drawn
+pub const DELTA_VISIBLE_LINES: usize = 4;
+
+/// Height of the delta field, borders included, until resized with Ctrl+Up/Ctrl+Down
+pub const DEFAULT_DELTA_HEIGHT: u16 = 6;
+
+/// The delta field can't be shrunk below this many rows; it can grow to half the screen
+pub const MIN_DELTA_HEIGHT: u16 = 4;
+
+/// Error responses in a row after which a column is flagged in the status bar
+pub const REPEATED_FAILURE_ALERT: usize = 2;
+
+/// Input edits that Ctrl+Z can take back
+pub const MAX_UNDO_DEPTH: usize = 100;
+
+/// Rough characters per token, for guessing how much of a context window a conversation takes
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens of a model's context window kept free for its answer when earlier turns are sent along
+pub const RESPONSE_TOKEN_RESERVE: usize = 4_096;
+
+/// Tokens `text` is guessed to take, at `CHARS_PER_TOKEN`
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Terminal cells `text` takes up, counted per grapheme as tui lays them out, so that emoji
+/// ZWJ sequences and combining marks agree with what is drawn
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(UnicodeWidthStr::width).sum()
+}
+
+/// Rows a line takes when word-wrapped to `width` columns, as `Paragraph` wraps it. Words
+/// wider than a row are broken where the row ends. A `width` of 0 counts one row per line.
+pub fn wrapped_rows(line: &str, width: usize) -> usize {
+    if width == 0 {
+        return 1;
+    }
+    let mut rows = 1;
+    let mut used = 0;
+    for word in line.split_whitespace() {
+        let mut len = display_width(word);
+        let needed = if used == 0 { len } else { used + 1 + len };
+        if needed <= width {
+            used = needed;
+            continue;
+        }
+        if used > 0 {
+            rows += 1;
+        }
+        while len > width {
+            rows += 1;
+            len -= width;
+        }
+        used = len;
+    }
+    rows
+}
+
+/// Shorten `note` to at most `max_chars` characters, marking the cut with `…`
+pub fn truncate_note(note: &str, max_chars: usize) -> String {
+    if note.chars().count() <= max_chars {
+        note.to_string()
+    } else {
+        let mut truncated: String = note.chars().take(max_chars).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Take up to `max` pending messages off `rx`, merging consecutive non-final
+/// stream chunks for the same provider into one
+pub fn drain_responses(rx: &mut mpsc::Receiver<ResponseType>, max: usize) -> Vec<ResponseType> {
+    let mut drained: Vec<ResponseType> = Vec::new();
+    for _ in 0..max {
+        let Ok(response_type) = rx.try_recv() else {
+            break;
+        };
+        if let ResponseType::StreamChunk(idx, chunk, false) = &response_type {
+            if let Some(ResponseType::StreamChunk(last_idx, last_chunk, false)) = drained.last_mut() {
+                if *last_idx == *idx {
+                    last_chunk.push_str(chunk);
+                    continue;
+                }
+            }
+        }
+        drained.push(response_type);
+    }
+    drained
+}
+
+/// `1214` as `1,214`
+pub fn format_count(count: usize) -> String {
+    let digits: Vec<char> = count.to_string().chars().collect();
+    let groups: Vec<String> = digits.rchunks(3).rev().map(|group| group.iter().collect()).collect();
+    groups.join(",")
+}
+
+/// Format a duration as `5m 23s`, or `1h 05m 23s` once it passes an hour
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else {
+        format!("{}m {:02}s", minutes, seconds)
+    }
+}
+
+/// `TRUNCATED_MARKER` in `marker_color`, set off by a space, for the end of a truncated
+/// response's last line
+pub fn truncation_spans(marker_color: Color) -> [Span<'static>; 2] {
+    [Span::raw(" "), Span::styled(TRUNCATED_MARKER, Style::default().fg(marker_color))]
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProviderState {
+    /// Has an API key and its health check hasn't answered yet
+    Connecting,
     Enabled,
+    /// Its health check failed; Alt+N turns it on anyway
+    Unavailable,
     Disabled,
 }
 
-#[derive(Debug, Clone)]
-pub enum ResponseType {
-    Provider(usize, String),  // (provider_index, response)
-    Delta(String),            // delta analysis
-    StreamChunk(usize, String, bool),  // (provider_index, chunk, is_final)
+impl ProviderState {
+    /// Whether the column gets prompts. A connecting column does, so the first prompt doesn't
+    /// wait for the health check.
+    pub fn is_active(self) -> bool {
+        matches!(self, ProviderState::Connecting | ProviderState::Enabled)
+    }
+}
+
+/// Where provider API keys are read from: the environment, unless fixed keys replace it
+#[derive(Clone, Default)]
+pub struct ApiKeys(Option<HashMap<String, String>>);
+
+impl ApiKeys {
+    /// Only these keys, by environment variable name, whatever the environment holds
+    pub fn fixed<'a>(keys: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        Self(Some(keys.into_iter().map(|(name, key)| (name.to_string(), key.to_string())).collect()))
+    }
+    
+    /// The key in `env_var`, e.g. `CLAUDE_API_KEY`
+    pub fn get(&self, env_var: &str) -> Option<String> {
+        match &self.0 {
+            Some(keys) => keys.get(env_var).cloned(),
+            None => std::env::var(env_var).ok(),
+        }
+    }
+}
+
+/// Where each part of the screen was drawn in the latest frame, so mouse events can be
+/// matched to what is under the pointer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScreenLayout {
+    pub screen: Rect,
+    pub columns: Vec<Rect>,
+    pub delta: Rect,
+    pub metrics: Rect,
+    pub input: Rect,
+    pub status: Rect,
+    pub progress: Rect,
+}
+
+/// Popup listing the models a column can switch to (Alt+M)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelPicker {
+    pub column: usize,
+    pub models: Vec<String>,
+    pub selected: usize,
+}
+
+/// A part of the screen that reacts to the mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenRegion {
+    Column(usize),
+    Delta,
+    Input,
+}
+
+/// The thumb of a scrollbar `track` rows high, as its first row and length, for a view of
+/// `visible` of `total` lines starting at `offset`. `None` when everything fits.
+pub fn scrollbar_thumb(track: u16, total: usize, visible: usize, offset: usize) -> Option<(u16, u16)> {
+    if track == 0 || total <= visible {
+        return None;
+    }
+    let track = track as usize;
+    let length = (visible * track / total).clamp(1, track);
+    let max_offset = total - visible;
+    let start = (offset.min(max_offset) * (track - length) + max_offset / 2) / max_offset;
+    Some((start as u16, length as u16))
+}
+
+/// Draw a scrollbar thumb over the right border of `area`, which serves as its track
+fn render_scrollbar<B: Backend>(f: &mut Frame<B>, area: Rect, (start, length): (u16, u16), color: Color) {
+    let thumb = Rect {
+        x: area.x + area.width.saturating_sub(1),
+        y: area.y + 1 + start,
+        width: 1,
+        height: length,
+    };
+    let rows: Vec<Spans> = (0..length).map(|_| Spans::from("█")).collect();
+    f.render_widget(Paragraph::new(rows).style(Style::default().fg(color)), thumb);
+}
+
+/// Color of the sentiment dot in a column title
+fn sentiment_color(sentiment: Sentiment, theme: &Theme) -> Color {
+    match sentiment {
+        Sentiment::Positive => theme.success,
+        Sentiment::Neutral => theme.warning,
+        Sentiment::Negative => theme.error,
+    }
+}
+
+/// The inside of a bordered block drawn over `area`
+fn inner_rect(area: Rect) -> Rect {
+    Block::default().borders(Borders::ALL).inner(area)
+}
+
+/// `area` without `top` rows at the top and `bottom` rows at the bottom
+fn shrink_rows(area: Rect, top: u16, bottom: u16) -> Rect {
+    let top = top.min(area.height);
+    Rect {
+        y: area.y + top,
+        height: area.height - top - bottom.min(area.height - top),
+        ..area
+    }
+}
+
+/// A `width` by `height` area in the middle of `size`, shrunk to fit
+fn centered_rect(size: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(size.width);
+    let height = height.min(size.height);
+    Rect {
+        x: size.x + (size.width - width) / 2,
+        y: size.y + (size.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn rect_contains(rect: Rect, x: u16, y: u16) -> bool {
+    x >= rect.x && x < rect.x + rect.width && y >= rect.y && y < rect.y + rect.height
 }
 
 pub struct Provider {
     pub name: &'static str,
     pub state: ProviderState,
     pub chat_history: Vec<String>,
-    pub client: Option<Box<dyn AiClient>>,
+    pub client: Option<Arc<dyn AiClient>>,
+    pub model_version: Option<String>, // Model checkpoint reported with the latest response
+    pub variants: Vec<String>, // Samples of the latest response when `/samples` is above one, or one per model of a multi-model client
+    pub variant_labels: Vec<String>, // Model of each variant from a multi-model client, shown as tabs; empty for samples
+    pub selected_variant: usize, // Variant that feeds the delta analysis
+    pub streaming_in_progress: bool, // Set until the final stream chunk arrives
+    pub spinner_frame: usize, // Index into SPINNER_FRAMES
+    pub last_stream_flush: Instant, // When buffered stream text was last added to chat_history
+    pub time_to_first_token: Option<Duration>, // Reported with the latest streamed response
+    pub last_thinking: Option<String>, // Reasoning the response filters took out of the latest response
+    pub response_stats: Option<String>, // Latency, tokens and cost of the latest response, from its log entry
+    pub model: Option<String>, // Picked with Alt+M; `None` while the catalog's default model is in use
+    pub display_name: Option<String>, // Set with /rename, shown instead of `name`
+    pub sentiment: Option<SentimentScore>, // Of the latest response, shown as a dot in the title
+    pub failures_in_a_row: usize, // Error responses since the last answer, flagged in the status bar
+    pub health_error: Option<String>, // Why the startup health check failed, shown in the state note while Unavailable
+    pub exchange_starts: Vec<(usize, Uuid)>, // History index of each exchange's "You:" line, with the exchange's request ID
+    pub starred_lines: HashSet<usize>, // History indexes of the "You:" lines of starred exchanges, drawn with STAR_MARKER
+    pub truncated_lines: HashSet<usize>, // History indexes of responses that hit the token limit, drawn with TRUNCATED_MARKER
+    pub conversation: Vec<ChatMessage>, // Earlier turns sent along with each prompt; Ctrl+K empties it, the history stays
+    pub pending_prompt: Option<String>, // Prompt being answered, added to `conversation` together with its answer
+    pub request_task: Option<AbortHandle>, // The column's latest request in `tasks`, aborted when Alt+R retries it before it answers
+}
+
+impl Provider {
+    /// Latest response as shown in the column: samples are laid out as separate blocks, and the
+    /// answers of a multi-model client as tabs showing the selected model's answer
+    fn render_variants(&self) -> String {
+        if self.variant_labels.is_empty() {
+            return format!("{}: {}", self.name, self.variant_blocks());
+        }
+        let tabs: Vec<String> = self.variant_labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| if i == self.selected_variant { format!("[{}]", label) } else { label.clone() })
+            .collect();
+        let answer = self.variants.get(self.selected_variant).map(String::as_str).unwrap_or_default();
+        format!("{}: {}  (Alt+V: next model)\n\n{}", self.name, tabs.join(" │ "), answer)
+    }
+    
+    /// Every variant as its own block, headed by its model or its number
+    fn variant_blocks(&self) -> String {
+        let blocks: Vec<String> = self.variants
+            .iter()
+            .enumerate()
+            .map(|(i, variant)| {
+                let marker = if i == self.selected_variant { " ◆" } else { "" };
+                match self.variant_labels.get(i) {
+                    Some(label) => format!("── {}{} ──\n{}", label, marker, variant),
+                    None => format!("── Variant {}/{}{} ──\n{}", i + 1, self.variants.len(), marker, variant),
+                }
+            })
+            .collect();
+        blocks.join("\n\n")
+    }
+    
+
+    /// The name the column is shown under: its `/rename` label, or the provider's name
+    pub fn label(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(self.name)
+    }
+
+    /// Column title, including the model checkpoint once a response has reported it (or the
+    /// picked model before that), the turns the next prompt carries and a spinner while a
+    /// response is streaming
+    pub fn title(&self) -> String {
+        let title = match self.model_version.as_ref().or(self.model.as_ref()) {
+            Some(version) => format!("{} ({})", self.label(), version),
+            None => self.label().to_string(),
+        };
+        let title = match self.turns_in_context() {
+            0 => title,
+            turns => format!("{} 💬 {}", title, turns),
+        };
+        match self.typing_indicator() {
+            Some(spinner) => format!("{} {}", spinner, title),
+            None => title,
+        }
+    }
+    
+    /// Exchanges in `conversation`, i.e. sent along with the next prompt
+    pub fn turns_in_context(&self) -> usize {
+        self.conversation.iter().filter(|message| message.role == Role::User).count()
+    }
+    
+    /// Add the pending prompt and its answer to the conversation
+    fn record_turn(&mut self, answer: &str) {
+        if let Some(prompt) = self.pending_prompt.take() {
+            self.conversation.push(ChatMessage::user(prompt));
+            self.conversation.push(ChatMessage::assistant(answer));
+        }
+    }
+    
+    /// Take the latest exchange back out of the conversation when it was for `prompt`, before
+    /// that prompt is asked again
+    fn forget_turn(&mut self, prompt: &str) {
+        let len = self.conversation.len();
+        if len >= 2 && self.conversation[len - 2].role == Role::User && self.conversation[len - 2].content == prompt {
+            self.conversation.truncate(len - 2);
+        }
+    }
+    
+    /// Drop the oldest exchanges until the conversation and `prompt` are guessed to fit in
+    /// `context_window` tokens with `RESPONSE_TOKEN_RESERVE` to spare. Returns how many went.
+    fn fit_conversation(&mut self, prompt: &str, context_window: Option<usize>) -> usize {
+        let Some(budget) = context_window.map(|window| window.saturating_sub(RESPONSE_TOKEN_RESERVE)) else {
+            return 0;
+        };
+        let mut tokens = estimate_tokens(prompt)
+            + self.conversation.iter().map(|message| estimate_tokens(&message.content)).sum::<usize>();
+        let mut dropped = 0;
+        while tokens > budget && !self.conversation.is_empty() {
+            let exchange = self.conversation.len().min(2);
+            tokens -= self.conversation.drain(..exchange).map(|message| estimate_tokens(&message.content)).sum::<usize>();
+            dropped += 1;
+        }
+        dropped
+    }
+    
+    /// The latest response without the provider name it is shown with; `None` before the first
+    /// prompt and while waiting for the first token
+    pub fn latest_response(&self) -> Option<&str> {
+        if self.chat_history.len() < 2 {
+            return None;
+        }
+        let response = self.chat_history.last()?.strip_prefix(self.name)?.strip_prefix(": ")?;
+        (response != "Thinking...").then_some(response)
+    }
+    
+    /// Lines shown at the bottom of the column: the first-token time of a streamed response,
+    /// and a hint when reasoning was hidden
+    pub fn footer(&self) -> Option<String> {
+        let lines: Vec<String> = [
+            self.response_stats.clone(),
+            self.time_to_first_token.map(|ttft| format!("⏱ First token: {} ms", ttft.as_millis())),
+            self.last_thinking.as_ref().map(|_| "💭 Reasoning hidden (Alt+T to show)".to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+    
+    /// Line shown above the history of a column that isn't simply on; `column` is its index
+    pub fn state_note(&self, column: usize) -> Option<String> {
+        match self.state {
+            ProviderState::Connecting => Some("⏳ Connecting...".to_string()),
+            ProviderState::Enabled => None,
+            ProviderState::Unavailable => Some(match &self.health_error {
+                Some(reason) => format!("⚠️ Unavailable (Alt+{} to turn on anyway): {}", column + 1, reason),
+                None => format!("⚠️ Unavailable (Alt+{} to turn on anyway)", column + 1),
+            }),
+            ProviderState::Disabled => Some(format!("⏸ Off (Alt+{} to turn on)", column + 1)),
+        }
+    }
+    
+    /// Current spinner frame, or `None` when nothing is streaming
+    pub fn typing_indicator(&self) -> Option<&'static str> {
+        self.streaming_in_progress
+            .then(|| SPINNER_FRAMES[self.spinner_frame % SPINNER_FRAMES.len()])
+    }
 }
 
 pub struct AppState {
     pub providers: Vec<Provider>,
     pub shared_input: String,
+    pub cursor_pos: usize, // Text cursor in shared_input, in characters
+    pub input_scroll_offset: usize, // First wrapped row of shared_input shown in the input field
+    pub undo_stack: Vec<(String, usize)>, // (shared_input, cursor_pos) before each edit, for Ctrl+Z
+    pub redo_stack: Vec<(String, usize)>, // States undone with Ctrl+Z, for Ctrl+Y
     pub selected_column: usize, // 0-2 for providers, 3 for delta field
     pub scroll_positions: Vec<usize>, // index 3 will be for delta field
+    pub following: Vec<bool>, // Per column: stays at the bottom as new text arrives (sticky scroll)
     pub delta_text: String,
-    pub show_delta: bool,
+    pub show_delta: bool, // False while the delta field is collapsed (Alt+H) or analysis is off
+    pub delta_height: u16, // Rows of the delta field when shown, borders included
     pub logger: Logger,
     pub use_streaming: bool,  // Toggle for streaming responses
+    pub tasks: JoinSet<()>,   // In-flight provider and delta requests
+    pub pinned_note: Option<String>, // Set with /pin, shown in the delta field title
+    pub delta_strategy: DeltaStrategy,
+    pub delta_diff: Option<ResponseDiff>, // Latest result of the CharDiff strategy
+    pub samples: u32, // Variants requested per provider, set with /samples
+    pub shared_context: Option<String>, // Set with /context, prepended to every prompt
+    pub attachments: Vec<ImageAttachment>, // Images from /attach or --image, sent with the next prompt
+    pub exchange_images: Vec<ImageAttachment>, // Images sent with the latest prompt, so Ctrl+R and Alt+R send them again
+    pub seed: Option<u64>, // Set with /seed, sent to providers that support it
+    pub similarity_threshold: Option<f32>, // Set with /similarity, enables the embeddings pre-check
+    pub started_at: Instant, // When the session began, for the status bar
+    pub single_provider_exchange: bool, // The latest prompt went to one column (Ctrl+Enter), so no delta
+    pub models: ModelCatalog, // Default model per provider, from ~/.chatdelta/models.toml
+    pub provider_configs: ProviderConfigs, // Timeouts and retries per provider, from ~/.chatdelta/providers.toml
+    pub api_keys: ApiKeys, // Read when a column's client is created after startup (Alt+1 to Alt+3, Alt+M) and for embeddings
+    pub streaming_buffers: HashMap<usize, String>, // Stream text per column not yet added to its chat_history
+    pub last_sent_prompt: Option<String>, // The latest prompt sent to any column, for Ctrl+R
+    pub library: PromptLibrary, // Presets for /preset, from ~/.chatdelta/presets.toml
+    pub batch_size: usize, // Columns asked in the current exchange, for the progress row
+    pub response_filters: ResponseFilterChain, // Run on each response before it is shown
+    pub history: InputHistory, // Sent prompts for Up/Down, from ~/.chatdelta/history
+    pub input_focused: bool, // Tab toggles; while set, Up/Down browse the history instead of scrolling
+    pub layout: ScreenLayout, // Set on every draw, for mouse clicks and the scroll wheel
+    pub viewports: Vec<Rect>, // Per column, then the delta field: where its scrolling text went in the latest frame
+    pub clipboard: Clipboard, // Target of Alt+C
+    pub notice: Option<(String, Instant)>, // Shown in the status bar for NOTICE_DURATION after it was set
+    pub config_warnings: Vec<String>, // Config files that were ignored as invalid, shown as a notice once the TUI is up
+    pub last_delta_key: Option<u64>, // Hash of the responses behind the latest delta summary, to skip asking again (Ctrl+D forces it)
+    pub pending_delta_key: Option<u64>, // Hash of the responses the judge is summarizing; becomes last_delta_key once it answers
+    pub aliases: ProviderAliases, // Names slash commands accept for columns, from ~/.chatdelta/aliases.toml
+    pub model_picker: Option<ModelPicker>, // Open while choosing a model for a column
+    pub show_help: bool, // The F1 overlay listing every key is open
+    pub request_log: RequestLog, // Latest requests and how they ended, shown with Ctrl+L
+    pub metrics: TuiMetrics, // Per-provider success rate, latency and tokens for the F3 panel
+    pub theme: Theme, // Colors the TUI is drawn with, from --theme, switched with F6
+    pub keys: KeyResolver, // Turns key presses into actions for the --keymap, with the vim mode
+    pub show_request_log: bool,
+    #[cfg(feature = "voice")]
+    pub voice_input: Option<Arc<dyn VoiceInputSource>>, // Microphone and transcription for F5
+    pub recording: bool, // F5 was pressed and the microphone is on
+    pub status_spinner: usize, // Index into SPINNER_FRAMES for the status bar's pending count
+    pub webhook: Option<WebhookNotifier>, // Told about each finished delta analysis (--webhook)
+    pub code_highlighter: CodeHighlighter, // Colors code blocks in the columns, caching each message
+    pub delta_judge: DeltaJudge, // Provider asked for the differences summary, from ~/.chatdelta/delta.toml, --judge or Alt+J
+    pub delta_author: Option<String>, // Column and model that wrote the latest differences summary
+    pub delta_started: Option<Instant>, // When the judge was asked for the pending differences summary
+    pub delta_footer: Option<String>, // Judge and latency of the latest differences summary, below it
 }
 
 impl AppState {
     pub fn new(provider_states: HashMap<&'static str, ProviderState>) -> Self {
+        Self::with_client_config(provider_states, Self::client_config())
+    }
+    
+    /// `new` with `client_config` as the default for every provider, e.g. with settings from
+    /// the command line
+    pub fn with_client_config(provider_states: HashMap<&'static str, ProviderState>, client_config: ClientConfig) -> Self {
         let mut providers = Vec::new();
-        // Use the new ClientConfigBuilder from v0.4.0
-        let config = ClientConfigBuilder::default()
-            .timeout(Duration::from_secs(30))
-            .retries(3)
-            .build();
+        let mut config_warnings = Vec::new();
+        let configs = Self::load_provider_configs(client_config, &mut config_warnings);
+        let models = Self::load_model_catalog(&mut config_warnings);
+        let mut logger = Logger::new();
+        logger.set_user_agent(&configs.default_config().user_agent);
         
-        for &name in ["ChatGPT", "Gemini", "Claude"].iter() {
+        for name in PROVIDER_NAMES {
+            if let Some(model) = Self::provider_id(name).and_then(|id| models.default_model(id)) {
+                logger.set_provider_model(name, model);
+            }
             let state = *provider_states.get(name).unwrap_or(&ProviderState::Disabled);
             let client = if state == ProviderState::Enabled {
-                Self::create_provider_client(name, &config)
+                Self::create_provider_client(name, &configs, &models)
             } else {
                 None
             };
@@ -70,477 +597,2690 @@ impl AppState {
             providers.push(Provider {
                 name,
                 state,
-                chat_history: vec![Self::create_welcome_message(name)],
+                chat_history: vec![Self::create_welcome_message(name, &models)],
                 client,
+                model_version: None,
+                variants: Vec::new(),
+                variant_labels: Vec::new(),
+                selected_variant: 0,
+                streaming_in_progress: false,
+                spinner_frame: 0,
+                last_stream_flush: Instant::now(),
+                time_to_first_token: None,
+                last_thinking: None,
+                response_stats: None,
+                model: None,
+                display_name: None,
+                sentiment: None,
+                failures_in_a_row: 0,
+                health_error: None,
+                exchange_starts: Vec::new(),
+                starred_lines: HashSet::new(),
+                truncated_lines: HashSet::new(),
+                conversation: Vec::new(),
+                pending_prompt: None,
+                request_task: None,
             });
         }
         let scroll_positions = vec![0; providers.len() + 1]; // +1 for delta field
+        let columns = scroll_positions.len();
+        let following = vec![true; providers.len()];
+        let aliases = ProviderAliases::load(&mut config_warnings);
+        let delta_judge = DeltaJudge::load(&aliases, &mut config_warnings);
+        let library = PromptLibrary::load(&mut config_warnings);
         Self { 
             providers, 
             shared_input: String::new(),
+            cursor_pos: 0,
+            input_scroll_offset: 0,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
             selected_column: 0,
+            following,
             scroll_positions,
             delta_text: "🔍 Differences between AI responses will appear here after you send a query to multiple providers".to_string(),
             show_delta: true,
-            logger: Logger::new(),
+            delta_height: DEFAULT_DELTA_HEIGHT,
+            logger,
             use_streaming: true,  // Enable streaming by default
+            tasks: JoinSet::new(),
+            pinned_note: None,
+            delta_strategy: DeltaStrategy::Summary,
+            delta_diff: None,
+            samples: 1,
+            shared_context: None,
+            attachments: Vec::new(),
+            exchange_images: Vec::new(),
+            seed: None,
+            similarity_threshold: None,
+            started_at: Instant::now(),
+            single_provider_exchange: false,
+            models,
+            provider_configs: configs,
+            api_keys: ApiKeys::default(),
+            streaming_buffers: HashMap::new(),
+            last_sent_prompt: None,
+            library,
+            batch_size: 0,
+            response_filters: ResponseFilterChain::standard(),
+            history: InputHistory::load(),
+            input_focused: false,
+            keys: KeyResolver::new(Keymap::Default, KeyTable::default()),
+            layout: ScreenLayout::default(),
+            viewports: vec![Rect::default(); columns],
+            clipboard: Clipboard::new(),
+            notice: None,
+            config_warnings,
+            last_delta_key: None,
+            pending_delta_key: None,
+            delta_judge,
+            delta_author: None,
+            delta_started: None,
+            delta_footer: None,
+            aliases,
+            model_picker: None,
+            show_help: false,
+            request_log: RequestLog::default(),
+            metrics: TuiMetrics::new(),
+            theme: Theme::default(),
+            show_request_log: false,
+            #[cfg(feature = "voice")]
+            voice_input: None,
+            recording: false,
+            status_spinner: 0,
+            webhook: None,
+            code_highlighter: CodeHighlighter::default(),
+        }
+    }
+    
+    pub fn create_welcome_message(name: &str, models: &ModelCatalog) -> String {
+        Self::welcome_message(name, None, models)
+    }
+    
+    /// Welcome message naming `model`, or the catalog's default when `None`, with its context
+    /// window when it is known
+    fn welcome_message(name: &str, model: Option<&str>, models: &ModelCatalog) -> String {
+        let id = Self::provider_id(name);
+        let model_name = model.or_else(|| id.and_then(|id| models.default_model(id)));
+        let capabilities = id.and_then(|id| models.capabilities(id, model));
+        let model = |emoji: &str| match (model_name, capabilities) {
+            (Some(model), Some(capabilities)) => format!("{} Model: {} ({}k context)", emoji, model, capabilities.max_context_tokens / 1000),
+            (Some(model), None) => format!("{} Model: {}", emoji, model),
+            (None, _) => format!("{} Model: unknown", emoji),
+        };
+        match name {
+            "ChatGPT" => format!(
+                "🤖 Welcome to ChatGPT!\n\n{}\n🏢 Provider: OpenAI\n\n✨ Ready to assist with your queries!\nI excel at general knowledge, coding, writing, and analysis.",
+                model("🧠")
+            ),
+            "Gemini" => format!(
+                "🌟 Welcome to Gemini!\n\n{}\n🏢 Provider: Google\n\n🎯 Ready for action!\nI'm great at multimodal tasks, long context understanding, and creative problem-solving.",
+                model("🚀")
+            ),
+            "Claude" => format!(
+                "🎭 Welcome to Claude!\n\n{}\n🏢 Provider: Anthropic\n\n👋 Hello there!\nI'm designed to be helpful, harmless, and honest. I excel at analysis, writing, coding, and thoughtful conversation.",
+                model("🧬")
+            ),
+            _ => "🤖 Welcome to AI Chat!\n\nReady to help with your questions!".to_string(),
+        }
+    }
+    
+    /// Catalog key of a provider column
+    pub(crate) fn provider_id(name: &str) -> Option<&'static str> {
+        match name {
+            "ChatGPT" => Some("openai"),
+            "Gemini" => Some("gemini"),
+            "Claude" => Some("claude"),
+            _ => None,
+        }
+    }
+    
+    /// `default` with the per-provider timeouts and retries from `~/.chatdelta/providers.toml`,
+    /// or alone with a warning in `warnings` when the file is invalid
+    pub(crate) fn load_provider_configs(default: ClientConfig, warnings: &mut Vec<String>) -> ProviderConfigs {
+        let Some(path) = dirs::home_dir().map(|home| home.join(".chatdelta").join("providers.toml")) else {
+            return ProviderConfigs::new(default);
+        };
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return ProviderConfigs::new(default);
+        };
+        match ProviderConfigs::from_toml(default.clone(), &contents) {
+            Ok(configs) => configs,
+            Err(e) => {
+                warnings.push(format!("Ignoring invalid provider config {}: {}", path.display(), e));
+                ProviderConfigs::new(default)
+            }
+        }
+    }
+    
+    /// Load model defaults from `~/.chatdelta/models.toml`, falling back to the built-in catalog
+    /// with a warning in `warnings` when the file is invalid
+    pub fn load_model_catalog(warnings: &mut Vec<String>) -> ModelCatalog {
+        let Some(path) = dirs::home_dir().map(|home| home.join(".chatdelta").join("models.toml")) else {
+            return ModelCatalog::builtin();
+        };
+        if !path.exists() {
+            return ModelCatalog::builtin();
+        }
+        match ModelCatalog::load(&path) {
+            Ok(catalog) => catalog,
+            Err(e) => {
+                warnings.push(format!("Ignoring invalid model catalog {}: {}", path.display(), e));
+                ModelCatalog::builtin()
+            }
+        }
+    }
+    
+    /// Client settings, including the OpenAI organization and project from the environment
+    pub fn client_config() -> ClientConfig {
+        let mut builder = ClientConfigBuilder::default()
+            .timeout(Duration::from_secs(30))
+            .retries(3)
+            .retry_budget(SESSION_RETRY_BUDGET);
+        if let Ok(organization) = std::env::var("OPENAI_ORG_ID") {
+            builder = builder.openai_organization(organization);
+        }
+        if let Ok(project) = std::env::var("OPENAI_PROJECT_ID") {
+            builder = builder.openai_project(project);
+        }
+        if let Some(proxy) = ProxyConfig::from_env() {
+            builder = builder.proxy(proxy);
+        }
+        builder.build()
+    }
+    
+    pub(crate) fn create_provider_client(name: &str, configs: &ProviderConfigs, models: &ModelCatalog) -> Option<Arc<dyn AiClient>> {
+        let model = models.default_model(Self::provider_id(name)?)?;
+        Self::create_provider_client_with_model(name, model, configs, &ApiKeys::default())
+    }
+    
+    /// A client for every provider with an API key, in column order, with the model it asks.
+    /// The CLI modes send to all of these.
+    pub(crate) fn keyed_provider_clients<'a>(configs: &ProviderConfigs, models: &'a ModelCatalog) -> Vec<(&'static str, &'a str, Arc<dyn AiClient>)> {
+        PROVIDER_NAMES
+            .into_iter()
+            .filter_map(|name| {
+                let model = models.default_model(Self::provider_id(name)?)?;
+                let client = Self::create_provider_client_with_model(name, model, configs, &ApiKeys::default())?;
+                Some((name, model, client))
+            })
+            .collect()
+    }
+    
+    fn create_provider_client_with_model(name: &str, model: &str, configs: &ProviderConfigs, keys: &ApiKeys) -> Option<Arc<dyn AiClient>> {
+        let env_var = match name {
+            "ChatGPT" => "CHATGPT_API_KEY",
+            "Gemini" => "GEMINI_API_KEY",
+            "Claude" => "CLAUDE_API_KEY",
+            _ => return None,
+        };
+        let provider_name = Self::provider_id(name)?;
+        
+        if let Some(api_key) = keys.get(env_var) {
+            // A comma-separated model list asks each of those models, shown as tabs
+            let provider_name = if model.contains(',') {
+                format!("{}-multi:{}", provider_name, model)
+            } else {
+                provider_name.to_string()
+            };
+            create_client_with_configs(&provider_name, &api_key, model, configs).ok().map(Arc::from)
+        } else {
+            None
+        }
+    }
+    
+    /// Send a prompt to every enabled provider, or with `only` to that column alone (Ctrl+Enter,
+    /// `/only`). The other columns are then left alone and no delta analysis runs for the
+    /// exchange. Columns that are off or have no client are skipped; when that leaves none,
+    /// nothing is sent.
+    pub fn send_to_active_providers(&mut self, prompt: &str, only: Option<usize>, tx: mpsc::Sender<ResponseType>) {
+        // Kept for ↑ even when no column takes it
+        self.history.push(prompt);
+        let mut targets = Vec::new();
+        for (idx, provider) in self.providers.iter().enumerate() {
+            if only.is_some_and(|only| only != idx) || !provider.state.is_active() || provider.client.is_none() {
+                continue;
+            }
+            targets.push(idx);
+        }
+        if targets.is_empty() {
+            self.set_notice(if only.is_some() {
+                "⚠️ Select an enabled provider column to send to it alone"
+            } else {
+                "⚠️ No enabled provider to send to"
+            });
+            return;
+        }
+        // Every column already answering this prompt shares that answer; nothing new is logged
+        let pending = PromptOptions { n: Some(self.samples), seed: self.seed, ..Default::default() };
+        if targets.iter().all(|&idx| self.shares_pending_request(idx, prompt, &pending)) {
+            for idx in targets {
+                self.dispatch_to_provider(idx, prompt, &pending, tx.clone());
+            }
+            return;
+        }
+        self.single_provider_exchange = only.is_some();
+        let options = self.start_exchange(prompt);
+        for idx in targets {
+            self.dispatch_to_provider(idx, prompt, &options, tx.clone());
+        }
+    }
+    
+    /// Ask the selected column for a new answer to the latest prompt (Ctrl+R). Its previous
+    /// answer to that prompt is replaced; nothing happens while it is still answering.
+    pub fn regenerate_selected(&mut self, tx: mpsc::Sender<ResponseType>) {
+        let Some(prompt) = self.last_sent_prompt.clone() else {
+            return;
+        };
+        let idx = self.selected_column;
+        let Some(provider) = self.providers.get_mut(idx) else {
+            return;
+        };
+        if provider.client.is_none() || provider.streaming_in_progress {
+            return;
+        }
+        let history = &mut provider.chat_history;
+        if history.last().is_some_and(|last| last.ends_with("Thinking...")) {
+            return;
+        }
+        
+        // send_to_active_providers adds the prompt and a "Thinking..." line back
+        let len = history.len();
+        if len >= 2 && history[len - 2] == format!("You: {}", prompt) {
+            history.truncate(len - 2);
+            provider.forget_turn(&prompt);
+        }
+        // The latest prompt's images go along again
+        if self.attachments.is_empty() {
+            self.attachments = self.exchange_images.clone();
+        }
+        self.send_to_active_providers(&prompt, Some(idx), tx);
+    }
+    
+    /// Send the latest prompt again to the selected column only, when it answered with an error
+    /// or is still waiting (Alt+R); a request still waiting is cancelled first. The error is
+    /// replaced by the new answer in the column and in the log, and the delta analysis runs
+    /// again once it arrives. Does nothing for any other answer.
+    pub fn retry_selected(&mut self, tx: mpsc::Sender<ResponseType>) {
+        let Some(prompt) = self.last_sent_prompt.clone() else {
+            return;
+        };
+        let idx = self.selected_column;
+        let Some(provider) = self.providers.get_mut(idx) else {
+            return;
+        };
+        if !provider.state.is_active() || provider.client.is_none() {
+            return;
+        }
+        let history = &mut provider.chat_history;
+        let response = history.last().and_then(|last| last.strip_prefix(provider.name));
+        let failed = response.is_some_and(|response| response.starts_with(": Error:"));
+        // Waiting for the first token, or part way through a stream
+        let pending = response == Some(": Thinking...") || provider.streaming_in_progress;
+        let len = history.len();
+        if !(failed || pending) || len < 2 || history[len - 2] != format!("You: {}", prompt) {
+            return;
+        }
+        
+        if pending {
+            if let Some(task) = provider.request_task.take() {
+                task.abort();
+            }
+            provider.streaming_in_progress = false;
+            self.streaming_buffers.remove(&idx);
+            self.request_log.finish(provider.name, RequestStatus::Cancelled);
+        }
+        // dispatch_to_provider adds the prompt and a "Thinking..." line back
+        provider.chat_history.truncate(len - 2);
+        let options = PromptOptions {
+            request_id: self.logger.current_conversation().map(|entry| entry.request_id.to_string()),
+            n: Some(self.samples),
+            seed: self.seed,
+            ..Default::default()
+        };
+        // The column rejoins the exchange it failed in rather than starting a new one
+        let batch_size = self.batch_size;
+        self.dispatch_to_provider(idx, &prompt, &options, tx);
+        self.batch_size = batch_size;
+    }
+    
+    /// Branch the session (Ctrl+F). The log so far is saved as it is, and recording continues in
+    /// a copy of it with a new session ID, which is saved right away. The columns keep their
+    /// history, so the fork carries on from the same point; later prompts only go to the fork.
+    pub fn fork_session(&mut self) -> Result<Uuid, Box<dyn std::error::Error>> {
+        self.logger.finalize_conversation();
+        self.logger.save()?;
+        let fork = self.logger.fork();
+        self.logger.save()?;
+        Ok(fork)
+    }
+    
+    /// Log the prompt and build the options shared by every request of the exchange
+    fn start_exchange(&mut self, prompt: &str) -> PromptOptions {
+        self.last_sent_prompt = Some(prompt.to_string());
+        self.batch_size = 0;
+        self.exchange_images = std::mem::take(&mut self.attachments);
+        // Its ID tags every provider request for this exchange
+        let request_id = self.logger.log_prompt(prompt);
+        self.logger.log_seed(self.seed);
+        PromptOptions {
+            request_id: Some(request_id.to_string()),
+            n: Some(self.samples),
+            seed: self.seed,
+            ..Default::default()
+        }
+    }
+    
+    /// Whether column `idx` would wait for an identical request that is still pending instead of
+    /// sending `prompt`. Requests sent with earlier turns of a conversation are never shared.
+    fn shares_pending_request(&self, idx: usize, prompt: &str, options: &PromptOptions) -> bool {
+        let Some(provider) = self.providers.get(idx) else {
+            return false;
+        };
+        provider.conversation.is_empty()
+            && provider.client.as_ref().is_some_and(|client| client.is_request_pending(&self.prompt_with_context(prompt), options))
+    }
+    
+    fn dispatch_to_provider(&mut self, idx: usize, prompt: &str, options: &PromptOptions, tx: mpsc::Sender<ResponseType>) {
+        // Columns and the log show the question; the shared context is only added to what is sent
+        let full_prompt = self.prompt_with_context(prompt);
+        let context_window = self.context_window(idx);
+        let shared = self.shares_pending_request(idx, prompt, options);
+        let Some(provider) = self.providers.get_mut(idx) else {
+            return;
+        };
+        if !provider.state.is_active() {
+            return;
+        }
+        let Some(client) = provider.client.clone() else {
+            return;
+        };
+        // The pending request will answer in place of its "Thinking..." line
+        if shared {
+            let last = provider.chat_history.len().saturating_sub(1);
+            provider.chat_history.insert(last, format!("You: {} (duplicate suppressed, sharing the pending response)", prompt));
+            return;
+        }
+        provider.variants.clear();
+        provider.variant_labels.clear();
+        provider.selected_variant = 0;
+        provider.time_to_first_token = None;
+        provider.last_thinking = None;
+        provider.response_stats = None;
+        provider.sentiment = None;
+        // Images go in a request of their own, which neither streams nor asks for variants
+        let with_images = !self.exchange_images.is_empty();
+        provider.streaming_in_progress = self.use_streaming && self.samples == 1 && client.supports_streaming() && !with_images;
+        provider.spinner_frame = 0;
+        // A regenerated or retried answer replaces the lines its exchange started at
+        let start = provider.chat_history.len();
+        provider.exchange_starts.retain(|&(line, _)| line < start);
+        provider.starred_lines.retain(|&line| line < start);
+        provider.truncated_lines.retain(|&line| line < start);
+        if let Some(request_id) = self.logger.current_conversation().map(|entry| entry.request_id) {
+            provider.exchange_starts.push((start, request_id));
+            if self.logger.is_starred(request_id) {
+                provider.starred_lines.insert(start);
+            }
+        }
+        provider.chat_history.push(format!("You: {}", prompt));
+        provider.chat_history.push(format!("{}: Thinking...", provider.name));
+        // Earlier turns go along, without the shared context they were asked with
+        let dropped = provider.fit_conversation(&full_prompt, context_window);
+        let mut messages = provider.conversation.clone();
+        messages.push(ChatMessage::user(full_prompt.as_str()));
+        provider.pending_prompt = Some(prompt.to_string());
+        let name = provider.name;
+        
+        // Start timer for this provider
+        self.logger.start_provider_timer(provider.name);
+        self.request_log.start(provider.name, prompt);
+        self.batch_size += 1;
+        
+        // The task shares the column's client, keeping its key and connection pool
+        let task = if with_images {
+            Self::spawn_image_request(&mut self.tasks, idx, client, full_prompt, self.exchange_images.clone(), options.clone(), tx)
+        } else {
+            Self::spawn_request(&mut self.tasks, idx, client, messages, options.clone(), self.use_streaming, tx)
+        };
+        self.providers[idx].request_task = Some(task);
+        self.follow_new_text(idx);
+        if dropped > 0 {
+            self.set_notice(format!("✂️ {}: {} oldest turns left out to fit the context window", name, dropped));
+        }
+    }
+    
+    /// Context window of the column's model in tokens, from the model catalog
+    fn context_window(&self, idx: usize) -> Option<usize> {
+        let provider = self.providers.get(idx)?;
+        self.models
+            .capabilities(Self::provider_id(provider.name)?, provider.model.as_deref())
+            .map(|capabilities| capabilities.max_context_tokens as usize)
+    }
+    
+    /// Send a prompt to a single provider column using the given client.
+    /// The task is tracked by `AppState` so it can be cancelled or shut down.
+    pub fn spawn_provider_request(&mut self, idx: usize, client: Arc<dyn AiClient>, prompt: String, options: PromptOptions, tx: mpsc::Sender<ResponseType>) {
+        let task = Self::spawn_request(&mut self.tasks, idx, client, vec![ChatMessage::user(prompt)], options, self.use_streaming, tx);
+        if let Some(provider) = self.providers.get_mut(idx) {
+            provider.request_task = Some(task);
+        }
+    }
+    
+    fn spawn_request(
+        tasks: &mut JoinSet<()>,
+        idx: usize,
+        client: Arc<dyn AiClient>,
+        messages: Vec<ChatMessage>,
+        options: PromptOptions,
+        use_streaming: bool,
+        tx: mpsc::Sender<ResponseType>,
+    ) -> AbortHandle {
+        tasks.spawn(async move {
+            // Samples and the answers of a multi-model client are only asked for the latest prompt
+            let prompt = messages.last().map(|message| message.content.clone()).unwrap_or_default();
+            if options.sample_count() > 1 || !client.fan_out_models().is_empty() {
+                // Variants (or the answers of a multi-model client) are requested together and
+                // shown once all have arrived
+                match client.send_prompt_multi(&prompt, &options).await {
+                    Ok(responses) => {
+                        let metadata = responses.first().map(|r| r.metadata.clone()).unwrap_or_default();
+                        let variants = responses.into_iter().map(|r| r.content).collect();
+                        let _ = tx.send(ResponseType::Variants(idx, variants)).await;
+                        let _ = tx.send(ResponseType::Metadata(idx, metadata)).await;
+                    }
+                    Err(e) => {
+                        let _ = tx.send(ResponseType::Provider(idx, format!("Error: {}", e))).await;
+                    }
+                }
+            } else if use_streaming && client.supports_streaming() {
+                // Use streaming API
+                let (stream_tx, mut stream_rx) = mpsc::channel::<StreamChunk>(STREAM_CHANNEL_CAPACITY);
+                
+                // Forward chunks within the same task so aborting it stops both halves
+                let forward = async {
+                    while let Some(chunk) = stream_rx.recv().await {
+                        if tx.send(ResponseType::StreamChunk(idx, chunk.content, chunk.finished)).await.is_err() {
+                            break;
+                        }
+                        if let Some(metadata) = chunk.metadata {
+                            let _ = tx.send(ResponseType::Metadata(idx, metadata)).await;
+                        }
+                    }
+                };
+                
+                // Start streaming; the sender is dropped when it finishes, which ends the forwarder
+                let stream = async {
+                    if let Err(e) = client.send_conversation_streaming(&messages, &options, stream_tx).await {
+                        let _ = tx.send(ResponseType::Provider(idx, format!("Error: {}", e))).await;
+                    }
+                };
+                
+                tokio::join!(stream, forward);
+            } else {
+                // Use non-streaming API; send results back, ignoring a receiver that is gone because the TUI has exited
+                // A lone prompt goes the usual way, where an identical pending request is shared
+                let response = match messages.as_slice() {
+                    [_] => client.send_prompt_detailed(&prompt, &options).await,
+                    _ => client.send_conversation(&messages, &options).await,
+                };
+                match response {
+                    Ok(response) => {
+                        let _ = tx.send(ResponseType::Provider(idx, response.content)).await;
+                        let _ = tx.send(ResponseType::Metadata(idx, response.metadata)).await;
+                    }
+                    Err(e) => {
+                        let _ = tx.send(ResponseType::Provider(idx, format!("Error: {}", e))).await;
+                    }
+                }
+            }
+        })
+    }
+    
+    /// Send a prompt with images to one column. The provider reports no metadata for these.
+    fn spawn_image_request(
+        tasks: &mut JoinSet<()>,
+        idx: usize,
+        client: Arc<dyn AiClient>,
+        prompt: String,
+        images: Vec<ImageAttachment>,
+        options: PromptOptions,
+        tx: mpsc::Sender<ResponseType>,
+    ) -> AbortHandle {
+        tasks.spawn(async move {
+            let response = match client.send_prompt_with_images(&prompt, &images, &options).await {
+                Ok(response) => response,
+                Err(e) => format!("Error: {}", e),
+            };
+            let _ = tx.send(ResponseType::Provider(idx, response)).await;
+        })
+    }
+    
+    /// Abort all in-flight requests and mark waiting columns as cancelled
+    pub fn cancel_pending(&mut self) {
+        self.tasks.abort_all();
+        self.streaming_buffers.clear();
+        self.request_log.cancel_pending();
+        // The summary being asked for never arrives, so the same responses ask again
+        self.last_delta_key = None;
+        self.pending_delta_key = None;
+        // Aborting the task doesn't end a recording on its blocking thread
+        if std::mem::take(&mut self.recording) {
+            #[cfg(feature = "voice")]
+            if let Some(source) = &self.voice_input {
+                source.stop();
+            }
+        }
+        for provider in &mut self.providers {
+            provider.streaming_in_progress = false;
+            provider.pending_prompt = None;
+            if let Some(last) = provider.chat_history.last_mut() {
+                if last.ends_with("Thinking...") {
+                    *last = format!("{}: ⏹ Cancelled", provider.name);
+                }
+            }
+        }
+    }
+    
+    /// Abort all spawned tasks and give them up to `grace` to wind down
+    pub async fn shutdown(&mut self, grace: Duration) {
+        self.tasks.abort_all();
+        let _ = tokio::time::timeout(grace, async {
+            while self.tasks.join_next().await.is_some() {}
+        })
+        .await;
+    }
+    
+    pub fn handle_response(&mut self, provider_idx: usize, response: String) {
+        if let Some(provider) = self.providers.get_mut(provider_idx) {
+            let provider_name = provider.name;
+            
+            // A streaming request that failed reports its error here
+            provider.streaming_in_progress = false;
+            self.streaming_buffers.remove(&provider_idx);
+            
+            // Log the response
+            let is_error = response.starts_with("Error:");
+            let elapsed = self.logger.elapsed(provider_name);
+            provider.failures_in_a_row = if is_error { provider.failures_in_a_row + 1 } else { 0 };
+            let response = if is_error {
+                // A failed exchange isn't kept as context
+                provider.pending_prompt = None;
+                response
+            } else {
+                let filtered = self.response_filters.apply(&response);
+                provider.last_thinking = filtered.extracted;
+                provider.record_turn(&filtered.text);
+                filtered.text
+            };
+            self.logger.log_provider_response(provider_name, &response, is_error);
+            let status = if is_error { RequestStatus::Error } else { RequestStatus::Success };
+            if let Some(latency_ms) = self.request_log.finish(provider_name, status) {
+                self.metrics.record_response(provider_name, !is_error, latency_ms, None);
+            }
+            
+            // Replace "Thinking..." with actual response; an error keeps the time it took
+            if let Some(last) = provider.chat_history.last_mut() {
+                *last = match elapsed {
+                    Some(elapsed) if is_error => format!("{}: {} (after {}s)", provider_name, response, elapsed.as_secs()),
+                    _ => format!("{}: {}", provider_name, response),
+                };
+            }
+        }
+        self.refresh_sentiment(provider_idx);
+        self.refresh_response_stats(provider_idx);
+        self.follow_new_text(provider_idx);
+        
+        // Note: Delta generation will be triggered from main loop after all responses are received
+    }
+    
+    pub fn handle_variants(&mut self, provider_idx: usize, variants: Vec<String>) {
+        if let Some(provider) = self.providers.get_mut(provider_idx) {
+            provider.variant_labels = provider.client
+                .as_ref()
+                .map(|client| client.fan_out_models().into_iter().map(str::to_string).collect::<Vec<_>>())
+                .filter(|labels| labels.len() == variants.len())
+                .unwrap_or_default();
+            provider.variants = variants;
+            provider.selected_variant = 0;
+            if let Some(first) = provider.variants.first().cloned() {
+                provider.record_turn(&first);
+            }
+            provider.failures_in_a_row = 0;
+            let rendered = provider.render_variants();
+            
+            self.logger.log_provider_response(provider.name, &provider.variant_blocks(), false);
+            if let Some(latency_ms) = self.request_log.finish(provider.name, RequestStatus::Success) {
+                self.metrics.record_response(provider.name, true, latency_ms, None);
+            }
+            
+            if let Some(last) = provider.chat_history.last_mut() {
+                *last = rendered;
+            }
+        }
+        self.refresh_sentiment(provider_idx);
+        self.refresh_response_stats(provider_idx);
+        self.follow_new_text(provider_idx);
+    }
+    
+    /// Score the column's newly logged response and keep it for the column title
+    fn refresh_sentiment(&mut self, provider_idx: usize) {
+        self.logger.compute_sentiment();
+        if let Some(provider) = self.providers.get_mut(provider_idx) {
+            provider.sentiment = self.logger
+                .current_conversation()
+                .and_then(|conversation| conversation.responses.get(provider.name))
+                .and_then(|response| response.sentiment);
+        }
+    }
+    
+    /// Keep the latency, tokens and cost logged for the column's latest response for its footer
+    fn refresh_response_stats(&mut self, provider_idx: usize) {
+        if let Some(provider) = self.providers.get_mut(provider_idx) {
+            provider.response_stats = self.logger
+                .current_conversation()
+                .and_then(|conversation| conversation.responses.get(provider.name))
+                .and_then(|response| response.stats_line());
+        }
+    }
+    
+    /// Select the next variant in the selected column. Returns whether the selection changed,
+    /// in which case the delta analysis should be regenerated.
+    pub fn cycle_variant(&mut self) -> bool {
+        let Some(provider) = self.providers.get_mut(self.selected_column) else {
+            return false;
+        };
+        if provider.variants.len() < 2 {
+            return false;
+        }
+        
+        provider.selected_variant = (provider.selected_variant + 1) % provider.variants.len();
+        let rendered = provider.render_variants();
+        if let Some(last) = provider.chat_history.last_mut() {
+            *last = rendered;
+        }
+        true
+    }
+    
+    /// Buffer a stream chunk. The column is updated right away for the first chunk and the
+    /// final one, otherwise at most every `STREAM_FLUSH_INTERVAL`.
+    pub fn handle_stream_chunk(&mut self, provider_idx: usize, chunk: String, is_final: bool) {
+        let Some(provider) = self.providers.get(provider_idx) else {
+            return;
+        };
+        let first_chunk = provider.chat_history.last().is_some_and(|last| last.contains("Thinking..."));
+        let flush_due = provider.last_stream_flush.elapsed() >= STREAM_FLUSH_INTERVAL;
+        self.streaming_buffers.entry(provider_idx).or_default().push_str(&chunk);
+        if !(is_final || first_chunk || flush_due) {
+            return;
+        }
+        self.flush_stream_buffer(provider_idx);
+        
+        // If this is the final chunk, log the complete response
+        if is_final {
+            let provider = &mut self.providers[provider_idx];
+            provider.streaming_in_progress = false;
+            provider.failures_in_a_row = 0;
+            let prefix = format!("{}: ", provider.name);
+            if let Some(last) = provider.chat_history.last_mut() {
+                // Thinking streams in as it comes and is filtered once the response is complete
+                let filtered = self.response_filters.apply(last.strip_prefix(&prefix).unwrap_or(last));
+                provider.last_thinking = filtered.extracted;
+                *last = format!("{}{}", prefix, filtered.text);
+                provider.record_turn(&filtered.text);
+                self.logger.log_provider_response(provider.name, &filtered.text, false);
+            }
+            if let Some(latency_ms) = self.request_log.finish(provider.name, RequestStatus::Success) {
+                self.metrics.record_response(provider.name, true, latency_ms, None);
+            }
+            self.refresh_sentiment(provider_idx);
+            self.refresh_response_stats(provider_idx);
+        }
+    }
+    
+    /// Add buffered stream text that has waited at least `STREAM_FLUSH_INTERVAL` to its
+    /// column; called once per render tick so a stalled stream still shows what arrived
+    pub fn flush_stream_buffers(&mut self) {
+        let due: Vec<usize> = self.streaming_buffers
+            .keys()
+            .copied()
+            .filter(|&idx| self.providers.get(idx).is_some_and(|p| p.last_stream_flush.elapsed() >= STREAM_FLUSH_INTERVAL))
+            .collect();
+        for idx in due {
+            self.flush_stream_buffer(idx);
+        }
+    }
+    
+    fn flush_stream_buffer(&mut self, provider_idx: usize) {
+        let Some(provider) = self.providers.get_mut(provider_idx) else {
+            return;
+        };
+        provider.last_stream_flush = Instant::now();
+        let Some(text) = self.streaming_buffers.remove(&provider_idx) else {
+            return;
+        };
+        if let Some(last) = provider.chat_history.last_mut() {
+            if last.contains("Thinking...") {
+                // First text - replace "Thinking..." with the actual response
+                *last = format!("{}: {}", provider.name, text);
+            } else {
+                last.push_str(&text);
+            }
+        }
+        self.follow_new_text(provider_idx);
+    }
+    
+    
+    /// Number of providers with an API key
+    pub fn active_provider_count(&self) -> usize {
+        self.providers.iter().filter(|p| p.state.is_active()).count()
+    }
+    
+    /// Time since the TUI was opened
+    pub fn session_duration(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+    
+    /// One-line summary shown in the status bar
+    pub fn status_line(&self) -> String {
+        let session_id = self.logger.session_id().to_string();
+        let mut parts = Vec::new();
+        if let Some(mode) = self.keys.mode_label() {
+            match self.keys.pending_keys() {
+                "" => parts.push(mode.to_string()),
+                keys => parts.push(format!("{} {}", mode, keys)),
+            }
+        }
+        // Notices come before the counters, which are cut off first on a narrow terminal
+        if let Some((notice, _)) = self.notice.as_ref().filter(|(_, at)| at.elapsed() < NOTICE_DURATION) {
+            parts.push(notice.clone());
+        }
+        parts.push(format!("{}/{} active", self.active_provider_count(), self.providers.len()));
+        parts.push(format!("{} chars", self.shared_input.chars().count()));
+        parts.push(format!("{} ({})", format_duration(self.session_duration()), &session_id[..8]));
+        parts.push(format!("{} exchanges", self.logger.exchange_count()));
+        let pending = self.pending_count();
+        if pending > 0 {
+            parts.push(format!("{} {} pending", SPINNER_FRAMES[self.status_spinner], pending));
+        }
+        parts.push(if self.use_streaming { "Stream on" } else { "Stream off" }.to_string());
+        parts.push(self.metrics.get_summary());
+        if let Some(context) = &self.shared_context {
+            parts.push(format!("Context: {} words", context.split_whitespace().count()));
+        }
+        if !self.attachments.is_empty() {
+            parts.push(format!("📎 {} attached", self.attachments.len()));
+        }
+        parts.join(" | ")
+    }
+    
+    /// Problems shown in red ahead of the status line: columns that keep failing and a retry
+    /// budget that is running out
+    pub fn status_alerts(&self) -> Vec<String> {
+        let mut alerts: Vec<String> = self.providers
+            .iter()
+            .filter(|provider| provider.failures_in_a_row >= REPEATED_FAILURE_ALERT)
+            .map(|provider| format!("⚠️ {} failed {} times in a row", provider.label(), provider.failures_in_a_row))
+            .collect();
+        let budget = &self.provider_configs.default_config().retry_budget;
+        if budget.fraction_used() > 0.5 {
+            alerts.push(format!(
+                "⚠️ Retries: {}/{} used (/reset-budget)",
+                budget.retries_used(),
+                budget.max_total_retries
+            ));
+        }
+        alerts
+    }
+    
+    /// Columns still waiting for (or streaming) their response
+    pub fn pending_count(&self) -> usize {
+        self.providers
+            .iter()
+            .filter(|p| p.streaming_in_progress || p.chat_history.last().is_some_and(|last| last.ends_with("Thinking...")))
+            .count()
+    }
+    
+    /// Finished and total columns of an exchange sent to several providers, while some are
+    /// still answering
+    pub fn batch_progress(&self) -> Option<(usize, usize)> {
+        if self.batch_size < 2 {
+            return None;
+        }
+        let waiting = self.pending_count();
+        let done = self.batch_size.saturating_sub(waiting);
+        (done < self.batch_size).then_some((done, self.batch_size))
+    }
+    
+    /// Check every active column's API key and model in the background. The columns show
+    /// "Connecting..." until `handle_health` hears back.
+    pub fn start_health_checks(&mut self, tx: mpsc::Sender<ResponseType>) {
+        for (idx, provider) in self.providers.iter_mut().enumerate() {
+            let Some(client) = provider.client.clone().filter(|_| provider.state.is_active()) else {
+                continue;
+            };
+            provider.state = ProviderState::Connecting;
+            let tx = tx.clone();
+            self.tasks.spawn(async move {
+                let started = Instant::now();
+                let status = match client.health_check().await {
+                    Ok(()) => HealthStatus::Ok(started.elapsed()),
+                    Err(e) => HealthStatus::Failed(e.to_string()),
+                };
+                let _ = tx.send(ResponseType::Health(idx, status)).await;
+            });
+        }
+    }
+
+    /// Finish connecting a column. Columns turned off meanwhile stay off.
+    pub fn handle_health(&mut self, provider_idx: usize, status: HealthStatus) {
+        let Some(provider) = self.providers.get_mut(provider_idx) else {
+            return;
+        };
+        if provider.state != ProviderState::Connecting {
+            return;
+        }
+        match status {
+            HealthStatus::Ok(_) | HealthStatus::Skipped => provider.state = ProviderState::Enabled,
+            HealthStatus::Failed(reason) => {
+                // Shown in the column's state note, so the history only holds exchanges
+                provider.state = ProviderState::Unavailable;
+                provider.health_error = Some(reason);
+            }
+        }
+    }
+
+    /// Turn a column off or back on (Alt+1 to Alt+3). A column turned off keeps its history
+    /// but gets no prompts and is left out of the delta analysis. Turning on a column that had
+    /// no API key at startup reads its environment variable again; one whose health check
+    /// failed is trusted without another check.
+    pub fn toggle_provider(&mut self, idx: usize) {
+        let Some(provider) = self.providers.get_mut(idx) else {
+            return;
+        };
+        let notice = match provider.state {
+            ProviderState::Connecting | ProviderState::Enabled => {
+                provider.state = ProviderState::Disabled;
+                format!("{} off", provider.name)
+            }
+            ProviderState::Unavailable | ProviderState::Disabled => {
+                if provider.client.is_none() {
+                    let model = provider.model.as_deref().or_else(|| {
+                        Self::provider_id(provider.name).and_then(|id| self.models.default_model(id))
+                    });
+                    provider.client = model.and_then(|model| {
+                        Self::create_provider_client_with_model(provider.name, model, &self.provider_configs, &self.api_keys)
+                    });
+                }
+                if provider.client.is_some() {
+                    provider.state = ProviderState::Enabled;
+                    provider.health_error = None;
+                    format!("{} on", provider.name)
+                } else {
+                    format!("{}: API key missing", provider.name)
+                }
+            }
+        };
+        self.set_notice(notice);
+    }
+    
+    /// Open the model list for the selected column (Alt+M), with its current model highlighted
+    pub fn open_model_picker(&mut self) {
+        let Some(provider) = self.providers.get(self.selected_column) else {
+            return;
+        };
+        if !provider.state.is_active() {
+            return;
+        }
+        let Some(id) = Self::provider_id(provider.name) else {
+            return;
+        };
+        let mut models: Vec<String> = self.models.known_models(id).into_iter().map(str::to_string).collect();
+        if let Some(model) = provider.model.as_ref().filter(|model| !models.contains(model)) {
+            models.push(model.clone());
+        }
+        let current = provider.model.as_deref().or(self.models.default_model(id));
+        let selected = models.iter().position(|model| Some(model.as_str()) == current).unwrap_or(0);
+        self.model_picker = Some(ModelPicker { column: self.selected_column, models, selected });
+    }
+    
+    /// Move the model picker's highlight up (`-1`) or down (`1`), wrapping around
+    pub fn move_model_picker(&mut self, step: isize) {
+        if let Some(picker) = self.model_picker.as_mut().filter(|picker| !picker.models.is_empty()) {
+            let len = picker.models.len() as isize;
+            picker.selected = (picker.selected as isize + step).rem_euclid(len) as usize;
+        }
+    }
+    
+    /// Switch the picker's column to the highlighted model and close the picker
+    pub fn confirm_model_picker(&mut self) {
+        let Some(picker) = self.model_picker.take() else {
+            return;
+        };
+        if let Some(model) = picker.models.get(picker.selected) {
+            match self.select_model(picker.column, model) {
+                Ok(()) => self.set_notice(format!("Switched to {}", model)),
+                Err(e) => self.set_notice(format!("⚠️ {}", e)),
+            }
+        }
+    }
+    
+    /// Rebuild a column's client for `model`, keeping its chat history. Later responses are
+    /// logged with the new model.
+    pub fn select_model(&mut self, idx: usize, model: &str) -> Result<(), String> {
+        let Some(provider) = self.providers.get_mut(idx) else {
+            return Err(format!("No column {}", idx));
+        };
+        let client = Self::create_provider_client_with_model(provider.name, model, &self.provider_configs, &self.api_keys)
+            .ok_or_else(|| format!("Could not create a {} client for {}", provider.name, model))?;
+        provider.client = Some(client);
+        let default = Self::provider_id(provider.name).and_then(|id| self.models.default_model(id));
+        provider.model = (default != Some(model)).then(|| model.to_string());
+        provider.model_version = None;
+        if let Some(welcome) = provider.chat_history.first_mut() {
+            *welcome = Self::welcome_message(provider.name, Some(model), &self.models);
+        }
+        self.logger.set_provider_model(provider.name, model);
+        Ok(())
+    }
+    
+    /// Column of a provider named in a slash command argument, by column name or alias
+    pub fn find_provider(&self, name: &str) -> Option<usize> {
+        let name = self.aliases.resolve(name)?;
+        self.providers.iter().position(|provider| provider.name == name)
+    }
+    
+    /// F6: switch to the next built-in theme, code highlighting included
+    pub fn cycle_theme(&mut self) {
+        let (name, theme) = self.theme.next_builtin();
+        self.code_highlighter = CodeHighlighter::new(&theme.code_theme);
+        self.theme = theme;
+        self.set_notice(format!("🎨 Theme: {}", name));
+    }
+    
+    /// Ctrl+K: start a new conversation. No column sends its earlier turns, or the answer it is
+    /// waiting for, along with later prompts; the columns and the log keep them.
+    pub fn clear_conversation(&mut self) {
+        for provider in &mut self.providers {
+            provider.conversation.clear();
+            provider.pending_prompt = None;
+        }
+        self.set_notice("🧹 New conversation: earlier turns are no longer sent");
+    }
+    
+    /// Alt+S: star the exchange at the top of the selected column, or the latest one while the
+    /// delta field is selected, so it can be found with `/browse --starred-only` and exported
+    /// with `--starred-only`. Starring it again removes the star.
+    pub fn toggle_star(&mut self) {
+        let Some(request_id) = self.visible_exchange() else {
+            self.set_notice("⚠️ Nothing to star yet - send a prompt first");
+            return;
+        };
+        let Some(starred) = self.logger.toggle_star(request_id) else {
+            return;
+        };
+        for provider in &mut self.providers {
+            for &(line, id) in &provider.exchange_starts {
+                if id != request_id {
+                    continue;
+                }
+                if starred {
+                    provider.starred_lines.insert(line);
+                } else {
+                    provider.starred_lines.remove(&line);
+                }
+            }
+        }
+        self.set_notice(if starred { "★ Exchange starred" } else { "☆ Star removed" });
+    }
+    
+    /// Request ID of the exchange whose prompt is at or above the top of the selected column,
+    /// or of its first exchange while the column is scrolled above that
+    fn visible_exchange(&self) -> Option<Uuid> {
+        let Some(provider) = self.providers.get(self.selected_column) else {
+            return self.logger.latest_request_id();
+        };
+        let top = self.scroll_offset(self.selected_column);
+        let mut rows = 0;
+        let top_message = self
+            .message_rows(self.selected_column)
+            .into_iter()
+            .position(|message_rows| {
+                rows += message_rows;
+                rows > top
+            })
+            .unwrap_or(provider.chat_history.len());
+        provider.exchange_starts
+            .iter()
+            .rev()
+            .find(|&&(line, _)| line <= top_message)
+            .or(provider.exchange_starts.first())
+            .map(|&(_, request_id)| request_id)
+    }
+    
+    /// Show `notice` in the status bar for a few seconds
+    pub fn set_notice(&mut self, notice: impl Into<String>) {
+        self.notice = Some((notice.into(), Instant::now()));
+    }
+    
+    /// F5: start recording a spoken prompt, or stop the recording so it is transcribed
+    #[cfg(feature = "voice")]
+    pub fn toggle_voice_input(&mut self, tx: mpsc::Sender<ResponseType>) {
+        let Some(source) = self.voice_input.clone() else {
+            self.set_notice("⚠️ Voice input needs CHATGPT_API_KEY or CHATDELTA_WHISPER_ENDPOINT");
+            return;
+        };
+        if self.recording {
+            source.stop();
+            self.set_notice("📝 Transcribing...");
+            return;
+        }
+        self.recording = true;
+        self.set_notice("🎙 Recording... (F5 to stop)");
+        self.tasks.spawn(async move {
+            let transcription = match tokio::task::spawn_blocking(move || source.record_and_transcribe()).await {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            let _ = tx.send(ResponseType::Transcription(transcription)).await;
+        });
+    }
+    
+    #[cfg(not(feature = "voice"))]
+    pub fn toggle_voice_input(&mut self, _tx: mpsc::Sender<ResponseType>) {
+        self.set_notice("⚠️ Voice input is not built in; rebuild with `--features voice`");
+    }
+    
+    /// Insert a transcription at the cursor, where it can be edited before sending
+    pub fn handle_transcription(&mut self, transcription: Result<String, String>) {
+        self.recording = false;
+        match transcription {
+            Ok(text) if !text.is_empty() => {
+                self.insert_text(&text);
+                self.notice = None;
+            }
+            Ok(_) => self.set_notice("⚠️ No speech was recognized"),
+            Err(e) => self.set_notice(format!("⚠️ Voice input failed: {}", e)),
+        }
+    }
+
+    /// Show `notice` for `duration`, at most `NOTICE_DURATION`
+    pub fn flash_notice(&mut self, notice: impl Into<String>, duration: Duration) {
+        let now = Instant::now();
+        let shown_at = now.checked_sub(NOTICE_DURATION.saturating_sub(duration)).unwrap_or(now);
+        self.notice = Some((notice.into(), shown_at));
+    }
+
+    /// Save the session log without ending the session (Ctrl+S). Saving again replaces the
+    /// same file.
+    pub fn save_snapshot(&mut self) {
+        match self.logger.save_snapshot() {
+            Ok(path) => self.flash_notice(format!("💾 Saved! {}", path.display()), SNAPSHOT_NOTICE_DURATION),
+            Err(e) => self.set_notice(format!("⚠️ Could not save the session: {}", e)),
+        }
+    }
+    
+    /// The screen as it is drawn now, as a PNG at the size of the latest frame
+    #[cfg(feature = "screenshot")]
+    pub fn export_screenshot(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let size = self.layout.screen;
+        let size = if size.area() > 0 { size } else { Rect::new(0, 0, 120, 40) };
+        let mut terminal = Terminal::new(tui::backend::TestBackend::new(size.width, size.height))?;
+        terminal.draw(|f| draw(f, self))?;
+        crate::screenshot::render_png(terminal.backend().buffer())
+    }
+    
+    /// Save `export_screenshot` as `~/chatdelta-screenshot-<timestamp>.png` (Ctrl+P)
+    #[cfg(feature = "screenshot")]
+    pub fn save_screenshot(&mut self) {
+        let saved = self.export_screenshot().and_then(|png| {
+            let home = dirs::home_dir().ok_or("no home directory")?;
+            let name = format!("chatdelta-screenshot-{}.png", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+            let path = home.join(name);
+            std::fs::write(&path, png)?;
+            Ok(path)
+        });
+        match saved {
+            Ok(path) => self.flash_notice(format!("📸 Saved! {}", path.display()), SNAPSHOT_NOTICE_DURATION),
+            Err(e) => self.set_notice(format!("⚠️ Could not save the screenshot: {}", e)),
+        }
+    }
+    
+    #[cfg(not(feature = "screenshot"))]
+    pub fn save_screenshot(&mut self) {
+        self.set_notice("⚠️ Screenshots are not built in; rebuild with `--features screenshot`");
+    }
+    
+    /// What Alt+C copies: the selected column's latest response, or the delta text when the
+    /// delta field is selected
+    pub fn selected_text(&self) -> Option<&str> {
+        match self.providers.get(self.selected_column) {
+            Some(provider) => provider.latest_response(),
+            None => (!self.delta_text.is_empty()).then_some(self.delta_text.as_str()),
+        }
+    }
+    
+    /// Copy `selected_text` to the clipboard (Alt+C) and report it in the status bar
+    pub fn copy_selection(&mut self) {
+        let Some(text) = self.selected_text().map(str::to_string) else {
+            self.set_notice("Nothing to copy yet");
+            return;
+        };
+        match self.clipboard.copy(&text) {
+            Ok(_) => self.set_notice(format!("Copied {} chars", format_count(text.chars().count()))),
+            Err(e) => self.set_notice(format!("⚠️ Copy failed: {}", e)),
+        }
+    }
+    
+    /// Show the reasoning hidden from the selected column's latest response in the delta
+    /// field (Alt+T)
+    pub fn show_thinking(&mut self) {
+        let Some(provider) = self.providers.get(self.selected_column) else {
+            return;
+        };
+        self.delta_text = match &provider.last_thinking {
+            Some(thinking) => format!("💭 {}'s reasoning:\n\n{}", provider.name, thinking),
+            None => format!("💭 No reasoning was hidden from {}'s latest response", provider.name),
+        };
+        if let Some(scroll_pos) = self.scroll_positions.get_mut(self.providers.len()) {
+            *scroll_pos = 0;
+        }
+    }
+    
+    /// Advance the spinner of every column that is still streaming or waiting for its answer;
+    /// called once per render tick
+    pub fn advance_spinners(&mut self) {
+        let waiting = |p: &Provider| p.chat_history.last().is_some_and(|last| last.ends_with("Thinking..."));
+        for provider in self.providers.iter_mut().filter(|p| p.streaming_in_progress || waiting(p)) {
+            provider.spinner_frame = (provider.spinner_frame + 1) % SPINNER_FRAMES.len();
+        }
+        if self.pending_count() > 0 {
+            self.status_spinner = (self.status_spinner + 1) % SPINNER_FRAMES.len();
+        }
+    }
+    
+    pub fn handle_metadata(&mut self, provider_idx: usize, metadata: ResponseMetadata) {
+        if let Some(provider) = self.providers.get_mut(provider_idx) {
+            if let Some(version) = metadata.model_version {
+                self.logger.log_model_version(provider.name, &version);
+                provider.model_version = Some(version);
+            }
+            if let Some(fingerprint) = metadata.system_fingerprint {
+                self.logger.log_system_fingerprint(provider.name, &fingerprint);
+            }
+            if metadata.validation_retried {
+                self.logger.log_validation_retry(provider.name);
+            }
+            if let Some(usage) = metadata.token_usage {
+                // Priced for the model picked with Alt+M, when it isn't the default
+                let cost = Self::provider_id(provider.name)
+                    .and_then(|id| self.models.capabilities(id, provider.model.as_deref()))
+                    .map(|model| model.estimate_cost(usage.input_tokens, usage.output_tokens));
+                self.metrics.record_tokens(provider.name, usage.input_tokens + usage.output_tokens);
+                self.logger.log_token_usage(provider.name, usage, cost);
+            }
+            if let Some(ttft) = metadata.time_to_first_token {
+                self.logger.log_time_to_first_token(provider.name, ttft);
+                provider.time_to_first_token = Some(ttft);
+            }
+            if metadata.truncated {
+                self.logger.log_truncated(provider.name);
+                // Only drawn, so the marker never reaches the judge or the conversation context
+                if let Some(last) = provider.chat_history.len().checked_sub(1) {
+                    provider.truncated_lines.insert(last);
+                }
+            }
+        }
+        self.refresh_response_stats(provider_idx);
+    }
+    
+    pub fn generate_delta_with_channel(&mut self, tx: mpsc::Sender<ResponseType>) {
+        if self.single_provider_exchange {
+            return;
+        }
+        
+        // Check if all enabled providers have recent responses (not "Thinking...")
+        let all_responded = self.providers
+            .iter()
+            .filter(|p| p.state.is_active())
+            .all(|p| {
+                p.chat_history.last()
+                    .map(|msg| !msg.contains("Thinking..."))
+                    .unwrap_or(false)
+            });
+            
+        if !all_responded {
+            return;
+        }
+        
+        self.generate_delta_internal(tx);
+    }
+    
+    /// Latest `(provider name, response)` pair from every enabled provider
+    fn latest_responses(&self) -> Vec<(String, String)> {
+        self.providers
+            .iter()
+            .filter(|p| p.state.is_active())
+            .filter_map(|p| {
+                if let Some(variant) = p.variants.get(p.selected_variant) {
+                    return Some((p.name.to_string(), variant.clone()));
+                }
+                p.chat_history.last().and_then(|msg| {
+                    if let Some(colon_pos) = msg.find(": ") {
+                        let response = &msg[colon_pos + 2..];
+                        Some((p.name.to_string(), response.to_string()))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect()
+    }
+    
+    fn generate_delta_internal(&mut self, tx: mpsc::Sender<ResponseType>) {
+        if self.delta_strategy == DeltaStrategy::Disabled {
+            return;
+        }
+        
+        // Get the latest responses from all enabled providers
+        let responses = self.latest_responses();
+        
+        if responses.len() >= 2 && self.delta_strategy == DeltaStrategy::CharDiff {
+            // The diff is computed locally, no judge model needed
+            self.update_response_diff(&responses);
+        } else if responses.len() >= 2 {
+            // Unchanged responses (e.g. a resent prompt with a seed) would get the same summary
+            let key = Self::delta_key(&responses);
+            if self.last_delta_key == Some(key) {
+                self.logger.log_delta_analysis(&self.delta_text);
+                return;
+            }
+            // The summary shown is about to be replaced
+            self.last_delta_key = None;
+            
+            // One of the columns' clients also writes the summary
+            let available: Vec<&'static str> = self.providers
+                .iter()
+                .filter(|p| p.state.is_active() && p.client.is_some())
+                .map(|p| p.name)
+                .collect();
+            let compared: Vec<&str> = responses.iter().map(|(name, _)| name.as_str()).collect();
+            let judge = self.delta_judge
+                .choose(&available, &compared)
+                .and_then(|name| self.providers.iter().find(|p| p.name == name));
+            let Some((judge, judge_client)) = judge.and_then(|judge| Some((judge, judge.client.clone()?))) else {
+                self.delta_author = None;
+                self.delta_footer = None;
+                self.delta_text = format!(
+                    "{}: no provider is available to summarize the differences\n\n{}",
+                    DELTA_ERROR_PREFIX,
+                    local_summary(&responses)
+                );
+                return;
+            };
+            let model = judge.model.as_deref().or_else(|| Self::provider_id(judge.name).and_then(|id| self.models.default_model(id)));
+            self.delta_author = Some(match model {
+                Some(model) => format!("{} · {}", judge.name, model),
+                None => judge.name.to_string(),
+            });
+            self.delta_started = Some(Instant::now());
+            self.delta_footer = None;
+            self.pending_delta_key = Some(key);
+            
+            let responses_clone = responses.clone();
+            let pre_check = self.similarity_threshold
+                .and_then(|threshold| Self::create_embeddings_client(&self.provider_configs, &self.api_keys).map(|client| (client, threshold)));
+            
+            // Create async task for delta generation
+            self.tasks.spawn(async move {
+                // Skip the judge model when the responses say the same thing
+                if let Some((embeddings, threshold)) = pre_check {
+                    if Self::responses_are_similar(embeddings.as_ref(), &responses_clone, threshold).await {
+                        let _ = tx.send(ResponseType::Delta(IDENTICAL_RESPONSES_MESSAGE.to_string())).await;
+                        return;
+                    }
+                }
+                
+                let prompt = Self::create_delta_prompt(&responses_clone);
+                // Without the judge, a local comparison is better than just the error
+                let delta = match judge_client.send_prompt(&prompt).await {
+                    Ok(delta) => delta,
+                    Err(e) => format!("{}: {}\n\n{}", DELTA_ERROR_PREFIX, e, local_summary(&responses_clone)),
+                };
+                let _ = tx.send(ResponseType::Delta(delta)).await;
+            });
+            
+            self.delta_text = "Generating differences summary...".to_string();
+        }
+    }
+    
+    /// Hash of the responses a delta summary was asked for
+    fn delta_key(responses: &[(String, String)]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        responses.hash(&mut hasher);
+        hasher.finish()
+    }
+    
+    /// Ask for a new delta summary of the latest responses even if they haven't changed (Ctrl+D)
+    pub fn regenerate_delta(&mut self, tx: mpsc::Sender<ResponseType>) {
+        self.last_delta_key = None;
+        self.generate_delta_with_channel(tx);
+    }
+    
+    /// Embeddings client for the similarity pre-check, preferring OpenAI over Gemini
+    fn create_embeddings_client(configs: &ProviderConfigs, keys: &ApiKeys) -> Option<Box<dyn EmbeddingsClient>> {
+        if let Some(api_key) = keys.get("CHATGPT_API_KEY") {
+            return create_embeddings_client("openai", &api_key, "text-embedding-3-small", configs.get("openai").clone()).ok();
+        }
+        let api_key = keys.get("GEMINI_API_KEY")?;
+        create_embeddings_client("gemini", &api_key, "text-embedding-004", configs.get("gemini").clone()).ok()
+    }
+    
+    /// Whether every pair of responses is at least `threshold` similar. Embedding failures
+    /// count as "not similar" so the judge model still runs.
+    async fn responses_are_similar(client: &dyn EmbeddingsClient, responses: &[(String, String)], threshold: f32) -> bool {
+        let texts: Vec<String> = responses.iter().map(|(_, response)| response.clone()).collect();
+        match client.embed(&texts).await {
+            Ok(embeddings) if embeddings.len() == texts.len() => {
+                min_pairwise_similarity(&embeddings).is_some_and(|similarity| similarity >= threshold)
+            }
+            _ => false,
+        }
+    }
+    
+    fn update_response_diff(&mut self, responses: &[(String, String)]) {
+        self.delta_diff = match responses {
+            [(first_name, first), (second_name, second), ..] => {
+                Some(ResponseDiff::compute((first_name, first), (second_name, second)))
+            }
+            _ => None,
+        };
+    }
+    
+    /// Switch to the next delta strategy, computing a diff right away if one is needed
+    pub fn cycle_delta_strategy(&mut self) {
+        self.delta_strategy = self.delta_strategy.next();
+        if let Some(scroll_pos) = self.scroll_positions.get_mut(self.providers.len()) {
+            *scroll_pos = 0;
+        }
+        if self.delta_strategy == DeltaStrategy::CharDiff {
+            let responses = self.latest_responses();
+            self.update_response_diff(&responses);
+        }
+    }
+    
+    /// Ask the next provider for differences summaries from now on (Alt+J)
+    pub fn cycle_delta_judge(&mut self) {
+        self.delta_judge = self.delta_judge.next();
+        self.set_notice(format!("Delta judge: {}", self.delta_judge));
+    }
+    
+    /// Turn delta analysis off, or back on with the model summary
+    pub fn toggle_delta(&mut self) {
+        if self.delta_strategy == DeltaStrategy::Disabled {
+            self.delta_strategy = DeltaStrategy::Summary;
+            self.show_delta = true;
+        } else {
+            self.delta_strategy = DeltaStrategy::Disabled;
+            self.show_delta = false;
+            self.delta_diff = None;
+            self.leave_hidden_delta();
+        }
+        if let Some(scroll_pos) = self.scroll_positions.get_mut(self.providers.len()) {
+            *scroll_pos = 0;
+        }
+    }
+    
+    /// Collapse the delta field so the columns get its rows, or bring it back (Alt+H)
+    pub fn toggle_delta_panel(&mut self) {
+        self.show_delta = !self.show_delta;
+        self.leave_hidden_delta();
+    }
+    
+    /// Make the delta field and metrics panel taller, up to half the screen (Ctrl+Up)
+    pub fn grow_delta(&mut self) {
+        self.delta_height = (self.delta_height + 1).min(self.max_delta_height());
+    }
+    
+    /// Make the delta field and metrics panel shorter, down to `MIN_DELTA_HEIGHT` (Ctrl+Down)
+    pub fn shrink_delta(&mut self) {
+        self.delta_height = self.delta_height.saturating_sub(1).max(MIN_DELTA_HEIGHT);
+    }
+    
+    /// Tallest the delta field may be on the screen of the latest frame
+    fn max_delta_height(&self) -> u16 {
+        (self.layout.screen.height / 2).max(MIN_DELTA_HEIGHT)
+    }
+    
+    /// Move the selection off the delta field once it is hidden
+    fn leave_hidden_delta(&mut self) {
+        if !self.show_delta && self.selected_column >= self.providers.len() {
+            self.selected_column = self.providers.len().saturating_sub(1);
+        }
+    }
+    
+    fn create_delta_prompt(responses: &[(String, String)]) -> String {
+        let mut prompt = String::from("Please analyze the following AI responses to the same question and summarize the key differences between them. Focus on factual differences, different approaches, or varying perspectives. Be concise but thorough:\n\n");
+        
+        for (provider, response) in responses {
+            prompt.push_str(&format!("**{}:**\n{}\n\n", provider, response));
+        }
+        
+        prompt.push_str("**Summary of key differences:**");
+        prompt
+    }
+    
+    /// Apply a slash command. Returns a prompt to send when the command asks for one
+    /// (`/preset send`, `/only`), with the only column to send it to.
+    pub fn handle_command(&mut self, command: Command) -> Option<(String, Option<usize>)> {
+        let mut to_send = None;
+        match command {
+            Command::Pin(note) => {
+                self.pinned_note = Some(note);
+            }
+            Command::Unpin => {
+                self.pinned_note = None;
+            }
+            Command::Samples(n) => {
+                self.samples = n;
+            }
+            Command::SetContext(context) => {
+                self.shared_context = Some(context);
+            }
+            Command::LoadContext(path) => match std::fs::read_to_string(&path) {
+                Ok(context) if !context.trim().is_empty() => {
+                    self.shared_context = Some(context.trim().to_string());
+                }
+                Ok(_) => {
+                    self.delta_text = format!("⚠️ {} is empty", path.display());
+                }
+                Err(e) => {
+                    self.delta_text = format!("⚠️ Could not read {}: {}", path.display(), e);
+                }
+            },
+            Command::ClearContext => {
+                self.shared_context = None;
+            }
+            Command::Attach(path) => match ImageAttachment::from_file(&path) {
+                Ok(image) => {
+                    self.attachments.push(image);
+                    self.set_notice(format!("📎 {} will be sent with the next prompt", path.display()));
+                }
+                Err(e) => {
+                    self.delta_text = format!("⚠️ Could not attach {}: {}", path.display(), e);
+                }
+            },
+            Command::Seed(seed) => {
+                self.seed = seed;
+            }
+            Command::Similarity(threshold) => {
+                self.similarity_threshold = threshold;
+            }
+            Command::Tag(tags) => {
+                if !self.logger.add_tags(&tags) {
+                    self.delta_text = "⚠️ Nothing to tag yet - send a prompt first".to_string();
+                }
+            }
+            Command::Browse { tag } => {
+                self.delta_text = self.browse_tagged(&tag);
+                if let Some(scroll_pos) = self.scroll_positions.get_mut(self.providers.len()) {
+                    *scroll_pos = 0;
+                }
+            }
+            Command::BrowseStarred => {
+                self.delta_text = self.browse_starred();
+                if let Some(scroll_pos) = self.scroll_positions.get_mut(self.providers.len()) {
+                    *scroll_pos = 0;
+                }
+            }
+            Command::ResetBudget => {
+                self.provider_configs.default_config().retry_budget.reset();
+            }
+            Command::Rename { provider, label } => match self.find_provider(&provider) {
+                Some(idx) => {
+                    let provider = &mut self.providers[idx];
+                    self.logger.set_display_name(provider.name, label.as_deref());
+                    let notice = match &label {
+                        Some(label) => format!("{} is now shown as {}", provider.name, label),
+                        None => format!("{} is shown under its own name again", provider.name),
+                    };
+                    provider.display_name = label;
+                    self.set_notice(notice);
+                }
+                None => {
+                    self.delta_text = format!("⚠️ Unknown provider '{}'", provider);
+                }
+            },
+            Command::PresetList => {
+                self.delta_text = self.list_presets();
+                if let Some(scroll_pos) = self.scroll_positions.get_mut(self.providers.len()) {
+                    *scroll_pos = 0;
+                }
+            }
+            Command::Preset { name, send } => match self.library.get(&name).cloned() {
+                Some(preset) => {
+                    if let Some(system) = preset.system {
+                        self.shared_context = Some(system);
+                    }
+                    if send {
+                        to_send = Some((preset.prompt, None));
+                    } else {
+                        // Left in the input box for editing
+                        self.cursor_pos = preset.prompt.chars().count();
+                        self.shared_input = preset.prompt;
+                    }
+                }
+                None => {
+                    self.delta_text = format!("⚠️ No preset named '{}' - see /preset list", name);
+                }
+            },
+            Command::Only(prompt) => to_send = Some((prompt, Some(self.selected_column))),
+        }
+        self.logger.set_session_note(self.session_note());
+        to_send
+    }
+    
+    /// One line per preset in the library, for `/preset list`
+    fn list_presets(&self) -> String {
+        let presets = self.library.presets();
+        if presets.is_empty() {
+            return "📚 No presets yet - add [[preset]] entries to ~/.chatdelta/presets.toml".to_string();
+        }
+        
+        let mut lines = vec![format!("📚 {} preset(s):", presets.len())];
+        for preset in presets {
+            lines.push(format!("• {}: {}", preset.name, truncate_note(&preset.prompt, MAX_PINNED_NOTE_CHARS)));
+        }
+        lines.join("\n")
+    }
+    
+    /// One line per exchange of this session tagged `tag`, with all of its tags
+    fn browse_tagged(&self, tag: &str) -> String {
+        let entries = self.logger.get_tagged_entries(tag);
+        if entries.is_empty() {
+            return format!("🏷️ No exchanges tagged '{}' in this session", tag);
+        }
+        
+        let mut lines = vec![format!("🏷️ {} exchange(s) tagged '{}':", entries.len(), tag)];
+        for entry in entries {
+            lines.push(format!(
+                "• {} {} [{}]",
+                entry.timestamp.format("%H:%M:%S"),
+                truncate_note(&entry.prompt, MAX_PINNED_NOTE_CHARS),
+                entry.tags.join(", ")
+            ));
+        }
+        lines.join("\n")
+    }
+    
+    /// One line per exchange of this session starred with Alt+S
+    fn browse_starred(&self) -> String {
+        let entries = self.logger.get_starred();
+        if entries.is_empty() {
+            return "★ No starred exchanges in this session - Alt+S stars the one at the top of the selected column".to_string();
+        }
+        
+        let mut lines = vec![format!("★ {} starred exchange(s):", entries.len())];
+        for entry in entries {
+            lines.push(format!("• {} {}", entry.timestamp.format("%H:%M:%S"), truncate_note(&entry.prompt, MAX_PINNED_NOTE_CHARS)));
+        }
+        lines.join("\n")
+    }
+    
+    /// The session note saved in the log: the pinned note followed by the shared context
+    fn session_note(&self) -> Option<String> {
+        match (&self.pinned_note, &self.shared_context) {
+            (Some(note), Some(context)) => Some(format!("{}\n\nContext:\n{}", note, context)),
+            (Some(note), None) => Some(note.clone()),
+            (None, Some(context)) => Some(format!("Context:\n{}", context)),
+            (None, None) => None,
+        }
+    }
+    
+    /// The prompt as sent to providers, with the shared context in front of it
+    pub fn prompt_with_context(&self, prompt: &str) -> String {
+        match &self.shared_context {
+            Some(context) => format!("Context:\n{}\n\nQuestion: {}", context, prompt),
+            None => prompt.to_string(),
+        }
+    }
+    
+    pub fn handle_delta_response(&mut self, delta: String) {
+        // Only finished analyses go to the webhook, not failed ones
+        if let Some(webhook) = self.webhook.as_ref().filter(|_| !delta.starts_with(DELTA_ERROR_PREFIX)) {
+            let prompt = self.logger.current_conversation().map(|entry| entry.prompt.as_str()).unwrap_or_default();
+            webhook.notify(&DeltaSummary { session_id: self.logger.session_id(), prompt, delta: &delta });
+        }
+        
+        // Log the delta analysis
+        self.logger.log_delta_analysis(&delta);
+        
+        // A failed summary is asked for again next time, even for the same responses
+        let key = self.pending_delta_key.take();
+        if !delta.starts_with(DELTA_ERROR_PREFIX) {
+            self.last_delta_key = key;
+        }
+        
+        // Replayed summaries and those the similarity check answered have no judge time
+        let elapsed = self.delta_started.take().map(|started| started.elapsed());
+        self.delta_footer = match (&self.delta_author, elapsed) {
+            (Some(author), Some(elapsed)) if delta != IDENTICAL_RESPONSES_MESSAGE => {
+                Some(format!("Judge: {} • {:.1}s", author, elapsed.as_secs_f64()))
+            }
+            _ => None,
+        };
+        self.delta_text = delta;
+    }
+    
+    /// Columns plus the delta field while it is shown
+    fn section_count(&self) -> usize {
+        self.providers.len() + usize::from(self.show_delta)
+    }
+    
+    pub fn select_previous_column(&mut self) {
+        let total_sections = self.section_count();
+        if self.selected_column == 0 {
+            self.selected_column = total_sections - 1; // Wrap to last section (delta field when shown)
+        } else {
+            self.selected_column -= 1;
+        }
+    }
+    
+    pub fn select_next_column(&mut self) {
+        self.selected_column = (self.selected_column + 1) % self.section_count();
+    }
+    
+    /// Scroll the selected column or delta field up by `step` lines
+    pub fn scroll_up(&mut self, step: usize) {
+        self.scroll_section_up(self.selected_column, step);
+    }
+    
+    /// Scroll the selected column or delta field down by `step` lines, stopping at the last page
+    pub fn scroll_down(&mut self, step: usize) {
+        self.scroll_section_down(self.selected_column, step);
+    }
+    
+    /// Lines of the selected column or delta field shown at once, from the latest frame
+    pub fn page_size(&self) -> usize {
+        self.visible_lines(self.selected_column)
+    }
+    
+    /// Rows of a provider column, or the delta field for `providers.len()`, shown at once
+    fn visible_lines(&self, section: usize) -> usize {
+        match self.viewports.get(section) {
+            Some(area) if area.height > 0 => area.height as usize,
+            _ if section < self.providers.len() => COLUMN_VISIBLE_LINES,
+            _ => DELTA_VISIBLE_LINES,
+        }
+    }
+    
+    /// Work out from `layout` where the scrolling text of each column and the delta field
+    /// goes: inside the border, below a column's state note, above its footer, and clear of
+    /// the rows scroll indicators take when the theme draws them
+    pub fn update_viewports(&mut self, scroll_indicators: bool) {
+        let indicator_rows = u16::from(scroll_indicators);
+        let mut viewports = Vec::with_capacity(self.providers.len() + 1);
+        for (i, provider) in self.providers.iter().enumerate() {
+            let inner = inner_rect(self.layout.columns.get(i).copied().unwrap_or_default());
+            let note_rows = if provider.state_note(i).is_some() { 2 } else { 0 };
+            let footer_rows = provider.footer().map_or(0, |footer| footer.lines().count() as u16 + 1);
+            viewports.push(shrink_rows(inner, note_rows + indicator_rows, footer_rows + indicator_rows));
+        }
+        // The delta field only shows its indicators while selected
+        let delta_indicator_rows = if self.selected_column == self.providers.len() { indicator_rows } else { 0 };
+        let delta_footer_rows = u16::from(self.delta_strategy == DeltaStrategy::Summary && self.delta_footer.is_some());
+        viewports.push(shrink_rows(inner_rect(self.layout.delta), delta_indicator_rows, delta_indicator_rows + delta_footer_rows));
+        self.viewports = viewports;
+    }
+    
+    /// Rows the text of a column, or the delta field for `providers.len()`, takes once wrapped
+    /// to the width it was last drawn at
+    pub fn content_rows(&self, section: usize) -> usize {
+        let width = self.viewports.get(section).map_or(0, |area| area.width as usize);
+        let rows = |text: &str| text.lines().map(|line| wrapped_rows(line, width)).sum::<usize>();
+        match self.providers.get(section) {
+            Some(_) => self.message_rows(section).into_iter().sum(),
+            None => match (&self.delta_strategy, &self.delta_diff) {
+                (DeltaStrategy::CharDiff, Some(diff)) => rows(&diff.plain_text()),
+                _ => rows(&self.delta_text),
+            },
+        }
+    }
+    
+    /// What a column waiting for its answer shows in place of "Thinking...": a spinner and the
+    /// seconds since its request was sent, and whether most of its timeout has passed
+    pub fn waiting_indicator(&self, idx: usize) -> Option<(String, bool)> {
+        let provider = self.providers.get(idx)?;
+        if *provider.chat_history.last()? != format!("{}: Thinking...", provider.name) {
+            return None;
+        }
+        let elapsed = self.logger.elapsed(provider.name).unwrap_or_default();
+        let config = match Self::provider_id(provider.name) {
+            Some(id) => self.provider_configs.get(id),
+            None => self.provider_configs.default_config(),
+        };
+        let frame = SPINNER_FRAMES[provider.spinner_frame % SPINNER_FRAMES.len()];
+        let near_timeout = elapsed.as_secs_f32() >= config.timeout.as_secs_f32() * TIMEOUT_WARNING_RATIO;
+        Some((format!("{} Thinking... {}s", frame, elapsed.as_secs()), near_timeout))
+    }
+    
+    /// Rows each message of a column takes once wrapped to the width it was last drawn at,
+    /// the star of a starred prompt, the truncation marker and the waiting timer included
+    fn message_rows(&self, section: usize) -> Vec<usize> {
+        let Some(provider) = self.providers.get(section) else {
+            return Vec::new();
+        };
+        let width = self.viewports.get(section).map_or(0, |area| area.width as usize);
+        let rows = |text: &str| text.lines().map(|line| wrapped_rows(line, width)).sum::<usize>();
+        let waiting = self.waiting_indicator(section).map(|(indicator, _)| format!("{}: {}", provider.name, indicator));
+        let last = provider.chat_history.len().saturating_sub(1);
+        provider.chat_history
+            .iter()
+            .enumerate()
+            .map(|(m, msg)| match &waiting {
+                Some(line) if m == last => rows(line),
+                _ if provider.starred_lines.contains(&m) => rows(&format!("{}{}", STAR_MARKER, msg)),
+                _ if provider.truncated_lines.contains(&m) => rows(&format!("{} {}", msg, TRUNCATED_MARKER)),
+                _ => rows(msg),
+            })
+            .collect()
+    }
+    
+    /// PageUp
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.page_size());
+    }
+    
+    /// PageDown
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.page_size());
+    }
+    
+    pub fn scroll_to_top(&mut self) {
+        self.scroll_up(usize::MAX);
+    }
+    
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_down(usize::MAX);
+    }
+    
+    /// Scroll a provider column, or the delta field for `providers.len()`, whether or not it is selected
+    fn scroll_section_up(&mut self, section: usize, step: usize) {
+        if let Some(scroll_pos) = self.scroll_positions.get_mut(section) {
+            *scroll_pos = scroll_pos.saturating_sub(step);
+        }
+        self.update_follow(section);
+    }
+    
+    fn scroll_section_down(&mut self, section: usize, step: usize) {
+        let max_scroll = self.max_scroll(section);
+        if let Some(scroll_pos) = self.scroll_positions.get_mut(section) {
+            // Content shorter than a page doesn't scroll at all
+            *scroll_pos = scroll_pos.saturating_add(step).min(max_scroll).max(*scroll_pos);
+        }
+        self.update_follow(section);
+    }
+    
+    /// The furthest a provider column, or the delta field for `providers.len()`, scrolls: its
+    /// last line at the bottom of the viewport
+    pub fn max_scroll(&self, section: usize) -> usize {
+        self.content_rows(section).saturating_sub(self.visible_lines(section))
+    }
+    
+    /// Where a column or the delta field is drawn from: the bottom while the column follows
+    /// new text, otherwise its scroll position as far as the content still reaches
+    pub fn scroll_offset(&self, section: usize) -> usize {
+        let max_scroll = self.max_scroll(section);
+        if self.following.get(section).copied().unwrap_or(false) {
+            return max_scroll;
+        }
+        self.scroll_positions.get(section).copied().unwrap_or(0).min(max_scroll)
+    }
+    
+    /// A column follows new text while it is scrolled to the bottom, and stops as soon as it
+    /// is scrolled up
+    fn update_follow(&mut self, section: usize) {
+        let at_bottom = self.scroll_positions.get(section).is_some_and(|&pos| pos >= self.max_scroll(section));
+        if let Some(following) = self.following.get_mut(section) {
+            *following = at_bottom;
+        }
+    }
+    
+    /// Keep a following column at the bottom after its text grew
+    fn follow_new_text(&mut self, provider_idx: usize) {
+        if self.following.get(provider_idx).copied().unwrap_or(false) {
+            let max_scroll = self.max_scroll(provider_idx);
+            if let Some(scroll_pos) = self.scroll_positions.get_mut(provider_idx) {
+                *scroll_pos = max_scroll;
+            }
+        }
+    }
+    
+    /// Insert a character at the text cursor
+    pub fn insert_char(&mut self, c: char) {
+        self.save_undo_state();
+        let byte_idx = self.cursor_byte_index();
+        self.shared_input.insert(byte_idx, c);
+        self.cursor_pos += 1;
+    }
+    
+    /// Start a new line in the input (Shift+Enter or Alt+Enter)
+    pub fn insert_newline(&mut self) {
+        self.insert_char('\n');
+    }
+    
+    /// Insert pasted text at the cursor as it is, newlines included, as one undoable edit
+    pub fn insert_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.save_undo_state();
+        let byte_idx = self.cursor_byte_index();
+        self.shared_input.insert_str(byte_idx, text);
+        self.cursor_pos += text.chars().count();
+    }
+    
+    /// Delete the character before the text cursor, with any accents or modifiers that belong
+    /// to it
+    pub fn delete_char_before_cursor(&mut self) {
+        if self.cursor_pos == 0 {
+            return;
+        }
+        self.save_undo_state();
+        let end = self.cursor_byte_index();
+        self.cursor_pos = self.previous_grapheme_boundary();
+        let start = self.cursor_byte_index();
+        self.shared_input.replace_range(start..end, "");
+    }
+    
+    pub fn move_cursor_left(&mut self) {
+        self.cursor_pos = self.previous_grapheme_boundary();
+    }
+    
+    pub fn move_cursor_right(&mut self) {
+        self.cursor_pos = self.next_grapheme_boundary();
+    }
+    
+    /// Delete the character under the text cursor, with any accents or modifiers that belong
+    /// to it
+    pub fn delete_char_at_cursor(&mut self) {
+        let next = self.next_grapheme_boundary();
+        if next > self.cursor_pos {
+            self.save_undo_state();
+            let start = self.cursor_byte_index();
+            let end = self.shared_input.char_indices().nth(next).map_or(self.shared_input.len(), |(idx, _)| idx);
+            self.shared_input.replace_range(start..end, "");
+        }
+    }
+    
+    /// Character index of the start of the grapheme before the cursor
+    fn previous_grapheme_boundary(&self) -> usize {
+        let mut boundary = 0;
+        let mut pos = 0;
+        for grapheme in self.shared_input.graphemes(true) {
+            if pos >= self.cursor_pos {
+                break;
+            }
+            boundary = pos;
+            pos += grapheme.chars().count();
+        }
+        boundary
+    }
+    
+    /// Character index of the end of the grapheme after the cursor
+    fn next_grapheme_boundary(&self) -> usize {
+        let mut pos = 0;
+        for grapheme in self.shared_input.graphemes(true) {
+            pos += grapheme.chars().count();
+            if pos > self.cursor_pos {
+                return pos;
+            }
+        }
+        pos
+    }
+    
+    /// Restore the input as it was before the last edit
+    pub fn undo(&mut self) {
+        if let Some((input, cursor_pos)) = self.undo_stack.pop() {
+            let current = (std::mem::replace(&mut self.shared_input, input), self.cursor_pos);
+            Self::push_bounded(&mut self.redo_stack, current);
+            self.cursor_pos = cursor_pos;
+        }
+    }
+    
+    /// Reapply the last undone edit
+    pub fn redo(&mut self) {
+        if let Some((input, cursor_pos)) = self.redo_stack.pop() {
+            let current = (std::mem::replace(&mut self.shared_input, input), self.cursor_pos);
+            Self::push_bounded(&mut self.undo_stack, current);
+            self.cursor_pos = cursor_pos;
+        }
+    }
+    
+    /// Snapshot the input before an edit; a new edit discards the redo history
+    fn save_undo_state(&mut self) {
+        Self::push_bounded(&mut self.undo_stack, (self.shared_input.clone(), self.cursor_pos));
+        self.redo_stack.clear();
+    }
+    
+    fn push_bounded(stack: &mut Vec<(String, usize)>, state: (String, usize)) {
+        if stack.len() >= MAX_UNDO_DEPTH {
+            stack.remove(0);
+        }
+        stack.push(state);
+    }
+    
+    pub fn move_cursor_home(&mut self) {
+        self.cursor_pos = 0;
+    }
+    
+    pub fn move_cursor_end(&mut self) {
+        self.cursor_pos = self.shared_input.chars().count();
+    }
+    
+    /// Move to the start of the current or previous word
+    pub fn move_cursor_word_left(&mut self) {
+        let chars: Vec<char> = self.shared_input.chars().collect();
+        let mut pos = self.cursor_pos.min(chars.len());
+        while pos > 0 && chars[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        while pos > 0 && !chars[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        self.cursor_pos = pos;
+    }
+    
+    /// Move past the end of the current or next word
+    pub fn move_cursor_word_right(&mut self) {
+        let chars: Vec<char> = self.shared_input.chars().collect();
+        let mut pos = self.cursor_pos.min(chars.len());
+        while pos < chars.len() && chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        while pos < chars.len() && !chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        self.cursor_pos = pos;
+    }
+    
+    /// Split the screen into columns, delta field, input, status bar and progress row
+    pub fn screen_layout(&self, size: Rect) -> ScreenLayout {
+        // Inner width of the input field: the screen minus its two borders
+        let input_width = size.width.saturating_sub(2) as usize;
+        // The delta field and metrics panel share a row, which is gone while both are hidden
+        let panel_height = if self.show_delta || self.metrics.is_enabled() {
+            self.delta_height.min(size.height / 2).max(MIN_DELTA_HEIGHT)
+        } else {
+            0
+        };
+        let main_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),           // Main provider columns
+                Constraint::Length(panel_height), // Delta field and metrics panel
+                Constraint::Length(self.input_height(input_width)), // Input field, grows with its text
+                Constraint::Length(1),        // Status bar
+                Constraint::Length(u16::from(self.batch_progress().is_some())), // Progress row, only while waiting
+            ])
+            .split(size);
+        
+        // Split main area into 3 columns
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(33),
+                Constraint::Percentage(34),
+                Constraint::Percentage(33),
+            ])
+            .split(main_chunks[0]);
+        
+        let panel = main_chunks[1];
+        let (delta, metrics) = match (self.show_delta, self.metrics.is_enabled()) {
+            (true, true) => {
+                let halves = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(67), Constraint::Percentage(33)])
+                    .split(panel);
+                (halves[0], halves[1])
+            }
+            (true, false) => (panel, Rect { width: 0, ..panel }),
+            (false, _) => (Rect { width: 0, ..panel }, panel),
+        };
+        
+        ScreenLayout {
+            screen: size,
+            columns,
+            delta,
+            metrics,
+            input: main_chunks[2],
+            status: main_chunks[3],
+            progress: main_chunks[4],
+        }
+    }
+    
+    /// The part of the latest frame at a terminal cell
+    pub fn region_at(&self, x: u16, y: u16) -> Option<ScreenRegion> {
+        if let Some(i) = self.layout.columns.iter().position(|&rect| rect_contains(rect, x, y)) {
+            return Some(ScreenRegion::Column(i));
+        }
+        if rect_contains(self.layout.delta, x, y) {
+            Some(ScreenRegion::Delta)
+        } else if rect_contains(self.layout.input, x, y) {
+            Some(ScreenRegion::Input)
+        } else {
+            None
+        }
+    }
+    
+    /// A click selects the column or delta field under the pointer, or focuses the input; the
+    /// wheel scrolls whatever is under the pointer without selecting it
+    pub fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let Some(region) = self.region_at(mouse.column, mouse.row) else {
+            return;
+        };
+        let section = match region {
+            ScreenRegion::Column(i) => i,
+            ScreenRegion::Delta => self.providers.len(),
+            ScreenRegion::Input => {
+                if mouse.kind == MouseEventKind::Down(MouseButton::Left) {
+                    self.input_focused = true;
+                }
+                return;
+            }
+        };
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.selected_column = section;
+                self.input_focused = false;
+            }
+            MouseEventKind::ScrollUp => self.scroll_section_up(section, 1),
+            MouseEventKind::ScrollDown => self.scroll_section_down(section, 1),
+            _ => {}
+        }
+    }
+    
+    /// Switch Up/Down between scrolling the selected column and browsing sent prompts (Tab)
+    pub fn toggle_input_focus(&mut self) {
+        self.input_focused = !self.input_focused;
+    }
+    
+    /// Replace the input with the previous sent prompt, keeping what was typed as the draft
+    pub fn history_previous(&mut self) {
+        if let Some(prompt) = self.history.previous(&self.shared_input).map(str::to_string) {
+            self.set_input(prompt);
+        }
+    }
+    
+    /// Replace the input with the next sent prompt, or the draft after the newest one
+    pub fn history_next(&mut self) {
+        if let Some(prompt) = self.history.newer().map(str::to_string) {
+            self.set_input(prompt);
         }
     }
     
-    fn create_welcome_message(name: &str) -> String {
-        match name {
-            "ChatGPT" => {
-                "🤖 Welcome to ChatGPT!\n\n🧠 Model: GPT-4o\n🏢 Provider: OpenAI\n\n✨ Ready to assist with your queries!\nI excel at general knowledge, coding, writing, and analysis."
-            },
-            "Gemini" => {
-                "🌟 Welcome to Gemini!\n\n🚀 Model: Gemini-1.5-Pro\n🏢 Provider: Google\n\n🎯 Ready for action!\nI'm great at multimodal tasks, long context understanding, and creative problem-solving."
-            },
-            "Claude" => {
-                "🎭 Welcome to Claude!\n\n🧬 Model: Claude-3.5-Sonnet\n🏢 Provider: Anthropic\n\n👋 Hello there!\nI'm designed to be helpful, harmless, and honest. I excel at analysis, writing, coding, and thoughtful conversation."
-            },
-            _ => "🤖 Welcome to AI Chat!\n\nReady to help with your questions!"
-        }.to_string()
+    /// Replace the whole input, with the cursor at its end; undoable like an edit
+    fn set_input(&mut self, input: String) {
+        self.save_undo_state();
+        self.cursor_pos = input.chars().count();
+        self.shared_input = input;
     }
     
-    fn create_provider_client(name: &str, config: &ClientConfig) -> Option<Box<dyn AiClient>> {
-        let (env_var, provider_name, model) = match name {
-            "ChatGPT" => ("CHATGPT_API_KEY", "openai", "gpt-4o"),
-            "Gemini" => ("GEMINI_API_KEY", "gemini", "gemini-1.5-pro"),
-            "Claude" => ("CLAUDE_API_KEY", "claude", "claude-3-5-sonnet-20241022"),
-            _ => return None,
-        };
-        
-        if let Ok(api_key) = std::env::var(env_var) {
-            create_client(provider_name, &api_key, model, config.clone()).ok()
-        } else {
-            None
-        }
+    pub fn clear_input(&mut self) {
+        self.shared_input.clear();
+        self.cursor_pos = 0;
+        self.input_scroll_offset = 0;
     }
     
-    pub fn send_to_active_providers(&mut self, prompt: &str, tx: mpsc::UnboundedSender<ResponseType>) {
-        let prompt = prompt.to_string();
-        
-        // Log the prompt
-        self.logger.log_prompt(&prompt);
-        
-        for (idx, provider) in self.providers.iter_mut().enumerate() {
-            if let Some(_client) = &provider.client {
-                provider.chat_history.push(format!("You: {}", prompt));
-                provider.chat_history.push(format!("{}: Thinking...", provider.name));
-                
-                // Start timer for this provider
-                self.logger.start_provider_timer(provider.name);
-                
-                // Get new client for the async task (since we can't move the trait object)
-                // Use the new ClientConfigBuilder from v0.4.0
-                let config = ClientConfigBuilder::default()
-                    .timeout(Duration::from_secs(30))
-                    .retries(3)
-                    .build();
-                if let Some(new_client) = Self::create_provider_client(provider.name, &config) {
-                    let prompt_clone = prompt.clone();
-                    let tx_clone = tx.clone();
-                    let use_streaming = self.use_streaming;
-                    
-                    // Spawn async task for each provider
-                    tokio::spawn(async move {
-                        if use_streaming && new_client.supports_streaming() {
-                            // Use streaming API
-                            let (stream_tx, mut stream_rx) = mpsc::unbounded_channel::<StreamChunk>();
-                            
-                            // Spawn task to handle streaming
-                            let tx_clone2 = tx_clone.clone();
-                            let idx_clone = idx;
-                            tokio::spawn(async move {
-                                while let Some(chunk) = stream_rx.recv().await {
-                                    if tx_clone2.send(ResponseType::StreamChunk(idx_clone, chunk.content, chunk.finished)).is_err() {
-                                        eprintln!("Failed to send stream chunk");
-                                        break;
-                                    }
-                                }
-                            });
-                            
-                            // Start streaming
-                            if let Err(e) = new_client.send_prompt_streaming(&prompt_clone, stream_tx).await {
-                                if tx_clone.send(ResponseType::Provider(idx, format!("Error: {}", e))).is_err() {
-                                    eprintln!("Failed to send error response");
-                                }
-                            }
-                        } else {
-                            // Use non-streaming API
-                            let response = match new_client.send_prompt(&prompt_clone).await {
-                                Ok(resp) => resp,
-                                Err(e) => format!("Error: {}", e),
-                            };
-                            
-                            // Send result back
-                            if tx_clone.send(ResponseType::Provider(idx, response)).is_err() {
-                                eprintln!("Failed to send response");
-                            }
-                        }
-                    });
+    /// Character ranges of the input's rows in a field `field_width` cells wide (inside the
+    /// borders). Each row leaves two cells for the "> " prompt or its indent; newlines and
+    /// long lines start new rows, and wide characters and graphemes are never split.
+    fn input_rows(&self, field_width: usize) -> Vec<(usize, usize)> {
+        // Room for at least one wide character
+        let text_width = field_width.saturating_sub(2).max(2);
+        let mut rows = Vec::new();
+        let mut line_start = 0;
+        for line in self.shared_input.split('\n') {
+            let mut row_start = line_start;
+            let mut row_width = 0;
+            let mut pos = line_start;
+            for grapheme in line.graphemes(true) {
+                let width = grapheme.width();
+                if row_width + width > text_width {
+                    rows.push((row_start, pos));
+                    row_start = pos;
+                    row_width = 0;
                 }
+                row_width += width;
+                pos += grapheme.chars().count();
             }
+            rows.push((row_start, pos));
+            // A full row is followed by another, so the cursor after it has somewhere to go
+            if row_width >= text_width {
+                rows.push((pos, pos));
+            }
+            line_start = pos + 1;
         }
+        rows
     }
     
-    pub fn handle_response(&mut self, provider_idx: usize, response: String) {
-        if let Some(provider) = self.providers.get_mut(provider_idx) {
-            let provider_name = provider.name;
+    /// Row and cell column of the text cursor among the rows of `input_rows`, the column
+    /// counting the prompt
+    fn input_cursor(&self, field_width: usize) -> (usize, usize) {
+        let rows = self.input_rows(field_width);
+        let row = rows
+            .iter()
+            .rposition(|&(start, end)| start <= self.cursor_pos && self.cursor_pos <= end)
+            .unwrap_or(rows.len() - 1);
+        let before_cursor: String = self.shared_input.chars().skip(rows[row].0).take(self.cursor_pos - rows[row].0).collect();
+        (row, display_width(&before_cursor) + 2)
+    }
+    
+    /// Height of the input field, borders included: one row per line of input, up to
+    /// `MAX_INPUT_LINES`
+    pub fn input_height(&self, field_width: usize) -> u16 {
+        self.input_rows(field_width).len().min(MAX_INPUT_LINES) as u16 + 2
+    }
+    
+    /// Scroll the input so the cursor's row stays visible in a field `field_width` characters
+    /// wide (inside the borders, including the "> " prompt)
+    pub fn update_input_scroll(&mut self, field_width: usize) {
+        let rows = self.input_rows(field_width).len();
+        let (cursor_row, _) = self.input_cursor(field_width);
+        self.input_scroll_offset = self
+            .input_scroll_offset
+            .clamp(cursor_row.saturating_sub(MAX_INPUT_LINES - 1), cursor_row)
+            .min(rows.saturating_sub(MAX_INPUT_LINES));
+    }
+    
+    /// The rows of the input that fit in the field, starting at `input_scroll_offset`
+    pub fn visible_input(&self, field_width: usize) -> String {
+        let chars: Vec<char> = self.shared_input.chars().collect();
+        self.input_rows(field_width)
+            .iter()
+            .enumerate()
+            .skip(self.input_scroll_offset)
+            .take(MAX_INPUT_LINES)
+            .map(|(row, &(start, end))| {
+                let prompt = if row == 0 { "> " } else { "  " };
+                format!("{}{}", prompt, chars[start..end].iter().collect::<String>())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    
+    /// Position of the text cursor inside the input field's borders, after `update_input_scroll`.
+    /// Kept inside the field even when it is too narrow for the prompt.
+    pub fn visible_input_cursor(&self, field_width: usize) -> (u16, u16) {
+        let (row, column) = self.input_cursor(field_width);
+        let column = column.min(field_width.saturating_sub(1));
+        let row = row.saturating_sub(self.input_scroll_offset).min(MAX_INPUT_LINES - 1);
+        (u16::try_from(column).unwrap_or(u16::MAX), row as u16)
+    }
+    
+    fn cursor_byte_index(&self) -> usize {
+        self.shared_input
+            .char_indices()
+            .nth(self.cursor_pos)
+            .map_or(self.shared_input.len(), |(idx, _)| idx)
+    }
+}
+
+/// Draw one frame of the TUI
+fn draw<B: Backend>(f: &mut Frame<B>, app: &mut AppState) {
+    let theme = app.theme.clone();
+    let theme = &theme;
+    let size = f.size();
+    // Inner width of the input field: the screen minus its two borders
+    let input_width = size.width.saturating_sub(2) as usize;
+    let batch_progress = app.batch_progress();
+    
+    // Split into provider columns, delta area, input area, status bar and progress row
+    let layout = app.screen_layout(size);
+    app.layout = layout.clone();
+    app.update_viewports(theme.scroll_indicators);
+
+    // Code blocks are highlighted once per message, not on every frame
+    for message in app.providers.iter().flat_map(|provider| &provider.chat_history) {
+        app.code_highlighter.prepare(message);
+    }
+    let highlighter = &app.code_highlighter;
+
+    // Render provider columns
+    for (i, provider) in app.providers.iter().enumerate() {
+        let is_selected = i == app.selected_column;
+        let title = if is_selected {
+            format!("► {} ◄", provider.title())
+        } else {
+            provider.title()
+        };
+        
+        let mut title_spans = vec![Span::styled(
+            title,
+            Style::default().fg(if provider.state.is_active() {
+                if is_selected { theme.selected_title } else { theme.provider_enabled }
+            } else {
+                theme.provider_disabled
+            }),
+        )];
+        if let Some(score) = provider.sentiment {
+            title_spans.push(Span::styled(" ●", Style::default().fg(sentiment_color(score.dominant(), theme))));
+        }
+        
+        let block = Block::default()
+            .title(Spans::from(title_spans))
+            .borders(Borders::ALL)
+            .border_style(if is_selected {
+                Style::default().fg(theme.selected_border)
+            } else {
+                Style::default()
+            });
+
+        // A column turned off with Alt+N still shows its history, greyed out
+        let style = if provider.state.is_active() {
+            Style::default()
+        } else {
+            Style::default().fg(theme.provider_disabled)
+        };
+        let inner = block.inner(layout.columns[i]);
+        f.render_widget(block, layout.columns[i]);
+        if provider.state.is_active() || provider.client.is_some() {
+            let scroll_pos = app.scroll_offset(i);
+            let text_area = app.viewports[i];
+            let total_rows = app.content_rows(i);
+            // Each line with its highlighted spans when it is code
+            let marker = theme.truncated_marker;
+            let star = Span::styled(STAR_MARKER, Style::default().fg(theme.selected_title));
+            let error_prefix = format!("{}: Error:", provider.name);
+            let mut lines: Vec<Spans<'static>> = provider.chat_history
+                .iter()
+                .enumerate()
+                .flat_map(|(m, msg)| {
+                    let code = highlighter.get(msg);
+                    let starred = provider.starred_lines.contains(&m);
+                    let truncated_at = provider.truncated_lines.contains(&m).then(|| msg.lines().count().saturating_sub(1));
+                    // Errors are drawn in the error color, whatever they contain
+                    let error = msg.starts_with(&error_prefix).then_some(Style::default().fg(theme.error));
+                    let star = star.clone();
+                    msg.lines().enumerate().map(move |(n, line)| {
+                        let mut spans = match (error, code.and_then(|lines| lines.get(n)?.as_ref())) {
+                            (Some(style), _) => Spans::from(Span::styled(line.to_string(), style)),
+                            (None, Some(spans)) => spans.clone(),
+                            (None, None) => Spans::from(line.to_string()),
+                        };
+                        if starred && n == 0 {
+                            spans.0.insert(0, star.clone());
+                        }
+                        if truncated_at == Some(n) {
+                            spans.0.extend(truncation_spans(marker));
+                        }
+                        spans
+                    })
+                })
+                .collect();
+            // The "Thinking..." line spins and counts the seconds, turning the warning color
+            // once the request nears its timeout
+            if let (Some((indicator, near_timeout)), Some(last)) = (app.waiting_indicator(i), lines.last_mut()) {
+                let style = if near_timeout { Style::default().fg(theme.warning) } else { Style::default() };
+                *last = Spans::from(vec![Span::raw(format!("{}: ", provider.name)), Span::styled(indicator, style)]);
+            }
             
-            // Log the response
-            let is_error = response.starts_with("Error:");
-            self.logger.log_provider_response(provider_name, &response, is_error);
+            // The state note and footer stay put while the history scrolls between them
+            let mut above = Vec::new();
+            if let Some(note) = provider.state_note(i) {
+                above.push(Spans::from(note));
+                above.push(Spans::default());
+            }
+            let mut below = Vec::new();
+            // Scroll indicators take a row each, so they are only drawn when asked for
+            if theme.scroll_indicators {
+                let more_above = scroll_pos > 0;
+                let more_below = scroll_pos + (text_area.height as usize) < total_rows;
+                above.push(Spans::from(if more_above { "⬆️ (scroll up for more)" } else { "" }));
+                below.push(Spans::from(if more_below { "⬇️ (scroll down for more)" } else { "" }));
+            }
+            if let Some(footer) = provider.footer() {
+                below.push(Spans::default());
+                below.extend(footer.lines().map(|line| Spans::from(Span::styled(line.to_string(), Style::default().add_modifier(Modifier::DIM)))));
+            }
             
-            // Replace "Thinking..." with actual response
-            if let Some(last) = provider.chat_history.last_mut() {
-                *last = format!("{}: {}", provider_name, response);
+            f.render_widget(Paragraph::new(above).style(style), Rect { height: text_area.y - inner.y, ..inner });
+            let history = Paragraph::new(lines)
+                .wrap(Wrap { trim: true })
+                .scroll((scroll_pos.min(u16::MAX as usize) as u16, 0))
+                .style(style);
+            f.render_widget(history, text_area);
+            f.render_widget(
+                Paragraph::new(below).style(style),
+                Rect { y: text_area.bottom(), height: inner.bottom() - text_area.bottom(), ..inner },
+            );
+            if !theme.scroll_indicators {
+                if let Some(thumb) = scrollbar_thumb(inner.height, total_rows, text_area.height as usize, scroll_pos) {
+                    render_scrollbar(f, layout.columns[i], thumb, theme.scrollbar);
+                }
             }
+        } else {
+            let missing_key = Paragraph::new("🔒 API key missing\n\nSet the appropriate environment variable to enable this provider:\n\n• CHATGPT_API_KEY for ChatGPT\n• GEMINI_API_KEY for Gemini\n• CLAUDE_API_KEY for Claude\n\nThen press Alt+1, Alt+2 or Alt+3 to turn the column on.")
+                .wrap(Wrap { trim: true })
+                .style(style);
+            f.render_widget(missing_key, inner);
         }
-        
-        // Note: Delta generation will be triggered from main loop after all responses are received
     }
     
-    pub fn handle_stream_chunk(&mut self, provider_idx: usize, chunk: String, is_final: bool) {
-        if let Some(provider) = self.providers.get_mut(provider_idx) {
-            let provider_name = provider.name;
-            
-            // Update the last message with streaming content
-            if let Some(last) = provider.chat_history.last_mut() {
-                if last.contains("Thinking...") {
-                    // First chunk - replace "Thinking..." with the actual response
-                    *last = format!("{}: {}", provider_name, chunk);
-                } else if !is_final {
-                    // Append chunk to existing response
-                    last.push_str(&chunk);
+    // Render delta field, unless collapsed to give the columns its rows
+    if app.show_delta {
+        let delta_field_selected = app.selected_column == app.providers.len();
+        let delta_label = match (&app.pinned_note, app.delta_strategy, &app.delta_diff) {
+            (Some(note), _, _) => format!("🔍 Differences | 📌 {}", truncate_note(note, MAX_PINNED_NOTE_CHARS)),
+            (None, DeltaStrategy::CharDiff, Some(diff)) => format!("🔍 Character Diff: {} vs {}", diff.first, diff.second),
+            (None, DeltaStrategy::CharDiff, None) => "🔍 Character Diff".to_string(),
+            (None, DeltaStrategy::Summary, _) => match &app.delta_author {
+                Some(author) => format!("🔍 Response Differences (by {})", author),
+                None => format!("🔍 Response Differences (judge: {})", app.delta_judge),
+            },
+            (None, DeltaStrategy::Disabled, _) => "🔍 Response Differences".to_string(),
+        };
+        let delta_title = if delta_field_selected {
+            format!("► {} ◄", delta_label)
+        } else {
+            delta_label
+        };
+    
+        let delta_block = Block::default()
+            .title(Span::styled(
+                delta_title,
+                Style::default().fg(if delta_field_selected { theme.selected_title } else { theme.delta_title }),
+            ))
+            .borders(Borders::ALL)
+            .border_style(if delta_field_selected {
+                Style::default().fg(theme.selected_border)
+            } else {
+                Style::default().fg(theme.delta_border)
+            });
+    
+        let delta_inner = delta_block.inner(layout.delta);
+        f.render_widget(delta_block, layout.delta);
+        let delta_section = app.providers.len();
+        let text_area = app.viewports[delta_section];
+        let scroll_pos = app.scroll_offset(delta_section);
+        let delta_para = match (app.delta_strategy, &app.delta_diff) {
+            (DeltaStrategy::CharDiff, Some(diff)) => {
+                Paragraph::new(diff.to_text(theme.diff_first_only, theme.diff_second_only))
+                    .scroll((scroll_pos.min(u16::MAX as usize) as u16, 0))
+            }
+            (DeltaStrategy::CharDiff, None) => {
+                Paragraph::new("Character diff needs responses from at least two providers")
+            }
+            (DeltaStrategy::Summary, _) => {
+                Paragraph::new(app.delta_text.as_str()).scroll((scroll_pos.min(u16::MAX as usize) as u16, 0))
+            }
+            (DeltaStrategy::Disabled, _) => Paragraph::new(DELTA_DISABLED_MESSAGE),
+        };
+        let delta_para = delta_para
+            .wrap(Wrap { trim: true })
+            .style(Style::default().fg(theme.delta_text));
+        f.render_widget(delta_para, text_area);
+        if let Some(footer) = app.delta_footer.as_ref().filter(|_| app.delta_strategy == DeltaStrategy::Summary) {
+            if delta_inner.height > 0 {
+                let footer_row = Rect { y: delta_inner.bottom() - 1, height: 1, ..delta_inner };
+                let style = Style::default().fg(theme.delta_text).add_modifier(Modifier::DIM);
+                f.render_widget(Paragraph::new(Span::styled(footer.as_str(), style)), footer_row);
+            }
+        }
+        if app.delta_strategy != DeltaStrategy::Disabled {
+            let total_rows = app.content_rows(delta_section);
+            // Scroll indicators for the delta field when selected, in the rows kept free for them
+            if delta_field_selected && theme.scroll_indicators {
+                if scroll_pos > 0 {
+                    f.render_widget(Paragraph::new("⬆️ (scroll up)"), Rect { height: 1, ..delta_inner });
                 }
-                
-                // If this is the final chunk, log the complete response
-                if is_final {
-                    let full_response = last.strip_prefix(&format!("{}: ", provider_name))
-                        .unwrap_or(last)
-                        .to_string();
-                    self.logger.log_provider_response(provider_name, &full_response, false);
+                if scroll_pos + (text_area.height as usize) < total_rows && text_area.bottom() < delta_inner.bottom() {
+                    f.render_widget(Paragraph::new("⬇️ (scroll down)"), Rect { y: text_area.bottom(), height: 1, ..delta_inner });
+                }
+            } else if !theme.scroll_indicators {
+                if let Some(thumb) = scrollbar_thumb(delta_inner.height, total_rows, text_area.height as usize, scroll_pos) {
+                    render_scrollbar(f, layout.delta, thumb, theme.scrollbar);
                 }
             }
         }
     }
     
+    // Metrics panel beside the delta field, or in its place while it is collapsed
+    if app.metrics.is_enabled() {
+        app.metrics.render(f, layout.metrics, theme);
+    }
     
-    pub fn generate_delta_with_channel(&mut self, tx: mpsc::UnboundedSender<ResponseType>) {
-        // Check if all enabled providers have recent responses (not "Thinking...")
-        let all_responded = self.providers
-            .iter()
-            .filter(|p| p.state == ProviderState::Enabled)
-            .all(|p| {
-                p.chat_history.last()
-                    .map(|msg| !msg.contains("Thinking..."))
-                    .unwrap_or(false)
-            });
-            
-        if !all_responded {
-            return;
-        }
-        
-        self.generate_delta_internal(tx);
+    // Render shared input box
+    let arrows = if app.input_focused { "↑↓: history, Tab: scroll columns" } else { "↑↓: scroll, Tab: history" };
+    let keys = &app.keys.table;
+    let quit = match app.keys.keymap {
+        Keymap::Default => format!("{}: quit", keys.label(Action::Quit)),
+        Keymap::Vim => "i: insert, Esc: normal, :q: quit".to_string(),
+    };
+    let title = format!(
+        "Shared Input ({}: send, {}: new line, {}, {}: all keys, {})",
+        keys.label(Action::Send),
+        keys.label(Action::NewLine),
+        arrows,
+        keys.label(Action::Help),
+        quit
+    );
+    let input_block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(if app.input_focused { theme.selected_border } else { theme.input_border }));
+    
+    app.update_input_scroll(input_width);
+    let input_para = Paragraph::new(app.visible_input(input_width))
+        .block(input_block)
+        .style(Style::default().fg(theme.input_text));
+    f.render_widget(input_para, layout.input);
+    
+    // Render status bar, with any alerts first in the theme's error color so they are never cut off
+    let mut status_spans: Vec<Span> = app.status_alerts()
+        .into_iter()
+        .map(|alert| Span::styled(format!("{} | ", alert), Style::default().fg(theme.error)))
+        .collect();
+    status_spans.push(Span::styled(app.status_line(), Style::default().fg(theme.status_bar)));
+    let status_para = Paragraph::new(Spans::from(status_spans));
+    f.render_widget(status_para, layout.status);
+    
+    if let Some((done, total)) = batch_progress {
+        let progress_para = Paragraph::new(format!("Responses {}", progress_bar(done, total)))
+            .style(Style::default().fg(theme.status_bar));
+        f.render_widget(progress_para, layout.progress);
     }
     
-    fn generate_delta_internal(&mut self, tx: mpsc::UnboundedSender<ResponseType>) {
-        // Get the latest responses from all enabled providers
-        let responses: Vec<(String, String)> = self.providers
+    // Model picker over its column
+    if let Some(picker) = &app.model_picker {
+        let column = layout.columns[picker.column];
+        let area = Rect {
+            x: column.x + 1,
+            y: column.y + 1,
+            width: column.width.saturating_sub(2),
+            height: (picker.models.len() as u16 + 2).min(column.height.saturating_sub(2)),
+        };
+        let lines: Vec<Spans> = picker.models
             .iter()
-            .filter(|p| p.state == ProviderState::Enabled)
-            .filter_map(|p| {
-                p.chat_history.last().and_then(|msg| {
-                    if let Some(colon_pos) = msg.find(": ") {
-                        let response = &msg[colon_pos + 2..];
-                        Some((p.name.to_string(), response.to_string()))
-                    } else {
-                        None
-                    }
-                })
+            .enumerate()
+            .map(|(i, model)| {
+                if i == picker.selected {
+                    Spans::from(Span::styled(format!("► {}", model), Style::default().fg(theme.selected_title)))
+                } else {
+                    Spans::from(format!("  {}", model))
+                }
             })
             .collect();
-            
-        if responses.len() >= 2 {
-            // Create a Gemini client for delta analysis
-            // Use the new ClientConfigBuilder from v0.4.0
-            let config = ClientConfigBuilder::default()
-                .timeout(Duration::from_secs(30))
-                .retries(3)
-                .build();
-            if let Some(gemini_client) = Self::create_provider_client("Gemini", &config) {
-                let responses_clone = responses.clone();
-                
-                // Create async task for delta generation
-                tokio::spawn(async move {
-                    let prompt = Self::create_delta_prompt(&responses_clone);
-                    match gemini_client.send_prompt(&prompt).await {
-                        Ok(delta) => {
-                            if tx.send(ResponseType::Delta(delta)).is_err() {
-                                eprintln!("Failed to send delta response");
-                            }
-                        }
-                        Err(e) => {
-                            let error_msg = format!("Error generating differences: {}", e);
-                            if tx.send(ResponseType::Delta(error_msg)).is_err() {
-                                eprintln!("Failed to send delta error");
-                            }
-                        }
-                    }
-                });
-            }
-            
-            self.show_delta = true;
-            self.delta_text = "Generating differences summary...".to_string();
-        }
+        let picker_para = Paragraph::new(lines).block(
+            Block::default()
+                .title("Model (↑↓, Enter: switch, Esc: cancel)")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.selected_border)),
+        );
+        f.render_widget(Clear, area);
+        f.render_widget(picker_para, area);
     }
     
-    fn create_delta_prompt(responses: &[(String, String)]) -> String {
-        let mut prompt = String::from("Please analyze the following AI responses to the same question and summarize the key differences between them. Focus on factual differences, different approaches, or varying perspectives. Be concise but thorough:\n\n");
-        
-        for (provider, response) in responses {
-            prompt.push_str(&format!("**{}:**\n{}\n\n", provider, response));
-        }
-        
-        prompt.push_str("**Summary of key differences:**");
-        prompt
+    // Latest requests, newest first
+    if app.show_request_log {
+        let rows: Vec<Row> = app.request_log
+            .entries()
+            .iter()
+            .rev()
+            .map(|entry| {
+                Row::new(vec![
+                    entry.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S").to_string(),
+                    entry.provider.clone(),
+                    entry.status.label().to_string(),
+                    entry.latency_ms.map(|ms| format!("{} ms", ms)).unwrap_or_default(),
+                    entry.prompt.replace('\n', " "),
+                ])
+            })
+            .collect();
+        let area = centered_rect(size, size.width.saturating_sub(4), rows.len() as u16 + 3);
+        let table = Table::new(rows)
+            .header(Row::new(vec!["Time", "Provider", "Status", "Latency", "Prompt"]).style(Style::default().fg(theme.selected_title)))
+            .block(
+                Block::default()
+                    .title(format!("Requests: last {} (any key to close)", REQUEST_LOG_SIZE))
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.selected_border)),
+            )
+            .widths(&[
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Length(12),
+                Constraint::Length(9),
+                Constraint::Min(10),
+            ]);
+        f.render_widget(Clear, area);
+        f.render_widget(table, area);
     }
     
-    pub fn handle_delta_response(&mut self, delta: String) {
-        // Log the delta analysis
-        self.logger.log_delta_analysis(&delta);
-        
-        self.delta_text = delta;
+    // Help over everything, centered
+    if app.show_help {
+        let help = app.keys.help_text();
+        let width = help.lines().map(display_width).max().unwrap_or(0) as u16 + 4;
+        let area = centered_rect(size, width, help.lines().count() as u16 + 2);
+        let help_para = Paragraph::new(help).block(
+            Block::default()
+                .title("Keys (any key to close)")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.selected_border)),
+        );
+        f.render_widget(Clear, area);
+        f.render_widget(help_para, area);
     }
     
-    pub fn select_previous_column(&mut self) {
-        let total_sections = self.providers.len() + 1; // +1 for delta field
-        if self.selected_column == 0 {
-            self.selected_column = total_sections - 1; // Wrap to last section (delta field)
-        } else {
-            self.selected_column -= 1;
+    // Set cursor position in input field
+    let (cursor_x, cursor_y) = app.visible_input_cursor(input_width);
+    f.set_cursor(
+        layout.input.x + cursor_x + 1, // +1 for border
+        layout.input.y + cursor_y + 1
+    );
+}
+
+/// How the TUI starts, from the command line and the files in `~/.chatdelta`
+pub struct TuiOptions {
+    pub theme: Theme,
+    /// Start with delta analysis turned off
+    pub no_delta: bool,
+    /// Capture the mouse to select columns and scroll
+    pub mouse: bool,
+    pub keymap: Keymap,
+    /// Keybindings with the user's overrides
+    pub keys: KeyTable,
+    pub judge: Option<DeltaJudge>,
+    pub webhook: Option<WebhookNotifier>,
+    /// Sent with the first prompt, from --image
+    pub images: Vec<ImageAttachment>,
+}
+
+/// The terminal modes the TUI turns on, turned off again when dropped, however `run_tui` ends
+struct TerminalGuard {
+    mouse: bool,
+    keyboard_enhanced: bool,
+}
+
+impl TerminalGuard {
+    fn enter(mouse: bool) -> io::Result<Self> {
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        // A pasted newline is typed rather than sending the prompt so far
+        execute!(stdout, ClearTerminal(ClearType::All), cursor::Hide, EnableBracketedPaste)?;
+        // Capturing the mouse takes over the terminal's own text selection, so it can be turned off
+        if mouse {
+            execute!(stdout, EnableMouseCapture)?;
         }
-    }
-    
-    pub fn select_next_column(&mut self) {
-        let total_sections = self.providers.len() + 1; // +1 for delta field
-        self.selected_column = (self.selected_column + 1) % total_sections;
-    }
-    
-    pub fn scroll_up(&mut self) {
-        if let Some(scroll_pos) = self.scroll_positions.get_mut(self.selected_column) {
-            if *scroll_pos > 0 {
-                *scroll_pos -= 1;
-            }
+        // Lets terminals that speak the kitty keyboard protocol report Ctrl+Enter and Shift+Enter
+        let keyboard_enhanced = supports_keyboard_enhancement().unwrap_or(false);
+        if keyboard_enhanced {
+            execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES))?;
         }
+        Ok(Self { mouse, keyboard_enhanced })
     }
-    
-    pub fn scroll_down(&mut self) {
-        if let Some(scroll_pos) = self.scroll_positions.get_mut(self.selected_column) {
-            let max_scroll = if self.selected_column < self.providers.len() {
-                // Provider column
-                if let Some(provider) = self.providers.get(self.selected_column) {
-                    let total_lines: usize = provider.chat_history
-                        .iter()
-                        .flat_map(|msg| msg.lines())
-                        .count();
-                    total_lines.saturating_sub(25) // Max visible lines is 25
-                } else {
-                    0
-                }
-            } else {
-                // Delta field
-                let total_lines = self.delta_text.lines().count();
-                total_lines.saturating_sub(4) // Visible lines in delta field
-            };
-            
-            if *scroll_pos < max_scroll {
-                *scroll_pos += 1;
-            }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.keyboard_enhanced {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        if self.mouse {
+            let _ = execute!(stdout, DisableMouseCapture);
         }
+        let _ = execute!(stdout, DisableBracketedPaste, cursor::Show);
+        let _ = disable_raw_mode();
     }
 }
 
-pub async fn run_tui(provider_states: HashMap<&'static str, ProviderState>) -> io::Result<()> {
-    enable_raw_mode()?;
+pub async fn run_tui(provider_states: HashMap<&'static str, ProviderState>, client_config: ClientConfig, options: TuiOptions) -> io::Result<()> {
+    let TuiOptions { theme, no_delta, mouse, keymap, keys, judge, webhook, images } = options;
+    let guard = TerminalGuard::enter(mouse)?;
     let mut stdout = io::stdout();
-    execute!(stdout, Clear(ClearType::All), cursor::Hide)?;
     let backend = CrosstermBackend::new(&mut stdout);
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let mut app = AppState::new(provider_states);
+    let mut app = AppState::with_client_config(provider_states, client_config);
+    app.code_highlighter = CodeHighlighter::new(&theme.code_theme);
+    app.theme = theme;
+    // Printing them would draw over the TUI, so they wait for the status bar
+    let mut warnings = std::mem::take(&mut app.config_warnings);
+    if !keys.warnings().is_empty() {
+        warnings.push(format!("{} problems with key bindings in {} (F1 lists them)", keys.warnings().len(), CONFIG_FILE));
+    }
+    if !warnings.is_empty() {
+        app.set_notice(format!("⚠️ {}", warnings.join("; ")));
+    }
+    app.keys = KeyResolver::new(keymap, keys);
+    app.keys.keyboard_enhanced = guard.keyboard_enhanced;
+    app.attachments = images;
+    #[cfg(feature = "voice")]
+    {
+        let config = app.provider_configs.default_config().clone();
+        app.voice_input = crate::voice::WhisperHttpSource::from_env(config).map(|source| Arc::new(source) as Arc<dyn VoiceInputSource>);
+    }
+    if no_delta {
+        app.toggle_delta();
+    }
+    if let Some(judge) = judge {
+        app.delta_judge = judge;
+    }
+    app.webhook = webhook;
     
     // Create channel for async responses
-    let (tx, mut rx) = mpsc::unbounded_channel::<ResponseType>();
+    let (tx, mut rx) = mpsc::channel::<ResponseType>(RESPONSE_CHANNEL_CAPACITY);
+    app.start_health_checks(tx.clone());
     
     loop {
-        terminal.draw(|f| {
-            let size = f.size();
-            
-            // Split into main area, delta area, and input area
-            let main_chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(0),           // Main provider columns
-                    Constraint::Length(6),        // Delta field
-                    Constraint::Length(3)         // Input field
-                ])
-                .split(size);
-            
-            // Split main area into 3 columns
-            let provider_chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([
-                    Constraint::Percentage(33),
-                    Constraint::Percentage(34),
-                    Constraint::Percentage(33),
-                ])
-                .split(main_chunks[0]);
-
-            // Render provider columns
-            for (i, provider) in app.providers.iter().enumerate() {
-                let is_selected = i == app.selected_column;
-                let title = if is_selected {
-                    format!("► {} ◄", provider.name)
-                } else {
-                    provider.name.to_string()
-                };
-                
-                let block = Block::default()
-                    .title(Span::styled(
-                        title,
-                        Style::default().fg(if provider.state == ProviderState::Enabled {
-                            if is_selected { Color::Yellow } else { Color::Cyan }
-                        } else {
-                            Color::DarkGray
-                        }),
-                    ))
-                    .borders(Borders::ALL)
-                    .border_style(if is_selected {
-                        Style::default().fg(Color::Yellow)
-                    } else {
-                        Style::default()
-                    });
-
-                let chat = if provider.state == ProviderState::Enabled {
-                    let scroll_pos = app.scroll_positions.get(i).copied().unwrap_or(0);
-                    let all_lines: Vec<&str> = provider.chat_history
-                        .iter()
-                        .flat_map(|msg| msg.lines())
-                        .collect();
-                    
-                    // Apply scrolling and limit visible lines
-                    let visible_lines: Vec<&str> = all_lines
-                        .iter()
-                        .skip(scroll_pos)
-                        .take(25) // Show max 25 lines at once
-                        .copied()
-                        .collect();
-                    
-                    let mut content = visible_lines.join("\n");
-                    
-                    // Add scroll indicators
-                    if scroll_pos > 0 {
-                        content = format!("⬆️ (scroll up for more)\n{}", content);
-                    }
-                    if scroll_pos + visible_lines.len() < all_lines.len() {
-                        content = format!("{}\n⬇️ (scroll down for more)", content);
-                    }
-                    
-                    content
-                } else {
-                    "🔒 API key missing\n\nSet the appropriate environment variable to enable this provider:\n\n• CHATGPT_API_KEY for ChatGPT\n• GEMINI_API_KEY for Gemini\n• CLAUDE_API_KEY for Claude".to_string()
-                };
-                
-                let para = Paragraph::new(chat)
-                    .block(block)
-                    .wrap(Wrap { trim: true })
-                    .style(if provider.state == ProviderState::Enabled {
-                        Style::default()
-                    } else {
-                        Style::default().fg(Color::DarkGray)
-                    });
-                f.render_widget(para, provider_chunks[i]);
-            }
-            
-            // Render delta field
-            let delta_field_selected = app.selected_column == app.providers.len();
-            let delta_title = if delta_field_selected {
-                "► 🔍 Response Differences (powered by Gemini) ◄"
-            } else {
-                "🔍 Response Differences (powered by Gemini)"
-            };
-            
-            let delta_block = Block::default()
-                .title(Span::styled(
-                    delta_title,
-                    Style::default().fg(if delta_field_selected { Color::Yellow } else { Color::Magenta }),
-                ))
-                .borders(Borders::ALL)
-                .border_style(if delta_field_selected {
-                    Style::default().fg(Color::Yellow)
-                } else {
-                    Style::default().fg(Color::Magenta)
-                });
-            
-            // Handle scrolling for delta field
-            let delta_content = {
-                let scroll_pos = app.scroll_positions.get(app.providers.len()).copied().unwrap_or(0);
-                let all_lines: Vec<&str> = app.delta_text.lines().collect();
-                
-                let visible_lines: Vec<&str> = all_lines
-                    .iter()
-                    .skip(scroll_pos)
-                    .take(4) // Show max 4 lines in delta field
-                    .copied()
-                    .collect();
-                
-                let mut content = visible_lines.join("\n");
-                
-                // Add scroll indicators for delta field when selected
-                if delta_field_selected {
-                    if scroll_pos > 0 {
-                        content = format!("⬆️ (scroll up)\n{}", content);
-                    }
-                    if scroll_pos + visible_lines.len() < all_lines.len() {
-                        content = format!("{}\n⬇️ (scroll down)", content);
-                    }
-                }
-                
-                content
-            };
-            
-            let delta_para = Paragraph::new(delta_content)
-                .block(delta_block)
-                .wrap(Wrap { trim: true })
-                .style(Style::default().fg(Color::White));
-            f.render_widget(delta_para, main_chunks[1]);
-            
-            // Render shared input box
-            let streaming_status = if app.use_streaming { " [STREAMING ON]" } else { " [STREAMING OFF]" };
-            let title = format!("Shared Input (Enter: send, ←→: cycle, ↑↓: scroll, F2: toggle streaming, Esc: quit){}", streaming_status);
-            let input_block = Block::default()
-                .title(title)
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Yellow));
-            
-            let input_para = Paragraph::new(format!("> {}", app.shared_input))
-                .block(input_block)
-                .style(Style::default().fg(Color::White));
-            f.render_widget(input_para, main_chunks[2]);
-            
-            // Set cursor position in input field
-            f.set_cursor(
-                main_chunks[2].x + app.shared_input.len() as u16 + 3, // +3 for "> " prefix and border
-                main_chunks[2].y + 1 // +1 for border
-            );
-        })?;
+        app.advance_spinners();
+        terminal.draw(|f| draw(f, &mut app))?;
 
         // Check for async responses
         let mut responses_received = 0;
-        while let Ok(response_type) = rx.try_recv() {
+        for response_type in drain_responses(&mut rx, MAX_RESPONSES_PER_FRAME) {
             match response_type {
                 ResponseType::Provider(provider_idx, response) => {
                     app.handle_response(provider_idx, response);
@@ -555,60 +3295,164 @@ pub async fn run_tui(provider_states: HashMap<&'static str, ProviderState>) -> i
                         responses_received += 1;
                     }
                 }
+                ResponseType::Metadata(provider_idx, metadata) => {
+                    app.handle_metadata(provider_idx, metadata);
+                }
+                ResponseType::Variants(provider_idx, variants) => {
+                    app.handle_variants(provider_idx, variants);
+                    responses_received += 1;
+                }
+                ResponseType::Health(provider_idx, status) => {
+                    app.handle_health(provider_idx, status);
+                }
+                ResponseType::Transcription(transcription) => app.handle_transcription(transcription),
             }
         }
         
+        app.flush_stream_buffers();
+        
         // Check if we should generate delta after receiving responses
         if responses_received > 0 {
             app.generate_delta_with_channel(tx.clone());
         }
         
+        // Reap finished tasks so the set only holds in-flight requests
+        while app.tasks.try_join_next().is_some() {}
+        
         if event::poll(std::time::Duration::from_millis(100))? {
             match event::read()? {
-                Event::Key(key) => match key.code {
-                    KeyCode::Esc => {
-                        disable_raw_mode()?;
-                        execute!(terminal.backend_mut(), cursor::Show)?;
-                        terminal.show_cursor()?;
+                // The model picker takes every key while it is open
+                Event::Key(key) if app.model_picker.is_some() => match key.code {
+                    KeyCode::Up => app.move_model_picker(-1),
+                    KeyCode::Down => app.move_model_picker(1),
+                    KeyCode::Enter => app.confirm_model_picker(),
+                    KeyCode::Esc => app.model_picker = None,
+                    _ => {}
+                },
+                // The help and request log overlays close on any key
+                Event::Key(_) if app.show_help => app.show_help = false,
+                Event::Key(_) if app.show_request_log => app.show_request_log = false,
+                Event::Key(key) => match app.keys.resolve(&key) {
+                    Some(Action::Quit) => {
+                        // Stop in-flight requests before the terminal is restored
+                        app.shutdown(Duration::from_millis(500)).await;
                         break;
                     }
-                    KeyCode::Left => {
-                        app.select_previous_column();
-                    }
-                    KeyCode::Right => {
-                        app.select_next_column();
-                    }
-                    KeyCode::Up => {
-                        app.scroll_up();
-                    }
-                    KeyCode::Down => {
-                        app.scroll_down();
+                    // `?` is typed like any other character once the input has text
+                    Some(Action::Help) if key.code == KeyCode::Char('?') && !app.shared_input.is_empty() => {
+                        app.insert_char('?');
                     }
-                    KeyCode::Char(c) => {
-                        app.shared_input.push(c);
-                    }
-                    KeyCode::Backspace => {
-                        app.shared_input.pop();
+                    Some(Action::Help) => app.show_help = true,
+                    Some(Action::RequestLog) => app.show_request_log = true,
+                    Some(Action::WordLeft) => app.move_cursor_word_left(),
+                    Some(Action::WordRight) => app.move_cursor_word_right(),
+                    // With text in the input, the arrows move the cursor instead of switching columns
+                    Some(Action::Left) if !app.shared_input.is_empty() => app.move_cursor_left(),
+                    Some(Action::Right) if !app.shared_input.is_empty() => app.move_cursor_right(),
+                    Some(Action::Left | Action::PreviousColumn) => app.select_previous_column(),
+                    Some(Action::Right | Action::NextColumn) => app.select_next_column(),
+                    Some(Action::ToggleInputFocus) => app.toggle_input_focus(),
+                    Some(Action::Up) if app.input_focused => app.history_previous(),
+                    Some(Action::Down) if app.input_focused => app.history_next(),
+                    Some(Action::Up) => app.scroll_up(1),
+                    Some(Action::Down) => app.scroll_down(1),
+                    Some(Action::PageUp) => app.page_up(),
+                    Some(Action::PageDown) => app.page_down(),
+                    Some(Action::Cancel) => app.cancel_pending(),
+                    Some(Action::Regenerate) => app.regenerate_selected(tx.clone()),
+                    Some(Action::Fork) => match app.fork_session() {
+                        Ok(fork) => app.set_notice(format!("🌿 Forked into session {}", &fork.to_string()[..8])),
+                        Err(e) => app.set_notice(format!("⚠️ Could not fork the session: {}", e)),
+                    },
+                    Some(Action::Save) => app.save_snapshot(),
+                    Some(Action::Screenshot) => app.save_screenshot(),
+                    Some(Action::Voice) => app.toggle_voice_input(tx.clone()),
+                    Some(Action::Undo) => app.undo(),
+                    Some(Action::Redo) => app.redo(),
+                    Some(Action::RegenerateDelta) => app.regenerate_delta(tx.clone()),
+                    Some(Action::CycleDeltaStrategy) => app.cycle_delta_strategy(),
+                    Some(Action::Copy) => app.copy_selection(),
+                    Some(Action::ToggleProvider) => {
+                        if let KeyCode::Char(c @ '1'..='9') = key.code {
+                            app.toggle_provider(c as usize - '1' as usize);
+                        }
                     }
-                    KeyCode::F(2) => {
-                        // Toggle streaming mode
-                        app.use_streaming = !app.use_streaming;
+                    Some(Action::Retry) => app.retry_selected(tx.clone()),
+                    Some(Action::CycleDeltaJudge) => app.cycle_delta_judge(),
+                    Some(Action::PickModel) => app.open_model_picker(),
+                    Some(Action::ShowThinking) => app.show_thinking(),
+                    Some(Action::NextVariant) if app.cycle_variant() => app.generate_delta_with_channel(tx.clone()),
+                    Some(Action::Backspace) => app.delete_char_before_cursor(),
+                    Some(Action::Delete) => app.delete_char_at_cursor(),
+                    // Like the arrows, Home and End edit the input once it has text
+                    Some(Action::Home) if !app.shared_input.is_empty() => app.move_cursor_home(),
+                    Some(Action::End) if !app.shared_input.is_empty() => app.move_cursor_end(),
+                    Some(Action::Home | Action::ScrollTop) => app.scroll_to_top(),
+                    Some(Action::End | Action::ScrollBottom) => app.scroll_to_bottom(),
+                    // Insert mode types into the input, so ↑ / ↓ browse the history there
+                    Some(Action::InsertMode) => app.input_focused = true,
+                    Some(Action::NormalMode) => app.input_focused = false,
+                    Some(Action::ToggleStreaming) => app.use_streaming = !app.use_streaming,
+                    Some(Action::CycleTheme) => app.cycle_theme(),
+                    Some(Action::ToggleStar) => app.toggle_star(),
+                    Some(Action::ClearConversation) => app.clear_conversation(),
+                    Some(Action::ToggleDelta) if app.metrics.is_enabled() => app.metrics.toggle_detailed(),
+                    Some(Action::ToggleDelta) => app.toggle_delta(),
+                    Some(Action::ToggleDeltaPanel) => app.toggle_delta_panel(),
+                    Some(Action::Metrics) => app.metrics.toggle_enabled(),
+                    Some(Action::GrowDelta) => app.grow_delta(),
+                    Some(Action::ShrinkDelta) => app.shrink_delta(),
+                    // Most terminals only report Shift+Enter with the kitty keyboard protocol;
+                    // Alt+Enter works everywhere
+                    Some(Action::NewLine) => app.insert_newline(),
+                    // Only reported by terminals with the kitty keyboard protocol; `/only` works in any
+                    Some(Action::SendToSelected) => {
+                        let msg = app.shared_input.trim().to_string();
+                        if !msg.is_empty() {
+                            let selected = app.selected_column;
+                            app.send_to_active_providers(&msg, Some(selected), tx.clone());
+                            app.clear_input();
+                        }
                     }
-                    KeyCode::Enter => {
+                    Some(Action::Send) => {
                         let msg = app.shared_input.trim().to_string();
                         if !msg.is_empty() {
-                            app.send_to_active_providers(&msg, tx.clone());
-                            app.shared_input.clear();
+                            // Cleared first, as `/preset` may fill the input again
+                            app.clear_input();
+                            match Command::parse(&msg) {
+                                Some(Ok(command)) => {
+                                    if let Some((prompt, only)) = app.handle_command(command) {
+                                        app.send_to_active_providers(&prompt, only, tx.clone());
+                                    }
+                                }
+                                Some(Err(e)) => app.delta_text = format!("⚠️ {}", e),
+                                None => app.send_to_active_providers(&msg, None, tx.clone()),
+                            }
                         }
                     }
-                    _ => {}
+                    // Vim's normal mode types nothing
+                    None if app.keys.accepts_text() => {
+                        if let KeyCode::Char(c) = key.code {
+                            app.insert_char(c);
+                        }
+                    }
+                    // A column without variants has nothing to cycle
+                    Some(Action::NextVariant) | None => {}
                 },
+                // Terminals send "\r" for line breaks in a paste
+                Event::Paste(text) if app.model_picker.is_none() => app.insert_text(&text.replace("\r\n", "\n").replace('\r', "\n")),
+                Event::Mouse(mouse) => app.handle_mouse(mouse),
                 _ => {}
             }
         }
     }
+    drop(terminal);
+    drop(guard);
     
     // Save conversation logs before exiting
+    if let Err(e) = app.history.save() {
+        eprintln!("\n⚠️  Failed to save input history: {}", e);
+    }
     app.logger.finalize_conversation();
     match app.logger.save() {
         Ok(path) => {
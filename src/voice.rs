@@ -0,0 +1,148 @@
+//! Voice prompts: F5 records from the microphone and types the transcription into the input
+//!
+//! Only built with the `voice` cargo feature, which pulls in `cpal` for audio capture and
+//! `hound` to write the recording as WAV.
+
+use chatdelta::{ClientConfig, TranscriptionClient, OPENAI_TRANSCRIPTION_ENDPOINT};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A recording stops by itself after this long, well below Whisper's 25 MB upload limit
+pub const MAX_RECORDING: Duration = Duration::from_secs(60);
+
+/// Turns speech into prompt text.
+///
+/// # Setup
+///
+/// Build with `cargo build --features voice`. On Linux, `cpal` needs the ALSA headers
+/// (`libasound2-dev` on Debian and Ubuntu, `alsa-lib-devel` on Fedora); macOS and Windows need
+/// nothing extra. The default input device is used, so pick the microphone in the system's
+/// sound settings.
+///
+/// `WhisperHttpSource` sends recordings to OpenAI with `CHATGPT_API_KEY`. To use another
+/// Whisper-compatible server, e.g. a local whisper.cpp, set `CHATDELTA_WHISPER_ENDPOINT` to its
+/// `/v1/audio/transcriptions` URL; the key may then be left unset if the server doesn't check it.
+///
+/// In the TUI, F5 starts recording and F5 again stops it; the transcription is inserted at the
+/// cursor so it can be edited before sending.
+pub trait VoiceInputSource: Send + Sync {
+    /// Record until `stop` is called or `MAX_RECORDING` has passed, then transcribe. Blocks
+    /// for the whole recording, so run it on a blocking thread of the tokio runtime.
+    fn record_and_transcribe(&self) -> Result<String, Box<dyn Error + Send + Sync>>;
+
+    /// End the recording in progress, if any
+    fn stop(&self);
+}
+
+/// Records the default microphone and transcribes it with a Whisper-compatible HTTP endpoint
+pub struct WhisperHttpSource {
+    pub endpoint: String,
+    api_key: String,
+    /// Settings for the upload, such as the proxy
+    config: ClientConfig,
+    recording: AtomicBool,
+}
+
+impl WhisperHttpSource {
+    pub fn new(endpoint: impl Into<String>, api_key: impl Into<String>, config: ClientConfig) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            api_key: api_key.into(),
+            config,
+            recording: AtomicBool::new(false),
+        }
+    }
+
+    /// Configured from `CHATDELTA_WHISPER_ENDPOINT` and `CHATGPT_API_KEY`; `None` when neither
+    /// a key nor another endpoint is set
+    pub fn from_env(config: ClientConfig) -> Option<Self> {
+        let endpoint = std::env::var("CHATDELTA_WHISPER_ENDPOINT").ok();
+        let api_key = std::env::var("CHATGPT_API_KEY").ok();
+        if endpoint.is_none() && api_key.is_none() {
+            return None;
+        }
+        Some(Self::new(
+            endpoint.unwrap_or_else(|| OPENAI_TRANSCRIPTION_ENDPOINT.to_string()),
+            api_key.unwrap_or_default(),
+            config,
+        ))
+    }
+
+    /// 16-bit samples from the default input device, with its channel count and sample rate
+    fn record(&self) -> Result<(Vec<i16>, u16, u32), Box<dyn Error + Send + Sync>> {
+        // Set before opening the device, so a stop that comes while it opens isn't lost
+        self.recording.store(true, Ordering::SeqCst);
+        let device = cpal::default_host().default_input_device().ok_or("no microphone found")?;
+        let supported = device.default_input_config()?;
+        let sample_format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&samples);
+        // Capture errors end up as a short or empty recording, which Whisper reports
+        let on_error = |_: cpal::StreamError| {};
+        let stream = match sample_format {
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &config,
+                move |data: &[i16], _: &_| sink.lock().unwrap().extend_from_slice(data),
+                on_error,
+                None,
+            )?,
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &config,
+                move |data: &[f32], _: &_| {
+                    let converted = data.iter().map(|&sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+                    sink.lock().unwrap().extend(converted);
+                },
+                on_error,
+                None,
+            )?,
+            other => return Err(format!("unsupported microphone sample format {:?}", other).into()),
+        };
+
+        stream.play()?;
+        let started = Instant::now();
+        while self.recording.load(Ordering::SeqCst) && started.elapsed() < MAX_RECORDING {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        self.recording.store(false, Ordering::SeqCst);
+        drop(stream);
+
+        let samples = std::mem::take(&mut *samples.lock().unwrap());
+        Ok((samples, config.channels, config.sample_rate.0))
+    }
+}
+
+impl VoiceInputSource for WhisperHttpSource {
+    fn record_and_transcribe(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let (samples, channels, sample_rate) = self.record()?;
+        if samples.is_empty() {
+            return Err("nothing was recorded".into());
+        }
+
+        let path = std::env::temp_dir().join(format!("chatdelta-voice-{}.wav", uuid::Uuid::new_v4()));
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec)?;
+        for sample in samples {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+
+        let client = TranscriptionClient::new(&self.api_key, self.config.clone())?.with_endpoint(self.endpoint.clone());
+        let text = tokio::runtime::Handle::current().block_on(client.transcribe(&path));
+        let _ = std::fs::remove_file(&path);
+        Ok(text?)
+    }
+
+    fn stop(&self) {
+        self.recording.store(false, Ordering::SeqCst);
+    }
+}
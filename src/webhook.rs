@@ -0,0 +1,103 @@
+//! Webhook posted after each delta analysis, for team setups where the differences should
+//! start a downstream workflow
+//!
+//! Configured with `--webhook <url>` or `CHATDELTA_WEBHOOK_URL`. With `CHATDELTA_WEBHOOK_SECRET`
+//! set, each request carries an `X-Signature: sha256=<hex>` header: the HMAC-SHA256 of the body
+//! keyed with the secret, so the receiver can check where it came from.
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::fmt::Write;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Body of each webhook request
+#[derive(Debug, Serialize)]
+pub struct DeltaSummary<'a> {
+    pub session_id: &'a Uuid,
+    pub prompt: &'a str,
+    pub delta: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    pub url: String,
+    pub secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>, secret: Option<String>) -> Self {
+        Self {
+            url: url.into(),
+            secret,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// The notifier for `CHATDELTA_WEBHOOK_URL`, if it is set
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("CHATDELTA_WEBHOOK_URL").ok().filter(|url| !url.is_empty())?;
+        Some(Self::new(url, secret_from_env()))
+    }
+
+    /// `url`, signed with `CHATDELTA_WEBHOOK_SECRET` if it is set
+    pub fn with_env_secret(url: impl Into<String>) -> Self {
+        Self::new(url, secret_from_env())
+    }
+
+    /// POST the summary in the background, so the TUI never waits on the receiver. Failures
+    /// are dropped: there is nowhere to report them that wouldn't get in the way.
+    pub fn notify(&self, summary: &DeltaSummary) -> JoinHandle<()> {
+        let body = serde_json::to_string(summary).unwrap_or_default();
+        let mut request = self.client
+            .post(&self.url)
+            .header("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header("X-Signature", signature(secret, &body));
+        }
+        let request = request.body(body);
+        tokio::spawn(async move {
+            let _ = request.send().await;
+        })
+    }
+}
+
+fn secret_from_env() -> Option<String> {
+    std::env::var("CHATDELTA_WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty())
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` keyed with `secret`
+pub fn signature(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body.as_bytes());
+    let mut hex = String::from("sha256=");
+    for byte in mac.finalize().into_bytes() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("key", "The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_summary_body() {
+        let session_id = Uuid::nil();
+        let summary = DeltaSummary { session_id: &session_id, prompt: "What is Rust?", delta: "Claude mentions Cargo" };
+        assert_eq!(
+            serde_json::to_string(&summary).unwrap(),
+            r#"{"session_id":"00000000-0000-0000-0000-000000000000","prompt":"What is Rust?","delta":"Claude mentions Cargo"}"#
+        );
+    }
+}
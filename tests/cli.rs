@@ -1,4 +1,6 @@
-use chatdelta_base::cli::Args;
+use chatdelta::models::ModelCatalog;
+use chatdelta_base::cli::{Args, CliCommand, OutputFormat};
+use chatdelta_base::keys::Keymap;
 use clap::Parser;
 
 #[test]
@@ -12,3 +14,171 @@ fn test_args_validate_empty() {
     let args = Args::parse_from(["chatdelta"]);
     assert!(args.validate().is_err());
 }
+
+#[test]
+fn test_args_theme() {
+    let args = Args::parse_from(["chatdelta", "--theme", "light"]);
+    assert_eq!(args.theme.as_deref(), Some("light"));
+}
+
+#[test]
+fn test_args_output_format() {
+    let args = Args::parse_from(["chatdelta", "--headless", "Hello"]);
+    assert!(args.headless);
+    assert_eq!(args.output_format, OutputFormat::Json);
+
+    let args = Args::parse_from(["chatdelta", "--headless", "--output-format", "csv", "Hello"]);
+    assert_eq!(args.output_format, OutputFormat::Csv);
+    assert!(Args::try_parse_from(["chatdelta", "--output-format", "xml"]).is_err());
+}
+
+#[test]
+fn test_args_pipe() {
+    assert!(Args::parse_from(["chatdelta", "--pipe"]).pipe);
+    assert!(!Args::parse_from(["chatdelta", "Hi"]).pipe);
+}
+
+#[test]
+fn test_args_webhook() {
+    let args = Args::parse_from(["chatdelta", "--webhook", "https://example.com/hook", "Hi"]);
+    assert_eq!(args.webhook.as_deref(), Some("https://example.com/hook"));
+    assert!(Args::parse_from(["chatdelta", "Hi"]).webhook.is_none());
+}
+
+#[test]
+fn test_args_transcribe() {
+    let args = Args::parse_from(["chatdelta", "--headless", "--transcribe", "question.wav"]);
+    assert_eq!(args.transcribe.as_deref(), Some(std::path::Path::new("question.wav")));
+    assert!(args.prompt.is_none());
+}
+
+#[test]
+fn test_args_no_delta() {
+    assert!(Args::parse_from(["chatdelta", "--no-delta", "Hi"]).no_delta);
+    assert!(!Args::parse_from(["chatdelta", "Hi"]).no_delta);
+}
+
+#[test]
+fn test_args_judge() {
+    assert_eq!(Args::parse_from(["chatdelta", "--judge", "claude"]).judge.as_deref(), Some("claude"));
+    assert_eq!(Args::parse_from(["chatdelta", "Hi"]).judge, None);
+}
+
+#[test]
+fn test_args_benchmark() {
+    let args = Args::parse_from(["chatdelta", "--benchmark", "Hello", "--n", "5"]);
+    assert_eq!(args.benchmark.as_deref(), Some("Hello"));
+    assert_eq!(args.n, 5);
+    assert_eq!(Args::parse_from(["chatdelta", "--benchmark", "Hello"]).n, 10);
+    assert!(Args::try_parse_from(["chatdelta", "--benchmark", "Hello", "--n", "0"]).is_err());
+}
+
+#[test]
+fn test_args_safety_thresholds() {
+    let args = Args::parse_from([
+        "chatdelta",
+        "--safety-threshold",
+        "harassment=block_only_high",
+        "--safety-threshold",
+        "dangerous_content=block_none",
+    ]);
+    let categories: Vec<&str> = args.safety_thresholds.iter().map(|s| s.category.as_str()).collect();
+    assert_eq!(categories, ["HARM_CATEGORY_HARASSMENT", "HARM_CATEGORY_DANGEROUS_CONTENT"]);
+    assert_eq!(args.safety_thresholds[1].threshold, "BLOCK_NONE");
+    assert!(Args::parse_from(["chatdelta"]).safety_thresholds.is_empty());
+    assert!(Args::try_parse_from(["chatdelta", "--safety-threshold", "harassment"]).is_err());
+}
+
+#[test]
+fn test_convert_subcommand() {
+    let args = Args::parse_from(["chatdelta", "convert", "--input", "session.json", "--output", "pairs.jsonl"]);
+    assert_eq!(
+        args.command,
+        Some(CliCommand::Convert { input: "session.json".into(), output: "pairs.jsonl".into() })
+    );
+    assert_eq!(args.prompt, None);
+    assert!(Args::try_parse_from(["chatdelta", "convert", "--input", "session.json"]).is_err());
+    assert!(!args.starred_only);
+
+    let args = Args::parse_from(["chatdelta", "convert", "--input", "session.json", "--output", "pairs.jsonl", "--starred-only"]);
+    assert!(args.starred_only);
+    let args = Args::parse_from(["chatdelta", "--stats", "session.json", "--starred-only"]);
+    assert!(args.starred_only);
+
+    let args = Args::parse_from(["chatdelta", "Explain lifetimes"]);
+    assert_eq!(args.command, None);
+    assert_eq!(args.prompt.as_deref(), Some("Explain lifetimes"));
+}
+
+#[test]
+fn test_args_dry_run() {
+    assert!(Args::parse_from(["chatdelta", "--dry-run"]).dry_run);
+    assert!(!Args::parse_from(["chatdelta"]).dry_run);
+}
+
+#[test]
+fn test_args_stats() {
+    let args = Args::parse_from(["chatdelta", "--stats", "session.json"]);
+    assert_eq!(args.stats.as_deref(), Some(std::path::Path::new("session.json")));
+    assert_eq!(Args::parse_from(["chatdelta"]).stats, None);
+}
+
+#[test]
+fn test_version_lists_endpoints() {
+    let err = Args::try_parse_from(["chatdelta", "--version"]).unwrap_err();
+    assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+    let output = err.to_string();
+    assert!(output.contains(env!("CARGO_PKG_VERSION")));
+    assert!(output.contains("openai: https://api.openai.com/v1/chat/completions"));
+    assert!(output.contains("gemini: https://generativelanguage.googleapis.com/v1beta/models/{model}:generateContent"));
+    assert!(output.contains("claude: https://api.anthropic.com/v1/messages"));
+
+    // -V stays short
+    let err = Args::try_parse_from(["chatdelta", "-V"]).unwrap_err();
+    assert!(!err.to_string().contains("Endpoints"));
+}
+
+#[test]
+fn test_args_max_tokens_fit_the_model() {
+    assert!(Args::parse_from(["chatdelta", "Hi"]).validate().is_ok());
+    assert!(Args::parse_from(["chatdelta", "--max-tokens", "8192", "Hi"]).validate().is_ok());
+    // Each provider's model is checked against its own output limit
+    let err = Args::parse_from(["chatdelta", "--max-tokens", "10000", "Hi"]).validate().unwrap_err();
+    assert!(err.contains("gemini-1.5-pro"), "{}", err);
+    assert!(err.contains("8192 tokens"));
+    let args = Args::parse_from(["chatdelta", "--only", "gpt", "--max-tokens", "10000", "Hi"]);
+    assert!(args.validate().is_ok());
+    let err = Args::parse_from(["chatdelta", "--only", "gpt", "--max-tokens", "20000", "Hi"]).validate().unwrap_err();
+    assert!(err.contains("gpt-4o"));
+    // A catalog file's model without a known limit isn't checked
+    let models = ModelCatalog::from_toml("[gemini]\ndefault_model = \"gemini-next\"\nmax_output_tokens = 0\n").unwrap();
+    assert!(Args::parse_from(["chatdelta", "--only", "gemini", "--max-tokens", "10000", "Hi"]).validate_max_tokens(&models).is_ok());
+}
+
+#[test]
+fn test_args_keymap() {
+    assert_eq!(Args::parse_from(["chatdelta", "--keymap", "vim", "Hi"]).keymap, Some(Keymap::Vim));
+    assert_eq!(Args::parse_from(["chatdelta", "Hi"]).keymap, None);
+    assert!(Args::try_parse_from(["chatdelta", "--keymap", "emacs", "Hi"]).is_err());
+}
+
+#[test]
+fn test_args_images() {
+    let args = Args::parse_from(["chatdelta", "--headless", "--image", "a.png", "--image", "b.jpg", "What differs?"]);
+    assert_eq!(args.images, [std::path::PathBuf::from("a.png"), std::path::PathBuf::from("b.jpg")]);
+    assert!(Args::parse_from(["chatdelta", "Hi"]).images.is_empty());
+}
+
+#[test]
+fn test_args_proxy() {
+    let args = Args::parse_from(["chatdelta", "--proxy", "socks5://127.0.0.1:1080", "Hi"]);
+    assert_eq!(args.proxy.as_deref(), Some("socks5://127.0.0.1:1080"));
+    assert_eq!(args.proxy_user, None);
+
+    let args = Args::parse_from(["chatdelta", "--proxy", "http://proxy.corp:3128", "--proxy-user", "alice", "--proxy-pass", "s3cret"]);
+    assert_eq!((args.proxy_user.as_deref(), args.proxy_pass.as_deref()), (Some("alice"), Some("s3cret")));
+
+    // A user needs a password and the other way round
+    assert!(Args::try_parse_from(["chatdelta", "--proxy-user", "alice"]).is_err());
+    assert!(Args::try_parse_from(["chatdelta", "--proxy-pass", "s3cret"]).is_err());
+}
@@ -1,5 +1,137 @@
-use std::collections::HashMap;
-use chatdelta_base::tui::{AppState, ProviderState};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use chatdelta::middleware::{Middleware, RequestParts};
+use chatdelta::models::ModelCatalog;
+use chatdelta::{AiClient, ChatMessage, ClientConfigBuilder, ImageAttachment, MultiModelClient, PromptOptions, ProviderConfigs, ResponseMetadata, StreamChunk, TokenUsage};
+use chatdelta_base::commands::Command;
+use chatdelta_base::delta::{DeltaJudge, DeltaStrategy};
+use chatdelta_base::events::ResponseType;
+use chatdelta_base::history::InputHistory;
+use chatdelta_base::keys::{KeyResolver, KeyTable, Keymap};
+use chatdelta_base::logger::{ConversationLog, Logger};
+use chatdelta_base::presets::PromptLibrary;
+use chatdelta_base::request_log::RequestStatus;
+use chatdelta_base::theme::Theme;
+use chatdelta_base::tui::{display_width, drain_responses, estimate_tokens, format_count, format_duration, scrollbar_thumb, truncate_note, truncation_spans, wrapped_rows, ApiKeys, AppState, ProviderState, ScreenRegion, CHARS_PER_TOKEN, COLUMN_VISIBLE_LINES, DEFAULT_DELTA_HEIGHT, MAX_INPUT_LINES, MIN_DELTA_HEIGHT, MAX_UNDO_DEPTH, RESPONSE_CHANNEL_CAPACITY, SESSION_RETRY_BUDGET, SPINNER_FRAMES, STREAM_FLUSH_INTERVAL, TRUNCATED_MARKER};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use tokio::sync::mpsc;
+use tui::layout::Rect;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Mock client that streams chunks until its receiver goes away
+struct EndlessStreamClient;
+
+#[async_trait]
+impl AiClient for EndlessStreamClient {
+    async fn send_prompt(&self, _prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok("mock response".to_string())
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    async fn send_prompt_streaming_with_options(
+        &self,
+        _prompt: &str,
+        _options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        loop {
+            let chunk = StreamChunk { content: "tick ".to_string(), finished: false, metadata: None };
+            if tx.send(chunk).await.is_err() {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+}
+
+/// Mock client that streams a fixed number of one-character chunks as fast as it can
+struct FloodStreamClient {
+    chunks: usize,
+}
+
+#[async_trait]
+impl AiClient for FloodStreamClient {
+    async fn send_prompt(&self, _prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok("x".repeat(self.chunks))
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    async fn send_prompt_streaming_with_options(
+        &self,
+        _prompt: &str,
+        _options: &PromptOptions,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for _ in 0..self.chunks {
+            tx.send(StreamChunk { content: "x".to_string(), finished: false, metadata: None }).await?;
+        }
+        tx.send(StreamChunk { content: String::new(), finished: true, metadata: None }).await?;
+        Ok(())
+    }
+}
+
+/// Answers every prompt with its own text
+struct EchoClient;
+
+#[async_trait]
+impl AiClient for EchoClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(prompt.to_string())
+    }
+}
+
+/// Answers with the prompt and how many images came with it
+struct VisionClient;
+
+#[async_trait]
+impl AiClient for VisionClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(prompt.to_string())
+    }
+
+    async fn send_prompt_with_images(&self, prompt: &str, images: &[ImageAttachment], _options: &PromptOptions) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(format!("{} ({} images)", prompt, images.len()))
+    }
+}
+
+/// Reports every request as a duplicate of one already pending
+struct PendingClient;
+
+#[async_trait]
+impl AiClient for PendingClient {
+    async fn send_prompt(&self, prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(prompt.to_string())
+    }
+
+    fn is_request_pending(&self, _prompt: &str, _options: &PromptOptions) -> bool {
+        true
+    }
+}
+
+/// Fails its health check as if its API key were rejected
+struct RejectedKeyClient;
+
+#[async_trait]
+impl AiClient for RejectedKeyClient {
+    async fn send_prompt(&self, _prompt: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Err("401 Unauthorized".into())
+    }
+
+    async fn health_check(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err("401 Unauthorized".into())
+    }
+}
 
 #[tokio::test]
 async fn test_app_state_new() {
@@ -14,3 +146,1705 @@ async fn test_app_state_new() {
     assert_eq!(app.providers[1].state, ProviderState::Disabled);
     assert_eq!(app.providers[2].state, ProviderState::Enabled);
 }
+
+#[tokio::test]
+async fn test_shutdown_stops_streaming_tasks() {
+    let mut app = AppState::new(HashMap::new());
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.spawn_provider_request(0, Arc::new(EndlessStreamClient), "Hi".to_string(), PromptOptions::default(), tx);
+    assert!(matches!(rx.recv().await, Some(ResponseType::StreamChunk(0, _, false))));
+
+    app.shutdown(Duration::from_secs(1)).await;
+
+    // Drain whatever was queued before the abort, then nothing else may arrive
+    while rx.try_recv().is_ok() {}
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(rx.try_recv().is_err());
+    assert!(app.tasks.is_empty());
+}
+
+#[tokio::test]
+async fn test_pin_and_unpin() {
+    let mut app = AppState::new(HashMap::new());
+
+    app.handle_command(Command::Pin("Rust async frameworks".to_string()));
+    assert_eq!(app.pinned_note.as_deref(), Some("Rust async frameworks"));
+
+    app.handle_command(Command::Unpin);
+    assert!(app.pinned_note.is_none());
+}
+
+#[tokio::test]
+async fn test_samples_render_as_variants() {
+    let mut app = AppState::new(HashMap::new());
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.handle_command(Command::Samples(3));
+    let options = PromptOptions { n: Some(app.samples), ..Default::default() };
+    app.spawn_provider_request(0, Arc::new(EchoClient), "Hi".to_string(), options, tx);
+
+    let Some(ResponseType::Variants(0, variants)) = rx.recv().await else {
+        panic!("expected variants");
+    };
+    assert_eq!(variants.len(), 3);
+    app.handle_variants(0, variants);
+
+    let column = app.providers[0].chat_history.last().unwrap().clone();
+    assert!(column.contains("── Variant 1/3 ◆ ──"));
+    assert!(column.contains("── Variant 3/3 ──"));
+
+    app.selected_column = 0;
+    assert!(app.cycle_variant());
+    assert_eq!(app.providers[0].selected_variant, 1);
+    assert!(app.providers[0].chat_history.last().unwrap().contains("── Variant 2/3 ◆ ──"));
+
+    // Columns with a single response have nothing to cycle
+    app.selected_column = 1;
+    assert!(!app.cycle_variant());
+}
+
+#[tokio::test]
+async fn test_attach_sends_images_with_the_next_prompt() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(VisionClient));
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    let path = std::env::temp_dir().join(format!("chatdelta-attach-{}.png", std::process::id()));
+    std::fs::write(&path, b"\x89PNG\r\n\x1a\n").unwrap();
+    app.handle_command(Command::Attach(path.clone()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(app.attachments.len(), 1);
+    assert!(app.status_line().contains("📎 1 attached"));
+
+    app.send_to_active_providers("What is this?", None, tx.clone());
+    assert!(app.attachments.is_empty());
+    let Some(ResponseType::Provider(0, response)) = rx.recv().await else {
+        panic!("expected a response");
+    };
+    assert_eq!(response, "What is this? (1 images)");
+
+    // Only the next prompt carries them
+    app.send_to_active_providers("And now?", None, tx);
+    let Some(ResponseType::Provider(0, response)) = rx.recv().await else {
+        panic!("expected a response");
+    };
+    assert_eq!(response, "And now?");
+
+    app.handle_command(Command::Attach("missing.png".into()));
+    assert!(app.attachments.is_empty());
+    assert!(app.delta_text.contains("Could not attach missing.png"));
+}
+
+#[tokio::test]
+async fn test_star_visible_exchange() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.toggle_star();
+    assert_eq!(app.notice.as_ref().map(|(notice, _)| notice.as_str()), Some("⚠️ Nothing to star yet - send a prompt first"));
+
+    for prompt in ["First", "Second"] {
+        app.send_to_active_providers(prompt, None, tx.clone());
+        let Some(ResponseType::Provider(0, response)) = rx.recv().await else {
+            panic!("expected a response");
+        };
+        app.handle_response(0, response);
+        // Its metadata follows every reply
+        let Some(ResponseType::Metadata(0, _)) = rx.recv().await else {
+            panic!("expected metadata");
+        };
+    }
+
+    // The whole column fits, so its top is the welcome message and the first exchange is starred
+    app.toggle_star();
+    assert_eq!(app.providers[0].starred_lines.iter().copied().collect::<Vec<_>>(), [1]);
+    assert_eq!(app.providers[0].chat_history[1], "You: First");
+    assert_eq!(app.logger.get_starred().len(), 1);
+    assert_eq!(app.logger.get_starred()[0].prompt, "First");
+
+    // With the delta field selected it is the latest exchange
+    app.selected_column = app.providers.len();
+    app.toggle_star();
+    assert_eq!(app.providers[0].starred_lines.len(), 2);
+    app.handle_command(Command::BrowseStarred);
+    assert!(app.delta_text.starts_with("★ 2 starred exchange(s):"));
+    assert!(app.delta_text.contains("Second"));
+
+    // Starring again removes the star
+    app.toggle_star();
+    assert_eq!(app.notice.as_ref().map(|(notice, _)| notice.as_str()), Some("☆ Star removed"));
+    assert_eq!(app.logger.get_starred().len(), 1);
+    assert!(!app.providers[0].starred_lines.contains(&3));
+}
+
+#[tokio::test]
+async fn test_multi_model_answers_render_as_tabs() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    let client = MultiModelClient::new(vec![
+        ("gpt-4o".to_string(), Box::new(EchoClient)),
+        ("gpt-4o-mini".to_string(), Box::new(EchoClient)),
+    ]);
+    app.providers[0].client = Some(Arc::new(client));
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    // One answer per model, even without /samples
+    app.send_to_active_providers("Hi", None, tx);
+    let Some(ResponseType::Variants(0, variants)) = rx.recv().await else {
+        panic!("expected one answer per model");
+    };
+    assert_eq!(variants, ["Hi", "Hi"]);
+    app.handle_variants(0, vec!["Big answer".to_string(), "Small answer".to_string()]);
+
+    let column = app.providers[0].chat_history.last().unwrap().clone();
+    assert!(column.starts_with("ChatGPT: [gpt-4o] │ gpt-4o-mini"));
+    assert!(column.ends_with("Big answer"));
+    assert!(!column.contains("Small answer"));
+
+    app.selected_column = 0;
+    assert!(app.cycle_variant());
+    let column = app.providers[0].chat_history.last().unwrap();
+    assert!(column.starts_with("ChatGPT: gpt-4o │ [gpt-4o-mini]"));
+    assert!(column.ends_with("Small answer"));
+
+    // The log keeps every model's answer
+    let logged = &app.logger.current_conversation().unwrap().responses["ChatGPT"].text;
+    assert!(logged.contains("── gpt-4o ◆ ──\nBig answer"));
+    assert!(logged.contains("── gpt-4o-mini ──\nSmall answer"));
+}
+
+#[tokio::test]
+async fn test_spinner_runs_until_final_chunk() {
+    let mut app = AppState::new(HashMap::new());
+    assert_eq!(app.providers[0].typing_indicator(), None);
+
+    app.providers[0].streaming_in_progress = true;
+    app.advance_spinners();
+    assert_eq!(app.providers[0].typing_indicator(), Some(SPINNER_FRAMES[1]));
+    assert!(app.providers[0].title().starts_with(SPINNER_FRAMES[1]));
+
+    for _ in 0..SPINNER_FRAMES.len() {
+        app.advance_spinners();
+    }
+    assert_eq!(app.providers[0].spinner_frame, 1);
+
+    app.handle_stream_chunk(0, String::new(), true);
+    assert_eq!(app.providers[0].typing_indicator(), None);
+    assert_eq!(app.providers[0].title(), "ChatGPT");
+}
+
+#[tokio::test]
+async fn test_truncated_response_gets_marker() {
+    let mut app = AppState::new(HashMap::new());
+    app.handle_response(0, "A long answer that".to_string());
+    app.handle_metadata(0, ResponseMetadata { truncated: true, ..Default::default() });
+
+    // The marker is only drawn, so the text passed on stays as the provider sent it
+    let history = &app.providers[0].chat_history;
+    assert_eq!(history.last().unwrap(), "ChatGPT: A long answer that");
+    let truncated = history.len() - 1;
+    assert_eq!(app.providers[0].truncated_lines, HashSet::from([truncated]));
+
+    let [space, marker] = truncation_spans(tui::style::Color::Yellow);
+    assert_eq!(space.content, " ");
+    assert_eq!(marker.content, TRUNCATED_MARKER);
+    assert_eq!(marker.style.fg, Some(tui::style::Color::Yellow));
+
+    // The next answer in the column isn't marked
+    app.use_streaming = false;
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[0].state = ProviderState::Enabled;
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+    app.send_to_active_providers("Go on", None, tx);
+    app.tasks.abort_all();
+    while app.tasks.join_next().await.is_some() {}
+    app.handle_response(0, "The rest".to_string());
+    assert_eq!(app.providers[0].chat_history.last().unwrap(), "ChatGPT: The rest");
+    assert_eq!(app.providers[0].truncated_lines, HashSet::from([truncated]));
+}
+
+#[tokio::test]
+async fn test_shared_context_is_prepended() {
+    let mut app = AppState::new(HashMap::new());
+    assert_eq!(app.prompt_with_context("Who wrote it?"), "Who wrote it?");
+
+    app.handle_command(Command::SetContext("A report on Rust adoption.".to_string()));
+    assert_eq!(
+        app.prompt_with_context("Who wrote it?"),
+        "Context:\nA report on Rust adoption.\n\nQuestion: Who wrote it?"
+    );
+
+    app.handle_command(Command::ClearContext);
+    assert!(app.shared_context.is_none());
+    assert_eq!(app.prompt_with_context("Who wrote it?"), "Who wrote it?");
+}
+
+#[tokio::test]
+async fn test_tag_and_browse() {
+    let mut app = AppState::new(HashMap::new());
+    app.logger.log_prompt("Why is my loop slow?");
+
+    app.handle_command(Command::Tag(vec!["performance".to_string()]));
+    app.handle_command(Command::Browse { tag: "performance".to_string() });
+    assert!(app.delta_text.contains("Why is my loop slow? [performance]"));
+
+    app.handle_command(Command::Browse { tag: "bug".to_string() });
+    assert!(app.delta_text.contains("No exchanges tagged 'bug'"));
+}
+
+#[tokio::test]
+async fn test_status_line() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.shared_input = "Hello".to_string();
+
+    assert_eq!(app.active_provider_count(), 2);
+    assert!(app.status_line().starts_with("2/3 active | 5 chars | 0m 0"));
+    let session_id = app.logger.session_id().to_string();
+    assert!(app.status_line().contains(&format!("({}) | 0 exchanges", &session_id[..8])));
+    assert!(app.status_line().contains("📊 Metrics: Ready"));
+    assert!(!app.status_line().contains("pending"));
+
+    // Notices go ahead of the counters, so a narrow terminal cuts off the counters instead
+    app.set_notice("Claude off");
+    assert!(app.status_line().starts_with("Claude off | 2/3 active"));
+}
+
+#[tokio::test]
+async fn test_status_line_vim_mode() {
+    let mut app = AppState::new(HashMap::new());
+    app.keys = KeyResolver::new(Keymap::Vim, KeyTable::default());
+    assert!(app.status_line().starts_with("-- NORMAL -- | 0/3 active"));
+
+    app.keys.resolve(&KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE));
+    assert!(app.status_line().starts_with("-- NORMAL -- g | "));
+    // `gi` is no sequence, so Esc drops the `g` before switching modes
+    app.keys.resolve(&KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+    assert!(app.status_line().starts_with("-- NORMAL -- | "));
+    app.keys.resolve(&KeyEvent::new(KeyCode::Char('i'), KeyModifiers::NONE));
+    assert!(app.status_line().starts_with("-- INSERT -- | "));
+}
+
+#[tokio::test]
+async fn test_status_bar_pending_and_failures() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.send_to_active_providers("What is Rust?", None, tx.clone());
+    assert_eq!(app.pending_count(), 2);
+    assert!(app.status_line().contains("1 exchanges | ⠋ 2 pending"));
+    app.advance_spinners();
+    assert!(app.status_line().contains("⠙ 2 pending"));
+
+    // A column that keeps failing is flagged until it answers again
+    app.handle_response(0, "A language.".to_string());
+    app.handle_response(2, "Error: 529 Overloaded".to_string());
+    assert_eq!(app.pending_count(), 0);
+    assert!(app.status_alerts().is_empty());
+    app.send_to_active_providers("Again", None, tx.clone());
+    app.handle_response(2, "Error: 529 Overloaded".to_string());
+    assert_eq!(app.status_alerts(), vec!["⚠️ Claude failed 2 times in a row".to_string()]);
+    app.send_to_active_providers("Once more", None, tx);
+    app.handle_response(2, "Fine now".to_string());
+    assert!(app.status_alerts().is_empty());
+}
+
+#[tokio::test]
+async fn test_waiting_timer() {
+    let mut states = HashMap::new();
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+    assert_eq!(app.waiting_indicator(2), None);
+
+    app.send_to_active_providers("What is Rust?", None, tx.clone());
+    assert_eq!(app.waiting_indicator(2), Some(("⠋ Thinking... 0s".to_string(), false)));
+    app.advance_spinners();
+    assert_eq!(app.waiting_indicator(2), Some(("⠙ Thinking... 0s".to_string(), false)));
+
+    // Most of the timeout gone: the timer turns the warning color
+    app.provider_configs = ProviderConfigs::new(ClientConfigBuilder::default().timeout(Duration::ZERO).build());
+    assert_eq!(app.waiting_indicator(2).map(|(_, near_timeout)| near_timeout), Some(true));
+
+    // An error keeps how long it took
+    app.handle_response(2, "Error: 529 Overloaded".to_string());
+    assert_eq!(app.waiting_indicator(2), None);
+    assert_eq!(app.providers[2].chat_history.last().unwrap(), "Claude: Error: 529 Overloaded (after 0s)");
+}
+
+#[tokio::test]
+async fn test_retry_budget_warning_and_reset() {
+    let mut app = AppState::new(HashMap::new());
+    let budget = Arc::clone(&app.provider_configs.default_config().retry_budget);
+    assert_eq!(budget.max_total_retries, SESSION_RETRY_BUDGET);
+
+    for _ in 0..SESSION_RETRY_BUDGET / 2 {
+        assert!(budget.try_consume());
+    }
+    assert!(app.status_alerts().is_empty());
+
+    assert!(budget.try_consume());
+    let warning = format!("⚠️ Retries: {}/{} used", SESSION_RETRY_BUDGET / 2 + 1, SESSION_RETRY_BUDGET);
+    assert!(app.status_alerts()[0].starts_with(&warning));
+
+    app.handle_command(Command::ResetBudget);
+    assert_eq!(budget.retries_used(), 0);
+    assert!(app.status_alerts().is_empty());
+}
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_secs(323)), "5m 23s");
+    assert_eq!(format_duration(Duration::from_secs(3_723)), "1h 02m 03s");
+}
+
+#[test]
+fn test_scrollbar_thumb() {
+    assert_eq!(scrollbar_thumb(10, 100, 25, 0), Some((0, 2)));
+    assert_eq!(scrollbar_thumb(10, 100, 25, 30), Some((3, 2)));
+    assert_eq!(scrollbar_thumb(10, 100, 25, 75), Some((8, 2)));
+    assert_eq!(scrollbar_thumb(10, 10_000, 25, 0), Some((0, 1)));
+    assert_eq!(scrollbar_thumb(10, 25, 25, 0), None);
+    assert_eq!(scrollbar_thumb(0, 100, 25, 0), None);
+}
+
+#[test]
+fn test_wrapped_rows() {
+    assert_eq!(wrapped_rows("", 10), 1);
+    assert_eq!(wrapped_rows("short line", 10), 1);
+    assert_eq!(wrapped_rows("one two three", 10), 2);
+    assert_eq!(wrapped_rows(&"x".repeat(25), 10), 3);
+    assert_eq!(wrapped_rows("ab 日本語日本語", 6), 3);
+    assert_eq!(wrapped_rows(&"word ".repeat(100), 0), 1);
+}
+
+#[test]
+fn test_viewports_follow_terminal_size() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].chat_history = vec!["word ".repeat(60)];
+
+    // A tall terminal shows more than the old fixed 25 rows
+    app.layout = app.screen_layout(Rect::new(0, 0, 90, 60));
+    app.update_viewports(false);
+    let column = app.layout.columns[0];
+    assert_eq!(app.viewports[0].height, column.height - 2);
+    assert_eq!(app.viewports[0].width, column.width - 2);
+    assert!(app.page_size() > COLUMN_VISIBLE_LINES);
+
+    // One long paragraph wraps to several rows, all reachable by scrolling
+    let width = app.viewports[0].width as usize;
+    assert!(app.content_rows(0) > 1);
+    assert_eq!(app.content_rows(0), wrapped_rows(&"word ".repeat(60), width));
+    app.layout = app.screen_layout(Rect::new(0, 0, 90, 14));
+    app.update_viewports(false);
+    assert_eq!(app.max_scroll(0), app.content_rows(0) - app.viewports[0].height as usize);
+    app.scroll_to_bottom();
+    assert_eq!(app.scroll_positions[0], app.max_scroll(0));
+
+    // Notes, footers and scroll indicators keep their rows out of the viewport
+    app.providers[0].state = ProviderState::Connecting;
+    app.layout = app.screen_layout(Rect::new(0, 0, 90, 60));
+    app.update_viewports(true);
+    assert_eq!(app.viewports[0].height, app.layout.columns[0].height - 2 - 2 - 2);
+}
+
+#[test]
+fn test_delta_panel_resize_and_collapse() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    let screen = Rect::new(0, 0, 90, 40);
+    app.layout = app.screen_layout(screen);
+    assert_eq!(app.layout.delta.height, DEFAULT_DELTA_HEIGHT);
+    let column_height = app.layout.columns[0].height;
+
+    // Growing stops at half the screen, and the columns give up the rows
+    for _ in 0..100 {
+        app.grow_delta();
+    }
+    app.layout = app.screen_layout(screen);
+    assert_eq!(app.layout.delta.height, 20);
+    assert_eq!(app.layout.columns[0].height, column_height - (20 - DEFAULT_DELTA_HEIGHT));
+
+    for _ in 0..100 {
+        app.shrink_delta();
+    }
+    app.layout = app.screen_layout(screen);
+    assert_eq!(app.layout.delta.height, MIN_DELTA_HEIGHT);
+
+    // Collapsed, the columns take the delta field's rows and it can't be selected
+    app.selected_column = app.providers.len();
+    app.toggle_delta_panel();
+    assert!(!app.show_delta);
+    assert!(app.selected_column < app.providers.len());
+    app.layout = app.screen_layout(screen);
+    assert_eq!(app.layout.delta.height, 0);
+    assert_eq!(app.layout.columns[0].height, column_height + DEFAULT_DELTA_HEIGHT);
+    app.selected_column = app.providers.len() - 1;
+    app.select_next_column();
+    assert_eq!(app.selected_column, 0);
+    app.select_previous_column();
+    assert_eq!(app.selected_column, app.providers.len() - 1);
+
+    app.toggle_delta_panel();
+    app.layout = app.screen_layout(screen);
+    assert_eq!(app.layout.delta.height, MIN_DELTA_HEIGHT);
+}
+
+#[test]
+fn test_truncate_note() {
+    assert_eq!(truncate_note("short", 60), "short");
+    let long = "a".repeat(70);
+    assert_eq!(truncate_note(&long, 60), format!("{}…", "a".repeat(60)));
+}
+
+#[tokio::test]
+async fn test_stream_stress_with_bounded_channels() {
+    const CHUNKS: usize = 100_000;
+    let mut app = AppState::new(HashMap::new());
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.providers[0].chat_history.push("ChatGPT: Thinking...".to_string());
+    app.spawn_provider_request(0, Arc::new(FloodStreamClient { chunks: CHUNKS }), "Hi".to_string(), PromptOptions::default(), tx);
+
+    let mut finished = false;
+    let mut batches = 0;
+    while !finished {
+        // Block for the next message, then take whatever else is queued, like one UI frame
+        let first = rx.recv().await.expect("stream ended without a final chunk");
+        let mut batch = vec![first];
+        batch.extend(drain_responses(&mut rx, RESPONSE_CHANNEL_CAPACITY));
+        batches += 1;
+        for response_type in batch {
+            if let ResponseType::StreamChunk(idx, chunk, is_final) = response_type {
+                app.handle_stream_chunk(idx, chunk, is_final);
+                finished |= is_final;
+            }
+        }
+    }
+
+    let last = app.providers[0].chat_history.last().unwrap();
+    assert_eq!(last.strip_prefix("ChatGPT: ").unwrap().len(), CHUNKS);
+    assert!(batches < CHUNKS, "chunks should be coalesced into fewer UI updates");
+}
+
+#[tokio::test]
+async fn test_drain_coalesces_consecutive_chunks() {
+    let (tx, mut rx) = mpsc::channel(16);
+    tx.send(ResponseType::StreamChunk(0, "a".to_string(), false)).await.unwrap();
+    tx.send(ResponseType::StreamChunk(0, "b".to_string(), false)).await.unwrap();
+    tx.send(ResponseType::StreamChunk(1, "c".to_string(), false)).await.unwrap();
+    tx.send(ResponseType::StreamChunk(0, String::new(), true)).await.unwrap();
+
+    let drained = drain_responses(&mut rx, 16);
+    assert_eq!(drained.len(), 3);
+    assert!(matches!(&drained[0], ResponseType::StreamChunk(0, chunk, false) if chunk == "ab"));
+}
+
+#[test]
+fn test_welcome_message_uses_model_catalog() {
+    let catalog = ModelCatalog::from_toml("[openai]\ndefault_model = \"gpt-4o-mini\"\n").unwrap();
+    let message = AppState::create_welcome_message("ChatGPT", &catalog);
+    assert!(message.contains("Model: gpt-4o-mini (128k context)"));
+    assert!(AppState::create_welcome_message("Claude", &catalog).contains("claude-3-5-sonnet-20241022"));
+}
+
+#[test]
+fn test_input_cursor_and_scrolling() {
+    let mut app = AppState::new(HashMap::new());
+    for c in "hello world".chars() {
+        app.insert_char(c);
+    }
+    app.move_cursor_left();
+    app.move_cursor_left();
+    app.insert_char('é');
+    assert_eq!(app.shared_input, "hello woréld");
+    app.delete_char_before_cursor();
+    assert_eq!(app.shared_input, "hello world");
+    assert_eq!(app.cursor_pos, 9);
+
+    // Wide enough: one row
+    app.update_input_scroll(40);
+    assert_eq!(app.input_scroll_offset, 0);
+    assert_eq!(app.visible_input(40), "> hello world");
+    assert_eq!(app.input_height(40), 3);
+    assert_eq!(app.visible_input_cursor(40), (11, 0));
+
+    // Eight columns leave six characters per row after the prompt
+    app.update_input_scroll(8);
+    assert_eq!(app.visible_input(8), "> hello \n  world");
+    assert_eq!(app.input_height(8), 4);
+    assert_eq!(app.visible_input_cursor(8), (5, 1));
+
+    app.clear_input();
+    assert_eq!((app.cursor_pos, app.input_scroll_offset), (0, 0));
+}
+
+#[test]
+fn test_input_cursor_with_wide_characters_and_graphemes() {
+    let mut app = AppState::new(HashMap::new());
+    // Two wide characters, then an e with a separate combining accent
+    for c in "你好e\u{301}".chars() {
+        app.insert_char(c);
+    }
+    assert_eq!(app.visible_input_cursor(40), (7, 0));
+
+    // The accent moves and is deleted together with its letter
+    app.move_cursor_left();
+    assert_eq!(app.cursor_pos, 2);
+    assert_eq!(app.visible_input_cursor(40), (6, 0));
+    app.move_cursor_right();
+    assert_eq!(app.cursor_pos, 4);
+    app.move_cursor_left();
+    app.delete_char_at_cursor();
+    assert_eq!(app.shared_input, "你好");
+    app.insert_char('e');
+    app.insert_char('\u{301}');
+    app.delete_char_before_cursor();
+    assert_eq!(app.shared_input, "你好");
+    app.delete_char_before_cursor();
+    assert_eq!((app.shared_input.as_str(), app.cursor_pos), ("你", 1));
+
+    // Rows of three cells fit one wide character each
+    app.clear_input();
+    for c in "你好你".chars() {
+        app.insert_char(c);
+    }
+    assert_eq!(app.visible_input(5), "> 你\n  好\n  你");
+    assert_eq!(app.visible_input_cursor(5), (4, 2));
+}
+
+#[test]
+fn test_display_width() {
+    assert_eq!(display_width("hello"), 5);
+    assert_eq!(display_width("🦀 rust"), 7);
+    assert_eq!(display_width("日本語"), 6);
+    assert_eq!(display_width("e\u{301}"), 1);
+    // A ZWJ family is one grapheme, measured the way tui draws it
+    let family = "👨\u{200d}👩\u{200d}👧";
+    assert_eq!(family.graphemes(true).count(), 1);
+    assert_eq!(display_width(family), UnicodeWidthStr::width(family));
+}
+
+#[test]
+fn test_input_cursor_after_emoji_and_zwj_sequences() {
+    let mut app = AppState::new(HashMap::new());
+    let family = "👨\u{200d}👩\u{200d}👧";
+    for c in format!("🦀{}a", family).chars() {
+        app.insert_char(c);
+    }
+    let crab_and_family = 2 + display_width(family);
+    assert_eq!(app.visible_input_cursor(40), (2 + crab_and_family as u16 + 1, 0));
+
+    // The whole sequence is one step for the cursor and for Backspace
+    app.move_cursor_left();
+    app.move_cursor_left();
+    assert_eq!(app.cursor_pos, 1);
+    app.move_cursor_right();
+    app.delete_char_before_cursor();
+    assert_eq!(app.shared_input, "🦀a");
+
+    // A very long line never puts the cursor outside the field
+    app.shared_input.push_str(&"字".repeat(100_000));
+    app.move_cursor_end();
+    let (column, row) = app.visible_input_cursor(3);
+    assert!(column < 3);
+    assert!((row as usize) < MAX_INPUT_LINES);
+}
+
+#[test]
+fn test_multi_line_input() {
+    let mut app = AppState::new(HashMap::new());
+    for c in "fn main() {".chars() {
+        app.insert_char(c);
+    }
+    app.insert_newline();
+    assert_eq!(app.visible_input_cursor(40), (2, 1));
+    for c in "}".chars() {
+        app.insert_char(c);
+    }
+    assert_eq!(app.shared_input, "fn main() {\n}");
+    assert_eq!(app.visible_input(40), "> fn main() {\n  }");
+    app.move_cursor_home();
+    assert_eq!(app.visible_input_cursor(40), (2, 0));
+
+    // The field stops growing at MAX_INPUT_LINES rows and scrolls to the cursor instead
+    app.move_cursor_end();
+    for _ in 0..MAX_INPUT_LINES + 2 {
+        app.insert_newline();
+    }
+    assert_eq!(app.input_height(40), MAX_INPUT_LINES as u16 + 2);
+    app.update_input_scroll(40);
+    assert_eq!(app.input_scroll_offset, 4);
+    assert_eq!(app.visible_input_cursor(40), (2, MAX_INPUT_LINES as u16 - 1));
+    assert_eq!(app.visible_input(40).lines().count(), MAX_INPUT_LINES);
+
+    app.move_cursor_home();
+    app.update_input_scroll(40);
+    assert_eq!(app.input_scroll_offset, 0);
+    assert!(app.visible_input(40).starts_with("> fn main() {"));
+}
+
+#[test]
+fn test_pasted_text_is_one_edit() {
+    let mut app = AppState::new(HashMap::new());
+    app.insert_char('>');
+    app.move_cursor_home();
+    app.insert_text("line one\nline two\n");
+    assert_eq!((app.shared_input.as_str(), app.cursor_pos), ("line one\nline two\n>", 18));
+    assert!(app.logger.current_conversation().is_none());
+
+    app.undo();
+    assert_eq!((app.shared_input.as_str(), app.cursor_pos), (">", 0));
+}
+
+#[test]
+fn test_input_word_navigation() {
+    let mut app = AppState::new(HashMap::new());
+    for c in "explain  rust lifetimes".chars() {
+        app.insert_char(c);
+    }
+
+    app.move_cursor_word_left();
+    assert_eq!(app.cursor_pos, 14);
+    app.move_cursor_word_left();
+    app.move_cursor_word_left();
+    assert_eq!(app.cursor_pos, 0);
+    app.move_cursor_word_right();
+    assert_eq!(app.cursor_pos, 7);
+
+    app.delete_char_at_cursor();
+    assert_eq!(app.shared_input, "explain rust lifetimes");
+    app.move_cursor_end();
+    app.delete_char_at_cursor();
+    assert_eq!(app.cursor_pos, 22);
+    app.move_cursor_home();
+    assert_eq!(app.cursor_pos, 0);
+}
+
+#[test]
+fn test_input_undo_redo() {
+    let mut app = AppState::new(HashMap::new());
+    app.insert_char('a');
+    app.insert_char('b');
+    app.delete_char_before_cursor();
+    assert_eq!(app.shared_input, "a");
+
+    app.undo();
+    assert_eq!((app.shared_input.as_str(), app.cursor_pos), ("ab", 2));
+    app.undo();
+    app.undo();
+    assert_eq!((app.shared_input.as_str(), app.cursor_pos), ("", 0));
+    app.undo();
+    assert_eq!(app.shared_input, "");
+
+    app.redo();
+    assert_eq!(app.shared_input, "a");
+    // A new edit drops what could still be redone
+    app.insert_char('c');
+    app.redo();
+    assert_eq!(app.shared_input, "ac");
+
+    for _ in 0..MAX_UNDO_DEPTH + 10 {
+        app.insert_char('x');
+    }
+    assert_eq!(app.undo_stack.len(), MAX_UNDO_DEPTH);
+}
+
+#[tokio::test]
+async fn test_toggle_delta_skips_analysis() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.handle_response(0, "Four".to_string());
+    app.handle_response(2, "4".to_string());
+
+    app.toggle_delta();
+    assert_eq!(app.delta_strategy, DeltaStrategy::Disabled);
+    let before = app.delta_text.clone();
+    let (tx, _rx) = mpsc::channel(8);
+    app.generate_delta_with_channel(tx);
+    assert_eq!(app.delta_text, before);
+    assert!(app.tasks.is_empty());
+
+    app.toggle_delta();
+    assert_eq!(app.delta_strategy, DeltaStrategy::Summary);
+    assert!(app.show_delta);
+}
+
+#[tokio::test]
+async fn test_send_to_single_provider() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(8);
+
+    // Gemini has no client, so nothing is sent
+    app.send_to_active_providers("Hi", Some(1), tx.clone());
+    assert!(app.logger.current_conversation().is_none());
+
+    app.send_to_active_providers("Follow-up", Some(2), tx.clone());
+    assert_eq!(app.providers[2].chat_history.last().unwrap(), "Claude: Thinking...");
+    assert!(!app.providers[0].chat_history.iter().any(|m| m == "You: Follow-up"));
+
+    app.handle_response(2, "Answer".to_string());
+    let before = app.delta_text.clone();
+    app.generate_delta_with_channel(tx.clone());
+    assert_eq!(app.delta_text, before);
+
+    // `/only` goes to the selected column the same way
+    app.selected_column = 0;
+    let to_send = app.handle_command(Command::Only("Just you".to_string()));
+    assert_eq!(to_send, Some(("Just you".to_string(), Some(0))));
+    app.send_to_active_providers("Just you", Some(0), tx.clone());
+    assert_eq!(app.providers[0].chat_history.last().unwrap(), "ChatGPT: Thinking...");
+    assert!(!app.providers[2].chat_history.iter().any(|m| m == "You: Just you"));
+
+    // With the delta field selected there is no column to send to
+    app.send_to_active_providers("Nobody", Some(app.providers.len()), tx);
+    assert_eq!(
+        app.notice.as_ref().map(|(notice, _)| notice.as_str()),
+        Some("⚠️ Select an enabled provider column to send to it alone")
+    );
+}
+
+#[tokio::test]
+async fn test_duplicate_request_is_suppressed() {
+    let mut states = HashMap::new();
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(8);
+
+    app.send_to_active_providers("Hi", Some(2), tx.clone());
+    let request_id = app.logger.current_conversation().unwrap().request_id;
+    app.providers[2].client = Some(Arc::new(PendingClient));
+    app.send_to_active_providers("Hi", Some(2), tx);
+
+    let history = &app.providers[2].chat_history;
+    assert_eq!(history.iter().filter(|m| m.ends_with("Thinking...")).count(), 1);
+    assert_eq!(history.last().unwrap(), "Claude: Thinking...");
+    assert!(history[history.len() - 2].contains("duplicate suppressed"));
+    // The pending answer is still logged under the exchange that asked for it
+    assert_eq!(app.logger.exchange_count(), 1);
+    assert_eq!(app.logger.current_conversation().unwrap().request_id, request_id);
+}
+
+/// Records the API key of each request and sends it to a local port nothing listens on
+struct KeyRecorder {
+    keys: Arc<Mutex<Vec<String>>>,
+    url: String,
+}
+
+impl Middleware for KeyRecorder {
+    fn on_request(&self, request: &mut RequestParts) {
+        if let Some(key) = request.headers.get("x-api-key").and_then(|key| key.to_str().ok()) {
+            self.keys.lock().unwrap().push(key.to_string());
+        }
+        request.url = self.url.clone();
+    }
+}
+
+#[tokio::test]
+async fn test_client_outlives_its_env_var() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let keys = Arc::new(Mutex::new(Vec::new()));
+    let recorder = KeyRecorder { keys: Arc::clone(&keys), url: format!("http://127.0.0.1:{}/v1/messages", port) };
+    let mut app = AppState::new(HashMap::new());
+    app.provider_configs = ProviderConfigs::new(ClientConfigBuilder::default().middleware(vec![Arc::new(recorder)]).build());
+    app.api_keys = ApiKeys::fixed([("CLAUDE_API_KEY", "test-key")]);
+    app.toggle_provider(2);
+    app.api_keys = ApiKeys::fixed([]);
+    assert!(app.providers[2].client.is_some());
+
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+    app.send_to_active_providers("Hi", None, tx);
+
+    // The request is still sent with the original key; nothing answers it
+    let response = tokio::time::timeout(Duration::from_secs(120), async {
+        loop {
+            match rx.recv().await {
+                Some(ResponseType::Provider(2, response)) => return Some(response),
+                Some(_) => continue,
+                None => return None,
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert!(response.is_some_and(|r| r.starts_with("Error:")));
+    assert_eq!(*keys.lock().unwrap(), ["test-key"]);
+}
+
+#[tokio::test]
+async fn test_preset_commands() {
+    let mut app = AppState::new(HashMap::new());
+    app.library = PromptLibrary::from_toml(
+        "[[preset]]\nname = \"review\"\nprompt = \"Review this code:\"\nsystem = \"You are a careful reviewer.\"",
+    )
+    .unwrap();
+
+    app.handle_command(Command::PresetList);
+    assert!(app.delta_text.contains("review: Review this code:"));
+
+    assert_eq!(app.handle_command(Command::Preset { name: "review".to_string(), send: false }), None);
+    assert_eq!(app.shared_input, "Review this code:");
+    assert_eq!(app.cursor_pos, "Review this code:".len());
+    assert_eq!(app.shared_context.as_deref(), Some("You are a careful reviewer."));
+
+    app.clear_input();
+    let sent = app.handle_command(Command::Preset { name: "review".to_string(), send: true });
+    assert_eq!(sent, Some(("Review this code:".to_string(), None)));
+    assert!(app.shared_input.is_empty());
+
+    app.handle_command(Command::Preset { name: "missing".to_string(), send: false });
+    assert!(app.delta_text.contains("No preset named 'missing'"));
+}
+
+#[tokio::test]
+async fn test_stream_chunks_are_flushed_in_batches() {
+    let mut app = AppState::new(HashMap::new());
+    app.providers[0].chat_history.push("ChatGPT: Thinking...".to_string());
+
+    // The first chunk replaces "Thinking..." right away, the next one waits for the interval
+    app.handle_stream_chunk(0, "Rust ".to_string(), false);
+    app.handle_stream_chunk(0, "is ".to_string(), false);
+    assert_eq!(app.providers[0].chat_history.last().unwrap(), "ChatGPT: Rust ");
+
+    tokio::time::sleep(STREAM_FLUSH_INTERVAL).await;
+    app.flush_stream_buffers();
+    assert_eq!(app.providers[0].chat_history.last().unwrap(), "ChatGPT: Rust is ");
+
+    app.handle_stream_chunk(0, "fast".to_string(), false);
+    app.handle_stream_chunk(0, String::new(), true);
+    assert_eq!(app.providers[0].chat_history.last().unwrap(), "ChatGPT: Rust is fast");
+    assert!(app.streaming_buffers.is_empty());
+}
+
+#[tokio::test]
+async fn test_time_to_first_token_is_shown_and_logged() {
+    let mut app = AppState::new(HashMap::new());
+    assert_eq!(app.providers[0].footer(), None);
+
+    app.logger.log_prompt("Hi");
+    app.handle_stream_chunk(0, "Hello".to_string(), true);
+    app.handle_metadata(0, ResponseMetadata {
+        time_to_first_token: Some(Duration::from_millis(320)),
+        ..Default::default()
+    });
+
+    assert_eq!(app.providers[0].footer().as_deref(), Some("⏱ First token: 320 ms"));
+    let entry = app.logger.current_conversation().unwrap();
+    assert_eq!(entry.responses["ChatGPT"].ttfb_ms, Some(320));
+}
+
+#[tokio::test]
+async fn test_response_and_judge_footers() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Gemini", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[1].client = Some(Arc::new(EchoClient));
+    app.use_streaming = false;
+    let (tx, mut rx) = mpsc::channel(8);
+
+    app.send_to_active_providers("What is 2 + 2?", None, tx.clone());
+    app.tasks.abort_all();
+    while app.tasks.join_next().await.is_some() {}
+    app.handle_response(0, "Four".to_string());
+    app.handle_response(1, "Error: 503 Service Unavailable".to_string());
+    app.handle_metadata(0, ResponseMetadata {
+        token_usage: Some(TokenUsage { input_tokens: 12, output_tokens: 300 }),
+        ..Default::default()
+    });
+
+    // Latency from the logger, tokens from the response metadata; errors get no stats line
+    let footer = app.providers[0].footer().unwrap();
+    let stats = footer.lines().next().unwrap();
+    let parts: Vec<&str> = stats.split(" • ").collect();
+    assert!(parts[0].ends_with('s') && parts[1] == "312 tokens", "{}", stats);
+    assert_eq!(app.providers[1].footer(), None);
+
+    // A new exchange starts without one
+    app.send_to_active_providers("And 3 + 3?", None, tx.clone());
+    assert_eq!(app.providers[0].footer(), None);
+    app.tasks.abort_all();
+    while app.tasks.join_next().await.is_some() {}
+    while rx.try_recv().is_ok() {}
+
+    // The delta field names the judge and how long it took
+    app.handle_response(0, "Six".to_string());
+    app.handle_response(1, "6".to_string());
+    app.generate_delta_with_channel(tx);
+    assert_eq!(app.delta_footer, None);
+    while app.tasks.join_next().await.is_some() {}
+    let Some(ResponseType::Delta(delta)) = rx.recv().await else {
+        panic!("expected a delta");
+    };
+    app.handle_delta_response(delta);
+    let author = app.delta_author.clone().unwrap();
+    let footer = app.delta_footer.clone().unwrap();
+    assert!(footer.starts_with(&format!("Judge: {} • ", author)) && footer.ends_with('s'), "{}", footer);
+}
+
+#[tokio::test]
+async fn test_retry_failed_column_only() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(8);
+
+    app.send_to_active_providers("What is Rust?", None, tx.clone());
+    app.selected_column = 2;
+    // Still waiting: the request is cancelled and sent again
+    let before = app.providers[2].chat_history.len();
+    app.retry_selected(tx.clone());
+    assert_eq!(app.providers[2].chat_history.len(), before);
+    assert_eq!(app.providers[2].chat_history.last().unwrap(), "Claude: Thinking...");
+    let claude_requests: Vec<RequestStatus> = app.request_log.entries()
+        .iter()
+        .filter(|entry| entry.provider == "Claude")
+        .map(|entry| entry.status)
+        .collect();
+    assert_eq!(claude_requests, [RequestStatus::Cancelled, RequestStatus::Pending]);
+
+    app.handle_response(0, "A language.".to_string());
+    app.handle_response(2, "Error: 529 Overloaded".to_string());
+    let before = app.providers[2].chat_history.len();
+    app.retry_selected(tx.clone());
+    let history = &app.providers[2].chat_history;
+    assert_eq!(history.len(), before);
+    assert_eq!(history[before - 2], "You: What is Rust?");
+    assert_eq!(history[before - 1], "Claude: Thinking...");
+    assert_eq!(app.providers[0].chat_history.last().unwrap(), "ChatGPT: A language.");
+    assert_eq!(app.logger.current_conversation().unwrap().prompt, "What is Rust?");
+
+    app.handle_response(2, "A systems language.".to_string());
+    assert!(app.logger.current_conversation().unwrap().responses["Claude"].error.is_none());
+
+    // A good answer is left alone
+    app.retry_selected(tx);
+    assert_eq!(app.providers[2].chat_history.last().unwrap(), "Claude: A systems language.");
+}
+
+#[tokio::test]
+async fn test_conversation_context() {
+    let mut states = HashMap::new();
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    app.selected_column = 2;
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+    // Each reply is followed by its metadata
+    async fn reply(rx: &mut mpsc::Receiver<ResponseType>) -> String {
+        let Some(ResponseType::Provider(2, response)) = rx.recv().await else {
+            panic!("expected a response");
+        };
+        let Some(ResponseType::Metadata(2, _)) = rx.recv().await else {
+            panic!("expected metadata");
+        };
+        response
+    }
+    async fn answer(app: &mut AppState, rx: &mut mpsc::Receiver<ResponseType>) -> String {
+        let response = reply(rx).await;
+        app.handle_response(2, response.clone());
+        response
+    }
+
+    app.send_to_active_providers("What is Rust?", None, tx.clone());
+    assert_eq!(answer(&mut app, &mut rx).await, "What is Rust?");
+    assert_eq!(app.providers[2].turns_in_context(), 1);
+    assert!(app.providers[2].title().ends_with("💬 1"));
+
+    // EchoClient answers with the flattened conversation, earlier turns included
+    let follow_up = "User: What is Rust?\n\nAssistant: What is Rust?\n\nUser: Can you shorten that?";
+    app.send_to_active_providers("Can you shorten that?", None, tx.clone());
+    assert_eq!(answer(&mut app, &mut rx).await, follow_up);
+    assert_eq!(app.providers[2].turns_in_context(), 2);
+
+    // Asking again replaces the latest turn instead of adding one
+    app.regenerate_selected(tx.clone());
+    assert_eq!(answer(&mut app, &mut rx).await, follow_up);
+    assert_eq!(app.providers[2].turns_in_context(), 2);
+
+    // A failed exchange isn't kept
+    app.send_to_active_providers("And now?", None, tx.clone());
+    reply(&mut rx).await;
+    app.handle_response(2, "Error: 529 Overloaded".to_string());
+    assert_eq!(app.providers[2].turns_in_context(), 2);
+
+    // Turns that would overflow the model's window are dropped, oldest first
+    let window = app.models.capabilities("claude", None).unwrap().max_context_tokens as usize;
+    app.providers[2].conversation[1] = ChatMessage::assistant("x".repeat(window * CHARS_PER_TOKEN));
+    app.send_to_active_providers("Next", None, tx.clone());
+    assert_eq!(
+        app.notice.as_ref().map(|(notice, _)| notice.as_str()),
+        Some("✂️ Claude: 1 oldest turns left out to fit the context window")
+    );
+    assert!(answer(&mut app, &mut rx).await.starts_with("User: Can you shorten that?"));
+    assert_eq!(app.providers[2].turns_in_context(), 2);
+
+    // Ctrl+K forgets the turns but not the column's history
+    let shown = app.providers[2].chat_history.len();
+    app.clear_conversation();
+    assert_eq!(app.providers[2].turns_in_context(), 0);
+    assert_eq!(app.providers[2].title(), "Claude");
+    assert_eq!(app.providers[2].chat_history.len(), shown);
+    app.send_to_active_providers("Hi", None, tx);
+    assert_eq!(answer(&mut app, &mut rx).await, "Hi");
+}
+
+#[test]
+fn test_estimate_tokens() {
+    assert_eq!(estimate_tokens(""), 0);
+    assert_eq!(estimate_tokens("abcd"), 1);
+    assert_eq!(estimate_tokens("abcde"), 2);
+}
+
+#[tokio::test]
+async fn test_regenerate_selected_column() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(8);
+
+    // Nothing has been sent yet
+    app.regenerate_selected(tx.clone());
+    assert_eq!(app.providers[0].chat_history.len(), 1);
+
+    app.send_to_active_providers("What is Rust?", None, tx.clone());
+    app.handle_response(0, "A language.".to_string());
+    app.handle_response(2, "A systems language.".to_string());
+    let before = app.providers[0].chat_history.len();
+
+    app.selected_column = 0;
+    app.regenerate_selected(tx);
+    let history = &app.providers[0].chat_history;
+    assert_eq!(history.len(), before);
+    assert_eq!(history[before - 2], "You: What is Rust?");
+    assert_eq!(history[before - 1], "ChatGPT: Thinking...");
+    assert_eq!(app.providers[2].chat_history.last().unwrap(), "Claude: A systems language.");
+}
+
+#[tokio::test]
+async fn test_batch_progress_while_waiting() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.use_streaming = false;
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+    assert_eq!(app.batch_progress(), None);
+
+    app.send_to_active_providers("Hi", None, tx.clone());
+    assert_eq!(app.batch_progress(), Some((0, 2)));
+    app.handle_response(0, "Hello".to_string());
+    assert_eq!(app.batch_progress(), Some((1, 2)));
+    app.handle_response(2, "Hello".to_string());
+    assert_eq!(app.batch_progress(), None);
+
+    // A single column is not a batch
+    app.send_to_active_providers("Again", Some(2), tx);
+    assert_eq!(app.batch_progress(), None);
+}
+
+#[tokio::test]
+async fn test_thinking_is_moved_out_of_the_column() {
+    let mut app = AppState::new(HashMap::new());
+    app.logger.log_prompt("Hi");
+    app.providers[2].chat_history.push("Claude: Thinking...".to_string());
+    app.handle_response(2, "<thinking>Keep it short.</thinking>\nHello!".to_string());
+
+    let provider = &app.providers[2];
+    assert_eq!(provider.chat_history.last().unwrap(), "Claude: Hello!");
+    assert_eq!(provider.last_thinking.as_deref(), Some("Keep it short."));
+    assert!(provider.footer().unwrap().contains("Reasoning hidden"));
+    assert_eq!(app.logger.current_conversation().map(|c| c.responses["Claude"].text.as_str()), Some("Hello!"));
+
+    app.selected_column = 2;
+    app.show_thinking();
+    assert!(app.delta_text.contains("Keep it short."));
+
+    // Streamed responses are filtered once complete
+    app.providers[2].chat_history.push("Claude: Thinking...".to_string());
+    app.handle_stream_chunk(2, "<thinking>Greet</thinking>".to_string(), false);
+    app.handle_stream_chunk(2, "Hi again".to_string(), true);
+    assert_eq!(app.providers[2].chat_history.last().unwrap(), "Claude: Hi again");
+    assert_eq!(app.providers[2].last_thinking.as_deref(), Some("Greet"));
+}
+
+#[tokio::test]
+async fn test_input_history_with_focus() {
+    let mut app = AppState::new(HashMap::new());
+    app.history = InputHistory::default();
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+    // Every column is off, so nothing is sent, but the prompts can still be recalled
+    app.send_to_active_providers("first", Some(2), tx.clone());
+    app.send_to_active_providers("second", None, tx);
+    assert_eq!(app.notice.as_ref().map(|(notice, _)| notice.as_str()), Some("⚠️ No enabled provider to send to"));
+    assert!(app.logger.current_conversation().is_none());
+
+    assert!(!app.input_focused);
+    app.toggle_input_focus();
+    assert!(app.input_focused);
+
+    app.insert_char('d');
+    app.history_previous();
+    assert_eq!((app.shared_input.as_str(), app.cursor_pos), ("second", 6));
+    app.history_previous();
+    assert_eq!(app.shared_input, "first");
+    app.history_previous();
+    assert_eq!(app.shared_input, "first");
+    app.history_next();
+    app.history_next();
+    assert_eq!((app.shared_input.as_str(), app.cursor_pos), ("d", 1));
+
+    // Recalling a prompt can be undone
+    app.history_previous();
+    app.undo();
+    assert_eq!(app.shared_input, "d");
+}
+
+fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+    MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE }
+}
+
+#[test]
+fn test_mouse_selects_and_scrolls_under_pointer() {
+    let mut app = AppState::new(HashMap::new());
+    // Nothing has been drawn yet, so the mouse hits nothing
+    app.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 60, 5));
+    assert_eq!(app.selected_column, 0);
+
+    app.layout = app.screen_layout(Rect::new(0, 0, 120, 40));
+    let second = app.layout.columns[1];
+    assert_eq!(app.region_at(second.x, second.y), Some(ScreenRegion::Column(1)));
+    assert_eq!(app.region_at(app.layout.status.x, app.layout.status.y), None);
+
+    app.input_focused = true;
+    app.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), second.x + 1, second.y + 1));
+    assert_eq!(app.selected_column, 1);
+    assert!(!app.input_focused);
+
+    let delta = app.layout.delta;
+    app.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), delta.x + 1, delta.y + 1));
+    assert_eq!(app.selected_column, app.providers.len());
+
+    let input = app.layout.input;
+    app.handle_mouse(mouse(MouseEventKind::Down(MouseButton::Left), input.x + 1, input.y + 1));
+    assert!(app.input_focused);
+    assert_eq!(app.selected_column, app.providers.len());
+
+    // The wheel scrolls the column under the pointer, not the selected one
+    app.providers[2].chat_history.push("line\n".repeat(40));
+    let third = app.layout.columns[2];
+    app.handle_mouse(mouse(MouseEventKind::ScrollDown, third.x + 1, third.y + 1));
+    app.handle_mouse(mouse(MouseEventKind::ScrollDown, third.x + 1, third.y + 1));
+    app.handle_mouse(mouse(MouseEventKind::ScrollUp, third.x + 1, third.y + 1));
+    assert_eq!(app.scroll_positions[2], 1);
+    assert_eq!(app.scroll_positions[app.providers.len()], 0);
+    assert_eq!(app.selected_column, app.providers.len());
+}
+
+#[test]
+fn test_format_count() {
+    assert_eq!(format_count(0), "0");
+    assert_eq!(format_count(999), "999");
+    assert_eq!(format_count(1214), "1,214");
+    assert_eq!(format_count(1_000_000), "1,000,000");
+}
+
+#[test]
+fn test_selected_text_for_copy_and_notice() {
+    let mut app = AppState::new(HashMap::new());
+    // Only the welcome message so far
+    assert_eq!(app.selected_text(), None);
+
+    app.providers[0].chat_history.push("You: Hi".to_string());
+    app.providers[0].chat_history.push("ChatGPT: Thinking...".to_string());
+    assert_eq!(app.selected_text(), None);
+    app.handle_response(0, "Hello: there".to_string());
+    assert_eq!(app.selected_text(), Some("Hello: there"));
+
+    app.selected_column = app.providers.len();
+    app.delta_text = "Both agree".to_string();
+    assert_eq!(app.selected_text(), Some("Both agree"));
+
+    app.set_notice("Copied 1,214 chars");
+    assert!(app.status_line().starts_with("Copied 1,214 chars | "));
+    app.notice = Some(("Copied 3 chars".to_string(), std::time::Instant::now() - Duration::from_secs(10)));
+    assert!(!app.status_line().contains("Copied"));
+
+    app.flash_notice("💾 Saved!", Duration::from_secs(2));
+    assert!(app.status_line().starts_with("💾 Saved! | "));
+    app.flash_notice("💾 Saved!", Duration::ZERO);
+    assert!(!app.status_line().contains("Saved"));
+}
+
+#[tokio::test]
+async fn test_delta_judge_without_gemini() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.delta_judge = DeltaJudge::Auto;
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(8);
+    app.handle_response(0, "Four".to_string());
+    app.handle_response(2, "4".to_string());
+
+    // No Gemini key: Claude is next in line
+    app.generate_delta_with_channel(tx.clone());
+    assert_eq!(app.tasks.len(), 1);
+    assert!(app.delta_author.as_deref().unwrap().starts_with("Claude · "));
+
+    app.cycle_delta_judge();
+    assert_eq!(app.delta_judge, DeltaJudge::Provider("ChatGPT"));
+    assert!(app.status_line().contains("Delta judge: ChatGPT"));
+    app.regenerate_delta(tx.clone());
+    assert!(app.delta_author.as_deref().unwrap().starts_with("ChatGPT · "));
+
+    // Nobody left to ask
+    app.providers[0].client = None;
+    app.providers[2].client = None;
+    app.regenerate_delta(tx);
+    assert!(app.delta_text.contains("no provider is available"));
+    assert!(app.delta_text.contains("(local diff)"));
+    assert_eq!(app.delta_author, None);
+}
+
+#[tokio::test]
+async fn test_unchanged_responses_reuse_delta() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Gemini", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[1].client = Some(Arc::new(EchoClient));
+    let (tx, mut rx) = mpsc::channel(8);
+    app.handle_response(0, "Four".to_string());
+    app.handle_response(1, "4".to_string());
+
+    app.generate_delta_with_channel(tx.clone());
+    assert_eq!(app.tasks.len(), 1);
+    while app.tasks.join_next().await.is_some() {}
+    let Some(ResponseType::Delta(delta)) = rx.recv().await else {
+        panic!("expected a delta");
+    };
+    app.handle_delta_response(delta.clone());
+
+    // The same answers again: no new request, and the summary stays
+    app.handle_response(0, "Four".to_string());
+    app.handle_response(1, "4".to_string());
+    app.generate_delta_with_channel(tx.clone());
+    assert!(app.tasks.is_empty());
+    assert_eq!(app.delta_text, delta);
+
+    // Ctrl+D asks anyway
+    app.regenerate_delta(tx.clone());
+    assert_eq!(app.tasks.len(), 1);
+    assert_eq!(app.delta_text, "Generating differences summary...");
+    while app.tasks.join_next().await.is_some() {}
+
+    // Esc before the summary arrives: nothing is remembered, so the same answers ask again
+    app.cancel_pending();
+    app.handle_response(0, "Four".to_string());
+    app.handle_response(1, "4".to_string());
+    app.generate_delta_with_channel(tx.clone());
+    assert_eq!(app.tasks.len(), 1);
+    while app.tasks.join_next().await.is_some() {}
+
+    // A changed answer asks again
+    app.handle_delta_response(delta);
+    app.handle_response(1, "Four".to_string());
+    app.generate_delta_with_channel(tx);
+    assert_eq!(app.tasks.len(), 1);
+}
+
+#[test]
+fn test_find_provider_by_alias() {
+    let app = AppState::new(HashMap::new());
+    assert_eq!(app.find_provider("openai"), Some(0));
+    assert_eq!(app.find_provider("Gemini"), Some(1));
+    assert_eq!(app.find_provider("anthropic"), Some(2));
+    assert_eq!(app.find_provider("llama"), None);
+}
+
+#[test]
+fn test_model_picker_switches_column_model() {
+    let mut states = HashMap::new();
+    states.insert("Gemini", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+
+    // Disabled columns have nothing to pick
+    app.open_model_picker();
+    assert!(app.model_picker.is_none());
+
+    app.selected_column = 1;
+    app.open_model_picker();
+    let picker = app.model_picker.clone().unwrap();
+    assert_eq!(picker.column, 1);
+    assert_eq!(picker.selected, 0);
+    assert_eq!(Some(picker.models[0].as_str()), app.models.default_model("gemini"));
+    app.move_model_picker(-1);
+    assert_eq!(app.model_picker.as_ref().unwrap().selected, picker.models.len() - 1);
+    app.move_model_picker(1);
+    app.move_model_picker(1);
+    let flash = app.model_picker.as_ref().unwrap().models[1].clone();
+
+    // Without a key the client can't be rebuilt, and nothing changes
+    app.api_keys = ApiKeys::fixed([]);
+    app.confirm_model_picker();
+    assert!(app.model_picker.is_none());
+    assert!(app.status_line().contains("⚠️ Could not create a Gemini client"));
+    assert_eq!(app.providers[1].model, None);
+
+    app.api_keys = ApiKeys::fixed([("GEMINI_API_KEY", "test-key")]);
+    app.select_model(1, &flash).unwrap();
+    app.providers[1].chat_history.push("You: Hi".to_string());
+    assert_eq!(app.providers[1].model.as_deref(), Some(flash.as_str()));
+    assert_eq!(app.providers[1].title(), format!("Gemini ({})", flash));
+    assert!(app.providers[1].chat_history[0].contains(&format!("Model: {}", flash)));
+    assert_eq!(app.providers[1].chat_history.len(), 2);
+
+    app.logger.log_prompt("Hi");
+    app.handle_response(1, "Hello".to_string());
+    app.handle_metadata(1, ResponseMetadata {
+        token_usage: Some(TokenUsage { input_tokens: 1_000, output_tokens: 1_000 }),
+        ..Default::default()
+    });
+    let entry = app.logger.current_conversation().unwrap();
+    assert_eq!(entry.responses["Gemini"].model.as_deref(), Some(flash.as_str()));
+    // Priced as the picked model, not the default gemini-1.5-pro
+    let expected = chatdelta::model_capabilities("gemini", &flash).unwrap().estimate_cost(1_000, 1_000);
+    assert_eq!(entry.responses["Gemini"].cost_usd, Some(expected));
+}
+
+#[test]
+fn test_rename_column() {
+    let mut app = AppState::new(HashMap::new());
+    app.handle_command(Command::Rename { provider: "gpt".to_string(), label: Some("GPT-4o".to_string()) });
+    assert_eq!(app.providers[0].title(), "GPT-4o");
+    assert!(app.status_line().contains("ChatGPT is now shown as GPT-4o"));
+    app.providers[0].model = Some("gpt-4o".to_string());
+    assert_eq!(app.providers[0].title(), "GPT-4o (gpt-4o)");
+
+    app.handle_command(Command::Rename { provider: "chatgpt".to_string(), label: None });
+    assert_eq!(app.providers[0].label(), "ChatGPT");
+
+    app.handle_command(Command::Rename { provider: "llama".to_string(), label: Some("Meta".to_string()) });
+    assert_eq!(app.delta_text, "⚠️ Unknown provider 'llama'");
+}
+
+#[tokio::test]
+async fn test_health_checks_at_startup() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(RejectedKeyClient));
+    let (tx, mut rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.start_health_checks(tx.clone());
+    assert_eq!(app.providers[0].state, ProviderState::Connecting);
+    assert_eq!(app.providers[1].state, ProviderState::Disabled);
+    assert_eq!(app.providers[2].state, ProviderState::Connecting);
+    assert_eq!(app.active_provider_count(), 2);
+
+    // Prompts don't wait for the checks
+    app.send_to_active_providers("Hi", None, tx.clone());
+    assert_eq!(app.providers[0].chat_history.last().unwrap(), "ChatGPT: Thinking...");
+
+    let mut checked = 0;
+    while checked < 2 {
+        if let Some(ResponseType::Health(idx, status)) = rx.recv().await {
+            app.handle_health(idx, status);
+            checked += 1;
+        }
+    }
+    assert_eq!(app.providers[0].state, ProviderState::Enabled);
+    assert_eq!(app.providers[2].state, ProviderState::Unavailable);
+    assert_eq!(app.providers[2].state_note(2).unwrap(), "⚠️ Unavailable (Alt+3 to turn on anyway): 401 Unauthorized");
+    assert!(!app.providers[2].chat_history.iter().any(|msg| msg.contains("401")));
+    assert_eq!(app.active_provider_count(), 1);
+
+    // Alt+3 turns it on anyway
+    app.toggle_provider(2);
+    assert_eq!(app.providers[2].state, ProviderState::Enabled);
+    assert_eq!(app.providers[2].health_error, None);
+}
+
+#[tokio::test]
+async fn test_toggle_provider_at_runtime() {
+    let mut states = HashMap::new();
+    states.insert("Gemini", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[1].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.toggle_provider(2);
+    assert_eq!(app.providers[2].state, ProviderState::Disabled);
+    assert!(app.status_line().contains("Claude off"));
+    assert_eq!(app.active_provider_count(), 1);
+    let claude_history = app.providers[2].chat_history.len();
+    app.send_to_active_providers("Hi", None, tx.clone());
+    app.send_to_active_providers("Only Claude", Some(2), tx.clone());
+    assert_eq!(app.providers[1].chat_history.last().unwrap(), "Gemini: Thinking...");
+    assert_eq!(app.providers[2].chat_history.len(), claude_history);
+
+    // Turning it back on keeps its client and history
+    app.toggle_provider(2);
+    assert_eq!(app.providers[2].state, ProviderState::Enabled);
+    assert_eq!(app.providers[2].chat_history.len(), claude_history);
+
+    // ChatGPT had no key at startup, and only comes on once one is set
+    app.api_keys = ApiKeys::fixed([]);
+    app.toggle_provider(0);
+    assert_eq!(app.providers[0].state, ProviderState::Disabled);
+    assert!(app.status_line().contains("ChatGPT: API key missing"));
+    app.api_keys = ApiKeys::fixed([("CHATGPT_API_KEY", "test-key")]);
+    app.toggle_provider(0);
+    assert_eq!(app.providers[0].state, ProviderState::Enabled);
+    assert!(app.providers[0].client.is_some());
+}
+
+#[tokio::test]
+async fn test_request_log() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    app.send_to_active_providers("What is Rust?", None, tx.clone());
+    let entries = app.request_log.entries();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|entry| entry.status == RequestStatus::Pending && entry.prompt == "What is Rust?"));
+
+    app.handle_response(0, "A language.".to_string());
+    app.handle_response(2, "Error: 529 Overloaded".to_string());
+    let entries = app.request_log.entries();
+    assert_eq!((entries[0].provider.as_str(), entries[0].status), ("ChatGPT", RequestStatus::Success));
+    assert_eq!((entries[1].provider.as_str(), entries[1].status), ("Claude", RequestStatus::Error));
+    assert!(entries.iter().all(|entry| entry.latency_ms.is_some()));
+
+    app.send_to_active_providers("Again", None, tx);
+    app.cancel_pending();
+    assert!(app.request_log.entries().iter().skip(2).all(|entry| entry.status == RequestStatus::Cancelled));
+}
+
+#[tokio::test]
+async fn test_metrics_panel() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    states.insert("Claude", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].client = Some(Arc::new(EchoClient));
+    app.providers[2].client = Some(Arc::new(EchoClient));
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+    assert_eq!(app.metrics.get_summary(), "📊 Metrics: Ready");
+
+    app.send_to_active_providers("What is Rust?", None, tx);
+    app.handle_response(0, "A language.".to_string());
+    app.handle_metadata(0, ResponseMetadata {
+        token_usage: Some(TokenUsage { input_tokens: 10, output_tokens: 32 }),
+        ..Default::default()
+    });
+    app.handle_response(2, "Error: 529 Overloaded".to_string());
+
+    let chatgpt = app.metrics.get_metrics("ChatGPT").get_stats();
+    assert_eq!((chatgpt.requests_total, chatgpt.requests_successful, chatgpt.total_tokens_used), (1, 1, 42));
+    let claude = app.metrics.get_metrics("Claude").get_stats();
+    assert_eq!((claude.requests_total, claude.requests_successful), (1, 0));
+    assert_eq!(app.metrics.get_summary(), "📊 2 req, 50% ok");
+
+    // The panel shares the delta field's row, and keeps it while the delta field is collapsed
+    let screen = Rect::new(0, 0, 90, 40);
+    assert_eq!(app.screen_layout(screen).metrics.width, 0);
+    app.metrics.toggle_enabled();
+    let layout = app.screen_layout(screen);
+    assert!(layout.metrics.width > 0 && layout.delta.width > 0);
+    assert_eq!(layout.metrics.y, layout.delta.y);
+    app.toggle_delta_panel();
+    let layout = app.screen_layout(screen);
+    assert_eq!((layout.delta.width, layout.metrics.width), (0, 90));
+}
+
+#[tokio::test]
+async fn test_page_scrolling() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].chat_history = (0..100).map(|n| format!("line {}", n)).collect();
+
+    // Before the first frame a page is the most a column shows
+    assert_eq!(app.page_size(), COLUMN_VISIBLE_LINES);
+    app.layout = app.screen_layout(Rect::new(0, 0, 120, 20));
+    app.update_viewports(false);
+    let page = app.layout.columns[0].height as usize - 2;
+    assert!(page < COLUMN_VISIBLE_LINES);
+    assert_eq!(app.page_size(), page);
+
+    app.page_down();
+    app.page_down();
+    assert_eq!(app.scroll_positions[0], 2 * page);
+    app.page_up();
+    assert_eq!(app.scroll_positions[0], page);
+    app.scroll_up(1000);
+    assert_eq!(app.scroll_positions[0], 0);
+    app.scroll_to_bottom();
+    assert_eq!(app.scroll_positions[0], 100 - page);
+    app.scroll_down(1);
+    assert_eq!(app.scroll_positions[0], 100 - page);
+    app.scroll_to_top();
+    assert_eq!(app.scroll_positions[0], 0);
+
+    // Content shorter than a page doesn't move
+    app.providers[0].chat_history.truncate(3);
+    app.page_down();
+    assert_eq!(app.scroll_positions[0], 0);
+}
+
+#[tokio::test]
+async fn test_sticky_scroll_follows_streaming() {
+    let mut states = HashMap::new();
+    states.insert("ChatGPT", ProviderState::Enabled);
+    let mut app = AppState::new(states);
+    app.providers[0].chat_history = vec!["You: Count".to_string(), "ChatGPT: Thinking...".to_string()];
+    // Twice the visible rows, so the column can scroll up a few lines from the bottom
+    let chunk = "line\n".repeat(COLUMN_VISIBLE_LINES * 2);
+
+    // At the bottom, the column follows the stream
+    app.handle_stream_chunk(0, chunk.clone(), false);
+    let bottom = app.max_scroll(0);
+    assert!(bottom > 3);
+    assert_eq!(app.scroll_positions[0], bottom);
+    assert_eq!(app.scroll_offset(0), bottom);
+
+    // Scrolling up stops following
+    app.scroll_up(3);
+    app.handle_stream_chunk(0, chunk.clone(), true);
+    assert_eq!(app.scroll_positions[0], bottom - 3);
+    assert_eq!(app.scroll_offset(0), bottom - 3);
+    assert!(!app.following[0]);
+
+    // End resumes it
+    app.scroll_to_bottom();
+    assert!(app.following[0]);
+    app.handle_response(0, chunk.repeat(2));
+    assert_eq!(app.scroll_positions[0], app.max_scroll(0));
+}
+
+#[test]
+fn test_transcription_is_typed_at_cursor() {
+    let mut app = AppState::new(HashMap::new());
+    app.shared_input = "Explain ".to_string();
+    app.cursor_pos = 8;
+    app.recording = true;
+
+    app.handle_transcription(Ok("the borrow checker".to_string()));
+    assert!(!app.recording);
+    assert_eq!(app.shared_input, "Explain the borrow checker");
+    assert_eq!(app.cursor_pos, app.shared_input.chars().count());
+    app.undo();
+    assert_eq!(app.shared_input, "Explain ");
+
+    // Esc while recording: F5 starts a new recording instead of stopping one that was cancelled
+    app.recording = true;
+    app.cancel_pending();
+    assert!(!app.recording);
+
+    app.handle_transcription(Err("no microphone found".to_string()));
+    assert_eq!(app.notice.as_ref().map(|(notice, _)| notice.as_str()), Some("⚠️ Voice input failed: no microphone found"));
+}
+
+#[tokio::test]
+async fn test_replayed_session_fills_columns() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/session.json");
+    let log = ConversationLog::load(&fixture).unwrap();
+    let (replay_tx, mut replay_rx) = mpsc::unbounded_channel();
+    Logger::replay_to_channel(&log, replay_tx);
+
+    let states = ["ChatGPT", "Gemini", "Claude"].into_iter().map(|name| (name, ProviderState::Enabled)).collect();
+    let mut app = AppState::new(states);
+    for provider in &mut app.providers {
+        provider.client = Some(Arc::new(EchoClient));
+    }
+    app.use_streaming = false;
+    let (tx, _rx) = mpsc::channel(RESPONSE_CHANNEL_CAPACITY);
+
+    // Each prompt is sent as if typed, and the saved answers arrive in place of the live ones
+    for entry in &log.conversations {
+        app.send_to_active_providers(&entry.prompt, None, tx.clone());
+        app.tasks.abort_all();
+        while app.tasks.join_next().await.is_some() {}
+        for _ in 0..entry.responses.len() {
+            let Ok(ResponseType::Provider(provider_idx, response)) = replay_rx.try_recv() else {
+                panic!("expected a response");
+            };
+            app.handle_response(provider_idx, response);
+        }
+        let Ok(ResponseType::Delta(delta)) = replay_rx.try_recv() else {
+            panic!("expected a delta");
+        };
+        app.handle_delta_response(delta);
+    }
+    assert!(replay_rx.try_recv().is_err());
+
+    let history = |provider_idx: usize| app.providers[provider_idx].chat_history[1..].to_vec();
+    assert_eq!(history(0), ["You: What is 2 + 2?", "ChatGPT: 4", "You: Name a prime number above 10", "ChatGPT: 11"]);
+    assert_eq!(
+        history(1),
+        ["You: What is 2 + 2?", "Gemini: Four", "You: Name a prime number above 10", "Gemini: Error: request timed out (after 0s)"]
+    );
+    assert_eq!(history(2), ["You: What is 2 + 2?", "Claude: 2 + 2 = 4", "You: Name a prime number above 10", "Claude: 13"]);
+    assert_eq!(app.providers[1].failures_in_a_row, 1);
+    assert_eq!(app.delta_text, "ChatGPT and Claude give different primes; Gemini timed out.");
+}
+
+#[test]
+fn test_cycle_theme() {
+    let mut app = AppState::new(HashMap::new());
+    app.cycle_theme();
+    assert_eq!(app.theme, Theme::light());
+    assert_eq!(app.notice.as_ref().map(|(notice, _)| notice.as_str()), Some("🎨 Theme: light"));
+    app.cycle_theme();
+    app.cycle_theme();
+    assert_eq!(app.theme, Theme::dark());
+}